@@ -1,4 +1,3 @@
-use rocksdb::DBVector;
 use crate::buffer_pool::BufErr;
 use serde::Serialize;
 use serde::de::DeserializeOwned;
@@ -15,10 +14,11 @@ use rl_bandit::bandit::{Bandit, UpdateType};
 use rl_bandit::bandits::egreedy::EGreedy;
 use crate::buffer_pool::BufErr::BufEmpty;
 use crate::compress::split_double::SplitBDDoubleCompress;
+use crate::shutdown::Shutdown;
 
 pub struct MABCompressionDaemon<T,U,F>
 	where T: Copy + Send + Serialize + DeserializeOwned+FromPrimitive+Into<f64>,
-	      U: FileManager<Vec<u8>,DBVector> + Sync + Send,
+	      U: FileManager<Vec<u8>,Vec<u8>> + Sync + Send,
 		  F: CompressionMethod<T>
 {
 	seg_buf: Arc<Mutex<SegmentBuffer<T> + Send + Sync>>,
@@ -28,13 +28,14 @@ pub struct MABCompressionDaemon<T,U,F>
 	uncomp_threshold: f32,
 	processed: usize,
 	compress_method: F,
-	mab: EGreedy
+	mab: EGreedy,
+	shutdown: Shutdown,
 }
 
 
 impl<T,U,F> MABCompressionDaemon<T,U,F>
 	where T: Copy + Send + Serialize + DeserializeOwned+FromPrimitive+Into<f64>,
-		  U: FileManager<Vec<u8>,DBVector> + Sync + Send,
+		  U: FileManager<Vec<u8>,Vec<u8>> + Sync + Send,
 		  F: CompressionMethod<T>
 {
 	pub fn new(seg_buf: Arc<Mutex<SegmentBuffer<T> + Send + Sync>>,
@@ -54,7 +55,8 @@ impl<T,U,F> MABCompressionDaemon<T,U,F>
 			uncomp_threshold: uncomp_threshold,
 			processed: 0,
 			compress_method: compress_method,
-			mab: EGreedy::new(10, e, 4.0, UpdateType::Nonstationary(s))
+			mab: EGreedy::new(10, e, 4.0, UpdateType::Nonstationary(s)),
+			shutdown: Shutdown::new(),
 		}
 	}
 
@@ -63,7 +65,8 @@ impl<T,U,F> MABCompressionDaemon<T,U,F>
 		match self.seg_buf.lock() {
 			Ok(mut buf) => {
 				//println!("Lock aquired");
-				if buf.exceed_threshold(self.uncomp_threshold) && buf.exceed_batch(self.compress_method.get_batch()) {
+				let stopping = self.shutdown.is_stopping();
+				if (stopping || buf.exceed_threshold(self.uncomp_threshold)) && buf.exceed_batch(self.compress_method.get_batch()) {
 					let batch_size = self.compress_method.get_batch();
 					//println!("Get segment for compression batch size:{}",batch_size);
 					let mut segs = Vec::with_capacity(batch_size);
@@ -79,6 +82,9 @@ impl<T,U,F> MABCompressionDaemon<T,U,F>
 						}
 					}
 					Ok(segs)
+				} else if stopping {
+					/* Less than a batch is left, it is persisted with the raw pool */
+					Err(BufErr::BufEmpty)
 				} else {
 					Err(BufErr::UnderThresh)
 				}
@@ -118,6 +124,11 @@ impl<T,U,F> MABCompressionDaemon<T,U,F>
 		}
 	}
 
+	/* Once the run shuts down the full batches left are compressed before the daemon exits */
+	pub fn set_shutdown(&mut self, shutdown: Shutdown){
+		self.shutdown = shutdown;
+	}
+
 	pub fn get_processed(&self) -> usize{
 		self.processed
 	}
@@ -142,7 +153,8 @@ impl<T,U,F> MABCompressionDaemon<T,U,F>
 					println!("segment compressed {}", self.processed);
 					match &self.file_manager {
 						Some(fm) => {
-							for mut seg in segs{
+							let mut batch = Vec::with_capacity(segs.len());
+							for seg in segs{
 								let key_bytes = match seg.get_key().convert_to_bytes() {
 									Ok(bytes) => bytes,
 									Err(_) => continue, /* silence failure to byte convert */
@@ -151,10 +163,11 @@ impl<T,U,F> MABCompressionDaemon<T,U,F>
 									Ok(bytes) => bytes,
 									Err(_) => continue, /* silence failure to byte convert */
 								};
-								match fm.fm_write(key_bytes, seg_bytes) {
-									Ok(()) => continue,
-									Err(_) => continue, /* currently silence error from fialed write */
-								}
+								batch.push((key_bytes, seg_bytes));
+							}
+							match fm.fm_write_batch(batch) {
+								Ok(()) => (),
+								Err(_) => (), /* currently silence error from fialed write */
 							}
 						}
 						None => {
@@ -1,4 +1,3 @@
-use rocksdb::DBVector;
 use crate::buffer_pool::{BufErr};
 use serde::Serialize;
 use serde::de::DeserializeOwned;
@@ -6,7 +5,7 @@ use crate::segment::{Segment, SegmentKey};
 use std::sync::{Arc,Mutex};
 use crate::buffer_pool::{SegmentBuffer,ClockBuffer};
 use crate::file_handler::{FileManager};
-use crate::{file_handler, GZipCompress, ZlibCompress, DeflateCompress, SnappyCompress, PAACompress, FourierCompress};
+use crate::{PAACompress, FourierCompress};
 use crate::methods::compress::CompressionMethod;
 use std::any::Any;
 use std::{fs, thread};
@@ -23,18 +22,17 @@ use smartcore::math::num::RealNumber;
 use crate::buffer_pool::BufErr::BufEmpty;
 use crate::compress::buff_lossy::BUFFlossy;
 use crate::methods::{IsLossless, Methods};
-use crate::compress::split_double::SplitBDDoubleCompress;
-use crate::compress::sprintz::SprintzDoubleCompress;
-use crate::compress::gorilla::{GorillaBDCompress, GorillaCompress};
+use crate::methods::registry::codec_for;
 use crate::compress::pla_lttb::PLACompress;
 use crate::compress::rrd_sample::RRDsample;
+use crate::shutdown::Shutdown;
 
 
 
 
 pub struct MABRecodingDaemon<T,U>
 	where T: Copy + Send + Serialize + DeserializeOwned + RealNumber,
-	      U: FileManager<Vec<u8>,DBVector> + Sync + Send,
+	      U: FileManager<Vec<u8>,Vec<u8>> + Sync + Send,
 {
 	seg_buf: Arc<Mutex<SegmentBuffer<T> + Send + Sync>>,
 	comp_seg_buf: Arc<Mutex<SegmentBuffer<T> + Send + Sync>>,
@@ -45,12 +43,13 @@ pub struct MABRecodingDaemon<T,U>
 	batch: usize,
 	lossy: Methods,
 	tcr: f64,
-	bestarms: (usize,usize,usize)
+	bestarms: (usize,usize,usize),
+	shutdown: Shutdown,
 }
 
 impl<T,U> MABRecodingDaemon<T,U>
 	where T: 'static + Copy + Send + Serialize + DeserializeOwned + FromPrimitive + Num+ FFTnum+Into<f64> + RealNumber,
-		  U: FileManager<Vec<u8>,DBVector> + Sync + Send,
+		  U: FileManager<Vec<u8>,Vec<u8>> + Sync + Send,
 {
 	pub fn new(seg_buf: Arc<Mutex<SegmentBuffer<T> + Send + Sync>>,
 			   comp_seg_buf: Arc<Mutex<SegmentBuffer<T> + Send + Sync>>,
@@ -72,7 +71,8 @@ impl<T,U> MABRecodingDaemon<T,U>
 			batch: batch,
 			lossy: lossy,
 			tcr : 0.0,
-			bestarms: (0, 0, 0)
+			bestarms: (0, 0, 0),
+			shutdown: Shutdown::new(),
 		}
 	}
 
@@ -81,8 +81,16 @@ impl<T,U> MABRecodingDaemon<T,U>
 		self.tcr = tcr;
 	}
 
+	/* Once the run shuts down the daemon exits after the batch it is recoding */
+	pub fn set_shutdown(&mut self, shutdown: Shutdown){
+		self.shutdown = shutdown;
+	}
+
 	fn get_seg_from_uncomp_buf(&mut self) -> Result<Vec<Segment<T>>,BufErr>
 	{
+		if self.shutdown.is_stopping() {
+			return Err(BufErr::BufEmpty);
+		}
 		match self.seg_buf.lock() {
 			Ok(mut buf) => {
 				//println!("Lock aquired");
@@ -345,7 +353,8 @@ impl<T,U> MABRecodingDaemon<T,U>
 					println!("segment recoded {}", self.processed);
 					match &self.file_manager {
 						Some(fm) => {
-							for mut seg in segs{
+							let mut batch = Vec::with_capacity(segs.len());
+							for seg in segs{
 								let key_bytes = match seg.get_key().convert_to_bytes() {
 									Ok(bytes) => bytes,
 									Err(_) => continue, /* silence failure to byte convert */
@@ -354,10 +363,11 @@ impl<T,U> MABRecodingDaemon<T,U>
 									Ok(bytes) => bytes,
 									Err(_) => continue, /* silence failure to byte convert */
 								};
-								match fm.fm_write(key_bytes, seg_bytes) {
-									Ok(()) => continue,
-									Err(_) => continue, /* currently silence error from fialed write */
-								}
+								batch.push((key_bytes, seg_bytes));
+							}
+							match fm.fm_write_batch(batch) {
+								Ok(()) => (),
+								Err(_) => (), /* currently silence error from fialed write */
 							}
 						}
 						None => {
@@ -371,34 +381,9 @@ impl<T,U> MABRecodingDaemon<T,U>
 									}
 
 									match seg.get_method().as_ref().unwrap() {
-										Methods::Sprintz (scale) => {
-											let pre = SprintzDoubleCompress::new(10, 20,*scale);
-											pre.run_decompress(seg);
-
-											self.lossy_comp_with_tcr(seg);
-
-										},
-										Methods::Buff(scale)=> {
-											let pre = SplitBDDoubleCompress::new(10,20, *scale);
-											pre.run_decompress(seg);
-
-											self.lossy_comp_with_tcr(seg);
-										},
-										Methods::Snappy=> {
-											let pre = SnappyCompress::new(10,20);
-											pre.run_decompress(seg);
-
-											self.lossy_comp_with_tcr(seg);
-										},
-										Methods::Gzip => {
-											let pre = GZipCompress::new(10,20);
-											pre.run_decompress(seg);
-
-											self.lossy_comp_with_tcr(seg);
-										},
-										Methods::Gorilla => {
-											let pre = GorillaCompress::new(10,20);
-											pre.run_decompress(seg);
+										m if IsLossless(m) => {
+											let codec = codec_for(m).unwrap();
+											codec.decompress(seg);
 
 											self.lossy_comp_with_tcr(seg);
 										},
@@ -417,9 +402,6 @@ impl<T,U> MABRecodingDaemon<T,U>
 										Methods::Rrd_sample => {
 											// do nothing
 										}
-										Methods::Zlib => {
-											// do nothing
-										}
 										_ => todo!()
 									}
 									seg.update_comp_times();
@@ -429,34 +411,9 @@ impl<T,U> MABRecodingDaemon<T,U>
 								for seg in &mut segs {
 									// println!("segment key: {:?} with comp time:{}",seg.get_key(),seg.get_comp_times());
 									match seg.get_method().as_ref().unwrap() {
-										Methods::Sprintz (scale) => {
-											let pre = SprintzDoubleCompress::new(10, 20,*scale);
-											pre.run_decompress(seg);
-
-											self.lossy_comp(seg);
-
-										},
-										Methods::Buff(scale)=> {
-											let pre = SplitBDDoubleCompress::new(10,20, *scale);
-											pre.run_decompress(seg);
-
-											self.lossy_comp(seg);
-										},
-										Methods::Snappy=> {
-											let pre = SnappyCompress::new(10,20);
-											pre.run_decompress(seg);
-
-											self.lossy_comp(seg);
-										},
-										Methods::Gzip => {
-											let pre = GZipCompress::new(10,20);
-											pre.run_decompress(seg);
-
-											self.lossy_comp(seg);
-										},
-										Methods::Gorilla => {
-											let pre = GorillaCompress::new(10,20);
-											pre.run_decompress(seg);
+										m if IsLossless(m) => {
+											let codec = codec_for(m).unwrap();
+											codec.decompress(seg);
 
 											self.lossy_comp(seg);
 										},
@@ -136,13 +136,15 @@ pub fn influx_bench(compression: &str, query: &str){
             let comp = SprintzDoubleCompress::new(10,10,scl);
             if query=="max"{
                 let start6 = Instant::now();
-                comp.max(comp_max);
+                let max = comp.max(comp_max);
                 duration6 = start6.elapsed();
+                println!("Max value:{}", max);
                 println!("Time elapsed in sprintz max function() is: {:?}", duration6);
             }else if query=="max_groupby"{
                 let start6 = Instant::now();
-                comp.max_range(comp_max,start,end,window);
+                let max_vec = comp.max_range(comp_max,start,end,window);
                 duration6 = start6.elapsed();
+                println!("Max value:{}", max_vec.len());
                 println!("Time elapsed in sprintz max_groupby function() is: {:?}", duration6);
 
             }
@@ -158,8 +160,9 @@ pub fn influx_bench(compression: &str, query: &str){
             }else if query=="max_groupby"{
                 // println!("Time elapsed in buff-slice max groupby not supported");
                 let start6 = Instant::now();
-                comp.buff_slice_max_range(comp_max,start,end,window);
+                let max_vec = comp.buff_slice_max_range(comp_max,start,end,window);
                 duration6 = start6.elapsed();
+                println!("Max value:{}", max_vec.len());
                 println!("Time elapsed in buff-slice max groupby function() is: {:?}", duration6);
             }
         },
@@ -169,14 +172,16 @@ pub fn influx_bench(compression: &str, query: &str){
 
             if query=="max"{
                 let start6 = Instant::now();
-                comp.scaled_slice_max(comp_max);
+                let max = comp.scaled_slice_max(comp_max);
                 duration6 = start6.elapsed();
+                println!("Max value:{}", max);
                 println!("Time elapsed in scaled-slice max function() is: {:?}", duration6);
             }else if query=="max_groupby"{
                 // println!("Time elapsed in buff-slice max groupby not supported");
                 let start6 = Instant::now();
-                comp.scaled_slice_max_range(comp_max,start,end,window);
+                let max_vec = comp.scaled_slice_max_range(comp_max,start,end,window);
                 duration6 = start6.elapsed();
+                println!("Max value:{}", max_vec.len());
                 println!("Time elapsed in scaled-slice max groupby function() is: {:?}", duration6);
             }
         },
@@ -3,7 +3,7 @@ use crate::client::construct_file_iterator_skip_newline;
 use std::collections::{HashSet, HashMap};
 use std::iter::FromIterator;
 use crate::compress::split_double::SplitBDDoubleCompress;
-use std::time::Instant;
+use std::time::{Instant, SystemTime};
 use crate::compress::buff_slice::BuffSliceCompress;
 use crate::compress::sprintz::SprintzDoubleCompress;
 use crate::compress::gorilla::{GorillaCompress, GorillaBDCompress};
@@ -11,8 +11,75 @@ use crate::methods::compress::{SnappyCompress, GZipCompress};
 use my_bit_vec::BitVec;
 use crate::query::bit_vec_iter::BVIter;
 use crate::compress::scaled_slice::ScaledSliceCompress;
+use crate::segment::columnar::ColumnarSegment;
+use crate::methods::registry::codec_by_name;
+
+/* Columnar compressions are named "columnar-<codec>", the codec compresses every column */
+const COLUMNAR_PREFIX: &str = "columnar-";
+
+fn get_f64_csv_file(f:&str) ->Vec<f64>
+{
+    let mut file = BENCH_DATA.to_owned();
+    file.push_str(f);
+    println!("get csv file:{}", file);
+    let file_iter = construct_file_iterator_skip_newline::<f64>(&file, 0, ',');
+    file_iter.unwrap().collect()
+}
+
+/* The project query on a single columnar segment holding latitude and
+ * longitude, so both columns are fetched with one lookup
+ */
+fn tsbs_project_columnar(codec: &str) {
+    let method = match codec_by_name(codec, 10) {
+        Some(c) => c.method().clone(),
+        None => panic!("Compression not supported yet."),
+    };
+    let latitude = get_f64_csv_file("r_latitude.csv");
+    let longtitude = get_f64_csv_file("r_longitude.csv");
+    let rows = latitude.iter().zip(longtitude.iter()).map(|(&la, &lo)| vec![la, lo]).collect();
+    let names = vec!["latitude".to_string(), "longitude".to_string()];
+    let mut seg = ColumnarSegment::from_rows(SystemTime::now(), 0, names, rows, None, None);
+    seg.compress_column("latitude", &method).expect("Failed to compress latitude");
+    seg.compress_column("longitude", &method).expect("Failed to compress longitude");
+    let bytes = seg.convert_to_bytes().expect("The segment should be byte convertible");
+    println!("columnar segment size: {} bytes for {} rows", bytes.len(), seg.get_size());
+
+    let r_tag = get_csv_file("r_tags_id.csv");
+    let t_id = get_csv_file("t_id_south.csv");
+    let start = Instant::now();
+
+    let mut i = r_tag.len()-1;
+    let mut cands = Vec::new();
+    let mut id_set:HashSet<usize> = HashSet::from_iter(t_id.iter().cloned());
+    // get tag id
+    for &e in r_tag.iter().rev() {
+        if id_set.contains(&e){
+            cands.insert(0,i);
+            id_set.remove(&e);
+            if id_set.is_empty(){
+                break;
+            }
+        }
+        i-=1;
+    }
+    println!("integer join runtime: {:?}",start.elapsed());
+
+    let start6 = Instant::now();
+    let seg = ColumnarSegment::<f64>::convert_from_bytes(&bytes).expect("The segment should be readable");
+    let projected = seg.project(&["latitude", "longitude"], cands.iter()).expect("Missing column");
+    let duration6 = start6.elapsed();
+    println!("Time elapsed in {} columnar project function() is: {:?}", codec, duration6);
+
+    println!("extracted latitude values: {:?}",projected[0]);
+    println!("extracted longitude values: {:?}",projected[1]);
+    println!("Time elapsed in tsbs last-loc function() is: {:?}", start.elapsed());
+}
 
 pub fn tsbs_bench(compression: &str, query: &str){
+    if query=="project" && compression.starts_with(COLUMNAR_PREFIX) {
+        tsbs_project_columnar(&compression[COLUMNAR_PREFIX.len()..]);
+        return;
+    }
     let mut other = 0.0;
     let mut fl_time = 0.0;
     let mut total = 0.0;
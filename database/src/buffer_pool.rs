@@ -1,39 +1,33 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::{Debug, Display};
 use serde::{Serialize};
 use serde::de::DeserializeOwned;
-use rocksdb::DBVector;
 use crate::file_handler::{FileManager};
-use std::collections::vec_deque::Drain;
 use std::collections::hash_map::{HashMap, Entry};
-use std::{fmt, fs};
+use std::fmt;
 use std::ops::Add;
+use std::sync::{Arc, Mutex};
 use std::time::{SystemTime, UNIX_EPOCH};
 use libc::time;
 use num::{FromPrimitive, Num, Signed, zero};
-use rl_bandit::bandit::{Bandit, UpdateType};
-use rl_bandit::bandits::egreedy::EGreedy;
 use rustfft::FFTnum;
-use smartcore::api::{Predictor, PredictorVec};
-use smartcore::cluster::kmeans::KMeans;
-use smartcore::ensemble::random_forest_classifier::RandomForestClassifier;
-use smartcore::linalg::{BaseVector, Matrix};
-use smartcore::linalg::naive::dense_matrix::DenseMatrix;
-use smartcore::math::distance::Distance;
-use smartcore::math::distance::euclidian::Euclidian;
 use smartcore::math::num::RealNumber;
-use smartcore::naive_bayes::gaussian::GaussianNB;
-use smartcore::neighbors::knn_classifier::KNNClassifier;
-use smartcore::tree::decision_tree_classifier::DecisionTreeClassifier;
 
-use crate::{CompressionMethod, FourierCompress, GorillaCompress, GZipCompress, PAACompress, segment, SnappyCompress, SprintzDoubleCompress, ZlibCompress};
+use crate::segment;
+
+pub mod workload;
+pub mod sharded;
+pub mod scan_resistant;
+pub mod governor;
 
 use segment::{Segment, SegmentKey};
-use crate::compress::buff_lossy::BUFFlossy;
-use crate::compress::pla_lttb::PLACompress;
-use crate::compress::rrd_sample::RRDsample;
-use crate::compress::split_double::SplitBDDoubleCompress;
-use crate::methods::{IsLossless, Methods};
+use segment::header::{FormatError, read_header};
+use segment::columnar::columnar_key_bytes;
+use crate::future_signal::SignalId;
+use crate::methods::Methods;
+use crate::methods::registry::decode_segment;
+use self::workload::WorkloadEvaluator;
+use self::governor::{governor, MemoryGovernor, MemoryAccount};
 
 /* 
  * Overview:
@@ -82,17 +76,17 @@ pub trait SegmentBuffer<T: Copy + Send> {
     fn put(&mut self, item: Segment<T>) -> Result<(), BufErr>;
 
 
-    /* Will return a Drain iterator that gains ownership over
-     * the items in the buffer, but will leave the allocation
-     * of the buffer untouched
+    /* Will take ownership over every segment in the buffer and
+     * return them, leaving the buffer empty but its allocation
+     * untouched
      */
-    fn drain(&mut self) -> Drain<Segment<T>> {
-        unimplemented!()
-    }
+    fn drain(&mut self) -> Vec<Segment<T>>;
 
-    /* provide encoding suggestion for the recoding daemon, only implemented by LRU cache */
+    /* provide encoding suggestion for the recoding daemon, only the LRU cache
+     * learns one, every other buffer recommends the first arm of each bandit
+     */
     fn get_recommend(&self) -> (usize,usize,usize) {
-        unimplemented!()
+        (0,0,0)
     }
 
     /* Will copy the buffer and collect it into a vector */
@@ -118,6 +112,25 @@ pub trait SegmentBuffer<T: Copy + Send> {
     /* Remove the segment from the buffer and return it */
     fn remove_segment(&mut self) -> Result<Segment<T>, BufErr>;
 
+    /* Returns the number of segments held in memory */
+    fn len(&self) -> usize;
+
+    /* Returns the segment if it is held in memory, without loading it
+     * from the file manager or touching the replacement state, so it
+     * can be answered through a shared reference
+     */
+    fn peek(&self, _key: SegmentKey) -> Option<&Segment<T>> {
+        None
+    }
+
+    /* Evicts one segment the way the buffer would to make room, writing
+     * it through the file manager if there is one. Returns false when
+     * the buffer has nothing it can evict.
+     */
+    fn evict_one(&mut self) -> Result<bool, BufErr> {
+        Ok(false)
+    }
+
     /* Returns true if the number of items in the buffer divided by
      * the maximum number of items the buffer can hold belows
      * the provided idle threshold
@@ -125,15 +138,72 @@ pub trait SegmentBuffer<T: Copy + Send> {
     fn idle_threshold(&self, threshold: f32) -> bool;
 
 
-    fn get_buffer_size(&self) -> usize {
-        unimplemented!()
-    }
+    /* Returns the number of bytes held by the buffered segments */
+    fn get_buffer_size(&self) -> usize;
+
     /* Signal done*/
     fn is_done(&self) -> bool;
 
+    /* Returns the keys of every segment of the signal with a timestamp
+     * in [start, end) in ascending time order. This covers both the
+     * segments held in the buffer and those persisted by the file manager.
+     */
+    fn range_keys(&self, signal: SignalId, start: SystemTime, end: SystemTime) -> Result<Vec<SegmentKey>, BufErr>;
+
+    /* Evaluates the query workload over the buffered segments,
+     * buffers that do not profile a workload have nothing to run
+     */
     fn run_query(&self) {
-        unimplemented!();
     }
+
+    /* Returns a handle onto the same segments for another thread when the
+     * buffer synchronizes itself internally, None when it can only be
+     * shared behind the mutex it is already wrapped in
+     */
+    fn handle(&self) -> Option<Arc<Mutex<SegmentBuffer<T> + Send + Sync>>> {
+        None
+    }
+
+    /* Returns how many lookups the buffer answered from memory, how many
+     * it could not and how many segments it evicted to make room
+     */
+    fn get_cache_stats(&self) -> CacheStats {
+        CacheStats::default()
+    }
+
+    /* Returns true once the segment was written through the file manager,
+     * buffers without one never persist anything
+     */
+    fn is_persisted(&self, _key: SegmentKey) -> bool {
+        false
+    }
+
+    /* Writes the bytes of a columnar segment, already compressed column
+     * by column, through the file manager under its tagged key. The pool
+     * does not hold it in memory, so the daemons never see it. Buffers
+     * without a file manager have nowhere to keep it and fail.
+     */
+    fn put_columnar(&mut self, _key: SegmentKey, _bytes: Vec<u8>) -> Result<(), BufErr> {
+        Err(BufErr::FailPut)
+    }
+
+    /* Removes every segment of the signal whose last point is older than
+     * the cutoff, from memory and from the file manager, and returns what
+     * was removed
+     */
+    fn expire(&mut self, signal: SignalId, before: SystemTime) -> Result<Expired, BufErr>;
+}
+
+
+/* The buffer to give one more signal or daemon, a handle of its own
+ * if the buffer hands those out and the shared buffer otherwise
+ */
+pub fn share_buffer<T: Copy + Send>(buf: &Arc<Mutex<SegmentBuffer<T> + Send + Sync>>) -> Arc<Mutex<SegmentBuffer<T> + Send + Sync>> {
+    let handle = match buf.lock() {
+        Ok(b) => b.handle(),
+        Err(_) => None,
+    };
+    handle.unwrap_or_else(|| buf.clone())
 }
 
 
@@ -155,6 +225,230 @@ pub enum BufErr {
     UnderThresh,
     RemoveFailure,
     CantGrabMutex,
+    CorruptSegment(SegmentKey),
+}
+
+
+/* A segment removed by a retention rule, bytes is what it took in the
+ * file manager or serialized in memory if it was never persisted
+ */
+#[derive(Clone,Debug,PartialEq)]
+pub struct ExpiredSegment {
+    pub key: SegmentKey,
+    pub bytes: usize,
+    pub points: usize,
+    pub method: Option<Methods>,
+    /* The time of the last point */
+    pub end: SystemTime,
+}
+
+impl ExpiredSegment {
+    fn of<T: Copy + Send + Serialize + DeserializeOwned>(seg: &Segment<T>) -> ExpiredSegment {
+        ExpiredSegment {
+            key: seg.get_key(),
+            bytes: seg.get_byte_size().unwrap_or(0),
+            points: seg.get_size(),
+            method: seg.get_method().clone(),
+            end: seg.get_end(),
+        }
+    }
+}
+
+/* A segment of the signal expires once its last point is older than the cutoff */
+fn is_expired<T>(seg: &Segment<T>, signal: SignalId, before: SystemTime) -> bool {
+    seg.get_signal() == signal && seg.get_end() < before
+}
+
+/* Everything one expiry removed from a buffer, the segments in key order */
+#[derive(Clone,Debug,Default,PartialEq)]
+pub struct Expired {
+    pub segments: Vec<ExpiredSegment>,
+}
+
+impl Expired {
+    /* The bytes reclaimed by the segments */
+    pub fn bytes(&self) -> usize {
+        self.segments.iter().map(|seg| seg.bytes).sum::<usize>()
+    }
+
+    pub fn merge(&mut self, other: Expired) {
+        self.segments.extend(other.segments);
+    }
+}
+
+
+/* Counters the buffers keep so their eviction policies can be compared,
+ * a lookup is a hit when the segment was found in memory
+ */
+#[derive(Clone,Copy,Debug,Default,PartialEq)]
+pub struct CacheStats {
+    pub hits: usize,
+    pub misses: usize,
+    pub evictions: usize,
+}
+
+impl CacheStats {
+    pub fn hit_ratio(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            lookups => self.hits as f64 / lookups as f64,
+        }
+    }
+
+    pub fn merge(&self, other: &CacheStats) -> CacheStats {
+        CacheStats {
+            hits: self.hits + other.hits,
+            misses: self.misses + other.misses,
+            evictions: self.evictions + other.evictions,
+        }
+    }
+}
+
+impl fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "hits: {}, misses: {}, evictions: {}, hit ratio: {:.3}",
+               self.hits, self.misses, self.evictions, self.hit_ratio())
+    }
+}
+
+
+/* Reads the persisted segment of the key back from the file manager */
+fn load_persisted<T, U>(file_manager: &U, key: SegmentKey) -> Result<Option<Segment<T>>, BufErr>
+    where T: Copy + Send + Serialize + DeserializeOwned,
+          U: FileManager<Vec<u8>, Vec<u8>>,
+{
+    let key_bytes = match key.convert_to_bytes() {
+        Ok(bytes) => bytes,
+        Err(_) => return Err(BufErr::ByteConvertFail),
+    };
+    match file_manager.fm_get(key_bytes) {
+        Err(_) => Err(BufErr::FileManagerErr),
+        Ok(None) => Ok(None),
+        Ok(Some(bytes)) => match Segment::convert_from_bytes(&bytes) {
+            Ok(seg) => Ok(Some(seg)),
+            Err(FormatError::ChecksumMismatch{..}) |
+            Err(FormatError::Truncated) => Err(BufErr::CorruptSegment(key)),
+            Err(_) => Err(BufErr::ByteConvertFail),
+        }
+    }
+}
+
+/* Writes a single segment through the file manager under its key */
+fn write_persisted<T, U>(file_manager: &U, key: SegmentKey, seg: &Segment<T>) -> Result<(), BufErr>
+    where T: Copy + Send + Serialize + DeserializeOwned,
+          U: FileManager<Vec<u8>, Vec<u8>>,
+{
+    let key_bytes = match key.convert_to_bytes() {
+        Ok(bytes) => bytes,
+        Err(()) => return Err(BufErr::FailedSegKeySer),
+    };
+    let seg_bytes = match seg.convert_to_bytes() {
+        Ok(bytes) => bytes,
+        Err(()) => return Err(BufErr::FailedSegSer),
+    };
+    match file_manager.fm_write(key_bytes, seg_bytes) {
+        Ok(()) => Ok(()),
+        Err(_) => Err(BufErr::FileManagerErr),
+    }
+}
+
+/* Writes the bytes of a columnar segment through the file manager under its tagged key */
+fn write_columnar<U>(file_manager: &U, key: SegmentKey, bytes: Vec<u8>) -> Result<(), BufErr>
+    where U: FileManager<Vec<u8>, Vec<u8>>,
+{
+    let key_bytes = match columnar_key_bytes(key) {
+        Ok(bytes) => bytes,
+        Err(()) => return Err(BufErr::FailedSegKeySer),
+    };
+    match file_manager.fm_write(key_bytes, bytes) {
+        Ok(()) => Ok(()),
+        Err(_) => Err(BufErr::FileManagerErr),
+    }
+}
+
+/* True if the file manager holds a segment under the key. Scans the keys
+ * in [key, key + 0), which only the key itself falls in, without its value.
+ */
+fn has_persisted<U>(file_manager: &U, key: SegmentKey) -> bool
+    where U: FileManager<Vec<u8>, Vec<u8>>,
+{
+    match key.convert_to_bytes() {
+        Ok(key_bytes) => {
+            let mut after = key_bytes.clone();
+            after.push(0);
+            match file_manager.fm_scan_prefix(key_bytes, after, 0) {
+                Ok(mut iter) => iter.next().is_some(),
+                Err(_) => false,
+            }
+        }
+        Err(()) => false,
+    }
+}
+
+/* Deletes the persisted segments of the signal whose last point is older
+ * than the cutoff, segments also removed from memory are counted with their
+ * size on disk. Segments without a header are aged by the time they started.
+ */
+fn expire_persisted<U>(file_manager: &U, signal: SignalId, before: SystemTime,
+                       expired: &mut BTreeMap<SegmentKey, ExpiredSegment>) -> Result<(), BufErr>
+    where U: FileManager<Vec<u8>, Vec<u8>>,
+{
+    let (start_bytes, end_bytes) = match SegmentKey::range_bounds(signal, UNIX_EPOCH, before) {
+        Ok(bounds) => bounds,
+        Err(()) => return Err(BufErr::FailedSegKeySer),
+    };
+    /* Collected first, the scan may hold the file manager until it is dropped */
+    let found: Vec<(Vec<u8>, Vec<u8>)> = match file_manager.fm_scan(start_bytes, end_bytes) {
+        Ok(iter) => iter.collect(),
+        Err(_) => return Err(BufErr::FileManagerErr),
+    };
+    for (key_bytes, seg_bytes) in found {
+        let key = match SegmentKey::convert_from_bytes(&key_bytes) {
+            Ok(key) => key,
+            Err(()) => continue,
+        };
+        let header = read_header(&seg_bytes).ok().map(|(header, _)| header);
+        let end = header.as_ref().map_or(key.get_timestamp(), |header| header.end);
+        if end >= before {
+            continue;
+        }
+        if file_manager.fm_delete(key_bytes).is_err() {
+            return Err(BufErr::FileManagerErr);
+        }
+        expired.insert(key, ExpiredSegment {
+            key: key,
+            bytes: seg_bytes.len(),
+            points: header.as_ref().map_or(0, |header| header.count as usize),
+            method: header.and_then(|header| header.method),
+            end: end,
+        });
+    }
+    Ok(())
+}
+
+/* Adds the keys of the persisted segments of the signal in [start, end),
+ * none of their values are read
+ */
+fn scan_persisted_keys<U>(file_manager: &U, signal: SignalId, start: SystemTime, end: SystemTime,
+                          keys: &mut BTreeSet<SegmentKey>) -> Result<(), BufErr>
+    where U: FileManager<Vec<u8>, Vec<u8>>,
+{
+    let (start_bytes, end_bytes) = match SegmentKey::range_bounds(signal, start, end) {
+        Ok(bounds) => bounds,
+        Err(()) => return Err(BufErr::FailedSegKeySer),
+    };
+    match file_manager.fm_scan_prefix(start_bytes, end_bytes, 0) {
+        Ok(iter) => {
+            for (key_bytes, _) in iter {
+                match SegmentKey::convert_from_bytes(&key_bytes) {
+                    Ok(key) => { keys.insert(key); }
+                    Err(()) => return Err(BufErr::ByteConvertFail),
+                }
+            }
+            Ok(())
+        }
+        Err(_) => Err(BufErr::FileManagerErr),
+    }
 }
 
 
@@ -166,7 +460,7 @@ pub enum BufErr {
 #[derive(Debug)]
 pub struct ClockBuffer<T, U>
     where T: Copy + Send,
-          U: FileManager<Vec<u8>, DBVector> + Sync + Send,
+          U: FileManager<Vec<u8>,Vec<u8>> + Sync + Send,
 {
     hand: usize,
     tail: usize,
@@ -176,12 +470,14 @@ pub struct ClockBuffer<T, U>
     file_manager: U,
     buf_size: usize,
     done: bool,
+    stats: CacheStats,
+    account: MemoryAccount,
 }
 
 
 impl<T, U> SegmentBuffer<T> for ClockBuffer<T, U>
     where T: Copy + Send + Serialize + DeserializeOwned + Debug,
-          U: FileManager<Vec<u8>, DBVector> + Sync + Send,
+          U: FileManager<Vec<u8>,Vec<u8>> + Sync + Send,
 {
     fn get(&mut self, key: SegmentKey) -> Result<Option<&Segment<T>>, BufErr> {
         if self.retrieve(key)? {
@@ -225,6 +521,7 @@ impl<T, U> SegmentBuffer<T> for ClockBuffer<T, U>
 
     /* Write to file system */
     fn persist(&self) -> Result<(), BufErr> {
+        let mut batch = Vec::with_capacity(self.buffer.len());
         for (seg_key, seg) in self.buffer.iter() {
             let seg_key_bytes = match seg_key.convert_to_bytes() {
                 Ok(bytes) => bytes,
@@ -234,19 +531,22 @@ impl<T, U> SegmentBuffer<T> for ClockBuffer<T, U>
                 Ok(bytes) => bytes,
                 Err(_) => return Err(BufErr::FailedSegSer),
             };
-
-            match self.file_manager.fm_write(seg_key_bytes, seg_bytes) {
-                Err(_) => return Err(BufErr::FileManagerErr),
-                _ => (),
-            }
+            batch.push((seg_key_bytes, seg_bytes));
         }
 
-        Ok(())
+        match self.file_manager.fm_write_batch(batch) {
+            Err(_) => Err(BufErr::FileManagerErr),
+            Ok(()) => Ok(()),
+        }
     }
 
     fn flush(&mut self) {
         self.buffer.clear();
         self.clock.clear();
+        self.clock_map.clear();
+        self.hand = 0;
+        self.tail = 0;
+        self.account.reset();
         self.done = true;
     }
 
@@ -257,29 +557,47 @@ impl<T, U> SegmentBuffer<T> for ClockBuffer<T, U>
     }
 
     fn idle_threshold(&self, threshold: f32) -> bool {
-        unimplemented!()
+        return (self.buffer.len() as f32 / self.buf_size as f32) < threshold;
     }
 
+    fn get_buffer_size(&self) -> usize {
+        self.buffer.values().map(|seg| seg.get_byte_size().unwrap_or(0)).sum()
+    }
 
+    fn drain(&mut self) -> Vec<Segment<T>> {
+        let buffer = &mut self.buffer;
+        let mut segs: Vec<Segment<T>> = self.clock.drain(..)
+            .filter_map(|(seg_key, _)| buffer.remove(&seg_key))
+            .collect();
+        segs.extend(buffer.drain().map(|(_, seg)| seg));
+        self.clock_map.clear();
+        self.hand = 0;
+        self.tail = 0;
+        self.account.reset();
+        segs
+    }
 
     fn remove_segment(&mut self) -> Result<Segment<T>, BufErr> {
         let mut counter = 0;
         loop {
+            if self.clock.is_empty() {
+                return Err(BufErr::BufEmpty);
+            }
+            if self.tail >= self.clock.len() {
+                self.tail = 0;
+            }
             if let (seg_key, false) = self.clock[self.tail] {
-                let seg = match self.buffer.remove(&seg_key) {
-                    Some(seg) => seg,
-                    None => {
-                        //println!("Failed to get segment from buffer.");
-                        self.update_tail();
-                        return Err(BufErr::EvictFailure);
+                /* The slot goes with the segment, a stale one would stop the next removal */
+                self.clock_map.remove(&seg_key);
+                let tail = self.tail;
+                self.remove_slot(tail);
+                match self.buffer.remove(&seg_key) {
+                    Some(seg) => {
+                        self.account.release(seg.get_heap_size());
+                        return Ok(seg);
                     }
-                };
-                match self.clock_map.remove(&seg_key) {
-                    None => panic!("Non-unique key panic as clock map and buffer are desynced somehow"),
-                    _ => (),
+                    None => continue,
                 }
-                //println!("fetch a segment from buffer.");
-                return Ok(seg);
             } else {
                 self.clock[self.tail].1 = false;
             }
@@ -295,12 +613,73 @@ impl<T, U> SegmentBuffer<T> for ClockBuffer<T, U>
     fn exceed_batch(&self, batchsize: usize) -> bool {
         return self.buffer.len() >= batchsize;
     }
+
+    fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    fn peek(&self, key: SegmentKey) -> Option<&Segment<T>> {
+        self.buffer.get(&key)
+    }
+
+    /* Sweeps the clock from the tail like remove_segment and writes the segment out */
+    fn evict_one(&mut self) -> Result<bool, BufErr> {
+        let seg = match self.remove_segment() {
+            Ok(seg) => seg,
+            Err(BufErr::BufEmpty) => return Ok(false),
+            Err(e) => return Err(e),
+        };
+        write_persisted(&self.file_manager, seg.get_key(), &seg)?;
+        self.stats.evictions += 1;
+        Ok(true)
+    }
+
+    fn range_keys(&self, signal: SignalId, start: SystemTime, end: SystemTime) -> Result<Vec<SegmentKey>, BufErr> {
+        let mut keys: BTreeSet<SegmentKey> = self.buffer.keys()
+            .filter(|k| k.in_range(signal, start, end))
+            .cloned()
+            .collect();
+        scan_persisted_keys(&self.file_manager, signal, start, end, &mut keys)?;
+        Ok(keys.into_iter().collect())
+    }
+
+    fn get_cache_stats(&self) -> CacheStats {
+        self.stats
+    }
+    fn is_persisted(&self, key: SegmentKey) -> bool {
+        has_persisted(&self.file_manager, key)
+    }
+
+    fn put_columnar(&mut self, key: SegmentKey, bytes: Vec<u8>) -> Result<(), BufErr> {
+        write_columnar(&self.file_manager, key, bytes)
+    }
+
+    fn expire(&mut self, signal: SignalId, before: SystemTime) -> Result<Expired, BufErr> {
+        let mut expired = BTreeMap::new();
+        let keys: Vec<SegmentKey> = self.buffer.iter()
+            .filter(|(_, seg)| is_expired(seg, signal, before))
+            .map(|(key, _)| *key)
+            .collect();
+        for key in keys.iter() {
+            if let Some(seg) = self.buffer.remove(key) {
+                self.account.release(seg.get_heap_size());
+                expired.insert(*key, ExpiredSegment::of(&seg));
+            }
+        }
+        if !keys.is_empty() {
+            self.compact_clock();
+        }
+        expire_persisted(&self.file_manager, signal, before, &mut expired)?;
+        Ok(Expired {
+            segments: expired.into_iter().map(|(_, seg)| seg).collect(),
+        })
+    }
 }
 
 
 impl<T, U> ClockBuffer<T, U>
     where T: Copy + Send + Serialize + DeserializeOwned + Debug,
-          U: FileManager<Vec<u8>, DBVector> + Sync + Send,
+          U: FileManager<Vec<u8>,Vec<u8>> + Sync + Send,
 {
     pub fn new(buf_size: usize, file_manager: U) -> ClockBuffer<T, U> {
         ClockBuffer {
@@ -312,6 +691,8 @@ impl<T, U> ClockBuffer<T, U>
             file_manager: file_manager,
             buf_size: buf_size,
             done: false,
+            stats: CacheStats::default(),
+            account: MemoryGovernor::register(&governor(), "clock"),
         }
     }
 
@@ -332,6 +713,29 @@ impl<T, U> ClockBuffer<T, U>
         self.tail = (self.tail + 1) % self.clock.len();
     }
 
+    /* Drops the slots of segments that are no longer buffered, so the
+     * clock is filled from the hand again
+     */
+    fn compact_clock(&mut self) {
+        let buffer = &self.buffer;
+        self.clock.retain(|(key, _)| buffer.contains_key(key));
+        self.clock_map = self.clock.iter().enumerate().map(|(idx, (key, _))| (*key, idx)).collect();
+        self.hand = self.clock.len() % self.buf_size;
+        self.tail = 0;
+    }
+
+    /* Drops a single slot, the slots after it move up by one and the
+     * tail stays on the slot that followed the dropped one
+     */
+    fn remove_slot(&mut self, idx: usize) {
+        self.clock.remove(idx);
+        for (i, (key, _)) in self.clock.iter().enumerate().skip(idx) {
+            self.clock_map.insert(*key, i);
+        }
+        self.hand = self.clock.len() % self.buf_size;
+        self.tail = if idx < self.clock.len() { idx } else { 0 };
+    }
+
     fn put_with_key(&mut self, key: SegmentKey, seg: Segment<T>) -> Result<(), BufErr> {
         let slot = if self.buffer.len() >= self.buf_size {
             let slot = self.evict_no_saving()?;
@@ -352,6 +756,7 @@ impl<T, U> ClockBuffer<T, U>
         match self.buffer.entry(key) {
             Entry::Occupied(_) => panic!("Non-unique key panic as clock map and buffer are desynced somehow"),
             Entry::Vacant(vacancy) => {
+                self.account.charge(seg.get_heap_size());
                 vacancy.insert(seg);
                 Ok(())
             }
@@ -362,10 +767,12 @@ impl<T, U> ClockBuffer<T, U>
     fn retrieve(&mut self, key: SegmentKey) -> Result<bool, BufErr> {
         if let Some(_) = self.buffer.get(&key) {
             println!("reading from the buffer");
+            self.stats.hits += 1;
             self.update(key);
             return Ok(true);
         }
         println!("reading from the file_manager");
+        self.stats.misses += 1;
         match key.convert_to_bytes() {
             Ok(key_bytes) => {
                 match self.file_manager.fm_get(key_bytes) {
@@ -377,7 +784,9 @@ impl<T, U> ClockBuffer<T, U>
                                 self.put_with_key(key, seg)?;
                                 Ok(true)
                             }
-                            Err(()) => Err(BufErr::ByteConvertFail),
+                            Err(FormatError::ChecksumMismatch{..}) |
+                            Err(FormatError::Truncated) => Err(BufErr::CorruptSegment(key)),
+                            Err(_) => Err(BufErr::ByteConvertFail),
                         }
                     }
                 }
@@ -397,6 +806,8 @@ impl<T, U> ClockBuffer<T, U>
                     None => panic!("Non-unique key panic as clock map and buffer are desynced somehow"),
                     _ => (),
                 }
+                self.stats.evictions += 1;
+                self.account.release(seg.get_heap_size());
 
                 /* Write the segment to disk */
                 let seg_key_bytes = match seg_key.convert_to_bytes() {
@@ -426,8 +837,11 @@ impl<T, U> ClockBuffer<T, U>
     fn evict_no_saving(&mut self) -> Result<usize, BufErr> {
         loop {
             if let (seg_key, false) = self.clock[self.hand] {
-                self.buffer.remove(&seg_key);
+                if let Some(seg) = self.buffer.remove(&seg_key) {
+                    self.account.release(seg.get_heap_size());
+                }
                 self.clock_map.remove(&seg_key);
+                self.stats.evictions += 1;
                 return Ok(self.hand);
             } else {
                 self.clock[self.hand].1 = false;
@@ -450,18 +864,39 @@ pub struct NoFmClockBuffer<T>
     clock_map: HashMap<SegmentKey, usize>,
     buf_size: usize,
     done: bool,
+    stats: CacheStats,
+    account: MemoryAccount,
 }
 
 
 impl<T> SegmentBuffer<T> for NoFmClockBuffer<T>
-    where T: Copy + Send + Debug,
+    where T: Copy + Send + Serialize + DeserializeOwned + Debug,
 {
-    fn get(&mut self, _key: SegmentKey) -> Result<Option<&Segment<T>>, BufErr> {
-        unimplemented!()
+    /* Without a file manager a segment that is not buffered does not exist */
+    fn get(&mut self, key: SegmentKey) -> Result<Option<&Segment<T>>, BufErr> {
+        if !self.buffer.contains_key(&key) {
+            self.stats.misses += 1;
+            return Ok(None);
+        }
+        self.stats.hits += 1;
+        self.update(key);
+        match self.buffer.get(&key) {
+            Some(seg) => Ok(Some(seg)),
+            None => Err(BufErr::GetFail),
+        }
     }
 
-    fn get_mut(&mut self, _key: SegmentKey) -> Result<Option<&Segment<T>>, BufErr> {
-        unimplemented!()
+    fn get_mut(&mut self, key: SegmentKey) -> Result<Option<&Segment<T>>, BufErr> {
+        if !self.buffer.contains_key(&key) {
+            self.stats.misses += 1;
+            return Ok(None);
+        }
+        self.stats.hits += 1;
+        self.update(key);
+        match self.buffer.get_mut(&key) {
+            Some(seg) => Ok(Some(seg)),
+            None => Err(BufErr::GetMutFail),
+        }
     }
 
     fn is_done(&self) -> bool {
@@ -475,22 +910,35 @@ impl<T> SegmentBuffer<T> for NoFmClockBuffer<T>
     }
 
 
-    fn drain(&mut self) -> Drain<Segment<T>> {
-        unimplemented!()
+    fn drain(&mut self) -> Vec<Segment<T>> {
+        let buffer = &mut self.buffer;
+        let mut segs: Vec<Segment<T>> = self.clock.drain(..)
+            .filter_map(|(seg_key, _)| buffer.remove(&seg_key))
+            .collect();
+        segs.extend(buffer.drain().map(|(_, seg)| seg));
+        self.clock_map.clear();
+        self.hand = 0;
+        self.tail = 0;
+        self.account.reset();
+        segs
     }
 
     fn copy(&self) -> Vec<Segment<T>> {
         self.buffer.values().map(|x| x.clone()).collect()
     }
 
-    /* Write to file system */
+    /* There is no file system to write to, the segments only live in memory */
     fn persist(&self) -> Result<(), BufErr> {
-        unimplemented!()
+        Ok(())
     }
 
     fn flush(&mut self) {
         self.buffer.clear();
         self.clock.clear();
+        self.clock_map.clear();
+        self.hand = 0;
+        self.tail = 0;
+        self.account.reset();
         self.done = true;
     }
 
@@ -499,35 +947,43 @@ impl<T> SegmentBuffer<T> for NoFmClockBuffer<T>
     }
 
     fn idle_threshold(&self, threshold: f32) -> bool {
-        unimplemented!()
+        return (self.buffer.len() as f32 / self.buf_size as f32) < threshold;
     }
 
     fn get_buffer_size(&self) -> usize {
-        unimplemented!()
+        self.buffer.values().map(|seg| seg.get_byte_size().unwrap_or(0)).sum()
     }
 
 
+    /* Walks the clock from the tail, the hand only marks where the next segment goes */
     fn remove_segment(&mut self) -> Result<Segment<T>, BufErr> {
         let mut counter = 0;
         loop {
-            if let (seg_key, false) = self.clock[self.hand] {
-                let seg = match self.buffer.remove(&seg_key) {
-                    Some(seg) => seg,
-                    None => return Err(BufErr::EvictFailure),
-                };
-                match self.clock_map.remove(&seg_key) {
-                    None => panic!("Non-unique key panic as clock map and buffer are desynced somehow"),
-                    _ => (),
+            if self.clock.is_empty() {
+                return Err(BufErr::BufEmpty);
+            }
+            if self.tail >= self.clock.len() {
+                self.tail = 0;
+            }
+            if let (seg_key, false) = self.clock[self.tail] {
+                /* The slot goes with the segment, a stale one would stop the next removal */
+                self.clock_map.remove(&seg_key);
+                let tail = self.tail;
+                self.remove_slot(tail);
+                match self.buffer.remove(&seg_key) {
+                    Some(seg) => {
+                        self.account.release(seg.get_heap_size());
+                        return Ok(seg);
+                    }
+                    None => continue,
                 }
-
-                return Ok(seg);
             } else {
-                self.clock[self.hand].1 = false;
+                self.clock[self.tail].1 = false;
             }
 
-            self.update_hand();
+            self.update_tail();
             counter += 1;
-            if counter >= self.clock.len() {
+            if counter > self.clock.len() {
                 return Err(BufErr::BufEmpty);
             }
         }
@@ -536,11 +992,64 @@ impl<T> SegmentBuffer<T> for NoFmClockBuffer<T>
     fn exceed_batch(&self, batchsize: usize) -> bool {
         return self.buffer.len() >= batchsize;
     }
+
+    fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    fn peek(&self, key: SegmentKey) -> Option<&Segment<T>> {
+        self.buffer.get(&key)
+    }
+
+    /* Without a file manager the evicted segment is dropped */
+    fn evict_one(&mut self) -> Result<bool, BufErr> {
+        match self.remove_segment() {
+            Ok(_) => {
+                self.stats.evictions += 1;
+                Ok(true)
+            }
+            Err(BufErr::BufEmpty) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn range_keys(&self, signal: SignalId, start: SystemTime, end: SystemTime) -> Result<Vec<SegmentKey>, BufErr> {
+        let keys: BTreeSet<SegmentKey> = self.buffer.keys()
+            .filter(|k| k.in_range(signal, start, end))
+            .cloned()
+            .collect();
+        Ok(keys.into_iter().collect())
+    }
+
+    fn get_cache_stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    fn expire(&mut self, signal: SignalId, before: SystemTime) -> Result<Expired, BufErr> {
+        let keys: Vec<SegmentKey> = self.buffer.iter()
+            .filter(|(_, seg)| is_expired(seg, signal, before))
+            .map(|(key, _)| *key)
+            .collect();
+        let mut expired = Vec::with_capacity(keys.len());
+        for key in keys.iter() {
+            if let Some(seg) = self.buffer.remove(key) {
+                self.account.release(seg.get_heap_size());
+                expired.push(ExpiredSegment::of(&seg));
+            }
+        }
+        if !keys.is_empty() {
+            self.compact_clock();
+        }
+        expired.sort_by_key(|seg| seg.key);
+        Ok(Expired {
+            segments: expired,
+        })
+    }
 }
 
 
 impl<T> NoFmClockBuffer<T>
-    where T: Copy + Send + Debug,
+    where T: Copy + Send + Serialize + DeserializeOwned + Debug,
 {
     pub fn new(buf_size: usize) -> NoFmClockBuffer<T> {
         NoFmClockBuffer {
@@ -551,6 +1060,8 @@ impl<T> NoFmClockBuffer<T>
             clock_map: HashMap::with_capacity(buf_size),
             buf_size: buf_size,
             done: false,
+            stats: CacheStats::default(),
+            account: MemoryGovernor::register(&governor(), "nofm clock"),
         }
     }
 
@@ -566,6 +1077,34 @@ impl<T> NoFmClockBuffer<T>
         self.hand = (self.hand + 1) % self.buf_size;
     }
 
+    #[inline]
+    fn update_tail(&mut self) {
+        self.tail = (self.tail + 1) % self.clock.len();
+    }
+
+    /* Drops the slots of segments that are no longer buffered, so the
+     * clock is filled from the hand again
+     */
+    fn compact_clock(&mut self) {
+        let buffer = &self.buffer;
+        self.clock.retain(|(key, _)| buffer.contains_key(key));
+        self.clock_map = self.clock.iter().enumerate().map(|(idx, (key, _))| (*key, idx)).collect();
+        self.hand = self.clock.len() % self.buf_size;
+        self.tail = 0;
+    }
+
+    /* Drops a single slot, the slots after it move up by one and the
+     * tail stays on the slot that followed the dropped one
+     */
+    fn remove_slot(&mut self, idx: usize) {
+        self.clock.remove(idx);
+        for (i, (key, _)) in self.clock.iter().enumerate().skip(idx) {
+            self.clock_map.insert(*key, i);
+        }
+        self.hand = self.clock.len() % self.buf_size;
+        self.tail = if idx < self.clock.len() { idx } else { 0 };
+    }
+
     fn put_with_key(&mut self, key: SegmentKey, seg: Segment<T>) -> Result<(), BufErr> {
         let slot = if self.buffer.len() >= self.buf_size {
             let slot = self.evict()?;
@@ -586,6 +1125,7 @@ impl<T> NoFmClockBuffer<T>
         match self.buffer.entry(key) {
             Entry::Occupied(_) => panic!("Non-unique key panic as clock map and buffer are desynced somehow"),
             Entry::Vacant(vacancy) => {
+                self.account.charge(seg.get_heap_size());
                 vacancy.insert(seg);
                 Ok(())
             }
@@ -595,14 +1135,17 @@ impl<T> NoFmClockBuffer<T>
     fn evict(&mut self) -> Result<usize, BufErr> {
         loop {
             if let (seg_key, false) = self.clock[self.hand] {
-                let _seg = match self.buffer.remove(&seg_key) {
+                let seg = match self.buffer.remove(&seg_key) {
                     Some(seg) => seg,
                     None => return Err(BufErr::EvictFailure),
                 };
+                self.account.release(seg.get_heap_size());
                 match self.clock_map.remove(&seg_key) {
                     None => panic!("Non-unique key panic as clock map and buffer are desynced somehow"),
                     _ => (),
                 }
+                self.stats.evictions += 1;
+                return Ok(self.hand);
             } else {
                 self.clock[self.hand].1 = false;
             }
@@ -613,9 +1156,9 @@ impl<T> NoFmClockBuffer<T>
 }
 
 
-pub fn Get_AggStats<T: Num + FromPrimitive + FFTnum+ Copy + Send + Into<f64> + PartialOrd + Add<T, Output=T>>(seg: &Segment<T>) -> AggStats<T> {
-    let vec = Get_Decomp(seg);
-    return Get_AggStatsFromVec(&vec);
+pub fn Get_AggStats<T: Num + FromPrimitive + FFTnum+ Copy + Send + Into<f64> + PartialOrd + Add<T, Output=T>>(seg: &Segment<T>) -> Option<AggStats<T>> {
+    let vec = Get_Decomp(seg)?;
+    return Some(Get_AggStatsFromVec(&vec));
 }
 
 pub fn Get_AggStatsFromVec<T: Num + FromPrimitive + Copy + Send + Into<f64> + PartialOrd + Add<T, Output=T>>(vec: &Vec<T>) -> AggStats<T> {
@@ -636,63 +1179,11 @@ pub fn Get_AggStatsFromVec<T: Num + FromPrimitive + Copy + Send + Into<f64> + Pa
 }
 
 
-pub fn Get_Decomp<T: Num + FromPrimitive + Copy + Send + FFTnum + Into<f64> >(seg: &Segment<T>) -> Vec<T> {
-    let mut vec = Vec::new();
-    let size = seg.get_size();
-    match seg.get_method().as_ref().unwrap() {
-        Methods::Sprintz(scale) => {
-            let pre = SprintzDoubleCompress::new(10, 20, *scale);
-            vec = pre.decode_general(seg.get_comp());
-        }
-        Methods::Buff(scale) => {
-            let pre = SplitBDDoubleCompress::new(10, 20, *scale);
-            vec = pre.decode_general(seg.get_comp());
-        }
-        Methods::Gorilla => {
-            let pre = GorillaCompress::new(10, 20);
-            vec = pre.decode_general(seg.get_comp());
-        }
-        Methods::Snappy => {
-            let pre = SnappyCompress::new(10, 20);
-            vec = pre.decode(seg.get_comp());
-        }
-        Methods::Gzip => {
-            let pre = GZipCompress::new(10, 20);
-            vec = pre.decode(seg.get_comp());
-        }
-        Methods::Zlib => {
-            let pre = ZlibCompress::new(10, 20, 5);
-            vec = pre.decode(seg.get_comp());
-        }
-        Methods::Paa(wsize) => {
-            // println!("compress time: {}, wsize:{}, paa segment size: {}", seg.get_comp_times(), wsize, size);
-            let cur = PAACompress::new(*wsize, 20);
-            vec = cur.decodeVec(seg.get_data());
-            vec.truncate(size);
-        }
-        Methods::Rrd_sample => {
-            // println!("compress time: {}, rrd segment size: {}", seg.get_comp_times(), size);
-            let cur = RRDsample::new(20);
-            vec = cur.decode(seg);
-        }
-        Methods::Pla(ratio) => {
-            // println!("compress time: {}, PLA segment size: {}", seg.get_comp_times(), size);
-            let cur = PLACompress::new(20,*ratio);
-            vec = cur.decode(seg);
-        }
-        Methods::Bufflossy(scale,bits) => {
-            // println!("compress time: {}, buff lossy bits size: {}", seg.get_comp_times(), bits);
-            let cur = BUFFlossy::new(20,*scale,*bits);
-            vec = cur.decode_general(seg.get_comp());
-        }
-        Methods::Fourier(ratio) => {
-            // println!("compress times: {}, data len:{}, ratio:{}, fft segment size: {}", seg.get_comp_times(), seg.get_data().len(), ratio, size);
-            let cur = FourierCompress::new(2, 20, *ratio);
-            vec = cur.decodeVec(seg.get_data(),seg.get_size());
-        }
-        _ => todo!()
-    }
-    return vec;
+/* Kept for the existing callers, the registry owns the method to codec mapping.
+ * None if the method of the segment has no codec.
+ */
+pub fn Get_Decomp<T: Num + FromPrimitive + Copy + Send + FFTnum + Into<f64> >(seg: &Segment<T>) -> Option<Vec<T>> {
+    decode_segment(seg)
 }
 
 
@@ -705,7 +1196,7 @@ pub fn Err_Eval<T:PartialEq>(va: &Vec<T>,vb: &Vec<T>)-> usize{
 
 impl<'a, T, U> fmt::Debug for LRUBuffer<'a, T, U>
     where T: Copy + Send + RealNumber,
-          U: FileManager<Vec<u8>, DBVector> + Sync + Send{
+          U: FileManager<Vec<u8>,Vec<u8>> + Sync + Send{
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "LRU buffer with budget [{}]", self.budget)
     }
@@ -719,7 +1210,7 @@ Use double linked list to achieve O(1) get and put time complexity*/
 
 pub struct LRUBuffer<'a, T, U>
     where T: Copy + Send + RealNumber,
-          U: FileManager<Vec<u8>, DBVector> + Sync + Send,
+          U: FileManager<Vec<u8>,Vec<u8>> + Sync + Send,
 
 {
     budget: usize,
@@ -727,20 +1218,12 @@ pub struct LRUBuffer<'a, T, U>
     head: Option<SegmentKey>,
     tail: Option<SegmentKey>,
     buffer: BTreeMap<SegmentKey, Node<T>>,
-    agg_stats: BTreeMap<SegmentKey, AggStats<T>>,
-    est_agg_stats: BTreeMap<SegmentKey, AggStats<T>>,
-    comp_runtime: BTreeMap<SegmentKey, f64>,
     file_manager: U,
     buf_size: usize,
     done: bool,
-    rlabel: BTreeMap<SegmentKey, Vec<T>>,
-    plabel: BTreeMap<SegmentKey, Vec<T>>,
-    predictor: Box<PredictorVec<T>+'a>,
-    task: usize,
-    // predictor: Option<KMeans<T>,
-    mab_250: EGreedy,
-    mab_125: EGreedy,
-    mab_000: EGreedy
+    stats: CacheStats,
+    account: MemoryAccount,
+    evaluator: Option<Box<dyn WorkloadEvaluator<T>+'a>>,
 }
 
 #[derive(Clone, Debug)]
@@ -792,575 +1275,27 @@ impl<T> AggStats<T> where T: Copy + Send + Add<Output=T> + PartialOrd, {
 
 impl<'a, T, U> LRUBuffer<'a, T, U>
     where T: Copy + Send + Serialize + DeserializeOwned + Debug + FFTnum + Num + FromPrimitive + PartialOrd + Into<f64> + num::Signed + Display+ RealNumber,
-          U: FileManager<Vec<u8>, DBVector> + Sync + Send
+          U: FileManager<Vec<u8>,Vec<u8>> + Sync + Send
 
 {
-    pub fn new(budget: usize, file_manager: U, task:&str) -> LRUBuffer<'a, T, U> {
-        let mut model_dir = format!("../lossyML/model/cbf_{}.model", task);
-        let kmeans_dir = format!("../lossyML/model/cbf_kmeans.model");
-        println!("created LRU comp buffer with budget {} bytes with model: {}", budget, model_dir);
-        let mut taskid : usize = 0; // 0 means ML task, 1 for max, 2 for sum, 3 for max_ML, 4 for sum_ML.
-
-        let  task_vec = task.split('_').collect::<Vec<&str>>();
-
-        if task_vec.len()==1{
-            let model : Box<PredictorVec<T>> = match task_vec[0] {
-                "kmeans" => {
-                    /* Construct the kmeans model	 */
-                    let ml_content = fs::read_to_string(model_dir).expect("Unable to read kmeans file");
-                    let deserialized_model: KMeans<T> = serde_json::from_str(&ml_content).unwrap();
-                    Box::new(deserialized_model )
-                },
-
-                "dtree" => {
-                    /* Construct the dtree model	 */
-                    let ml_content = fs::read_to_string(model_dir).expect("Unable to read dtree file");
-                    let deserialized_model: DecisionTreeClassifier<T> = serde_json::from_str(&ml_content).unwrap();
-                    Box::new(deserialized_model )
-                },
-                "knn" => {
-                    /* Construct the knn model	 */
-                    let ml_content = fs::read_to_string(model_dir).expect("Unable to read knn file");
-                    let deserialized_model: KNNClassifier<T, Euclidian> = serde_json::from_str(&ml_content).unwrap();
-                    Box::new(deserialized_model )
-                },
-                "rforest" => {
-                    let ml_content = fs::read_to_string(model_dir).expect("Unable to read knn file");
-                    let deserialized_model: RandomForestClassifier<T> = serde_json::from_str(&ml_content).unwrap();
-                    Box::new(deserialized_model )
-                },
-                // we use kmeans model as dummy ml task for aggregation workload
-                "max" => {
-                    /* Construct the kmeans model	 */
-                    let ml_content = fs::read_to_string(kmeans_dir).expect("Unable to read kmeans file for max workload");
-                    let deserialized_model: KMeans<T> = serde_json::from_str(&ml_content).unwrap();
-                    taskid = 1;
-                    Box::new(deserialized_model )
-                },
-                "sum" => {
-                    /* Construct the kmeans model	 */
-                    let ml_content = fs::read_to_string(kmeans_dir).expect("Unable to read kmeans file for sum workload");
-                    let deserialized_model: KMeans<T> = serde_json::from_str(&ml_content).unwrap();
-                    taskid = 2;
-                    Box::new(deserialized_model )
-                },
-                // "nb" => {
-                //     let ml_content = fs::read_to_string(model_dir).expect("Unable to read knn file");
-                //     let deserialized_model: GaussianNB<T, DenseMatrix<T> > = serde_json::from_str(&ml_content).unwrap();
-                //     Box::new(deserialized_model )
-                // },
-                _ => panic!(format!("{} is not supported yet|", task)),
-            };
-            println!("task: {}, task id: {}", task, taskid);
-            LRUBuffer {
-                budget: budget,
-                cur_size: 0,
-                head: None,
-                tail: None,
-                buffer: BTreeMap::new(),
-                agg_stats: BTreeMap::new(),
-                est_agg_stats: BTreeMap::new(),
-                comp_runtime: BTreeMap::new(),
-                file_manager: file_manager,
-                buf_size: 0,
-                done: false,
-                rlabel: BTreeMap::new(),
-                plabel: BTreeMap::new(),
-                predictor: model,
-                task: taskid,
-                mab_250: EGreedy::new(4, 0.01, 2.0, UpdateType::Average),
-                mab_125: EGreedy::new(4, 0.01, 2.0, UpdateType::Average),
-                mab_000: EGreedy::new(4, 0.01, 2.0, UpdateType::Average)
-            }
-        }
-        else{
-            model_dir = format!("../lossyML/model/cbf_{}.model", task_vec[1]);
-            let model : Box<PredictorVec<T>> = match task_vec[1] {
-                "kmeans" => {
-                    /* Construct the kmeans model	 */
-                    let ml_content = fs::read_to_string(model_dir).expect("Unable to read kmeans file");
-                    let deserialized_model: KMeans<T> = serde_json::from_str(&ml_content).unwrap();
-                    Box::new(deserialized_model )
-                },
-
-                "dtree" => {
-                    /* Construct the dtree model	 */
-                    let ml_content = fs::read_to_string(model_dir).expect("Unable to read dtree file");
-                    let deserialized_model: DecisionTreeClassifier<T> = serde_json::from_str(&ml_content).unwrap();
-                    Box::new(deserialized_model )
-                },
-                "knn" => {
-                    /* Construct the knn model	 */
-                    let ml_content = fs::read_to_string(model_dir).expect("Unable to read knn file");
-                    let deserialized_model: KNNClassifier<T, Euclidian> = serde_json::from_str(&ml_content).unwrap();
-                    Box::new(deserialized_model )
-                },
-                "rforest" => {
-                    let ml_content = fs::read_to_string(model_dir).expect("Unable to read knn file");
-                    let deserialized_model: RandomForestClassifier<T> = serde_json::from_str(&ml_content).unwrap();
-                    Box::new(deserialized_model )
-                },
-                _ => panic!(format!("{} is not supported yet|", task)),
-            };
-
-            if task_vec[0]=="max"{
-                taskid = 3;
-            }
-            else if task_vec[0]=="sum"{
-                taskid = 4;
-            }
-            else if task_vec[0]=="speed"{
-                taskid = 5;
-            }
-            println!("task: {}, {}-{} task id: {}", task, task_vec[0],task_vec[1], taskid);
-            LRUBuffer {
-                budget: budget,
-                cur_size: 0,
-                head: None,
-                tail: None,
-                buffer: BTreeMap::new(),
-                agg_stats: BTreeMap::new(),
-                comp_runtime: BTreeMap::new(),
-                est_agg_stats: BTreeMap::new(),
-                file_manager: file_manager,
-                buf_size: 0,
-                done: false,
-                rlabel: BTreeMap::new(),
-                plabel: BTreeMap::new(),
-                predictor: model,
-                task: taskid,
-                mab_250: EGreedy::new(4, 0.005, 40.0, UpdateType::Average),
-                mab_125: EGreedy::new(4, 0.005, 40.0, UpdateType::Average),
-                mab_000: EGreedy::new(4, 0.001, 40.0, UpdateType::Average)
-            }
-        }
-
-    }
-
-    fn evaluate_query(&self) {
-        let n =10;
-        let vector_per_seg = 10;
-        let labels_of_N = vector_per_seg *n;
-        let mut firstNerr = 0;
-        let mut lastNerr = 0;
-        let mut totalErr = 0;
-        let mut maxErr = 0.0;
-        let mut sumErr = 0.0;
-        let nSeg = self.buffer.len();
-        let mut comp_runtime = 0.0;
-        let mut estlatestn: AggStats<T> = AggStats {
-            max: (T::one()),
-            min: (T::one()),
-            sum: (T::one()),
-            count: 0,
-        };
-        let mut estuntilnow = AggStats {
-            max: (T::one()),
-            min: (T::one()),
-            sum: (T::one()),
-            count: 0,
-        };
-        let mut latestn = AggStats {
-            max: (T::one()),
-            min: (T::one()),
-            sum: (T::one()),
-            count: 0,
-        };
-        let mut untilnow = AggStats {
-            max: (T::one()),
-            min: (T::one()),
-            sum: (T::one()),
-            count: 0,
-        };
-        let mut earliestn = AggStats {
-            max: (T::one()),
-            min: (T::one()),
-            sum: (T::one()),
-            count: 0,
-        };
-        let mut estearliestn = AggStats {
-            max: (T::one()),
-            min: (T::one()),
-            sum: (T::one()),
-            count: 0,
-        };
-
-        let mut cnt: usize = 0;
-        for (k, v) in self.agg_stats.iter().rev() {
-            if cnt == 0 {
-                latestn = AggStats::new(v.max, v.min, v.sum, v.count);
-                untilnow = AggStats::new(v.max, v.min, v.sum, v.count);
-                // println!("{:?}",k);
-            } else if cnt < n {
-                latestn = latestn.merge(v);
-                untilnow = untilnow.merge(v);
-            } else {
-                untilnow = untilnow.merge(v);
-            }
-            cnt += 1;
-        }
-
-        cnt = 0;
-        let mut speedcnt =0;
-        for (k, v) in self.est_agg_stats.iter().rev() {
-            let agg = v;
-
-            if cnt<(nSeg-n){
-                let real_agg = self.agg_stats.get(k).unwrap();
-                let runtime = self.comp_runtime.get(k).unwrap();
-                maxErr += num::abs(agg.max.into()-real_agg.max.into())/num::abs(real_agg.max.into());
-                sumErr += num::abs(agg.sum.into()-real_agg.sum.into())/num::abs(real_agg.sum.into());
-                if *runtime!=0.0{
-                    comp_runtime += runtime;
-                    speedcnt += 1;
-                }
-
-            }
-            let plabels = self.plabel.get(k).unwrap();
-            let cur_err = Err_Eval(plabels, self.rlabel.get(k).unwrap());
-            if (cnt<n){
-                lastNerr += cur_err;
-            }
-            totalErr += cur_err;
-
-            if cnt == 0 {
-                // println!("{:?}",k);
-                estlatestn = AggStats::new(agg.max, agg.min, agg.sum, agg.count);
-                estuntilnow = AggStats::new(agg.max, agg.min, agg.sum, agg.count);
-            } else if cnt < n {
-                estlatestn = estlatestn.merge(&agg);
-                estuntilnow = estuntilnow.merge(&agg);
-            }
-            else {
-                estuntilnow = estuntilnow.merge(&agg);
-            }
-
-            cnt += 1;
-        }
-        maxErr = maxErr/(nSeg-n) as f64;
-        sumErr = sumErr/(nSeg-n) as f64;
-
-        if speedcnt==0{
-            comp_runtime = 0.0;
-        }
-        else {
-            comp_runtime = comp_runtime/speedcnt as f64;
-        }
-
-        // calculate the earliest N
-        cnt = 0;
-        for (k, v) in self.agg_stats.iter() {
-            if cnt == 0 {
-                // println!("{:?}",k);
-                earliestn = AggStats::new(v.max, v.min, v.sum, v.count);
-
-            } else if cnt < n {
-                earliestn = earliestn.merge(v);
-            }else {
-                break;
-            }
-            cnt += 1;
-        }
-
-        cnt = 0;
-        for (k, v) in self.est_agg_stats.iter() {
-            let agg = v;
-            let plabels = self.plabel.get(k).unwrap();
-            let cur_err = Err_Eval(plabels, self.rlabel.get(k).unwrap());
-            if (cnt<n){
-                firstNerr += cur_err;
-            }
-
-            if cnt == 0 {
-                // println!("segment size: {}, compress timm:{}, first segment: {:?}", v.value.get_data().len(),v.value.get_comp_times(), &vec[..10]);
-                estearliestn = AggStats::new(agg.max, agg.min, agg.sum, agg.count);
-            } else if cnt < n {
-                estearliestn = estearliestn.merge(&agg);
-            }
-            else {
-                break;
-            }
-            cnt += 1;
-        }
-        let total_label = vector_per_seg*nSeg;
-
-
-
-        // println!("true earliest max: {}, est max: {}",earliestn.max , estearliestn.max);
-        // println!("true max: {}, est max: {}",untilnow.max , estuntilnow.max);
-        // println!("true nmax: {}, est nmax: {}",latestn.max , estlatestn.max);
-        // println!("Aggregation stats (earlies-latest-untilnow): {},{:.4},{:.4},{:.4},{:.4},{:.4},{:.4},{:.4},{:.4},{:.4},{:.4},{:.4},{:.4}", SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_micros() as f64/1000000.0,
-        println!("Aggregation stats (earlies-latest-untilnow): {},{},{},{},{},{},{},{},{},{},{},{},{},{:.4},{:.4},{:.4}", SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_micros() as f64/1000000.0,
-                 num::abs((earliestn.max - estearliestn.max) / earliestn.max), num::abs((earliestn.min - estearliestn.min) / earliestn.min),
-                 num::abs((earliestn.sum - estearliestn.sum) / earliestn.sum),
-                 num::abs((latestn.max - estlatestn.max) / latestn.max), num::abs((latestn.min - estlatestn.min) / latestn.min),
-                 num::abs((latestn.sum - estlatestn.sum) / latestn.sum), num::abs((untilnow.max - estuntilnow.max) / untilnow.max),
-                 num::abs((untilnow.min - estuntilnow.min) / untilnow.min), num::abs((untilnow.sum - estuntilnow.sum) / untilnow.sum),maxErr,sumErr,comp_runtime,
-                 firstNerr as f64 /labels_of_N as f64, lastNerr as f64 /labels_of_N as f64, totalErr as f64 / total_label as f64
-        )
-    }
-
-    fn update_ml_mab(&mut self, m: &Methods, acc: f64){
-        let mut ratio = 0.0;
-        let c = 1.0;
-        match m {
-            Methods::Bufflossy(_,bits) => {
-                // only for bits >= 8, fall back to rrd otherwise
-                ratio = (*bits) as f64/64.0;
-                if ratio>=0.25 {
-                    self.mab_250.update(0, acc/(c+ratio));
-                }
-                else if ratio >=0.125{
-                    self.mab_125.update(0, acc/(c+ratio));
-                }
-            }
-            Methods::Rrd_sample => {
-                // only for 1 sample, so no value for mab125 and mab 250.
-                ratio = 1.0/10000.0;
-                self.mab_000.update(0, acc/(c+ratio));
-            }
-            Methods::Paa(wsize) => {
-                ratio = 1.0/(*wsize) as f64;
-                if ratio>=0.25 {
-                    self.mab_250.update(1, acc/(c+ratio));
-                }
-                else if ratio >=0.125{
-                    self.mab_125.update(1, acc/(c+ratio));
-                }
-                else{
-                    self.mab_000.update(1, acc/(c+ratio));
-                }
-            }
-            Methods::Fourier(ratio) => {
-                if *ratio>=0.25 {
-                    self.mab_250.update(2, acc/(c+ratio));
-                }
-                else if *ratio >=0.125{
-                    self.mab_125.update(2, acc/(c+ratio));
-                }
-                else{
-                    self.mab_000.update(2, acc/(c+ratio));
-                }
-
-            }
-            Methods::Pla(ratio) => {
-                if *ratio>=0.25 {
-                    self.mab_250.update(3, acc/(c+ratio));
-                }
-                else if *ratio >=0.125{
-                    self.mab_125.update(3, acc/(c+ratio));
-                }
-                else{
-                    self.mab_000.update(3, acc/(c+ratio));
-                }
-            }
-            _ => {panic!("lossy compression is not supported by LRU ML query");}
-        }
-        println!("agg_ML reward: {}", acc/(c+ratio));
-    }
-
-    fn update_agg_ml_mab(&mut self, m: &Methods, key: &SegmentKey, aggacc: f64, mlacc: f64){
-        let r = 0.3;
-        let mut agg = 0.0;
-        if aggacc==0.0{
-            agg = 20.0
-        }
-        else{
-            agg = -aggacc.log10();
-        }
-
-        let mut ratio = 0.0;
-        let c = 1.0;
-
-        self.comp_runtime.insert(key.clone(), 10.0*mlacc + r*agg);
-
-        match m {
-            Methods::Bufflossy(_,bits) => {
-                // only for bits >= 8, fall back to rrd otherwise
-                ratio = (*bits) as f64/64.0;
-                if ratio>=0.25 {
-                    self.mab_250.update(0, 10.0*mlacc + r*agg);
-                }
-                else if ratio >=0.125{
-                    self.mab_125.update(0, 10.0*mlacc + r*agg);
-                }
-            }
-            Methods::Rrd_sample => {
-                // only for 1 sample, so no value for mab125 and mab 250.
-                ratio = 1.0/10000.0;
-                self.mab_000.update(0, 10.0*mlacc + r*agg);
-            }
-            Methods::Paa(wsize) => {
-                ratio = 1.0/(*wsize) as f64;
-                if ratio>=0.25 {
-                    self.mab_250.update(1, 10.0*mlacc + r*agg);
-                }
-                else if ratio >=0.125{
-                    self.mab_125.update(1, 10.0*mlacc + r*agg);
-                }
-                else{
-                    self.mab_000.update(1, 10.0*mlacc + r*agg);
-                }
-            }
-            Methods::Fourier(ratio) => {
-                if *ratio>=0.25 {
-                    self.mab_250.update(2, 10.0*mlacc + r*agg);
-                }
-                else if *ratio >=0.125{
-                    self.mab_125.update(2, 10.0*mlacc + r*agg);
-                }
-                else{
-                    self.mab_000.update(2, 10.0*mlacc + r*agg);
-                }
-
-            }
-            Methods::Pla(ratio) => {
-                if *ratio>=0.25 {
-                    self.mab_250.update(3, 10.0*mlacc + r*agg);
-                }
-                else if *ratio >=0.125{
-                    self.mab_125.update(3, 10.0*mlacc + r*agg);
-                }
-                else{
-                    self.mab_000.update(3, 10.0*mlacc + r*agg);
-                }
-            }
-            _ => {panic!("lossy compression is not supported by LRU ML query");}
-        }
-        println!("agg_ML reward: {}", 10.0*mlacc + r*agg);
-
-    }
-
-    fn update_speed_ml_mab(&mut self, m: &Methods, speed: f64, mlacc: f64){
-        let r = 1.1;
-        let mut spd = speed/200000.0;
-
-
-        let mut ratio = 0.0;
-        match m {
-            Methods::Bufflossy(_,bits) => {
-                // only for bits >= 8, fall back to rrd otherwise
-                ratio = (*bits) as f64/64.0;
-                if ratio>=0.25 {
-                    self.mab_250.update(0, 1.0*mlacc + r*spd);
-                }
-                else if ratio >=0.125{
-                    self.mab_125.update(0, 1.0*mlacc + r*spd);
-                }
-            }
-            Methods::Rrd_sample => {
-                // only for 1 sample, so no value for mab125 and mab 250.
-                ratio = 1.0/10000.0;
-                self.mab_000.update(0, 1.0*mlacc + r*spd);
-            }
-            Methods::Paa(wsize) => {
-                ratio = 1.0/(*wsize) as f64;
-                if ratio>=0.25 {
-                    self.mab_250.update(1, 1.0*mlacc + r*spd);
-                }
-                else if ratio >=0.125{
-                    self.mab_125.update(1, 1.0*mlacc + r*spd);
-                }
-                else{
-                    self.mab_000.update(1, 1.0*mlacc + r*spd);
-                }
-            }
-            Methods::Fourier(ratio) => {
-                if *ratio>=0.25 {
-                    self.mab_250.update(2, 1.0*mlacc + r*spd);
-                }
-                else if *ratio >=0.125{
-                    self.mab_125.update(2, 1.0*mlacc + r*spd);
-                }
-                else{
-                    self.mab_000.update(2, 1.0*mlacc + r*spd);
-                }
-
-            }
-            Methods::Pla(ratio) => {
-                if *ratio>=0.25 {
-                    self.mab_250.update(3, 1.0*mlacc + r*spd);
-                }
-                else if *ratio >=0.125{
-                    self.mab_125.update(3, 1.0*mlacc + r*spd);
-                }
-                else{
-                    self.mab_000.update(3, 1.0*mlacc + r*spd);
-                }
-            }
-            _ => {panic!("lossy compression is not supported by LRU ML query");}
-        }
-        println!("speedd_ML reward: {}", 1.0*mlacc + r*spd);
-
-    }
-
-    fn update_agg_mab(&mut self, m: &Methods, val: f64){
-        let mut reward = 0.0;
-        if val==0.0{
-            reward = 20.0
-        }
-        else{
-            reward = -val.log10();
-        }
-        println!("agg reward: {}", reward);
-
-        match m {
-            Methods::Bufflossy(_,bits) => {
-                // only for bits >= 8, fall back to rrd otherwise
-                let ratio = (*bits) as f64/64.0;
-                if ratio>=0.25 {
-                    self.mab_250.update(0, reward);
-                }
-                else if ratio >=0.125{
-                    self.mab_125.update(0, reward);
-                }
-            }
-            Methods::Rrd_sample => {
-                // only for 1 sample, so no value for mab125 and mab 250.
-                self.mab_000.update(0, reward);
-            }
-            Methods::Paa(wsize) => {
-                let ratio = 1.0/(*wsize) as f64;
-                if ratio>=0.25 {
-                    self.mab_250.update(1, reward);
-                }
-                else if ratio >=0.125{
-                    self.mab_125.update(1, reward);
-                }
-                else{
-                    self.mab_000.update(1, reward);
-                    // println!("update 000");
-                }
-            }
-            Methods::Fourier(ratio) => {
-                if *ratio>=0.25 {
-                    self.mab_250.update(2, reward);
-                }
-                else if *ratio >=0.125{
-                    self.mab_125.update(2, reward);
-                }
-                else{
-                    self.mab_000.update(2, reward);
-                }
-            }
-            Methods::Pla(ratio) => {
-                if *ratio>=0.25 {
-                    self.mab_250.update(3, reward);
-                }
-                else if *ratio >=0.125{
-                    self.mab_125.update(3, reward);
-                }
-                else{
-                    self.mab_000.update(3, reward);
-                }
-            }
-            _ => {panic!("lossy compression is not supported by LRU agg query");}
+    /* The evaluator drives the recoding recommendations, without one the
+     * buffer recodes with the first arm of every bandit
+     */
+    pub fn new(budget: usize, file_manager: U, evaluator: Option<Box<dyn WorkloadEvaluator<T>+'a>>) -> LRUBuffer<'a, T, U> {
+        println!("created LRU comp buffer with budget {} bytes, workload aware: {}", budget, evaluator.is_some());
+        LRUBuffer {
+            budget: budget,
+            cur_size: 0,
+            head: None,
+            tail: None,
+            buffer: BTreeMap::new(),
+            file_manager: file_manager,
+            buf_size: 0,
+            done: false,
+            stats: CacheStats::default(),
+            account: MemoryGovernor::register(&governor(), "lru"),
+            evaluator: evaluator,
         }
-
-    }
-
-    fn get_recommend(&self) -> (usize,usize,usize){
-        return (self.mab_250.choose(),self.mab_125.choose(),self.mab_000.choose());
     }
 
     fn push_back_node(&mut self, key: SegmentKey, value: Segment<T>) {
@@ -1377,6 +1312,7 @@ impl<'a, T, U> LRUBuffer<'a, T, U>
             Some(tail) => self.buffer.get_mut(&tail).unwrap().next = Some(key),
         }
         self.tail = Some(key);
+        self.account.charge(node.value.get_heap_size());
         self.buffer.insert(key, node);
         self.cur_size += size;
         self.buf_size += 1;
@@ -1395,7 +1331,9 @@ impl<'a, T, U> LRUBuffer<'a, T, U>
             }
             self.cur_size -= size;
             self.buf_size -= 1;
-            self.buffer.remove(&key);
+            if let Some(node) = self.buffer.remove(&key) {
+                self.account.release(node.value.get_heap_size());
+            }
             key
         });
     }
@@ -1418,7 +1356,9 @@ impl<'a, T, U> LRUBuffer<'a, T, U>
         };
         self.cur_size -= self.buffer.get(&key).unwrap().value.get_byte_size().unwrap();
         self.buf_size -= 1;
-        self.buffer.remove(&key);
+        if let Some(node) = self.buffer.remove(&key) {
+            self.account.release(node.value.get_heap_size());
+        }
     }
     pub fn get(&mut self, key: SegmentKey) -> Option<Segment<T>> {
         match self.buffer.entry(key) {
@@ -1437,7 +1377,10 @@ impl<'a, T, U> LRUBuffer<'a, T, U>
             Some(v) => {
                 self.cur_size -= v.get_byte_size().unwrap();
                 let size = value.get_byte_size().unwrap();
-                self.buffer.get_mut(&key).unwrap().value = value;
+                let node = self.buffer.get_mut(&key).unwrap();
+                self.account.release(node.value.get_heap_size());
+                self.account.charge(value.get_heap_size());
+                node.value = value;
                 self.cur_size += size;
                 return;
             }
@@ -1448,74 +1391,18 @@ impl<'a, T, U> LRUBuffer<'a, T, U>
 
 
     fn put_with_key(&mut self, key: SegmentKey, seg: Segment<T>) -> Result<(), BufErr> {
-        // update aggstats and ML labels for query accuracy profiling
-        let entry_size = seg.get_size();
-        let size = seg.get_byte_size().unwrap();
-        let comp_runtime = seg.get_comp_runtime();
-        self.comp_runtime.insert(key, comp_runtime);
-        // println!("recode method: {:?}",seg.get_method().as_ref().unwrap());
-        if IsLossless(seg.get_method().as_ref().unwrap())==true {
-            // println!("buffer size: {}, agg stats size: {}",self.buffer.len(), self.agg_stats.len() );
-            let vec = Get_Decomp(&seg);
-            if !self.rlabel.contains_key(&key){
-                // println!("vec size {}", size);
-                // let label = self.predictor.as_ref().unwrap().predictVec(&vec,entry_size/1000);
-                let label = self.predictor.predictVec(&vec,entry_size/1000);
-                self.rlabel.insert(key, label.clone());
-                self.plabel.insert(key, label);
-            }
-            let stats =  Get_AggStatsFromVec(&vec);
-            self.agg_stats.insert(key,stats.clone());
-            self.est_agg_stats.insert(key, stats);
-        }
-        else {
-            let vec = Get_Decomp(&seg);
-            // println!("vec size {}", size);
-            // let label = self.predictor.as_ref().unwrap().predictVec(&vec,entry_size/1000);
-            let label = self.predictor.predictVec(&vec,entry_size/1000);
-            // println!("plabel {:?}", &label);
-            // println!("rlabel {:?}", self.rlabel.get(&key).unwrap());
-            let acc =  1.0 - Err_Eval(&label, self.rlabel.get(&key).unwrap()) as f64/ label.len() as f64;
-            self.plabel.insert(key, label);
-            let estaggstats = Get_AggStatsFromVec(&vec);
-            let estmax = estaggstats.max.into();
-            let estsum = estaggstats.sum.into();
-            self.est_agg_stats.insert(key, estaggstats);
-            if self.task==0{
-                self.update_ml_mab(seg.get_method().as_ref().unwrap(),acc);
-            }
-            else if self.task==1 {
-                let agg = self.agg_stats.get(&key).unwrap();
-                let max= agg.max.into();
-                self.update_agg_mab(seg.get_method().as_ref().unwrap(),num::abs((max - estmax) / max));
-            }
-            else if self.task==2 {
-                let agg = self.agg_stats.get(&key).unwrap();
-                let sum = agg.sum.into();
-                self.update_agg_mab(seg.get_method().as_ref().unwrap(),num::abs((sum - estsum) / sum));
-            }
-            else if self.task==3 {
-                let agg = self.agg_stats.get(&key).unwrap();
-                let max= agg.max.into();
-                self.update_agg_ml_mab(seg.get_method().as_ref().unwrap(), &key,num::abs((max - estmax) / max),acc);
-            }
-            else if self.task==4 {
-                let agg = self.agg_stats.get(&key).unwrap();
-                let sum = agg.sum.into();
-                self.update_agg_ml_mab(seg.get_method().as_ref().unwrap(), &key,num::abs((sum - estsum) / sum),acc);
-            }
-            else if self.task==5 {
-                let &comp_runtime = &seg.get_comp_runtime();
-                self.update_speed_ml_mab(seg.get_method().as_ref().unwrap(),comp_runtime,acc);
-                self.comp_runtime.insert(key, 1.1*comp_runtime/200000.0 + acc);
-            }
+        if let Some(evaluator) = self.evaluator.as_mut() {
+            evaluator.observe(key, &seg);
         }
 
         match self.get(key) {
             Some(v) => {
                 self.cur_size -= v.get_byte_size().unwrap();
                 let nsize = &seg.get_byte_size().unwrap();
-                self.buffer.get_mut(&key).unwrap().value = seg;
+                let node = self.buffer.get_mut(&key).unwrap();
+                self.account.release(node.value.get_heap_size());
+                self.account.charge(seg.get_heap_size());
+                node.value = seg;
                 self.cur_size += nsize;
                 return Ok(());
             }
@@ -1529,9 +1416,11 @@ impl<'a, T, U> LRUBuffer<'a, T, U>
     fn retrieve(&mut self, key: SegmentKey) -> Result<bool, BufErr> {
         if let Some(_) = self.get(key) {
             println!("reading from the buffer");
+            self.stats.hits += 1;
             return Ok(true);
         }
         println!("reading from the file_manager");
+        self.stats.misses += 1;
         match key.convert_to_bytes() {
             Ok(key_bytes) => {
                 match self.file_manager.fm_get(key_bytes) {
@@ -1543,7 +1432,9 @@ impl<'a, T, U> LRUBuffer<'a, T, U>
                                 self.put_with_key(key, seg)?;
                                 Ok(true)
                             }
-                            Err(()) => Err(BufErr::ByteConvertFail),
+                            Err(FormatError::ChecksumMismatch{..}) |
+                            Err(FormatError::Truncated) => Err(BufErr::CorruptSegment(key)),
+                            Err(_) => Err(BufErr::ByteConvertFail),
                         }
                     }
                 }
@@ -1557,7 +1448,7 @@ impl<'a, T, U> LRUBuffer<'a, T, U>
 
 impl<'a, T, U> SegmentBuffer<T> for LRUBuffer<'a, T, U>
     where T: Copy + Send + Serialize + DeserializeOwned + Debug + Num +FFTnum+ FromPrimitive + PartialOrd + Into<f64> + Signed + Display+ RealNumber,
-          U: FileManager<Vec<u8>, DBVector> + Sync + Send
+          U: FileManager<Vec<u8>,Vec<u8>> + Sync + Send
 {
     fn get(&mut self, key: SegmentKey) -> Result<Option<&Segment<T>>, BufErr> {
         if self.retrieve(key)? {
@@ -1587,7 +1478,9 @@ impl<'a, T, U> SegmentBuffer<T> for LRUBuffer<'a, T, U>
     }
 
     fn run_query(&self) {
-        self.evaluate_query();
+        if let Some(evaluator) = &self.evaluator {
+            evaluator.evaluate(self.buffer.len());
+        }
     }
 
     #[inline]
@@ -1597,8 +1490,28 @@ impl<'a, T, U> SegmentBuffer<T> for LRUBuffer<'a, T, U>
     }
 
 
-    fn drain(&mut self) -> Drain<Segment<T>> {
-        unimplemented!()
+    /* Hands the segments out from the least to the most recently used,
+     * the evaluator keeps what it learnt from them
+     */
+    fn drain(&mut self) -> Vec<Segment<T>> {
+        let mut segs = Vec::with_capacity(self.buffer.len());
+        let mut cur = self.head;
+        while let Some(key) = cur {
+            match self.buffer.remove(&key) {
+                Some(node) => {
+                    cur = node.next;
+                    segs.push(node.value);
+                }
+                None => break,
+            }
+        }
+        segs.extend(std::mem::replace(&mut self.buffer, BTreeMap::new()).into_iter().map(|(_, node)| node.value));
+        self.head = None;
+        self.tail = None;
+        self.cur_size = 0;
+        self.buf_size = 0;
+        self.account.reset();
+        segs
     }
 
     fn copy(&self) -> Vec<Segment<T>> {
@@ -1607,6 +1520,7 @@ impl<'a, T, U> SegmentBuffer<T> for LRUBuffer<'a, T, U>
 
     /* Write to file system */
     fn persist(&self) -> Result<(), BufErr> {
+        let mut batch = Vec::with_capacity(self.buffer.len());
         for (seg_key, seg) in self.buffer.iter() {
             let seg_key_bytes = match seg_key.convert_to_bytes() {
                 Ok(bytes) => bytes,
@@ -1616,18 +1530,22 @@ impl<'a, T, U> SegmentBuffer<T> for LRUBuffer<'a, T, U>
                 Ok(bytes) => bytes,
                 Err(_) => return Err(BufErr::FailedSegSer),
             };
-
-            match self.file_manager.fm_write(seg_key_bytes, seg_bytes) {
-                Err(_) => return Err(BufErr::FileManagerErr),
-                _ => (),
-            }
+            batch.push((seg_key_bytes, seg_bytes));
         }
 
-        Ok(())
+        match self.file_manager.fm_write_batch(batch) {
+            Err(_) => Err(BufErr::FileManagerErr),
+            Ok(()) => Ok(()),
+        }
     }
 
     fn flush(&mut self) {
         self.buffer.clear();
+        self.head = None;
+        self.tail = None;
+        self.cur_size = 0;
+        self.buf_size = 0;
+        self.account.reset();
         self.done = true;
     }
 
@@ -1642,6 +1560,9 @@ impl<'a, T, U> SegmentBuffer<T> for LRUBuffer<'a, T, U>
     }
 
 
+    /* Segments are recoded in place, so the least recently used one is
+     * handed out and moved to the back instead of being dropped
+     */
     fn remove_segment(&mut self) -> Result<Segment<T>, BufErr> {
         match self.head {
             None => {
@@ -1669,9 +1590,205 @@ impl<'a, T, U> SegmentBuffer<T> for LRUBuffer<'a, T, U>
         return self.buffer.len() >= batchsize;
     }
 
+    fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    fn peek(&self, key: SegmentKey) -> Option<&Segment<T>> {
+        self.buffer.get(&key).map(|node| &node.value)
+    }
+
+    /* The least recently used segment is written out and dropped */
+    fn evict_one(&mut self) -> Result<bool, BufErr> {
+        let head = match self.head {
+            Some(head) => head,
+            None => return Ok(false),
+        };
+        match self.buffer.get(&head) {
+            Some(node) => write_persisted(&self.file_manager, head, &node.value)?,
+            None => return Err(BufErr::EvictFailure),
+        }
+        self.pop_front_node();
+        self.stats.evictions += 1;
+        Ok(true)
+    }
+
     fn get_recommend(&self) -> (usize,usize,usize){
-        return self.get_recommend();
+        match &self.evaluator {
+            Some(evaluator) => evaluator.recommend(),
+            None => (0,0,0),
+        }
+    }
+
+    fn range_keys(&self, signal: SignalId, start: SystemTime, end: SystemTime) -> Result<Vec<SegmentKey>, BufErr> {
+        let mut keys: BTreeSet<SegmentKey> = self.buffer.keys()
+            .filter(|k| k.in_range(signal, start, end))
+            .cloned()
+            .collect();
+        scan_persisted_keys(&self.file_manager, signal, start, end, &mut keys)?;
+        Ok(keys.into_iter().collect())
+    }
+
+    fn get_cache_stats(&self) -> CacheStats {
+        self.stats
+    }
+    fn is_persisted(&self, key: SegmentKey) -> bool {
+        has_persisted(&self.file_manager, key)
+    }
+
+    fn put_columnar(&mut self, key: SegmentKey, bytes: Vec<u8>) -> Result<(), BufErr> {
+        write_columnar(&self.file_manager, key, bytes)
+    }
+
+    fn expire(&mut self, signal: SignalId, before: SystemTime) -> Result<Expired, BufErr> {
+        let mut expired = BTreeMap::new();
+        let keys: Vec<SegmentKey> = self.buffer.iter()
+            .filter(|(_, node)| is_expired(&node.value, signal, before))
+            .map(|(key, _)| *key)
+            .collect();
+        for key in keys {
+            let node = match self.buffer.get(&key) {
+                Some(node) => node.clone(),
+                None => continue,
+            };
+            expired.insert(key, ExpiredSegment::of(&node.value));
+            self.unlink_node(key, &node);
+        }
+        expire_persisted(&self.file_manager, signal, before, &mut expired)?;
+        Ok(Expired {
+            segments: expired.into_iter().map(|(_, seg)| seg).collect(),
+        })
+    }
+}
+
+
+/* The contract every SegmentBuffer has to keep, n must fit in the buffer */
+#[cfg(test)]
+fn check_segment_buffer<B: SegmentBuffer<f64>>(buf: &mut B, n: usize) {
+    use std::time::Duration;
+    use crate::methods::Methods;
+
+    let start = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+    let segs: Vec<Segment<f64>> = (0..n).map(|i| {
+        let data = (0..100).map(|x| (x + i) as f64 * 0.5).collect();
+        Segment::new(Some(Methods::Uncompr), start + Duration::from_secs(i as u64 * 10), 7, data, None, None)
+    }).collect();
+    let keys: Vec<SegmentKey> = segs.iter().map(|seg| seg.get_key()).collect();
+
+    for seg in &segs {
+        buf.put(seg.clone()).expect("Failed to put segment");
+    }
+    for (key, seg) in keys.iter().zip(segs.iter()) {
+        assert_eq!(buf.get(*key).expect("Failed to get segment"), Some(seg));
+        assert_eq!(buf.get_mut(*key).expect("Failed to get segment"), Some(seg));
+    }
+    assert_eq!(buf.get(SegmentKey::new(start, 8)).expect("Failed to get missing segment"), None);
+
+    assert_eq!(buf.copy().len(), n);
+    assert!(buf.exceed_batch(n) && !buf.exceed_batch(n + 1));
+    assert!(!buf.idle_threshold(0.0));
+    assert!(buf.get_buffer_size() > 0);
+    assert_eq!(buf.range_keys(7, start, start + Duration::from_secs(n as u64 * 10)).unwrap(), keys);
+    buf.persist().expect("Failed to persist");
+    buf.run_query();
+    let _ = buf.get_recommend();
+
+    let removed = buf.remove_segment().expect("Failed to remove segment");
+    assert!(segs.contains(&removed));
+    /* A removed segment leaves no slot behind, so removing again and putting keep working */
+    let again = buf.remove_segment().expect("Failed to remove a second segment");
+    assert!(segs.contains(&again) && again != removed);
+    buf.put(removed.clone()).expect("Failed to put a removed segment back");
+    assert_eq!(buf.get(removed.get_key()).expect("Failed to get segment"), Some(&removed));
+
+    let drained = buf.drain();
+    assert!(drained.len() + 1 >= n && drained.iter().all(|seg| segs.contains(seg)));
+    assert!(buf.copy().is_empty() && !buf.exceed_batch(1));
+    assert!(buf.idle_threshold(0.5));
+    assert_eq!(buf.get_buffer_size(), 0);
+    assert!(buf.remove_segment().is_err());
+
+    /* The buffer is still usable after a drain */
+    buf.put(segs[0].clone()).expect("Failed to put segment after drain");
+    assert_eq!(buf.get(keys[0]).expect("Failed to get segment"), Some(&segs[0]));
+    buf.flush();
+    assert!(buf.is_done());
+}
+
+#[cfg(test)]
+struct ConstantPredictor;
+
+#[cfg(test)]
+impl smartcore::api::PredictorVec<f64> for ConstantPredictor {
+    fn predictVec(&self, _x: &Vec<f64>, n: usize) -> Vec<f64> {
+        vec![0.0; n.max(1)]
     }
 }
 
+#[test]
+fn clock_buffer_conformance_test() {
+    use crate::file_handler::LogFileManager;
+
+    let path = "../clock_buffer_test";
+    let _ = LogFileManager::destroy(path);
+    let fm = LogFileManager::open(path).expect("Failed to open log file manager");
+    check_segment_buffer(&mut ClockBuffer::<f64, LogFileManager>::new(16, fm), 12);
+    LogFileManager::destroy(path).expect("Failed to destroy log file manager");
+
+    /* Persisted keys are found on disk once the segments left memory */
+    let fm = LogFileManager::open(path).expect("Failed to open log file manager");
+    let mut buf = ClockBuffer::<f64, LogFileManager>::new(16, fm);
+    let segs: Vec<Segment<f64>> = (0..3u64).map(|i| Segment::new(None, UNIX_EPOCH + std::time::Duration::from_secs(i * 10), 5, vec![i as f64; 8], None, None)).collect();
+    for seg in &segs {
+        buf.put(seg.clone()).expect("Failed to put segment");
+    }
+    buf.persist().expect("Failed to persist");
+    buf.drain();
+    let keys: Vec<SegmentKey> = segs.iter().map(|seg| seg.get_key()).collect();
+    assert!(keys.iter().all(|key| buf.is_persisted(*key)));
+    assert!(!buf.is_persisted(SegmentKey::new(UNIX_EPOCH + std::time::Duration::from_secs(5), 5)));
+    assert_eq!(buf.range_keys(5, UNIX_EPOCH, UNIX_EPOCH + std::time::Duration::from_secs(30)).unwrap(), keys);
+    drop(buf);
+    LogFileManager::destroy(path).expect("Failed to destroy log file manager");
+}
+
+#[test]
+fn nofm_clock_buffer_conformance_test() {
+    check_segment_buffer(&mut NoFmClockBuffer::<f64>::new(16), 12);
 
+    /* A full buffer drops the segments the clock hand evicts */
+    let mut buf = NoFmClockBuffer::<f64>::new(4);
+    for i in 0..10u64 {
+        let seg = Segment::new(None, UNIX_EPOCH + std::time::Duration::from_secs(i), 1, vec![i as f64; 8], None, None);
+        buf.put(seg).expect("Failed to put segment into a full buffer");
+    }
+    assert_eq!(buf.copy().len(), 4);
+
+    /* Segments removed from a full buffer free their slots for the next puts */
+    for _ in 0..2 {
+        buf.remove_segment().expect("Failed to remove segment");
+    }
+    for i in 10..12u64 {
+        let seg = Segment::new(None, UNIX_EPOCH + std::time::Duration::from_secs(i), 1, vec![i as f64; 8], None, None);
+        buf.put(seg).expect("Failed to put segment after removing");
+    }
+    assert_eq!(buf.copy().len(), 4);
+    assert!(buf.remove_segment().is_ok());
+}
+
+#[test]
+fn lru_buffer_conformance_test() {
+    use crate::file_handler::LogFileManager;
+
+    let path = "../lru_buffer_test";
+    let _ = LogFileManager::destroy(path);
+    let fm = LogFileManager::open(path).expect("Failed to open log file manager");
+    let evaluator = workload::ModelEvaluator::new(Box::new(ConstantPredictor), 0);
+    check_segment_buffer(&mut LRUBuffer::<f64, LogFileManager>::new(1 << 20, fm, Some(Box::new(evaluator))), 12);
+    LogFileManager::destroy(path).expect("Failed to destroy log file manager");
+
+    /* Without an evaluator no model is needed at all */
+    let fm = LogFileManager::open(path).expect("Failed to open log file manager");
+    check_segment_buffer(&mut LRUBuffer::<f64, LogFileManager>::new(1 << 20, fm, None), 12);
+    LogFileManager::destroy(path).expect("Failed to destroy log file manager");
+}
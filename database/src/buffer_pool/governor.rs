@@ -0,0 +1,242 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use futures::Async;
+use futures::task::{self, Task};
+
+/*
+ * Overview:
+ * A process wide account of the memory held by the buffer pools. The
+ * clock buffers are sized in segments and the LRU buffer in serialized
+ * bytes, so the raw and compressed pools of a run could not be bounded
+ * together. Every buffer registers a MemoryAccount with the governor
+ * and charges it the heap bytes of the segments it holds, data and
+ * compressed binary alike.
+ *
+ * Design Choice:
+ * The governor never refuses a segment, a buffer that is over budget
+ * still takes what it is given so nothing is dropped. Instead the
+ * producers are held back: a signal asks poll_admit before it reads
+ * more values and is parked while the budget is exceeded. The tasks
+ * parked are woken once the daemons release enough memory to bring the
+ * total back under the budget. Without a budget the governor only counts.
+ * The budget has to leave room above the threshold the daemons start
+ * draining the raw buffer at, otherwise nothing is left to wake the
+ * signals, so the config loader rejects a budget that does not.
+ */
+
+lazy_static! {
+    static ref GOVERNOR: Arc<MemoryGovernor> = Arc::new(MemoryGovernor::new(None));
+}
+
+/* The governor every buffer registers with unless it is given another */
+pub fn governor() -> Arc<MemoryGovernor> {
+    GOVERNOR.clone()
+}
+
+/* Subtracts at most what the counter holds and returns what was subtracted */
+fn saturating_release(counter: &AtomicUsize, bytes: usize) -> usize {
+    let mut cur = counter.load(Ordering::Acquire);
+    loop {
+        let released = if bytes < cur { bytes } else { cur };
+        match counter.compare_exchange(cur, cur - released, Ordering::AcqRel, Ordering::Acquire) {
+            Ok(_) => return released,
+            Err(actual) => cur = actual,
+        }
+    }
+}
+
+pub struct MemoryGovernor {
+    /* usize::MAX when there is no budget */
+    budget: AtomicUsize,
+    used: AtomicUsize,
+    pools: Mutex<Vec<(String, Arc<AtomicUsize>)>>,
+    parked: Mutex<Vec<Task>>,
+}
+
+impl MemoryGovernor {
+    pub fn new(budget: Option<usize>) -> MemoryGovernor {
+        MemoryGovernor {
+            budget: AtomicUsize::new(budget.unwrap_or(usize::max_value())),
+            used: AtomicUsize::new(0),
+            pools: Mutex::new(Vec::new()),
+            parked: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn set_budget(&self, budget: Option<usize>) {
+        self.budget.store(budget.unwrap_or(usize::max_value()), Ordering::Release);
+        if !self.exceeded() {
+            self.wake();
+        }
+    }
+
+    pub fn get_budget(&self) -> Option<usize> {
+        match self.budget.load(Ordering::Acquire) {
+            b if b == usize::max_value() => None,
+            b => Some(b),
+        }
+    }
+
+    /* Heap bytes held by every registered buffer together */
+    pub fn get_used(&self) -> usize {
+        self.used.load(Ordering::Acquire)
+    }
+
+    pub fn exceeded(&self) -> bool {
+        self.get_used() > self.budget.load(Ordering::Acquire)
+    }
+
+    /* Opens the account a buffer charges its segments to, the name is only
+     * used to report the pools
+     */
+    pub fn register(governor: &Arc<MemoryGovernor>, name: &str) -> MemoryAccount {
+        let bytes = Arc::new(AtomicUsize::new(0));
+        if let Ok(mut pools) = governor.pools.lock() {
+            pools.retain(|(_, held)| Arc::strong_count(held) > 1);
+            pools.push((name.to_string(), bytes.clone()));
+        }
+        MemoryAccount {
+            governor: governor.clone(),
+            bytes: bytes,
+        }
+    }
+
+    /* The bytes held by every buffer still registered */
+    pub fn get_pools(&self) -> Vec<(String, usize)> {
+        match self.pools.lock() {
+            Ok(pools) => pools.iter()
+                .filter(|(_, held)| Arc::strong_count(held) > 1)
+                .map(|(name, held)| (name.clone(), held.load(Ordering::Acquire)))
+                .collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /* Ready while the budget holds. Otherwise the current task is parked
+     * and notified once memory is released, so this must be called from
+     * within a task.
+     */
+    pub fn poll_admit(&self) -> Async<()> {
+        if !self.exceeded() {
+            return Async::Ready(());
+        }
+        if let Ok(mut parked) = self.parked.lock() {
+            parked.push(task::current());
+        }
+        /* Memory may have been released before the task was parked */
+        if !self.exceeded() {
+            self.wake();
+            return Async::Ready(());
+        }
+        Async::NotReady
+    }
+
+    fn charge(&self, bytes: usize) {
+        self.used.fetch_add(bytes, Ordering::AcqRel);
+    }
+
+    fn release(&self, bytes: usize) {
+        saturating_release(&self.used, bytes);
+        if !self.exceeded() {
+            self.wake();
+        }
+    }
+
+    /* Notifies every parked task, also used on shutdown so they see the stop */
+    pub fn wake(&self) {
+        let parked = match self.parked.lock() {
+            Ok(mut parked) => std::mem::replace(&mut *parked, Vec::new()),
+            Err(_) => return,
+        };
+        for task in parked {
+            task.notify();
+        }
+    }
+}
+
+impl fmt::Debug for MemoryGovernor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Memory governor using {} bytes of {:?}", self.get_used(), self.get_budget())
+    }
+}
+
+/* What one buffer holds, everything left on it is released when it is dropped */
+pub struct MemoryAccount {
+    governor: Arc<MemoryGovernor>,
+    bytes: Arc<AtomicUsize>,
+}
+
+impl MemoryAccount {
+    pub fn charge(&self, bytes: usize) {
+        self.bytes.fetch_add(bytes, Ordering::AcqRel);
+        self.governor.charge(bytes);
+    }
+
+    pub fn release(&self, bytes: usize) {
+        let released = saturating_release(&self.bytes, bytes);
+        self.governor.release(released);
+    }
+
+    /* Releases everything, for buffers that were drained or flushed */
+    pub fn reset(&self) {
+        let held = self.bytes.swap(0, Ordering::AcqRel);
+        self.governor.release(held);
+    }
+
+    pub fn get_bytes(&self) -> usize {
+        self.bytes.load(Ordering::Acquire)
+    }
+}
+
+impl Drop for MemoryAccount {
+    fn drop(&mut self) {
+        self.reset();
+    }
+}
+
+impl fmt::Debug for MemoryAccount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Memory account holding {} bytes", self.get_bytes())
+    }
+}
+
+#[test]
+fn memory_governor_test() {
+    use std::thread;
+    use std::time::Duration;
+    use futures::Future;
+    use futures::future::poll_fn;
+
+    let governor = Arc::new(MemoryGovernor::new(Some(1000)));
+    let raw = MemoryGovernor::register(&governor, "raw");
+    let comp = MemoryGovernor::register(&governor, "comp");
+    raw.charge(600);
+    comp.charge(300);
+    assert_eq!(governor.get_used(), 900);
+    assert!(!governor.exceeded());
+    comp.charge(200);
+    assert!(governor.exceeded());
+
+    /* A producer waits until the pools are drained below the budget */
+    let waiting = governor.clone();
+    let producer = thread::spawn(move || {
+        poll_fn(|| -> Result<Async<()>, ()> { Ok(waiting.poll_admit()) }).wait().unwrap();
+        waiting.get_used()
+    });
+    thread::sleep(Duration::from_millis(50));
+    raw.release(150);
+    assert!(producer.join().unwrap() <= 1000);
+
+    /* Releasing more than an account holds can not drive the total below zero */
+    comp.release(10_000);
+    assert_eq!(comp.get_bytes(), 0);
+    assert_eq!(governor.get_used(), 450);
+    drop(raw);
+    assert_eq!(governor.get_used(), 0);
+    assert_eq!(governor.get_pools(), vec![("comp".to_string(), 0)]);
+
+    governor.set_budget(None);
+    comp.charge(usize::max_value() / 2);
+    assert!(!governor.exceeded() && governor.get_budget().is_none());
+}
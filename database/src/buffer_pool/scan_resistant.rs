@@ -0,0 +1,894 @@
+use std::cmp::{max, min};
+use std::collections::{BTreeMap, BTreeSet};
+use std::collections::hash_map::HashMap;
+use std::fmt::Debug;
+use std::time::SystemTime;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::file_handler::FileManager;
+use crate::segment::{Segment, SegmentKey};
+use crate::future_signal::SignalId;
+use super::{SegmentBuffer, BufErr, CacheStats, Expired, ExpiredSegment, load_persisted, write_persisted, scan_persisted_keys, has_persisted, write_columnar, expire_persisted, is_expired};
+use super::governor::{governor, MemoryGovernor, MemoryAccount};
+
+/*
+ * Overview:
+ * Buffers whose eviction policy survives a large scan. Under CLOCK or
+ * LRU every segment a historical query reads once pushes out one of the
+ * recently ingested segments the daemons keep coming back to. ARC and
+ * 2Q only promote a segment to the protected part of the buffer once
+ * it has been referenced again, so a scan only cycles through the
+ * probationary part.
+ *
+ * Design Choice:
+ * Both buffers keep their segments in a Resident store and only differ
+ * in the lists of keys they order them by. Evicted segments are written
+ * through the file manager unless they were read from it and never
+ * replaced, so a later lookup can bring them back, and the ghost lists
+ * only ever hold keys. remove_segment hands out probationary segments
+ * first and leaves no ghost, the caller owns the segment from then on.
+ *
+ * Current Implementations:
+ * ArcBuffer follows Megiddo and Modha, "ARC: A Self-Tuning, Low Overhead
+ * Replacement Cache". TwoQueueBuffer is the full version of Johnson and
+ * Shasha, "2Q: A Low Overhead High Performance Buffer Management
+ * Replacement Algorithm". Both are sized in segments, like the clock.
+ */
+
+/* Share of a 2Q buffer that A1in may hold before it is evicted from */
+const TWO_QUEUE_IN_RATIO: f32 = 0.25;
+/* Number of ghost keys a 2Q buffer remembers, relative to its size */
+const TWO_QUEUE_OUT_RATIO: f32 = 0.5;
+
+/* Keys ordered from the least to the most recently pushed */
+#[derive(Debug, Default)]
+struct RecencyList {
+    order: BTreeMap<u64, SegmentKey>,
+    ticks: HashMap<SegmentKey, u64>,
+    next: u64,
+}
+
+impl RecencyList {
+    fn len(&self) -> usize {
+        self.ticks.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.ticks.is_empty()
+    }
+
+    fn contains(&self, key: &SegmentKey) -> bool {
+        self.ticks.contains_key(key)
+    }
+
+    /* Makes the key the most recent one, moving it if it is already listed */
+    fn push(&mut self, key: SegmentKey) {
+        self.remove(&key);
+        self.order.insert(self.next, key);
+        self.ticks.insert(key, self.next);
+        self.next += 1;
+    }
+
+    fn remove(&mut self, key: &SegmentKey) -> bool {
+        match self.ticks.remove(key) {
+            Some(tick) => {
+                self.order.remove(&tick);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn pop_oldest(&mut self) -> Option<SegmentKey> {
+        let tick = match self.order.keys().next() {
+            Some(tick) => *tick,
+            None => return None,
+        };
+        let key = self.order.remove(&tick)?;
+        self.ticks.remove(&key);
+        Some(key)
+    }
+
+    /* From the oldest to the most recent key */
+    fn keys(&self) -> Vec<SegmentKey> {
+        self.order.values().cloned().collect()
+    }
+
+    fn clear(&mut self) {
+        self.order.clear();
+        self.ticks.clear();
+    }
+}
+
+#[derive(Debug)]
+struct Slot<T>
+    where T: Copy + Send,
+{
+    seg: Segment<T>,
+    /* False while the segment is the one persisted under its key */
+    dirty: bool,
+}
+
+/* The segments held by a buffer, with the file manager behind them */
+#[derive(Debug)]
+struct Resident<T, U>
+    where T: Copy + Send,
+          U: FileManager<Vec<u8>,Vec<u8>> + Sync + Send,
+{
+    buffer: HashMap<SegmentKey, Slot<T>>,
+    file_manager: U,
+    buf_size: usize,
+    stats: CacheStats,
+    account: MemoryAccount,
+    done: bool,
+}
+
+impl<T, U> Resident<T, U>
+    where T: Copy + Send + Serialize + DeserializeOwned + Debug,
+          U: FileManager<Vec<u8>,Vec<u8>> + Sync + Send,
+{
+    fn new(buf_size: usize, file_manager: U, name: &str) -> Resident<T, U> {
+        Resident {
+            buffer: HashMap::with_capacity(buf_size),
+            file_manager: file_manager,
+            buf_size: buf_size,
+            stats: CacheStats::default(),
+            account: MemoryGovernor::register(&governor(), name),
+            done: false,
+        }
+    }
+
+    fn contains(&self, key: &SegmentKey) -> bool {
+        self.buffer.contains_key(key)
+    }
+
+    fn is_full(&self) -> bool {
+        self.buffer.len() >= self.buf_size
+    }
+
+    fn get(&self, key: &SegmentKey) -> Option<&Segment<T>> {
+        self.buffer.get(key).map(|slot| &slot.seg)
+    }
+
+    fn insert(&mut self, key: SegmentKey, seg: Segment<T>, dirty: bool) {
+        self.account.charge(seg.get_heap_size());
+        if let Some(old) = self.buffer.insert(key, Slot { seg: seg, dirty: dirty }) {
+            self.account.release(old.seg.get_heap_size());
+        }
+    }
+
+    /* Replaces a buffered segment, the segment is handed back if its key is not buffered */
+    fn replace(&mut self, key: SegmentKey, seg: Segment<T>) -> Result<(), Segment<T>> {
+        match self.buffer.get_mut(&key) {
+            Some(slot) => {
+                self.account.release(slot.seg.get_heap_size());
+                self.account.charge(seg.get_heap_size());
+                slot.seg = seg;
+                slot.dirty = true;
+                Ok(())
+            }
+            None => Err(seg),
+        }
+    }
+
+    /* Counts the lookup and reads the segment from the file manager on a miss,
+     * None when it is buffered already or was never persisted
+     */
+    fn lookup(&mut self, key: SegmentKey) -> Result<Option<Segment<T>>, BufErr> {
+        if self.contains(&key) {
+            self.stats.hits += 1;
+            return Ok(None);
+        }
+        self.stats.misses += 1;
+        load_persisted(&self.file_manager, key)
+    }
+
+    fn take(&mut self, key: &SegmentKey) -> Result<Segment<T>, BufErr> {
+        match self.buffer.remove(key) {
+            Some(slot) => {
+                self.account.release(slot.seg.get_heap_size());
+                Ok(slot.seg)
+            }
+            None => Err(BufErr::RemoveFailure),
+        }
+    }
+
+    /* Drops the segment from memory, writing it out first if it changed */
+    fn evict(&mut self, key: SegmentKey) -> Result<(), BufErr> {
+        let slot = match self.buffer.remove(&key) {
+            Some(slot) => slot,
+            None => return Err(BufErr::EvictFailure),
+        };
+        self.account.release(slot.seg.get_heap_size());
+        if slot.dirty {
+            write_persisted(&self.file_manager, key, &slot.seg)?;
+        }
+        self.stats.evictions += 1;
+        Ok(())
+    }
+
+    /* Hands out the segments of the keys in order, then whatever is left */
+    fn drain(&mut self, keys: Vec<SegmentKey>) -> Vec<Segment<T>> {
+        let mut segs = Vec::with_capacity(self.buffer.len());
+        for key in keys {
+            if let Some(slot) = self.buffer.remove(&key) {
+                segs.push(slot.seg);
+            }
+        }
+        segs.extend(self.buffer.drain().map(|(_, slot)| slot.seg));
+        self.account.reset();
+        segs
+    }
+
+    fn clear(&mut self) {
+        self.buffer.clear();
+        self.account.reset();
+    }
+
+    fn copy(&self) -> Vec<Segment<T>> {
+        self.buffer.values().map(|slot| slot.seg.clone()).collect()
+    }
+
+    fn persist(&self) -> Result<(), BufErr> {
+        let mut batch = Vec::with_capacity(self.buffer.len());
+        for (seg_key, slot) in self.buffer.iter() {
+            let seg_key_bytes = match seg_key.convert_to_bytes() {
+                Ok(bytes) => bytes,
+                Err(_) => return Err(BufErr::FailedSegKeySer),
+            };
+            let seg_bytes = match slot.seg.convert_to_bytes() {
+                Ok(bytes) => bytes,
+                Err(_) => return Err(BufErr::FailedSegSer),
+            };
+            batch.push((seg_key_bytes, seg_bytes));
+        }
+
+        match self.file_manager.fm_write_batch(batch) {
+            Err(_) => Err(BufErr::FileManagerErr),
+            Ok(()) => Ok(()),
+        }
+    }
+
+    fn fill(&self) -> f32 {
+        self.buffer.len() as f32 / self.buf_size as f32
+    }
+
+    fn bytes(&self) -> usize {
+        self.buffer.values().map(|slot| slot.seg.get_byte_size().unwrap_or(0)).sum()
+    }
+
+    fn range_keys(&self, signal: SignalId, start: SystemTime, end: SystemTime) -> Result<Vec<SegmentKey>, BufErr> {
+        let mut keys: BTreeSet<SegmentKey> = self.buffer.keys()
+            .filter(|k| k.in_range(signal, start, end))
+            .cloned()
+            .collect();
+        scan_persisted_keys(&self.file_manager, signal, start, end, &mut keys)?;
+        Ok(keys.into_iter().collect())
+    }
+
+    /* Removes the segments of the signal whose last point is older than the
+     * cutoff from memory and the file manager, the lists are left for the
+     * caller to clean
+     */
+    fn expire(&mut self, signal: SignalId, before: SystemTime) -> Result<Expired, BufErr> {
+        let mut expired = BTreeMap::new();
+        let keys: Vec<SegmentKey> = self.buffer.iter()
+            .filter(|(_, slot)| is_expired(&slot.seg, signal, before))
+            .map(|(key, _)| *key)
+            .collect();
+        for key in keys {
+            let seg = self.take(&key)?;
+            expired.insert(key, ExpiredSegment::of(&seg));
+        }
+        expire_persisted(&self.file_manager, signal, before, &mut expired)?;
+        Ok(Expired {
+            segments: expired.into_iter().map(|(_, seg)| seg).collect(),
+        })
+    }
+}
+
+
+/***************************************************************
+ **************************ARC_Buffer***************************
+ ***************************************************************/
+
+/* t1 holds the segments referenced once since they came in, t2 the ones
+ * referenced again. b1 and b2 remember the keys evicted from each, a
+ * lookup of one of those moves the target size of t1 towards the list
+ * that would have kept it.
+ */
+#[derive(Debug)]
+pub struct ArcBuffer<T, U>
+    where T: Copy + Send,
+          U: FileManager<Vec<u8>,Vec<u8>> + Sync + Send,
+{
+    resident: Resident<T, U>,
+    target: usize,
+    t1: RecencyList,
+    t2: RecencyList,
+    b1: RecencyList,
+    b2: RecencyList,
+}
+
+impl<T, U> ArcBuffer<T, U>
+    where T: Copy + Send + Serialize + DeserializeOwned + Debug,
+          U: FileManager<Vec<u8>,Vec<u8>> + Sync + Send,
+{
+    pub fn new(buf_size: usize, file_manager: U) -> ArcBuffer<T, U> {
+        ArcBuffer {
+            resident: Resident::new(buf_size, file_manager, "arc"),
+            target: 0,
+            t1: RecencyList::default(),
+            t2: RecencyList::default(),
+            b1: RecencyList::default(),
+            b2: RecencyList::default(),
+        }
+    }
+
+    /* The number of segments t1 is currently allowed to hold */
+    pub fn get_target(&self) -> usize {
+        self.target
+    }
+
+    /* Evicts from t1 while it is over its target and from t2 otherwise */
+    fn replace(&mut self, in_b2: bool) -> Result<(), BufErr> {
+        if !self.resident.is_full() {
+            return Ok(());
+        }
+        let over_target = self.t1.len() > self.target || (in_b2 && self.t1.len() == self.target);
+        if !self.t1.is_empty() && (over_target || self.t2.is_empty()) {
+            if let Some(key) = self.t1.pop_oldest() {
+                self.resident.evict(key)?;
+                self.b1.push(key);
+            }
+        } else if let Some(key) = self.t2.pop_oldest() {
+            self.resident.evict(key)?;
+            self.b2.push(key);
+        }
+        Ok(())
+    }
+
+    /* Brings a segment that is not buffered in */
+    fn admit(&mut self, key: SegmentKey, seg: Segment<T>, dirty: bool) -> Result<(), BufErr> {
+        let buf_size = self.resident.buf_size;
+        if self.b1.contains(&key) {
+            let delta = max(self.b2.len() / self.b1.len(), 1);
+            self.target = min(self.target + delta, buf_size);
+            self.replace(false)?;
+            self.b1.remove(&key);
+            self.t2.push(key);
+        } else if self.b2.contains(&key) {
+            let delta = max(self.b1.len() / self.b2.len(), 1);
+            self.target = self.target.saturating_sub(delta);
+            self.replace(true)?;
+            self.b2.remove(&key);
+            self.t2.push(key);
+        } else {
+            let l1 = self.t1.len() + self.b1.len();
+            if l1 >= buf_size && self.t1.len() >= buf_size {
+                /* t1 fills the whole buffer, its oldest segment leaves without a ghost */
+                if let Some(old) = self.t1.pop_oldest() {
+                    self.resident.evict(old)?;
+                }
+            } else {
+                if l1 >= buf_size {
+                    self.b1.pop_oldest();
+                } else if l1 + self.t2.len() + self.b2.len() >= 2 * buf_size {
+                    self.b2.pop_oldest();
+                }
+                self.replace(false)?;
+            }
+            self.t1.push(key);
+        }
+        self.resident.insert(key, seg, dirty);
+        Ok(())
+    }
+
+    /* Makes the segment available in memory, false if it does not exist */
+    fn retrieve(&mut self, key: SegmentKey) -> Result<bool, BufErr> {
+        match self.resident.lookup(key)? {
+            None if self.resident.contains(&key) => {
+                self.t1.remove(&key);
+                self.t2.push(key);
+                Ok(true)
+            }
+            None => Ok(false),
+            Some(seg) => {
+                self.admit(key, seg, false)?;
+                Ok(true)
+            }
+        }
+    }
+
+    fn put_with_key(&mut self, key: SegmentKey, seg: Segment<T>) -> Result<(), BufErr> {
+        match self.resident.replace(key, seg) {
+            Ok(()) => {
+                self.t1.remove(&key);
+                self.t2.push(key);
+                Ok(())
+            }
+            Err(seg) => self.admit(key, seg, true),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.t1.clear();
+        self.t2.clear();
+        self.b1.clear();
+        self.b2.clear();
+        self.target = 0;
+    }
+}
+
+impl<T, U> SegmentBuffer<T> for ArcBuffer<T, U>
+    where T: Copy + Send + Serialize + DeserializeOwned + Debug,
+          U: FileManager<Vec<u8>,Vec<u8>> + Sync + Send,
+{
+    fn get(&mut self, key: SegmentKey) -> Result<Option<&Segment<T>>, BufErr> {
+        if self.retrieve(key)? {
+            match self.resident.get(&key) {
+                Some(seg) => Ok(Some(seg)),
+                None => Err(BufErr::GetFail),
+            }
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn get_mut(&mut self, key: SegmentKey) -> Result<Option<&Segment<T>>, BufErr> {
+        if self.retrieve(key)? {
+            match self.resident.get(&key) {
+                Some(seg) => Ok(Some(seg)),
+                None => Err(BufErr::GetMutFail),
+            }
+        } else {
+            Ok(None)
+        }
+    }
+
+    #[inline]
+    fn put(&mut self, seg: Segment<T>) -> Result<(), BufErr> {
+        let seg_key = seg.get_key();
+        self.put_with_key(seg_key, seg)
+    }
+
+    fn drain(&mut self) -> Vec<Segment<T>> {
+        let mut keys = self.t1.keys();
+        keys.extend(self.t2.keys());
+        self.clear();
+        self.resident.drain(keys)
+    }
+
+    fn copy(&self) -> Vec<Segment<T>> {
+        self.resident.copy()
+    }
+
+    fn persist(&self) -> Result<(), BufErr> {
+        self.resident.persist()
+    }
+
+    fn flush(&mut self) {
+        self.resident.clear();
+        self.clear();
+        self.resident.done = true;
+    }
+
+    fn exceed_threshold(&self, threshold: f32) -> bool {
+        self.resident.fill() >= threshold
+    }
+
+    fn exceed_batch(&self, batchsize: usize) -> bool {
+        self.resident.buffer.len() >= batchsize
+    }
+
+    fn len(&self) -> usize {
+        self.resident.buffer.len()
+    }
+
+    fn peek(&self, key: SegmentKey) -> Option<&Segment<T>> {
+        self.resident.get(&key)
+    }
+
+    /* Evicts like replace does when t1 is over its target, the key is remembered as a ghost */
+    fn evict_one(&mut self) -> Result<bool, BufErr> {
+        if let Some(key) = self.t1.pop_oldest() {
+            self.resident.evict(key)?;
+            self.b1.push(key);
+        } else if let Some(key) = self.t2.pop_oldest() {
+            self.resident.evict(key)?;
+            self.b2.push(key);
+        } else {
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    /* Segments referenced only once go first */
+    fn remove_segment(&mut self) -> Result<Segment<T>, BufErr> {
+        let key = match self.t1.pop_oldest() {
+            Some(key) => key,
+            None => match self.t2.pop_oldest() {
+                Some(key) => key,
+                None => return Err(BufErr::BufEmpty),
+            }
+        };
+        self.resident.take(&key)
+    }
+
+    fn idle_threshold(&self, threshold: f32) -> bool {
+        self.resident.fill() < threshold
+    }
+
+    fn get_buffer_size(&self) -> usize {
+        self.resident.bytes()
+    }
+
+    fn is_done(&self) -> bool {
+        self.resident.done
+    }
+
+    fn range_keys(&self, signal: SignalId, start: SystemTime, end: SystemTime) -> Result<Vec<SegmentKey>, BufErr> {
+        self.resident.range_keys(signal, start, end)
+    }
+
+    fn get_cache_stats(&self) -> CacheStats {
+        self.resident.stats
+    }
+    fn is_persisted(&self, key: SegmentKey) -> bool {
+        has_persisted(&self.resident.file_manager, key)
+    }
+
+    fn put_columnar(&mut self, key: SegmentKey, bytes: Vec<u8>) -> Result<(), BufErr> {
+        write_columnar(&self.resident.file_manager, key, bytes)
+    }
+
+    /* Expired keys are forgotten by the ghost lists as well */
+    fn expire(&mut self, signal: SignalId, before: SystemTime) -> Result<Expired, BufErr> {
+        let expired = self.resident.expire(signal, before)?;
+        for seg in expired.segments.iter() {
+            self.t1.remove(&seg.key);
+            self.t2.remove(&seg.key);
+            self.b1.remove(&seg.key);
+            self.b2.remove(&seg.key);
+        }
+        Ok(expired)
+    }
+}
+
+
+/***************************************************************
+ ***********************TwoQueue_Buffer*************************
+ ***************************************************************/
+
+/* New segments wait in the a1in FIFO, a segment looked up again after
+ * it left a1in is remembered by a1out and goes to the am LRU instead.
+ * Lookups while a segment is still in a1in are taken to be correlated
+ * and do not promote it.
+ */
+#[derive(Debug)]
+pub struct TwoQueueBuffer<T, U>
+    where T: Copy + Send,
+          U: FileManager<Vec<u8>,Vec<u8>> + Sync + Send,
+{
+    resident: Resident<T, U>,
+    kin: usize,
+    kout: usize,
+    a1in: RecencyList,
+    a1out: RecencyList,
+    am: RecencyList,
+}
+
+impl<T, U> TwoQueueBuffer<T, U>
+    where T: Copy + Send + Serialize + DeserializeOwned + Debug,
+          U: FileManager<Vec<u8>,Vec<u8>> + Sync + Send,
+{
+    pub fn new(buf_size: usize, file_manager: U) -> TwoQueueBuffer<T, U> {
+        let kin = max((buf_size as f32 * TWO_QUEUE_IN_RATIO) as usize, 1);
+        let kout = max((buf_size as f32 * TWO_QUEUE_OUT_RATIO) as usize, 1);
+        TwoQueueBuffer::with_queues(buf_size, kin, kout, file_manager)
+    }
+
+    /* kin is the number of segments a1in may hold before it is evicted
+     * from, kout the number of keys a1out remembers
+     */
+    pub fn with_queues(buf_size: usize, kin: usize, kout: usize, file_manager: U) -> TwoQueueBuffer<T, U> {
+        TwoQueueBuffer {
+            resident: Resident::new(buf_size, file_manager, "2q"),
+            kin: kin,
+            kout: kout,
+            a1in: RecencyList::default(),
+            a1out: RecencyList::default(),
+            am: RecencyList::default(),
+        }
+    }
+
+    fn reclaim(&mut self) -> Result<(), BufErr> {
+        if !self.resident.is_full() {
+            return Ok(());
+        }
+        if self.a1in.len() > self.kin || self.am.is_empty() {
+            if let Some(key) = self.a1in.pop_oldest() {
+                self.resident.evict(key)?;
+                self.a1out.push(key);
+                if self.a1out.len() > self.kout {
+                    self.a1out.pop_oldest();
+                }
+                return Ok(());
+            }
+        }
+        if let Some(key) = self.am.pop_oldest() {
+            self.resident.evict(key)?;
+        }
+        Ok(())
+    }
+
+    /* Brings a segment that is not buffered in */
+    fn admit(&mut self, key: SegmentKey, seg: Segment<T>, dirty: bool) -> Result<(), BufErr> {
+        let seen = self.a1out.remove(&key);
+        self.reclaim()?;
+        if seen {
+            self.am.push(key);
+        } else {
+            self.a1in.push(key);
+        }
+        self.resident.insert(key, seg, dirty);
+        Ok(())
+    }
+
+    #[inline]
+    fn touch(&mut self, key: SegmentKey) {
+        if self.am.contains(&key) {
+            self.am.push(key);
+        }
+    }
+
+    /* Makes the segment available in memory, false if it does not exist */
+    fn retrieve(&mut self, key: SegmentKey) -> Result<bool, BufErr> {
+        match self.resident.lookup(key)? {
+            None if self.resident.contains(&key) => {
+                self.touch(key);
+                Ok(true)
+            }
+            None => Ok(false),
+            Some(seg) => {
+                self.admit(key, seg, false)?;
+                Ok(true)
+            }
+        }
+    }
+
+    fn put_with_key(&mut self, key: SegmentKey, seg: Segment<T>) -> Result<(), BufErr> {
+        match self.resident.replace(key, seg) {
+            Ok(()) => {
+                self.touch(key);
+                Ok(())
+            }
+            Err(seg) => self.admit(key, seg, true),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.a1in.clear();
+        self.a1out.clear();
+        self.am.clear();
+    }
+}
+
+impl<T, U> SegmentBuffer<T> for TwoQueueBuffer<T, U>
+    where T: Copy + Send + Serialize + DeserializeOwned + Debug,
+          U: FileManager<Vec<u8>,Vec<u8>> + Sync + Send,
+{
+    fn get(&mut self, key: SegmentKey) -> Result<Option<&Segment<T>>, BufErr> {
+        if self.retrieve(key)? {
+            match self.resident.get(&key) {
+                Some(seg) => Ok(Some(seg)),
+                None => Err(BufErr::GetFail),
+            }
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn get_mut(&mut self, key: SegmentKey) -> Result<Option<&Segment<T>>, BufErr> {
+        if self.retrieve(key)? {
+            match self.resident.get(&key) {
+                Some(seg) => Ok(Some(seg)),
+                None => Err(BufErr::GetMutFail),
+            }
+        } else {
+            Ok(None)
+        }
+    }
+
+    #[inline]
+    fn put(&mut self, seg: Segment<T>) -> Result<(), BufErr> {
+        let seg_key = seg.get_key();
+        self.put_with_key(seg_key, seg)
+    }
+
+    fn drain(&mut self) -> Vec<Segment<T>> {
+        let mut keys = self.a1in.keys();
+        keys.extend(self.am.keys());
+        self.clear();
+        self.resident.drain(keys)
+    }
+
+    fn copy(&self) -> Vec<Segment<T>> {
+        self.resident.copy()
+    }
+
+    fn persist(&self) -> Result<(), BufErr> {
+        self.resident.persist()
+    }
+
+    fn flush(&mut self) {
+        self.resident.clear();
+        self.clear();
+        self.resident.done = true;
+    }
+
+    fn exceed_threshold(&self, threshold: f32) -> bool {
+        self.resident.fill() >= threshold
+    }
+
+    fn exceed_batch(&self, batchsize: usize) -> bool {
+        self.resident.buffer.len() >= batchsize
+    }
+
+    fn len(&self) -> usize {
+        self.resident.buffer.len()
+    }
+
+    fn peek(&self, key: SegmentKey) -> Option<&Segment<T>> {
+        self.resident.get(&key)
+    }
+
+    /* Evicts from a1in before am, like reclaim does once am is empty */
+    fn evict_one(&mut self) -> Result<bool, BufErr> {
+        if let Some(key) = self.a1in.pop_oldest() {
+            self.resident.evict(key)?;
+            self.a1out.push(key);
+            if self.a1out.len() > self.kout {
+                self.a1out.pop_oldest();
+            }
+        } else if let Some(key) = self.am.pop_oldest() {
+            self.resident.evict(key)?;
+        } else {
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    /* Segments still in a1in go first */
+    fn remove_segment(&mut self) -> Result<Segment<T>, BufErr> {
+        let key = match self.a1in.pop_oldest() {
+            Some(key) => key,
+            None => match self.am.pop_oldest() {
+                Some(key) => key,
+                None => return Err(BufErr::BufEmpty),
+            }
+        };
+        self.resident.take(&key)
+    }
+
+    fn idle_threshold(&self, threshold: f32) -> bool {
+        self.resident.fill() < threshold
+    }
+
+    fn get_buffer_size(&self) -> usize {
+        self.resident.bytes()
+    }
+
+    fn is_done(&self) -> bool {
+        self.resident.done
+    }
+
+    fn range_keys(&self, signal: SignalId, start: SystemTime, end: SystemTime) -> Result<Vec<SegmentKey>, BufErr> {
+        self.resident.range_keys(signal, start, end)
+    }
+
+    fn get_cache_stats(&self) -> CacheStats {
+        self.resident.stats
+    }
+    fn is_persisted(&self, key: SegmentKey) -> bool {
+        has_persisted(&self.resident.file_manager, key)
+    }
+
+    fn put_columnar(&mut self, key: SegmentKey, bytes: Vec<u8>) -> Result<(), BufErr> {
+        write_columnar(&self.resident.file_manager, key, bytes)
+    }
+
+    fn expire(&mut self, signal: SignalId, before: SystemTime) -> Result<Expired, BufErr> {
+        let expired = self.resident.expire(signal, before)?;
+        for seg in expired.segments.iter() {
+            self.a1in.remove(&seg.key);
+            self.a1out.remove(&seg.key);
+            self.am.remove(&seg.key);
+        }
+        Ok(expired)
+    }
+}
+
+
+/* Two hot segments are looked up every third step of a scan over sixty
+ * persisted ones that do not fit in the buffer, once the hot segments
+ * have been promoted the scan must not evict them again
+ */
+#[cfg(test)]
+pub(super) fn check_scan_resistance<B, U>(buf: &mut B, file_manager: &U)
+    where B: SegmentBuffer<f64>,
+          U: FileManager<Vec<u8>,Vec<u8>>,
+{
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let make = |i: u64, signal: SignalId| Segment::new(None, UNIX_EPOCH + Duration::from_secs(i), signal, vec![i as f64; 16], None, None);
+    let hot: Vec<SegmentKey> = (0..2).map(|i| {
+        let seg = make(i, 1);
+        let key = seg.get_key();
+        buf.put(seg).expect("Failed to put segment");
+        key
+    }).collect();
+    let cold: Vec<SegmentKey> = (0..60).map(|i| {
+        let seg = make(i, 2);
+        let key = seg.get_key();
+        write_persisted(file_manager, key, &seg).expect("Failed to persist segment");
+        key
+    }).collect();
+
+    let mut hot_misses = 0;
+    for (i, key) in cold.iter().enumerate() {
+        assert!(buf.get(*key).expect("Failed to get segment").is_some());
+        if i % 3 == 0 {
+            for key in &hot {
+                let before = buf.get_cache_stats();
+                assert!(buf.get(*key).expect("Failed to get segment").is_some());
+                if i >= 30 && buf.get_cache_stats().misses > before.misses {
+                    hot_misses += 1;
+                }
+            }
+        }
+    }
+    assert_eq!(hot_misses, 0);
+    let stats = buf.get_cache_stats();
+    assert!(stats.misses >= 60 && stats.evictions >= 50, "{}", stats);
+}
+
+#[test]
+fn arc_buffer_test() {
+    use std::sync::Arc;
+    use crate::file_handler::LogFileManager;
+
+    let path = "../arc_buffer_test";
+    let _ = LogFileManager::destroy(path);
+    let fm = LogFileManager::open(path).expect("Failed to open log file manager");
+    super::check_segment_buffer(&mut ArcBuffer::<f64, LogFileManager>::new(16, fm), 12);
+    LogFileManager::destroy(path).expect("Failed to destroy log file manager");
+
+    let fm = Arc::new(LogFileManager::open(path).expect("Failed to open log file manager"));
+    let mut buf = ArcBuffer::<f64, Arc<LogFileManager>>::new(8, fm.clone());
+    check_scan_resistance(&mut buf, &fm);
+    assert_eq!(buf.get_target(), 0);
+    drop(buf);
+    drop(fm);
+    LogFileManager::destroy(path).expect("Failed to destroy log file manager");
+}
+
+#[test]
+fn two_queue_buffer_test() {
+    use std::sync::Arc;
+    use crate::file_handler::LogFileManager;
+
+    let path = "../two_queue_buffer_test";
+    let _ = LogFileManager::destroy(path);
+    let fm = LogFileManager::open(path).expect("Failed to open log file manager");
+    super::check_segment_buffer(&mut TwoQueueBuffer::<f64, LogFileManager>::new(16, fm), 12);
+    LogFileManager::destroy(path).expect("Failed to destroy log file manager");
+
+    let fm = Arc::new(LogFileManager::open(path).expect("Failed to open log file manager"));
+    let mut buf = TwoQueueBuffer::<f64, Arc<LogFileManager>>::new(8, fm.clone());
+    check_scan_resistance(&mut buf, &fm);
+    drop(buf);
+    drop(fm);
+    LogFileManager::destroy(path).expect("Failed to destroy log file manager");
+}
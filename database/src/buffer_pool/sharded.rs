@@ -0,0 +1,452 @@
+use std::cmp::{max, Reverse};
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::SystemTime;
+
+use crate::segment::{Segment, SegmentKey};
+use crate::future_signal::SignalId;
+use super::{SegmentBuffer, BufErr, CacheStats, Expired};
+
+/*
+ * Overview:
+ * A buffer pool split into shards by signal id, so the signals and
+ * daemons sharing it only contend when they touch the same shard.
+ *
+ * Design Choice:
+ * Every shard is a complete SegmentBuffer behind its own RwLock, so any
+ * buffer can be sharded. Scans take the read lock. Lookups go through the
+ * inner get and get_mut under the write lock of their shard, so its
+ * policy sees every hit: CLOCK reference bits, LRU order and the ARC and
+ * 2Q promotions. Anything else the inner buffer needs &mut self for
+ * takes the write lock of that one shard as well.
+ * The entry count and byte size of each shard are mirrored in
+ * atomics after every write, so the threshold checks the daemons poll
+ * in a loop never lock at all.
+ * The pool itself is a cheap handle onto the shards. handle() gives
+ * every signal and daemon a handle of its own, so the mutex they wrap
+ * it in is never contended and the shard locks do the synchronization.
+ * The optional budget is a global byte budget over all shards that the
+ * thresholds are measured against, a put evicts from the shard it wrote
+ * to until the pool is back within it. Without one the pool exceeds a
+ * threshold as soon as one of its shards does.
+ */
+
+/* Shards used when the config does not provide a count */
+pub const DEFAULT_SHARDS: usize = 8;
+
+/* The segments each of count shards holds, so that together they hold total */
+pub fn shard_size(total: usize, count: usize) -> usize {
+    let count = max(count, 1);
+    max((total + count - 1) / count, 1)
+}
+
+struct Shard<B> {
+    buf: RwLock<B>,
+    len: AtomicUsize,
+    bytes: AtomicUsize,
+}
+
+pub struct ShardedBuffer<T, B>
+    where T: Copy + Send,
+          B: SegmentBuffer<T> + Send + Sync,
+{
+    shards: Arc<Vec<Shard<B>>>,
+    budget: Option<usize>,
+    done: Arc<AtomicBool>,
+    /* Where this handle starts looking for a segment to remove */
+    cursor: usize,
+    /* The segment this handle last handed out by reference */
+    fetched: Option<Segment<T>>,
+    _marker: PhantomData<T>,
+}
+
+impl<T, B> ShardedBuffer<T, B>
+    where T: Copy + Send,
+          B: SegmentBuffer<T> + Send + Sync,
+{
+    /* Builds the pool from its shards, signals are spread over them by id */
+    pub fn new(shards: Vec<B>, budget: Option<usize>) -> ShardedBuffer<T, B> {
+        assert!(!shards.is_empty(), "A sharded buffer needs at least one shard");
+        let shards = shards.into_iter().map(|buf| Shard {
+            buf: RwLock::new(buf),
+            len: AtomicUsize::new(0),
+            bytes: AtomicUsize::new(0),
+        }).collect();
+        ShardedBuffer {
+            shards: Arc::new(shards),
+            budget: budget,
+            done: Arc::new(AtomicBool::new(false)),
+            cursor: 0,
+            fetched: None,
+            _marker: PhantomData,
+        }
+    }
+
+    /* Builds count shards with make, which is given the index of the shard */
+    pub fn with_shards<F: FnMut(usize) -> B>(count: usize, budget: Option<usize>, make: F) -> ShardedBuffer<T, B> {
+        ShardedBuffer::new((0..count).map(make).collect(), budget)
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /* Another handle onto the same shards, with a cursor and fetch slot of its own */
+    pub fn share(&self) -> ShardedBuffer<T, B> {
+        ShardedBuffer {
+            shards: self.shards.clone(),
+            budget: self.budget,
+            done: self.done.clone(),
+            cursor: 0,
+            fetched: None,
+            _marker: PhantomData,
+        }
+    }
+
+    #[inline]
+    fn shard_of(&self, signal: SignalId) -> usize {
+        (signal % self.shards.len() as u64) as usize
+    }
+
+    /* Mirrors the state of the shard after a write, must be called under its lock */
+    fn refresh(&self, idx: usize, buf: &B) {
+        let shard = &self.shards[idx];
+        shard.len.store(buf.len(), Ordering::Release);
+        if self.budget.is_some() {
+            shard.bytes.store(buf.get_buffer_size(), Ordering::Release);
+        }
+    }
+
+    fn bytes(&self) -> usize {
+        self.shards.iter().map(|shard| shard.bytes.load(Ordering::Acquire)).sum()
+    }
+
+    /* Looks the segment up in its shard and copies it out */
+    fn fetch(&mut self, key: SegmentKey, mutable: bool) -> Result<Option<&Segment<T>>, BufErr> {
+        let idx = self.shard_of(key.get_signal());
+        let seg = {
+            let mut buf = match self.shards[idx].buf.write() {
+                Ok(buf) => buf,
+                Err(_) => return Err(BufErr::CantGrabMutex),
+            };
+            let seg = if mutable {
+                buf.get_mut(key)?.cloned()
+            } else {
+                buf.get(key)?.cloned()
+            };
+            self.refresh(idx, &buf);
+            seg
+        };
+        self.fetched = seg;
+        Ok(self.fetched.as_ref())
+    }
+
+    /* Evicts from the shard until the pool is within the budget again,
+     * the last segment of the shard stays even if it alone exceeds it.
+     * Must be called under the write lock of the shard.
+     */
+    fn enforce_budget(&self, idx: usize, buf: &mut B, budget: usize) -> Result<(), BufErr> {
+        let others = self.bytes().saturating_sub(self.shards[idx].bytes.load(Ordering::Acquire));
+        while others + buf.get_buffer_size() > budget && buf.len() > 1 {
+            if !buf.evict_one()? {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+/* The arm most shards recommend, ties go to the lower arm */
+fn majority<I: Iterator<Item=usize>>(arms: I) -> usize {
+    let mut votes = BTreeMap::new();
+    for arm in arms {
+        *votes.entry(arm).or_insert(0) += 1;
+    }
+    votes.into_iter().max_by_key(|&(arm, count)| (count, Reverse(arm))).map_or(0, |(arm, _)| arm)
+}
+
+impl<T, B> SegmentBuffer<T> for ShardedBuffer<T, B>
+    where T: Copy + Send + Sync + 'static,
+          B: SegmentBuffer<T> + Send + Sync + 'static,
+{
+    /* The segment is copied out of its shard so the shard lock is not held by the caller */
+    fn get(&mut self, key: SegmentKey) -> Result<Option<&Segment<T>>, BufErr> {
+        self.fetch(key, false)
+    }
+
+    fn get_mut(&mut self, key: SegmentKey) -> Result<Option<&Segment<T>>, BufErr> {
+        self.fetch(key, true)
+    }
+
+    fn put(&mut self, seg: Segment<T>) -> Result<(), BufErr> {
+        let idx = self.shard_of(seg.get_signal());
+        let mut buf = match self.shards[idx].buf.write() {
+            Ok(buf) => buf,
+            Err(_) => return Err(BufErr::CantGrabMutex),
+        };
+        let mut res = buf.put(seg);
+        if let (Ok(()), Some(budget)) = (&res, self.budget) {
+            res = self.enforce_budget(idx, &mut buf, budget);
+        }
+        self.refresh(idx, &buf);
+        res
+    }
+
+    fn drain(&mut self) -> Vec<Segment<T>> {
+        let mut segs = Vec::with_capacity(self.len());
+        for idx in 0..self.shards.len() {
+            if let Ok(mut buf) = self.shards[idx].buf.write() {
+                segs.extend(buf.drain());
+                self.refresh(idx, &buf);
+            }
+        }
+        segs
+    }
+
+    /* Every shard learns from its own segments, the pool goes with the majority */
+    fn get_recommend(&self) -> (usize,usize,usize) {
+        let recommends: Vec<(usize,usize,usize)> = self.shards.iter()
+            .filter_map(|shard| shard.buf.read().ok().map(|buf| buf.get_recommend()))
+            .collect();
+        (majority(recommends.iter().map(|r| r.0)),
+         majority(recommends.iter().map(|r| r.1)),
+         majority(recommends.iter().map(|r| r.2)))
+    }
+
+    fn copy(&self) -> Vec<Segment<T>> {
+        let mut segs = Vec::with_capacity(self.len());
+        for shard in self.shards.iter() {
+            if let Ok(buf) = shard.buf.read() {
+                segs.extend(buf.copy());
+            }
+        }
+        segs
+    }
+
+    fn persist(&self) -> Result<(), BufErr> {
+        for shard in self.shards.iter() {
+            match shard.buf.read() {
+                Ok(buf) => buf.persist()?,
+                Err(_) => return Err(BufErr::CantGrabMutex),
+            }
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) {
+        for idx in 0..self.shards.len() {
+            if let Ok(mut buf) = self.shards[idx].buf.write() {
+                buf.flush();
+                self.refresh(idx, &buf);
+            }
+        }
+        self.fetched = None;
+        self.done.store(true, Ordering::Release);
+    }
+
+    fn exceed_threshold(&self, threshold: f32) -> bool {
+        match self.budget {
+            Some(budget) => (self.bytes() as f32 / budget as f32) >= threshold,
+            None => self.shards.iter().any(|shard| match shard.buf.read() {
+                Ok(buf) => buf.exceed_threshold(threshold),
+                Err(_) => false,
+            }),
+        }
+    }
+
+    fn exceed_batch(&self, batchsize: usize) -> bool {
+        self.len() >= batchsize
+    }
+
+    fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.len.load(Ordering::Acquire)).sum()
+    }
+
+    /* Takes the segments from the shards in turn, so no shard is starved */
+    fn remove_segment(&mut self) -> Result<Segment<T>, BufErr> {
+        let count = self.shards.len();
+        for i in 0..count {
+            let idx = (self.cursor + i) % count;
+            if self.shards[idx].len.load(Ordering::Acquire) == 0 {
+                continue;
+            }
+            let mut buf = match self.shards[idx].buf.write() {
+                Ok(buf) => buf,
+                Err(_) => return Err(BufErr::CantGrabMutex),
+            };
+            let res = buf.remove_segment();
+            self.refresh(idx, &buf);
+            if let Ok(seg) = res {
+                self.cursor = (idx + 1) % count;
+                return Ok(seg);
+            }
+        }
+        Err(BufErr::BufEmpty)
+    }
+
+    fn idle_threshold(&self, threshold: f32) -> bool {
+        match self.budget {
+            Some(budget) => (self.bytes() as f32 / budget as f32) < threshold,
+            None => self.shards.iter().all(|shard| match shard.buf.read() {
+                Ok(buf) => buf.idle_threshold(threshold),
+                Err(_) => true,
+            }),
+        }
+    }
+
+    fn get_buffer_size(&self) -> usize {
+        match self.budget {
+            Some(_) => self.bytes(),
+            None => self.shards.iter().map(|shard| match shard.buf.read() {
+                Ok(buf) => buf.get_buffer_size(),
+                Err(_) => 0,
+            }).sum(),
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.done.load(Ordering::Acquire)
+    }
+
+    fn range_keys(&self, signal: SignalId, start: SystemTime, end: SystemTime) -> Result<Vec<SegmentKey>, BufErr> {
+        match self.shards[self.shard_of(signal)].buf.read() {
+            Ok(buf) => buf.range_keys(signal, start, end),
+            Err(_) => Err(BufErr::CantGrabMutex),
+        }
+    }
+
+    fn run_query(&self) {
+        for shard in self.shards.iter() {
+            if let Ok(buf) = shard.buf.read() {
+                buf.run_query();
+            }
+        }
+    }
+
+    fn handle(&self) -> Option<Arc<Mutex<SegmentBuffer<T> + Send + Sync>>> {
+        Some(Arc::new(Mutex::new(self.share())))
+    }
+
+    fn get_cache_stats(&self) -> CacheStats {
+        self.shards.iter().fold(CacheStats::default(), |stats, shard| match shard.buf.read() {
+            Ok(buf) => stats.merge(&buf.get_cache_stats()),
+            Err(_) => stats,
+        })
+    }
+    fn is_persisted(&self, key: SegmentKey) -> bool {
+        match self.shards[self.shard_of(key.get_signal())].buf.read() {
+            Ok(buf) => buf.is_persisted(key),
+            Err(_) => false,
+        }
+    }
+
+    fn put_columnar(&mut self, key: SegmentKey, bytes: Vec<u8>) -> Result<(), BufErr> {
+        match self.shards[self.shard_of(key.get_signal())].buf.write() {
+            Ok(mut buf) => buf.put_columnar(key, bytes),
+            Err(_) => Err(BufErr::CantGrabMutex),
+        }
+    }
+
+    fn expire(&mut self, signal: SignalId, before: SystemTime) -> Result<Expired, BufErr> {
+        let idx = self.shard_of(signal);
+        let mut buf = match self.shards[idx].buf.write() {
+            Ok(buf) => buf,
+            Err(_) => return Err(BufErr::CantGrabMutex),
+        };
+        let res = buf.expire(signal, before);
+        self.refresh(idx, &buf);
+        res
+    }
+}
+
+#[test]
+fn sharded_buffer_test() {
+    use std::thread;
+    use std::time::{Duration, UNIX_EPOCH};
+    use super::{NoFmClockBuffer, share_buffer};
+
+    super::check_segment_buffer(&mut ShardedBuffer::<f64,_>::with_shards(4, None, |_| NoFmClockBuffer::<f64>::new(16)), 12);
+
+    /* Every thread writes through a handle of its own */
+    let pool: Arc<Mutex<SegmentBuffer<f64> + Send + Sync>> = Arc::new(Mutex::new(
+        ShardedBuffer::<f64,_>::with_shards(4, Some(1 << 20), |_| NoFmClockBuffer::<f64>::new(64))));
+    let threads: Vec<_> = (0..8u64).map(|signal| {
+        let buf = share_buffer(&pool);
+        thread::spawn(move || {
+            for i in 0..10u64 {
+                let seg = Segment::new(None, UNIX_EPOCH + Duration::from_secs(i), signal, vec![i as f64; 100], None, None);
+                buf.lock().unwrap().put(seg).expect("Failed to put segment");
+            }
+        })
+    }).collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+
+    let mut buf = pool.lock().unwrap();
+    assert_eq!(buf.copy().len(), 80);
+    assert!(buf.exceed_batch(80) && !buf.exceed_batch(81));
+    assert!(buf.exceed_threshold(0.0) && !buf.exceed_threshold(0.5));
+    assert_eq!(buf.range_keys(3, UNIX_EPOCH, UNIX_EPOCH + Duration::from_secs(10)).unwrap().len(), 10);
+    for _ in 0..80 {
+        buf.remove_segment().expect("Failed to remove segment");
+    }
+    assert!(buf.remove_segment().is_err() && buf.get_buffer_size() == 0);
+}
+
+#[test]
+fn sharded_buffer_budget_test() {
+    use std::time::{Duration, UNIX_EPOCH};
+    use super::NoFmClockBuffer;
+
+    assert_eq!(shard_size(150, 8), 19);
+    assert_eq!(shard_size(3, 8), 1);
+    assert_eq!(majority(vec![2, 1, 2, 0].into_iter()), 2);
+    assert_eq!(majority(vec![1, 0].into_iter()), 0);
+
+    let segs: Vec<Segment<f64>> = (0..10u64)
+        .map(|i| Segment::new(None, UNIX_EPOCH + Duration::from_secs(i), i % 2, vec![i as f64; 100], None, None))
+        .collect();
+    let seg_bytes = segs[0].get_byte_size().unwrap();
+
+    /* The shards could hold every segment, the budget only allows four */
+    let mut buf = ShardedBuffer::<f64,_>::with_shards(2, Some(4 * seg_bytes), |_| NoFmClockBuffer::<f64>::new(16));
+    for seg in segs.iter() {
+        buf.put(seg.clone()).expect("Failed to put segment");
+        assert!(buf.get_buffer_size() <= 4 * seg_bytes);
+    }
+    assert_eq!(buf.len(), 4);
+    assert_eq!(buf.get_cache_stats().evictions, 6);
+
+    /* The newest segments are held and answered under the read lock */
+    let last = segs[9].get_key();
+    assert_eq!(buf.get(last).expect("Failed to get segment"), Some(&segs[9]));
+    assert_eq!(buf.get_cache_stats().hits, 1);
+    assert_eq!(buf.get_recommend(), (0,0,0));
+}
+
+#[test]
+fn sharded_buffer_policy_test() {
+    use std::sync::Arc;
+    use crate::file_handler::LogFileManager;
+    use super::scan_resistant::{ArcBuffer, TwoQueueBuffer, check_scan_resistance};
+
+    /* Hits reach the inner policy, so a sharded ARC or 2Q pool keeps its scan resistance */
+    let path = "../sharded_policy_test";
+    let _ = LogFileManager::destroy(path);
+    let fm = Arc::new(LogFileManager::open(path).expect("Failed to open log file manager"));
+    let mut buf = ShardedBuffer::<f64,_>::with_shards(1, None, |_| ArcBuffer::<f64, Arc<LogFileManager>>::new(8, fm.clone()));
+    check_scan_resistance(&mut buf, &fm);
+    drop(buf);
+    drop(fm);
+    LogFileManager::destroy(path).expect("Failed to destroy log file manager");
+
+    let fm = Arc::new(LogFileManager::open(path).expect("Failed to open log file manager"));
+    let mut buf = ShardedBuffer::<f64,_>::with_shards(1, None, |_| TwoQueueBuffer::<f64, Arc<LogFileManager>>::new(8, fm.clone()));
+    check_scan_resistance(&mut buf, &fm);
+    drop(buf);
+    drop(fm);
+    LogFileManager::destroy(path).expect("Failed to destroy log file manager");
+}
@@ -0,0 +1,404 @@
+use std::fmt::{Debug, Display};
+use std::fs;
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use num::{FromPrimitive, Num, Signed};
+use rl_bandit::bandit::{Bandit, UpdateType};
+use rl_bandit::bandits::egreedy::EGreedy;
+use rustfft::FFTnum;
+use smartcore::api::PredictorVec;
+use smartcore::cluster::kmeans::KMeans;
+use smartcore::ensemble::random_forest_classifier::RandomForestClassifier;
+use smartcore::math::distance::euclidian::Euclidian;
+use smartcore::math::num::RealNumber;
+use smartcore::neighbors::knn_classifier::KNNClassifier;
+use smartcore::tree::decision_tree_classifier::DecisionTreeClassifier;
+
+use log::{info, warn};
+use crate::segment::{Segment, SegmentKey};
+use crate::methods::{IsLossless, Methods};
+use super::{AggStats, Get_AggStatsFromVec, Get_Decomp, Err_Eval};
+
+/*
+ * Overview:
+ * The workload aware feedback a compressed buffer uses to pick how
+ * segments are recoded. An evaluator sees every segment that enters the
+ * buffer, learns the reference answers of the workload from the lossless
+ * ones and scores the recoded ones against them.
+ *
+ * Design Choice:
+ * The buffer only holds an optional evaluator and asks it for a
+ * recommendation, so it runs without any model on disk. The bandits
+ * choosing the recoding arms live with the evaluator since they learn
+ * from its rewards. The arms of each bandit are Bufflossy (or RRD below
+ * a ratio of 0.125), PAA, Fourier and PLA.
+ *
+ * Current Implementations:
+ * ModelEvaluator scores against the labels of a smartcore predictor,
+ * the max or sum of the segment, the recoding speed, or a mix of them.
+ */
+
+/* Where the benchmark models trained by lossyML are kept */
+pub const DEFAULT_MODEL_DIR: &str = "../lossyML/model";
+
+/* The evaluator of an LRU buffer for a benchmark task, loaded from
+ * model_dir or DEFAULT_MODEL_DIR when none is configured. A model that
+ * can not be loaded leaves the buffer without an evaluator instead of
+ * failing the run.
+ */
+pub fn evaluator_for_task<'a, T>(model_dir: Option<&str>, task: &str) -> Option<Box<dyn WorkloadEvaluator<T> + 'a>>
+    where T: Copy + Send + Serialize + DeserializeOwned + Debug + FFTnum + Num + FromPrimitive + PartialOrd + Into<f64> + Signed + Display + RealNumber + 'a,
+{
+    let dir = model_dir.unwrap_or(DEFAULT_MODEL_DIR);
+    match ModelEvaluator::from_task(dir, task) {
+        Ok(evaluator) => Some(Box::new(evaluator)),
+        Err(e) => {
+            warn!("The LRU buffer runs without a workload model, loading {} from {} failed: {:?}", task, dir, e);
+            None
+        }
+    }
+}
+
+pub trait WorkloadEvaluator<T: Copy + Send>: Send + Sync {
+    /* Learns from a segment entering the buffer, lossless segments give
+     * the reference answers and recoded segments are scored against them
+     */
+    fn observe(&mut self, key: SegmentKey, seg: &Segment<T>);
+
+    /* The arm to recode with for ratios of at least 0.25, at least 0.125 and below */
+    fn recommend(&self) -> (usize,usize,usize) {
+        (0,0,0)
+    }
+
+    /* Reports how well the workload is answered given the number of buffered segments */
+    fn evaluate(&self, _buffered: usize) {
+    }
+}
+
+#[derive(Debug)]
+pub enum ModelErr {
+    UnknownTask(String),
+    ReadFail(String),
+    ParseFail(String),
+}
+
+/* The share of the original size a lossy method keeps */
+fn method_ratio(m: &Methods) -> f64 {
+    match m {
+        Methods::Bufflossy (_, bits) => (*bits) as f64 / 64.0,
+        Methods::Rrd_sample => 1.0 / 10000.0,
+        Methods::Paa (wsize) => 1.0 / (*wsize) as f64,
+        Methods::Fourier (ratio) => *ratio,
+        Methods::Pla (ratio) => *ratio,
+        _ => panic!("lossy compression is not supported by LRU query"),
+    }
+}
+
+/* Reward for an aggregation answered with the relative error err */
+fn agg_reward(err: f64) -> f64 {
+    if err == 0.0 {
+        20.0
+    } else {
+        -err.log10()
+    }
+}
+
+pub struct ModelEvaluator<'a, T>
+    where T: Copy + Send + RealNumber,
+{
+    predictor: Box<PredictorVec<T>+'a>,
+    /* 0 means ML task, 1 for max, 2 for sum, 3 for max_ML, 4 for sum_ML, 5 for speed_ML */
+    task: usize,
+    agg_stats: BTreeMap<SegmentKey, AggStats<T>>,
+    est_agg_stats: BTreeMap<SegmentKey, AggStats<T>>,
+    comp_runtime: BTreeMap<SegmentKey, f64>,
+    rlabel: BTreeMap<SegmentKey, Vec<T>>,
+    plabel: BTreeMap<SegmentKey, Vec<T>>,
+    mab_250: EGreedy,
+    mab_125: EGreedy,
+    mab_000: EGreedy
+}
+
+impl<'a, T> ModelEvaluator<'a, T>
+    where T: Copy + Send + Serialize + DeserializeOwned + Debug + FFTnum + Num + FromPrimitive + PartialOrd + Into<f64> + Signed + Display + RealNumber,
+{
+    /* Builds the evaluator around a predictor that is already in memory */
+    pub fn new(model: Box<PredictorVec<T>+'a>, task: usize) -> ModelEvaluator<'a, T> {
+        ModelEvaluator::build(model, task, task >= 3)
+    }
+
+    fn build(model: Box<PredictorVec<T>+'a>, task: usize, mixed: bool) -> ModelEvaluator<'a, T> {
+        /* Mixed tasks have larger rewards and explore less */
+        let (mab_250, mab_125, mab_000) = if mixed {
+            (EGreedy::new(4, 0.005, 40.0, UpdateType::Average),
+             EGreedy::new(4, 0.005, 40.0, UpdateType::Average),
+             EGreedy::new(4, 0.001, 40.0, UpdateType::Average))
+        } else {
+            (EGreedy::new(4, 0.01, 2.0, UpdateType::Average),
+             EGreedy::new(4, 0.01, 2.0, UpdateType::Average),
+             EGreedy::new(4, 0.01, 2.0, UpdateType::Average))
+        };
+        ModelEvaluator {
+            predictor: model,
+            task: task,
+            agg_stats: BTreeMap::new(),
+            est_agg_stats: BTreeMap::new(),
+            comp_runtime: BTreeMap::new(),
+            rlabel: BTreeMap::new(),
+            plabel: BTreeMap::new(),
+            mab_250: mab_250,
+            mab_125: mab_125,
+            mab_000: mab_000
+        }
+    }
+
+    /* Loads the model a benchmark task names from model_dir. A task is a
+     * model (kmeans, dtree, knn, rforest), an aggregation (max, sum) run
+     * with the kmeans model as a dummy, or an aggregation or speed and a
+     * model joined by '_' such as max_knn
+     */
+    pub fn from_task(model_dir: &str, task: &str) -> Result<ModelEvaluator<'a, T>, ModelErr> {
+        let task_vec = task.split('_').collect::<Vec<&str>>();
+        let (model_name, taskid) = match task_vec.as_slice() {
+            ["max"] => ("kmeans", 1),
+            ["sum"] => ("kmeans", 2),
+            [model] => (*model, 0),
+            ["max", model] => (*model, 3),
+            ["sum", model] => (*model, 4),
+            ["speed", model] => (*model, 5),
+            [_, model] => (*model, 0),
+            _ => return Err(ModelErr::UnknownTask(task.to_string())),
+        };
+        let model = ModelEvaluator::load_predictor(model_dir, model_name)?;
+        info!("task: {}, task id: {}", task, taskid);
+        Ok(ModelEvaluator::build(model, taskid, task_vec.len() > 1))
+    }
+
+    fn load_predictor(model_dir: &str, name: &str) -> Result<Box<PredictorVec<T>+'a>, ModelErr> {
+        fn parse<M: DeserializeOwned>(content: &str) -> Result<M, ModelErr> {
+            serde_json::from_str(content).map_err(|e| ModelErr::ParseFail(e.to_string()))
+        }
+
+        match name {
+            "kmeans" | "dtree" | "knn" | "rforest" => (),
+            _ => return Err(ModelErr::UnknownTask(name.to_string())),
+        }
+        let path = format!("{}/cbf_{}.model", model_dir, name);
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) => return Err(ModelErr::ReadFail(format!("{}: {}", path, e))),
+        };
+        let model: Box<PredictorVec<T>> = match name {
+            "kmeans" => Box::new(parse::<KMeans<T>>(&content)?),
+            "dtree" => Box::new(parse::<DecisionTreeClassifier<T>>(&content)?),
+            "knn" => Box::new(parse::<KNNClassifier<T, Euclidian>>(&content)?),
+            _ => Box::new(parse::<RandomForestClassifier<T>>(&content)?),
+        };
+        Ok(model)
+    }
+
+    fn update_mab(&mut self, m: &Methods, reward: f64) {
+        let arm = match m {
+            Methods::Bufflossy (_,_) | Methods::Rrd_sample => 0,
+            Methods::Paa (_) => 1,
+            Methods::Fourier (_) => 2,
+            Methods::Pla (_) => 3,
+            _ => panic!("lossy compression is not supported by LRU query"),
+        };
+        let ratio = method_ratio(m);
+        match m {
+            /* Only 1 sample is kept, so there is no value for mab_125 and mab_250 */
+            Methods::Rrd_sample => self.mab_000.update(arm, reward),
+            _ if ratio >= 0.25 => self.mab_250.update(arm, reward),
+            _ if ratio >= 0.125 => self.mab_125.update(arm, reward),
+            /* Bufflossy only covers bits >= 8, rrd takes over below */
+            Methods::Bufflossy (_,_) => (),
+            _ => self.mab_000.update(arm, reward),
+        }
+    }
+}
+
+impl<'a, T> WorkloadEvaluator<T> for ModelEvaluator<'a, T>
+    where T: Copy + Send + Serialize + DeserializeOwned + Debug + FFTnum + Num + FromPrimitive + PartialOrd + Into<f64> + Signed + Display + RealNumber,
+{
+    fn observe(&mut self, key: SegmentKey, seg: &Segment<T>) {
+        // update aggstats and ML labels for query accuracy profiling
+        let entry_size = seg.get_size();
+        let comp_runtime = seg.get_comp_runtime();
+        self.comp_runtime.insert(key, comp_runtime);
+        /* Segments the registry can not decode are left out of the profile */
+        let vec = match Get_Decomp(seg) {
+            Some(vec) => vec,
+            None => return,
+        };
+        let method = match seg.get_method() {
+            Some(m) if !IsLossless(m) => m,
+            _ => {
+                if !self.rlabel.contains_key(&key) {
+                    let label = self.predictor.predictVec(&vec,entry_size/1000);
+                    self.rlabel.insert(key, label.clone());
+                    self.plabel.insert(key, label);
+                }
+                let stats = Get_AggStatsFromVec(&vec);
+                self.agg_stats.insert(key,stats.clone());
+                self.est_agg_stats.insert(key, stats);
+                return;
+            }
+        };
+
+        /* A recoded segment can only be scored against answers taken before it lost precision */
+        let (rlabel, agg) = match (self.rlabel.get(&key), self.agg_stats.get(&key)) {
+            (Some(rlabel), Some(agg)) => (rlabel, agg.clone()),
+            _ => return,
+        };
+        let label = self.predictor.predictVec(&vec,entry_size/1000);
+        let acc = 1.0 - Err_Eval(&label, rlabel) as f64/ label.len() as f64;
+        self.plabel.insert(key, label);
+        let estaggstats = Get_AggStatsFromVec(&vec);
+        let (max, estmax): (f64, f64) = (agg.max.into(), estaggstats.max.into());
+        let (sum, estsum): (f64, f64) = (agg.sum.into(), estaggstats.sum.into());
+        let max_err = num::abs((max - estmax) / max);
+        let sum_err = num::abs((sum - estsum) / sum);
+        self.est_agg_stats.insert(key, estaggstats);
+
+        let reward = match self.task {
+            0 => acc/(1.0+method_ratio(method)),
+            1 | 2 => agg_reward(if self.task == 1 { max_err } else { sum_err }),
+            3 | 4 => {
+                let reward = 10.0*acc + 0.3*agg_reward(if self.task == 3 { max_err } else { sum_err });
+                self.comp_runtime.insert(key, reward);
+                reward
+            }
+            _ => {
+                let reward = 1.0*acc + 1.1*comp_runtime/200000.0;
+                self.comp_runtime.insert(key, reward);
+                reward
+            }
+        };
+        self.update_mab(method, reward);
+    }
+
+    fn recommend(&self) -> (usize,usize,usize) {
+        return (self.mab_250.choose(),self.mab_125.choose(),self.mab_000.choose());
+    }
+
+    fn evaluate(&self, buffered: usize) {
+        let n =10;
+        let vector_per_seg = 10;
+        let labels_of_N = vector_per_seg *n;
+        let mut firstNerr = 0;
+        let mut lastNerr = 0;
+        let mut totalErr = 0;
+        let mut maxErr = 0.0;
+        let mut sumErr = 0.0;
+        let nSeg = buffered;
+        let mut comp_runtime = 0.0;
+        let one = AggStats::new(T::one(), T::one(), T::one(), 0);
+        let mut estlatestn = one.clone();
+        let mut estuntilnow = one.clone();
+        let mut latestn = one.clone();
+        let mut untilnow = one.clone();
+        let mut earliestn = one.clone();
+        let mut estearliestn = one;
+
+        let mut cnt: usize = 0;
+        for (_, v) in self.agg_stats.iter().rev() {
+            if cnt == 0 {
+                latestn = v.clone();
+                untilnow = v.clone();
+            } else if cnt < n {
+                latestn = latestn.merge(v);
+                untilnow = untilnow.merge(v);
+            } else {
+                untilnow = untilnow.merge(v);
+            }
+            cnt += 1;
+        }
+
+        cnt = 0;
+        let mut speedcnt =0;
+        for (k, agg) in self.est_agg_stats.iter().rev() {
+            if cnt<nSeg.saturating_sub(n){
+                let real_agg = self.agg_stats.get(k).unwrap();
+                let runtime = self.comp_runtime.get(k).unwrap();
+                maxErr += num::abs(agg.max.into()-real_agg.max.into())/num::abs(real_agg.max.into());
+                sumErr += num::abs(agg.sum.into()-real_agg.sum.into())/num::abs(real_agg.sum.into());
+                if *runtime!=0.0{
+                    comp_runtime += runtime;
+                    speedcnt += 1;
+                }
+            }
+            let cur_err = Err_Eval(self.plabel.get(k).unwrap(), self.rlabel.get(k).unwrap());
+            if cnt<n {
+                lastNerr += cur_err;
+            }
+            totalErr += cur_err;
+
+            if cnt == 0 {
+                estlatestn = agg.clone();
+                estuntilnow = agg.clone();
+            } else if cnt < n {
+                estlatestn = estlatestn.merge(agg);
+                estuntilnow = estuntilnow.merge(agg);
+            } else {
+                estuntilnow = estuntilnow.merge(agg);
+            }
+            cnt += 1;
+        }
+        maxErr = maxErr/nSeg.saturating_sub(n) as f64;
+        sumErr = sumErr/nSeg.saturating_sub(n) as f64;
+
+        if speedcnt==0{
+            comp_runtime = 0.0;
+        }
+        else {
+            comp_runtime = comp_runtime/speedcnt as f64;
+        }
+
+        // calculate the earliest N
+        for (cnt, v) in self.agg_stats.values().take(n).enumerate() {
+            if cnt == 0 {
+                earliestn = v.clone();
+            } else {
+                earliestn = earliestn.merge(v);
+            }
+        }
+
+        for (cnt, (k, agg)) in self.est_agg_stats.iter().take(n).enumerate() {
+            firstNerr += Err_Eval(self.plabel.get(k).unwrap(), self.rlabel.get(k).unwrap());
+            if cnt == 0 {
+                estearliestn = agg.clone();
+            } else {
+                estearliestn = estearliestn.merge(agg);
+            }
+        }
+        let total_label = vector_per_seg*nSeg;
+
+        println!("Aggregation stats (earlies-latest-untilnow): {},{},{},{},{},{},{},{},{},{},{},{},{},{:.4},{:.4},{:.4}", SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_micros() as f64/1000000.0,
+                 num::abs((earliestn.max - estearliestn.max) / earliestn.max), num::abs((earliestn.min - estearliestn.min) / earliestn.min),
+                 num::abs((earliestn.sum - estearliestn.sum) / earliestn.sum),
+                 num::abs((latestn.max - estlatestn.max) / latestn.max), num::abs((latestn.min - estlatestn.min) / latestn.min),
+                 num::abs((latestn.sum - estlatestn.sum) / latestn.sum), num::abs((untilnow.max - estuntilnow.max) / untilnow.max),
+                 num::abs((untilnow.min - estuntilnow.min) / untilnow.min), num::abs((untilnow.sum - estuntilnow.sum) / untilnow.sum),maxErr,sumErr,comp_runtime,
+                 firstNerr as f64 /labels_of_N as f64, lastNerr as f64 /labels_of_N as f64, totalErr as f64 / total_label as f64
+        )
+    }
+}
+
+#[test]
+fn test_model_evaluator_from_task() {
+    match ModelEvaluator::<f64>::from_task("../no_such_model_dir", "kmeans") {
+        Err(ModelErr::ReadFail(_)) => (),
+        Err(e) => panic!("Expected a read failure, got {:?}", e),
+        Ok(_) => panic!("Loaded a model that does not exist"),
+    }
+    match ModelEvaluator::<f64>::from_task("../no_such_model_dir", "svm") {
+        Err(ModelErr::UnknownTask(name)) => assert_eq!(name, "svm"),
+        Err(e) => panic!("Expected an unknown task, got {:?}", e),
+        Ok(_) => panic!("Loaded a model that does not exist"),
+    }
+    /* A buffer whose model is missing runs without one */
+    assert!(evaluator_for_task::<f64>(Some("../no_such_model_dir"), "kmeans").is_none());
+}
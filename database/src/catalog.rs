@@ -0,0 +1,667 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self,File};
+use std::io::{Read,Write};
+use std::path::{Path,PathBuf};
+use std::sync::{Arc,Mutex};
+use std::time::SystemTime;
+use num::{FromPrimitive,Num};
+use rustfft::FFTnum;
+use serde::{Serialize,Deserialize};
+
+use crate::bincode;
+use crate::buffer_pool::ExpiredSegment;
+use crate::file_handler::{Error,FileManager,crc32,read_u32,sync_dir};
+use crate::future_signal::SignalId;
+use crate::methods::Methods;
+use crate::methods::compress::CompressionMethod;
+use crate::methods::registry::{MethodCodec,codec_for,codec_with_batch,method_name};
+use crate::segment::{Segment,SegmentKey,KEY_TAG_SEGMENT,migrate_legacy_keys};
+use crate::segment::header::{peek_header,FormatError,HEADER_PEEK_LEN};
+
+/*
+ * Overview:
+ * What the store holds of every signal: the timestamps of its oldest
+ * and newest segment, how many segments and points it has and which
+ * codecs they are encoded with. Nothing kept this across a restart, so
+ * the signals started a new implicit linked list and SegmentIter had
+ * no way to find where the old one ended.
+ *
+ * Design Choice:
+ * The catalog is rebuilt by a recovery pass when a run opens its file
+ * managers, before the buffers take them. The pass only reads the keys
+ * and the first HEADER_PEEK_LEN bytes of every segment, no payload is
+ * read, so it does not need to know the element type of the signals and
+ * a damaged payload is only found when the segment is loaded. Segments
+ * written before the header existed are counted as unframed with no
+ * points. A segment found in more than one file manager, raw in one and
+ * compressed in the other, is cataloged once as found in the file
+ * manager scanned last.
+ * Signals record every segment they seal while the run goes on, and a
+ * signal that is already cataloged links its first segment to the newest
+ * one in the catalog, so its chain continues where it left off.
+ *
+ * Signals can also be registered with a SignalMeta, a unique name, tags
+ * such as host, region or unit, the type of their values and the codec
+ * they should be compressed with. The type is kept as the element type
+ * id of the segment header, which does not change with the compiler.
+ * The compression daemons read the codec through a CatalogCodec, the
+ * MAB daemons explore their own arms and leave it unused.
+ * Unlike the segments the metadata can not be read back from the
+ * store, so it is kept in a file of its own,
+ *   [crc32 of the rest: u32 LE][bincode of every SignalMeta by id]
+ * rewritten through a temporary file on every registration. A name
+ * keeps the id it was first registered with, so clients that give one
+ * find their segments again after a restart, and queries can address
+ * signals through a SignalSelector instead of their id.
+ */
+
+/* Codec mix entries of the segments that are not encoded by a method */
+pub const CODEC_RAW: &str = "raw";
+pub const CODEC_UNFRAMED: &str = "unframed";
+pub const CODEC_CORRUPT: &str = "corrupt";
+
+/* The codec mix entry of a segment with the given method */
+pub fn codec_of(method: &Option<Methods>) -> &'static str {
+	match method {
+		Some(m) => method_name(m),
+		None => CODEC_RAW,
+	}
+}
+
+/* Reads the codec and element count from the first bytes of a stored segment */
+fn describe(bytes: &[u8]) -> (&'static str, u64) {
+	match peek_header(bytes) {
+		Ok(header) => (codec_of(&header.method), header.count),
+		Err(FormatError::UnsupportedVersion(0)) => (CODEC_UNFRAMED, 0),
+		Err(_) => (CODEC_CORRUPT, 0),
+	}
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct SignalEntry {
+	signal: SignalId,
+	first: SystemTime,
+	last: SystemTime,
+	segments: usize,
+	points: u64,
+	codecs: BTreeMap<&'static str,usize>,
+}
+
+impl SignalEntry {
+	fn new(key: SegmentKey) -> SignalEntry {
+		SignalEntry {
+			signal: key.get_signal(),
+			first: key.get_timestamp(),
+			last: key.get_timestamp(),
+			segments: 0,
+			points: 0,
+			codecs: BTreeMap::new(),
+		}
+	}
+
+	fn add(&mut self, key: SegmentKey, codec: &'static str, points: u64) {
+		let timestamp = key.get_timestamp();
+		if timestamp < self.first {
+			self.first = timestamp;
+		}
+		if timestamp > self.last {
+			self.last = timestamp;
+		}
+		self.segments += 1;
+		self.points += points;
+		*self.codecs.entry(codec).or_insert(0) += 1;
+	}
+
+	/* Drops an expired segment, the entry keeps no keys so the oldest
+	 * timestamp only moves up to the last point of the expired segment,
+	 * where the segment after it starts at the earliest
+	 */
+	fn remove(&mut self, seg: &ExpiredSegment) {
+		self.first = self.first.max(seg.end).min(self.last);
+		self.segments = self.segments.saturating_sub(1);
+		self.points = self.points.saturating_sub(seg.points as u64);
+		let codec = codec_of(&seg.method);
+		if let Some(count) = self.codecs.get_mut(codec) {
+			*count -= 1;
+			if *count == 0 {
+				self.codecs.remove(codec);
+			}
+		}
+	}
+
+	pub fn get_signal(&self) -> SignalId {
+		self.signal
+	}
+
+	/* The timestamp of the oldest segment */
+	pub fn get_first(&self) -> SystemTime {
+		self.first
+	}
+
+	/* The timestamp of the newest segment */
+	pub fn get_last(&self) -> SystemTime {
+		self.last
+	}
+
+	/* The key of the newest segment, where the implicit linked list ends */
+	pub fn get_last_key(&self) -> SegmentKey {
+		SegmentKey::new(self.last, self.signal)
+	}
+
+	pub fn get_segments(&self) -> usize {
+		self.segments
+	}
+
+	pub fn get_points(&self) -> u64 {
+		self.points
+	}
+
+	/* The number of segments per codec */
+	pub fn get_codecs(&self) -> &BTreeMap<&'static str,usize> {
+		&self.codecs
+	}
+}
+
+impl fmt::Display for SignalEntry {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "Signal {}: {} segments with {} points from {:?} to {:?}, codecs {:?}",
+			self.signal, self.segments, self.points, self.first, self.last, self.codecs)
+	}
+}
+
+#[derive(Clone,Debug,Serialize,Deserialize,PartialEq)]
+pub struct SignalMeta {
+	name: String,
+	tags: BTreeMap<String,String>,
+	value_type: u8,
+	codec: Option<Methods>,
+}
+
+impl SignalMeta {
+	/* The value type is the element type id the segment header uses, see element_type_id */
+	pub fn new(name: &str, value_type: u8) -> SignalMeta {
+		SignalMeta {
+			name: name.to_string(),
+			tags: BTreeMap::new(),
+			value_type: value_type,
+			codec: None,
+		}
+	}
+
+	pub fn with_tag(mut self, key: &str, value: &str) -> SignalMeta {
+		self.tags.insert(key.to_string(), value.to_string());
+		self
+	}
+
+	/* The codec, with its parameters, the signal should be compressed with */
+	pub fn with_codec(mut self, codec: Methods) -> SignalMeta {
+		self.codec = Some(codec);
+		self
+	}
+
+	pub fn get_name(&self) -> &str {
+		&self.name
+	}
+
+	pub fn get_tags(&self) -> &BTreeMap<String,String> {
+		&self.tags
+	}
+
+	pub fn get_tag(&self, key: &str) -> Option<&str> {
+		self.tags.get(key).map(|v| v.as_str())
+	}
+
+	pub fn get_value_type(&self) -> u8 {
+		self.value_type
+	}
+
+	pub fn get_codec(&self) -> &Option<Methods> {
+		&self.codec
+	}
+}
+
+/* Addresses signals in the catalog:
+ *   an id                  "42"
+ *   a name                 "boiler_temp"
+ *   tags that must match   "host=h1,unit=celsius"
+ */
+#[derive(Clone,Debug,PartialEq)]
+pub enum SignalSelector {
+	Id(SignalId),
+	Name(String),
+	Tags(Vec<(String,String)>),
+}
+
+impl SignalSelector {
+	/* Anything with a '=' is read as tags, anything that is a number as an id */
+	pub fn parse(selector: &str) -> SignalSelector {
+		let selector = selector.trim();
+		if selector.contains('=') {
+			SignalSelector::Tags(selector.split(',')
+				.filter(|pair| !pair.trim().is_empty())
+				.map(|pair| {
+					let mut kv = pair.splitn(2, '=');
+					let key = kv.next().unwrap_or("").trim().to_string();
+					let value = kv.next().unwrap_or("").trim().to_string();
+					(key, value)
+				})
+				.collect())
+		} else {
+			match selector.parse::<SignalId>() {
+				Ok(id) => SignalSelector::Id(id),
+				Err(_) => SignalSelector::Name(selector.to_string()),
+			}
+		}
+	}
+}
+
+#[derive(Debug)]
+pub enum CatalogError {
+	Io(Error),
+	/* The id is registered under another name */
+	IdTaken(SignalId, String),
+	/* The meta file is too short, fails its checksum or does not decode */
+	CorruptMeta(PathBuf),
+	/* The metadata could not be encoded for the meta file */
+	EncodeMeta,
+}
+
+impl From<Error> for CatalogError {
+	fn from(e: Error) -> CatalogError {
+		CatalogError::Io(e)
+	}
+}
+
+#[derive(Clone,Debug,Default,PartialEq)]
+pub struct SignalCatalog {
+	entries: BTreeMap<SignalId,SignalEntry>,
+	metas: BTreeMap<SignalId,SignalMeta>,
+	meta_path: Option<PathBuf>,
+}
+
+impl SignalCatalog {
+	pub fn new() -> SignalCatalog {
+		SignalCatalog::default()
+	}
+
+	/* Scans every segment the file managers hold, the raw file manager
+	 * should come before the compressed one
+	 */
+	pub fn recover<U>(file_managers: &[&U]) -> Result<SignalCatalog,Error>
+		where U: FileManager<Vec<u8>,Vec<u8>>
+	{
+		let mut found = BTreeMap::new();
+		for fm in file_managers {
+			/* Segments under legacy keys are moved first, range scans would not find them otherwise */
+			migrate_legacy_keys(*fm)?;
+			for (key_bytes, prefix) in fm.fm_scan_prefix(vec![KEY_TAG_SEGMENT], vec![KEY_TAG_SEGMENT + 1], HEADER_PEEK_LEN)? {
+				let key = match SegmentKey::convert_from_bytes(&key_bytes) {
+					Ok(key) => key,
+					Err(_) => continue,
+				};
+				/* A header with long codec params does not fit the prefix */
+				let described = match peek_header(&prefix) {
+					Err(FormatError::Truncated) if prefix.len() == HEADER_PEEK_LEN => match fm.fm_get(key_bytes)? {
+						Some(bytes) => describe(&bytes),
+						None => continue,
+					},
+					_ => describe(&prefix),
+				};
+				found.insert(key, described);
+			}
+		}
+
+		let mut catalog = SignalCatalog::new();
+		for (key, (codec, points)) in found {
+			catalog.record(key, codec, points);
+		}
+		Ok(catalog)
+	}
+
+	/* Adds a segment, which must not be cataloged already */
+	pub fn record(&mut self, key: SegmentKey, codec: &'static str, points: u64) {
+		self.entries.entry(key.get_signal())
+			.or_insert_with(|| SignalEntry::new(key))
+			.add(key, codec, points);
+	}
+
+	/* Forgets the segments a retention rule expired,
+	 * a signal without segments left keeps only its metadata
+	 */
+	pub fn expire(&mut self, signal: SignalId, expired: &[ExpiredSegment]) {
+		let empty = match self.entries.get_mut(&signal) {
+			Some(entry) => {
+				for seg in expired.iter().filter(|seg| seg.key.get_signal() == signal) {
+					entry.remove(seg);
+				}
+				entry.segments == 0
+			}
+			None => return,
+		};
+		if empty {
+			self.entries.remove(&signal);
+		}
+	}
+
+	/* Loads the metadata kept in the file at the path, which is created
+	 * if missing, and keeps every later registration in it
+	 */
+	pub fn with_meta_file(mut self, path: &str) -> Result<SignalCatalog,CatalogError> {
+		let path = PathBuf::from(path);
+		if path.exists() {
+			let mut bytes = Vec::new();
+			File::open(&path).and_then(|mut f| f.read_to_end(&mut bytes)).map_err(Error::IoError)?;
+			if bytes.len() < 4 || read_u32(&bytes) != crc32(&bytes[4..]) {
+				return Err(CatalogError::CorruptMeta(path));
+			}
+			match bincode::deserialize(&bytes[4..]) {
+				Ok(metas) => self.metas = metas,
+				Err(_) => return Err(CatalogError::CorruptMeta(path)),
+			}
+		}
+		self.meta_path = Some(path);
+		Ok(self)
+	}
+
+	/* Registers the metadata of a signal and returns the id it goes by.
+	 * A name registered before keeps its id and gets the new metadata,
+	 * the id passed is only taken by names that are new.
+	 */
+	pub fn register(&mut self, signal: SignalId, meta: SignalMeta) -> Result<SignalId,CatalogError> {
+		let id = match self.lookup_name(meta.get_name()) {
+			Some(id) => id,
+			None => match self.metas.get(&signal) {
+				Some(other) => return Err(CatalogError::IdTaken(signal, other.get_name().to_string())),
+				None => signal,
+			}
+		};
+		self.metas.insert(id, meta);
+		self.save_meta()?;
+		Ok(id)
+	}
+
+	fn save_meta(&self) -> Result<(),CatalogError> {
+		let path = match &self.meta_path {
+			Some(path) => path,
+			None => return Ok(()),
+		};
+		let body = bincode::serialize(&self.metas).map_err(|_| CatalogError::EncodeMeta)?;
+		let mut bytes = crc32(&body).to_le_bytes().to_vec();
+		bytes.extend(body);
+
+		let tmp = path.with_extension("tmp");
+		File::create(&tmp)
+			.and_then(|mut f| f.write_all(&bytes).and_then(|_| f.sync_all()))
+			.map_err(Error::IoError)?;
+		fs::rename(&tmp, path).map_err(Error::IoError)?;
+		match path.parent() {
+			Some(dir) if dir != Path::new("") => sync_dir(dir)?,
+			_ => sync_dir(Path::new("."))?,
+		}
+		Ok(())
+	}
+
+	pub fn get_meta(&self, signal: SignalId) -> Option<&SignalMeta> {
+		self.metas.get(&signal)
+	}
+
+	pub fn lookup_name(&self, name: &str) -> Option<SignalId> {
+		self.metas.iter().find(|(_, meta)| meta.get_name() == name).map(|(id, _)| *id)
+	}
+
+	/* The ids the selector addresses, an id is returned even if nothing is known of it */
+	pub fn select(&self, selector: &SignalSelector) -> Vec<SignalId> {
+		match selector {
+			SignalSelector::Id(id) => vec![*id],
+			SignalSelector::Name(name) => self.lookup_name(name).into_iter().collect(),
+			SignalSelector::Tags(tags) => self.metas.iter()
+				.filter(|(_, meta)| tags.iter().all(|(k, v)| meta.get_tag(k) == Some(v.as_str())))
+				.map(|(id, _)| *id)
+				.collect(),
+		}
+	}
+
+	pub fn resolve(&self, selector: &str) -> Vec<SignalId> {
+		self.select(&SignalSelector::parse(selector))
+	}
+
+	pub fn get(&self, signal: SignalId) -> Option<&SignalEntry> {
+		self.entries.get(&signal)
+	}
+
+	pub fn get_last_key(&self, signal: SignalId) -> Option<SegmentKey> {
+		self.get(signal).map(|entry| entry.get_last_key())
+	}
+
+	/* Every signal with segments or metadata */
+	pub fn signals(&self) -> Vec<SignalId> {
+		let mut signals: Vec<SignalId> = self.entries.keys().chain(self.metas.keys()).cloned().collect();
+		signals.sort();
+		signals.dedup();
+		signals
+	}
+
+	pub fn len(&self) -> usize {
+		self.entries.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.entries.is_empty()
+	}
+}
+
+impl fmt::Display for SignalCatalog {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "Catalog of {} signals", self.entries.len())?;
+		for entry in self.entries.values() {
+			match self.metas.get(&entry.get_signal()) {
+				Some(meta) => write!(f, "\n\t{} ({} {:?})", entry, meta.get_name(), meta.get_tags())?,
+				None => write!(f, "\n\t{}", entry)?,
+			}
+		}
+		Ok(())
+	}
+}
+
+/* The compression method of a daemon when the run has a catalog. Segments
+ * of a signal registered with a codec are compressed with that codec, the
+ * rest with the method the run was configured with.
+ */
+pub struct CatalogCodec<F> {
+	method: F,
+	catalog: Arc<Mutex<SignalCatalog>>,
+}
+
+impl<F> CatalogCodec<F> {
+	pub fn new(method: F, catalog: Arc<Mutex<SignalCatalog>>) -> CatalogCodec<F> {
+		CatalogCodec {
+			method: method,
+			catalog: catalog,
+		}
+	}
+
+	fn codec_of<T>(&self, seg: &Segment<T>, batchsize: usize) -> Option<MethodCodec> {
+		let catalog = self.catalog.lock().expect("Failed to acquire the catalog lock");
+		catalog.get_meta(seg.get_signal())
+			.and_then(|meta| meta.get_codec().as_ref())
+			.and_then(|codec| codec_with_batch(codec, batchsize))
+	}
+}
+
+impl<'a,T,F> CompressionMethod<T> for CatalogCodec<F>
+	where T: Num + FromPrimitive + Copy + Send + FFTnum + Into<f64> + Serialize + Deserialize<'a>,
+	      F: CompressionMethod<T>
+{
+	fn get_segments(&self) {
+		self.method.get_segments()
+	}
+
+	fn get_batch(&self) -> usize {
+		self.method.get_batch()
+	}
+
+	fn run_compress<'b>(&self, segs: &mut Vec<Segment<T>>) {
+		let batchsize = self.get_batch();
+		let mut rest = Vec::with_capacity(segs.len());
+		let mut registered = Vec::new();
+		for mut seg in segs.drain(..) {
+			match self.codec_of(&seg, batchsize) {
+				Some(codec) => {
+					codec.encode(&mut seg);
+					registered.push(seg);
+				}
+				None => rest.push(seg),
+			}
+		}
+		if !rest.is_empty() {
+			self.method.run_compress(&mut rest);
+		}
+		segs.extend(registered);
+		segs.extend(rest);
+	}
+
+	fn run_single_compress(&self, seg: &mut Segment<T>) {
+		match self.codec_of(seg, self.get_batch()) {
+			Some(codec) => codec.encode(seg),
+			None => self.method.run_single_compress(seg),
+		}
+	}
+
+	/* Decodes with the method the segment was written with, the signal
+	 * may have been registered with another codec since.
+	 */
+	fn run_decompress(&self, seg: &mut Segment<T>) {
+		let codec = match seg.get_method() {
+			Some(method) => codec_for(method),
+			None => return,
+		};
+		match codec {
+			Some(codec) => codec.decompress(seg),
+			None => self.method.run_decompress(seg),
+		}
+	}
+}
+
+#[test]
+fn signal_catalog_test() {
+	use std::sync::{Arc,Mutex};
+	use std::time::{Duration,UNIX_EPOCH};
+	use crate::buffer_pool::{ClockBuffer,SegmentBuffer};
+	use crate::file_handler::LogFileManager;
+	use crate::methods::registry::codec_by_name;
+	use crate::segment::{Segment,SegmentIter};
+
+	let path = "../catalog_test";
+	let _ = LogFileManager::destroy(path);
+	let gorilla = codec_by_name("gorilla", 1).unwrap();
+	{
+		let mut buf = ClockBuffer::<f64,_>::new(16, LogFileManager::open(path).expect("Failed to open log file manager"));
+		for i in 0..6u64 {
+			/* Every segment of signal 1 links to the one a second before it */
+			let prev = if i == 0 { None } else { Some(Duration::from_secs(1)) };
+			let mut seg = Segment::new(None, UNIX_EPOCH + Duration::from_secs(10 + i), 1, vec![i as f64; 50], None, prev);
+			if i % 2 == 1 {
+				gorilla.encode(&mut seg);
+			}
+			buf.put(seg).expect("Failed to put segment");
+		}
+		buf.put(Segment::new(None, UNIX_EPOCH + Duration::from_secs(3), 2, vec![0.5; 20], None, None)).expect("Failed to put segment");
+		buf.persist().expect("Failed to persist the buffer");
+	}
+
+	let fm = LogFileManager::open(path).expect("Failed to reopen log file manager");
+	let catalog = SignalCatalog::recover(&[&fm]).expect("Failed to recover the catalog");
+	assert_eq!(catalog.signals(), vec![1, 2]);
+	let entry = catalog.get(1).unwrap();
+	assert_eq!(entry.get_segments(), 6);
+	assert_eq!(entry.get_first(), UNIX_EPOCH + Duration::from_secs(10));
+	assert_eq!(entry.get_last(), UNIX_EPOCH + Duration::from_secs(15));
+	assert_eq!(entry.get_codecs().get(CODEC_RAW), Some(&3));
+	assert_eq!(entry.get_codecs().get("gorilla"), Some(&3));
+	let entry = catalog.get(2).unwrap();
+	assert_eq!((entry.get_segments(), entry.get_points()), (1, 20));
+	assert!(catalog.get(3).is_none());
+
+	/* The tail in the catalog leads back through the whole chain */
+	let buf: Arc<Mutex<SegmentBuffer<f64>>> = Arc::new(Mutex::new(ClockBuffer::<f64,_>::new(16, fm)));
+	let chain: Vec<SystemTime> = SegmentIter::from_tail(catalog.get_last_key(1).unwrap(), buf).map(|seg| seg.expect("Failed to load segment").get_key().get_timestamp()).collect();
+	assert_eq!(chain, (10..16u64).rev().map(|s| UNIX_EPOCH + Duration::from_secs(s)).collect::<Vec<_>>());
+	LogFileManager::destroy(path).expect("Failed to destroy log file manager");
+}
+
+#[test]
+fn signal_meta_test() {
+	use crate::methods::registry::method_with_param;
+	use crate::segment::header::element_type_id;
+
+	let path = "../catalog_meta_test";
+	let value_type = element_type_id::<f64>();
+	let _ = fs::remove_file(path);
+	{
+		let mut catalog = SignalCatalog::new().with_meta_file(path).expect("Failed to open the meta file");
+		let boiler = SignalMeta::new("boiler", value_type).with_tag("host", "h1").with_tag("unit", "celsius")
+			.with_codec(method_with_param("buff", Some(1000.0)).unwrap());
+		assert_eq!(catalog.register(7, boiler).unwrap(), 7);
+		let fan = SignalMeta::new("fan", value_type).with_tag("host", "h2").with_tag("unit", "rpm");
+		assert_eq!(catalog.register(8, fan).unwrap(), 8);
+		match catalog.register(7, SignalMeta::new("pump", value_type)) {
+			Err(CatalogError::IdTaken(7, name)) => assert_eq!(name, "boiler"),
+			r => panic!("Registered a taken id: {:?}", r),
+		}
+	}
+
+	/* Names keep their ids across a restart, whatever id they are given */
+	let mut catalog = SignalCatalog::new().with_meta_file(path).expect("Failed to reopen the meta file");
+	assert_eq!(catalog.register(99, SignalMeta::new("fan", value_type).with_tag("host", "h2")).unwrap(), 8);
+	assert_eq!(catalog.get_meta(7).unwrap().get_codec(), &Some(Methods::Buff(1000)));
+	assert_eq!(catalog.get_meta(7).unwrap().get_value_type(), value_type);
+	assert_eq!(catalog.signals(), vec![7, 8]);
+	assert_eq!(catalog.resolve("boiler"), vec![7]);
+	assert_eq!(catalog.resolve("host=h2"), vec![8]);
+	assert_eq!(catalog.resolve("host=h1, unit=celsius"), vec![7]);
+	assert!(catalog.resolve("unit=rpm").is_empty());
+	assert!(catalog.resolve("pump").is_empty());
+	assert_eq!(catalog.resolve("42"), vec![42]);
+
+	/* A damaged meta file is reported instead of starting without metadata */
+	let mut bytes = fs::read(path).expect("Failed to read the meta file");
+	let last = bytes.len() - 1;
+	bytes[last] ^= 0xFF;
+	fs::write(path, bytes).expect("Failed to damage the meta file");
+	match SignalCatalog::new().with_meta_file(path) {
+		Err(CatalogError::CorruptMeta(_)) => (),
+		r => panic!("Loaded a damaged meta file: {:?}", r),
+	}
+	fs::remove_file(path).expect("Failed to remove the meta file");
+}
+
+#[test]
+fn catalog_codec_test() {
+	use std::time::UNIX_EPOCH;
+	use crate::methods::registry::codec_by_name;
+	use crate::segment::header::element_type_id;
+
+	let mut catalog = SignalCatalog::new();
+	catalog.register(7, SignalMeta::new("boiler", element_type_id::<f64>()).with_codec(Methods::Gorilla)).unwrap();
+	let codec = CatalogCodec::new(codec_by_name("snappy", 2).unwrap(), Arc::new(Mutex::new(catalog)));
+
+	/* The registered signal gets its own codec, the other one the codec of the run */
+	let mut segs: Vec<Segment<f64>> = vec![7, 8].into_iter()
+		.map(|signal| Segment::new(None, UNIX_EPOCH, signal, vec![1.5; 20], None, None))
+		.collect();
+	codec.run_compress(&mut segs);
+	let mut methods: Vec<(SignalId, Option<Methods>)> = segs.iter().map(|seg| (seg.get_signal(), seg.get_method().clone())).collect();
+	methods.sort_by_key(|(signal, _)| *signal);
+	assert_eq!(methods, vec![(7, Some(Methods::Gorilla)), (8, Some(Methods::Snappy))]);
+	for seg in segs.iter_mut() {
+		codec.run_decompress(seg);
+		assert_eq!(seg.get_data(), &vec![1.5; 20]);
+	}
+
+	/* A segment written before the signal switched codecs keeps its own */
+	let mut seg = Segment::new(None, UNIX_EPOCH, 7, vec![2.5; 20], None, None);
+	codec_by_name("gzip", 2).unwrap().encode(&mut seg);
+	codec.run_decompress(&mut seg);
+	assert_eq!(seg.get_data(), &vec![2.5; 20]);
+}
@@ -20,6 +20,7 @@ use std::sync::Arc;
 use tokio::prelude::*;
 
 use crate::future_signal::BufferedSignal;
+use crate::segment::validity::Nullable;
 use ndarray::{Array1, Array2};
 use std::borrow::Borrow;
 
@@ -184,6 +185,35 @@ pub fn construct_file_iterator_skip_newline<T>(file: &str, skip_val: usize, deli
 	)
 }
 
+/* Like construct_file_iterator_skip_newline but keeps the position of
+ * gaps. Empty or unparsable entries become the missing value of T, see
+ * segment/validity.rs, "NaN" and "inf" parse as themselves. Types that
+ * can not hold a missing value drop those entries instead.
+ */
+pub fn construct_file_iterator_with_gaps<T>(file: &str, skip_val: usize, delim: char) -> Result<impl Iterator<Item=T>,()>
+	where T: FromStr + Nullable
+{
+	let f = match File::open(file) {
+		Ok(f) => f,
+		Err(_) => return Err(()),
+	};
+
+	Ok(BufReader::new(f)
+		.lines()
+		.filter_map(Result::ok)
+		.flat_map(move |line: String| {
+			line.split(delim)
+				.skip(skip_val)
+				.filter_map(|item: &str| match item.trim().parse::<T>() {
+					Ok(v) => Some(v),
+					Err(_) => T::null(),
+				})
+				.collect::<Vec<T>>()
+				.into_iter()
+		})
+	)
+}
+
 pub fn construct_file_iterator_int(file: &str, skip_val: usize, delim: char, scl:i32) -> Result<impl Iterator<Item=u32>,()>
 {
 	let f = match File::open(file) {
@@ -226,6 +256,50 @@ pub fn construct_file_iterator_int_signed(file: &str, skip_val: usize, delim: ch
 	)
 }
 
+/* Scales an entry read as an i64. An i64 has no missing value, so one
+ * the scale takes out of range is logged and clamped to keep its position.
+ * Entries that are not numbers are dropped as in the other readers.
+ */
+fn scale_int64(item: &str, scl: i64) -> Option<i64> {
+	let item = item.trim();
+	let scaled = match item.parse::<i64>() {
+		Ok(x) => match x.checked_mul(scl) {
+			Some(v) => return Some(v),
+			None => x as f64 * scl as f64,
+		},
+		Err(_) => item.parse::<f64>().ok()? * scl as f64,
+	};
+	let rounded = scaled.round();
+	if rounded.is_nan() || rounded < i64::MIN as f64 || rounded >= i64::MAX as f64 {
+		println!("{} scaled by {} does not fit an i64, clamped", item, scl);
+	}
+	/* Saturates, NaN becomes 0 */
+	Some(rounded as i64)
+}
+
+/* Counters and nanosecond timestamps do not fit an i32 or survive an f32, integers
+ * are read as they are and only values with a fraction go through the scale
+ */
+pub fn construct_file_iterator_int64(file: &str, skip_val: usize, delim: char, scl:i64) -> Result<impl Iterator<Item=i64>,()>
+{
+	let f = match File::open(file) {
+		Ok(f) => f,
+		Err(_) => return Err(()),
+	};
+
+	Ok(BufReader::new(f)
+		.lines()
+		.filter_map(Result::ok)
+		.flat_map(move |line: String| {
+			line.split(delim)
+				.skip(skip_val)
+				.filter_map(|item: &str| scale_int64(item, scl))
+				.collect::<Vec<i64>>()
+				.into_iter()
+		})
+	)
+}
+
 
 
 pub fn construct_file_client<T>(file: &str, delim: u8, amount: Amount, 
@@ -247,6 +321,15 @@ pub fn construct_file_client_skip_newline<T>(file: &str, skip_val: usize, delim:
 	Ok(client_from_iter(producer, amount, run_period, frequency))
 }
 
+pub fn construct_file_client_with_gaps<T>(file: &str, skip_val: usize, delim: char, amount: Amount, run_period: RunPeriod,
+				   		 frequency: Frequency)
+				    	 -> Result<impl Stream<Item=T,Error=()>,()>
+	where T: FromStr + Nullable,
+{
+	let producer = construct_file_iterator_with_gaps::<T>(file, skip_val, delim)?;
+	Ok(client_from_iter(producer, amount, run_period, frequency))
+}
+
 
 /* First approach at enabling a framework for random generation 
  * Failed because f32 does not implement From<f64>
@@ -372,4 +455,14 @@ pub fn read_dict<T>(filename: &str, delim: char) -> Array2<T>
 fn test_read_dict() {
 	let dic = read_dict::<f32>("../UCRArchive2018/kernel-test/dict.tsv".borrow(), ',');
 	println!("{:?}",dic.shape());
-}
\ No newline at end of file
+}
+#[test]
+fn test_int64_iterator_keeps_positions() {
+	use std::io::Write;
+
+	let path = "../int64_iterator_test.csv";
+	File::create(path).unwrap().write_all(b"1,2.5,9223372036854775807\n-9223372036854775807,x,4\n").unwrap();
+	let values: Vec<i64> = construct_file_iterator_int64(path, 0, ',', 10).unwrap().collect();
+	let _ = std::fs::remove_file(path);
+	assert_eq!(values, vec![10, 25, i64::MAX, i64::MIN, 40]);
+}
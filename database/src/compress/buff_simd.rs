@@ -3,7 +3,7 @@ use crate::methods::compress::SCALE;
 use crate::segment::Segment;
 use std::time::{SystemTime, Instant};
 use crate::compress::split_double::{SplitBDDoubleCompress, SAMPLE, MAJOR_R};
-use crate::methods::prec_double::{get_precision_bound, PrecisionBound};
+use crate::methods::prec_double::{get_precision_bound, PrecisionBound, InferScale};
 use crate::methods::bit_packing::{BitPack, BYTE_BITS};
 use crate::simd::vectorize_query::{range_simd_myroaring, equal_simd_myroaring, equal_simd_mybitvec, range_simd_mybitvec};
 use std::mem;
@@ -33,7 +33,7 @@ pub fn run_buff_simd_encoding_decoding(test_file:&str, scl:usize, pred: f64) {
 
     let mut seg = Segment::new(None,SystemTime::now(),0,file_vec.clone(),None,None);
     let org_size = seg.get_byte_size().unwrap();
-    let comp = SplitBDDoubleCompress::new(10,10,scl);
+    let comp = SplitBDDoubleCompress::new(10,10,scl).for_segment(&seg).expect("No scale reproduces the test file");
     let start1 = Instant::now();
     let mut compressed= comp.buff_simd256_encode(&mut seg);
 
@@ -103,7 +103,7 @@ pub fn run_buff_encoding_decoding(test_file:&str, scl:usize, pred: f64) {
 
     let mut seg = Segment::new(None,SystemTime::now(),0,file_vec.clone(),None,None);
     let org_size = seg.get_byte_size().unwrap();
-    let comp = SplitBDDoubleCompress::new(10,10,scl);
+    let comp = SplitBDDoubleCompress::new(10,10,scl).for_segment(&seg).expect("No scale reproduces the test file");
     let start1 = Instant::now();
     let mut compressed= comp.buff_simd256_encode(&mut seg);
 
@@ -172,7 +172,7 @@ pub fn run_buff_encoding_decoding_mybitvec(test_file:&str, scl:usize,pred: f64)
 
     let mut seg = Segment::new(None,SystemTime::now(),0,file_vec.clone(),None,None);
     let org_size = seg.get_byte_size().unwrap();
-    let comp = SplitBDDoubleCompress::new(10,10,scl);
+    let comp = SplitBDDoubleCompress::new(10,10,scl).for_segment(&seg).expect("No scale reproduces the test file");
     let start1 = Instant::now();
     let mut compressed;
     if FILE_MIN_MAX.contains_key(test_file){
@@ -192,8 +192,9 @@ pub fn run_buff_encoding_decoding_mybitvec(test_file:&str, scl:usize,pred: f64)
     println!("Time elapsed in splitbd byte compress function() is: {:?}", duration1);
 
     let start2 = Instant::now();
-    comp.byte_fixed_decode(compressed);
+    let decoded = comp.byte_fixed_decode(compressed);
     let duration2 = start2.elapsed();
+    println!("Number of scan items:{}", decoded.len());
     println!("Time elapsed in splitbd byte decompress function() is: {:?}", duration2);
 
     let start3 = Instant::now();
@@ -207,8 +208,9 @@ pub fn run_buff_encoding_decoding_mybitvec(test_file:&str, scl:usize,pred: f64)
     println!("Time elapsed in splitbd byte equal filter function() is: {:?}", duration4);
 
     let start5 = Instant::now();
-    comp.byte_fixed_sum(comp_sum);
+    let sum = comp.byte_fixed_sum(comp_sum);
     let duration5 = start5.elapsed();
+    println!("sum is: {:?}", sum);
     println!("Time elapsed in byte_splitbd sum function() is: {:?}", duration5);
 
     let start6 = Instant::now();
@@ -251,7 +253,7 @@ pub fn run_buff_majority_encoding_decoding(test_file:&str, scl:usize,pred: f64)
 
     let mut seg = Segment::new(None,SystemTime::now(),0,file_vec.clone(),None,None);
     let org_size = seg.get_byte_size().unwrap();
-    let comp = SplitBDDoubleCompress::new(10,10,scl);
+    let comp = SplitBDDoubleCompress::new(10,10,scl).for_segment(&seg).expect("No scale reproduces the test file");
     let start1 = Instant::now();
     let compressed = comp.buff_encode_majority(&mut seg);
     let duration1 = start1.elapsed();
@@ -2,11 +2,11 @@ use crate::client::construct_file_iterator_skip_newline;
 use crate::methods::compress::{SCALE, CompressionMethod};
 use crate::segment::Segment;
 use std::time::{SystemTime, Instant};
-use crate::methods::prec_double::{get_precision_bound, PrecisionBound};
+use crate::methods::prec_double::{get_precision_bound, PrecisionBound, InferScale};
 use crate::methods::bit_packing::{BitPack, BYTE_BITS};
 use crate::simd::vectorize_query::{range_simd_myroaring, equal_simd_myroaring};
 use std::mem;
-use log::info;
+use log::{info, warn};
 use serde::{Deserialize, Serialize};
 use tsz::stream::BufferedWriter;
 use itertools::Itertools;
@@ -35,7 +35,7 @@ pub fn run_buff_slice_encoding_decoding(test_file:&str, scl:usize, pred: f64) {
 
     let mut seg = Segment::new(None,SystemTime::now(),0,file_vec.clone(),None,None);
     let org_size = seg.get_byte_size().unwrap();
-    let comp = BuffSliceCompress::new(10,10,scl);
+    let comp = BuffSliceCompress::new(10,10,scl).for_segment(&seg).expect("No scale reproduces the test file");
     let start1 = Instant::now();
     let mut compressed= comp.buff_slice_encode(&mut seg);
 
@@ -48,8 +48,9 @@ pub fn run_buff_slice_encoding_decoding(test_file:&str, scl:usize, pred: f64) {
     println!("Time elapsed in buff slice compress function() is: {:?}", duration1);
 
     let start2 = Instant::now();
-    comp.buff_slice_decode(compressed);
+    let decoded = comp.buff_slice_decode(compressed);
     let duration2 = start2.elapsed();
+    println!("Number of scan items:{}", decoded.len());
     println!("Time elapsed in buff slice decompress function() is: {:?}", duration2);
 
     let start3 = Instant::now();
@@ -108,7 +109,7 @@ pub fn run_buff_slice_scalar_encoding_decoding(test_file:&str, scl:usize, pred:
 
     let mut seg = Segment::new(None,SystemTime::now(),0,file_vec.clone(),None,None);
     let org_size = seg.get_byte_size().unwrap();
-    let comp = BuffSliceCompress::new(10,10,scl);
+    let comp = BuffSliceCompress::new(10,10,scl).for_segment(&seg).expect("No scale reproduces the test file");
     let start1 = Instant::now();
     let mut compressed= comp.buff_slice_encode(&mut seg);
 
@@ -136,14 +137,16 @@ pub fn run_buff_slice_scalar_encoding_decoding(test_file:&str, scl:usize, pred:
     println!("Time elapsed in buff slice equal filter function() is: {:?}", duration4);
 
     let start5 = Instant::now();
-    comp.buff_slice_sum(comp_sum);
+    let sum = comp.buff_slice_sum(comp_sum);
     let duration5 = start5.elapsed();
+    println!("sum is: {:?}", sum);
     // println!("Time elapsed in buff slice range no simd function() is: {:?}", duration5);
     println!("Time elapsed in buff slice sum function() is: {:?}", duration5);
 
     let start6 = Instant::now();
-    comp.buff_slice_max(comp_max);
+    let max = comp.buff_slice_max(comp_max);
     let duration6 = start6.elapsed();
+    println!("Max value:{}", max);
     // println!("Time elapsed in buff slice eqaul no simd function() is: {:?}", duration6);
     println!("Time elapsed in buff slice max function() is: {:?}", duration6);
 
@@ -446,11 +449,8 @@ impl BuffSliceCompress {
         let higher = bitpack.read(32).unwrap();
         let ubase_int= (lower as u64)|((higher as u64)<<32);
         let base_int = unsafe { mem::transmute::<u64, i64>(ubase_int) };
-        println!("base integer: {}",base_int);
         let len = bitpack.read(32).unwrap();
-        println!("total vector size:{}",len);
         let ilen = bitpack.read(32).unwrap();
-        println!("bit packing length:{}",ilen);
         let dlen = bitpack.read(32).unwrap();
         bound.set_length(ilen as u64, dlen as u64);
         // check integer part and update bitmap;
@@ -459,7 +459,6 @@ impl BuffSliceCompress {
         let mut fixed_vec:Vec<u64> = Vec::new();
 
         let mut dec_scl:f64 = 2.0f64.powi(dlen as i32);
-        println!("Scale for decimal:{}", dec_scl);
 
         let mut remain = dlen+ilen;
         let mut bytec = 0;
@@ -495,7 +494,6 @@ impl BuffSliceCompress {
                     fixed_vec.push((flip(*x) as u64)<<remain)
                 }
             }
-            println!("read the {}th byte of dec",bytec);
 
             while (remain>=8){
                 bytec+=1;
@@ -519,7 +517,6 @@ impl BuffSliceCompress {
                 }
 
 
-                println!("read the {}th byte of dec",bytec);
             }
             // let duration = start.elapsed();
             // println!("Time elapsed in leading bytes: {:?}", duration);
@@ -527,8 +524,6 @@ impl BuffSliceCompress {
 
             // let start5 = Instant::now();
             if (remain>0){
-                println!("read remaining {} bits of dec",remain);
-                println!("length for fixed:{}", fixed_vec.len());
                 chunk = bitpack.read_n_byte(len as usize).unwrap();
                 let padding = 8-remain;
                 for (&cur_fixed, &x) in fixed_vec.iter().zip(chunk.iter()){
@@ -540,7 +535,6 @@ impl BuffSliceCompress {
         // for i in 0..10{
         //     println!("{}th item:{}",i,expected_datapoints.get(i).unwrap())
         // }
-        println!("Number of scan items:{}", expected_datapoints.len());
         expected_datapoints
     }
 
@@ -813,7 +807,7 @@ impl BuffSliceCompress {
     }
 
     /// load all data, deocde and max
-    pub fn buff_slice_max(&self, bytes: Vec<u8>){
+    pub fn buff_slice_max(&self, bytes: Vec<u8>) -> f64 {
         let prec = (self.scale as f32).log10() as i32;
         let prec_delta = get_precision_bound(prec);
 
@@ -823,17 +817,13 @@ impl BuffSliceCompress {
         let higher = bitpack.read(32).unwrap();
         let ubase_int= (lower as u64)|((higher as u64)<<32);
         let base_int = unsafe { mem::transmute::<u64, i64>(ubase_int) };
-        println!("base integer: {}",base_int);
         let len = bitpack.read(32).unwrap();
-        println!("total vector size:{}",len);
         let ilen = bitpack.read(32).unwrap();
-        println!("bit packing length:{}",ilen);
         let dlen = bitpack.read(32).unwrap();
         bound.set_length(ilen as u64, dlen as u64);
 
 
         let mut dec_scl:f64 = 2.0f64.powi(dlen as i32);
-        println!("Scale for decimal:{}", dec_scl);
 
         let mut remain = dlen+ilen;
         let mut bytec = 0;
@@ -843,7 +833,6 @@ impl BuffSliceCompress {
         let mut chunk3:&[u8];
         let mut f_cur = 0f64;
         let num = ceil(remain, 8);
-        println!("Number of chunks:{}", num);
 
         let padding = num*8-ilen-dlen;
         let mut res = BitVec::from_elem(len as usize, false);
@@ -922,12 +911,11 @@ impl BuffSliceCompress {
             }
             _ => {panic!("bit length greater than 32 is not supported yet.")}
         }
-        println!("Number of qualified max items:{}", res.cardinality());
-        println!("Max value:{}", max);
+        max
     }
 
 
-    pub fn buff_slice_max_range(&self, bytes: Vec<u8>,s:u32, e:u32, window:u32) {
+    pub fn buff_slice_max_range(&self, bytes: Vec<u8>,s:u32, e:u32, window:u32) -> Vec<f64> {
         let prec = (self.scale as f32).log10() as i32;
         let prec_delta = get_precision_bound(prec);
 
@@ -937,17 +925,13 @@ impl BuffSliceCompress {
         let higher = bitpack.read(32).unwrap();
         let ubase_int= (lower as u64)|((higher as u64)<<32);
         let base_int = unsafe { mem::transmute::<u64, i64>(ubase_int) };
-        println!("base integer: {}",base_int);
         let len = bitpack.read(32).unwrap();
-        println!("total vector size:{}",len);
         let ilen = bitpack.read(32).unwrap();
-        println!("bit packing length:{}",ilen);
         let dlen = bitpack.read(32).unwrap();
         bound.set_length(ilen as u64, dlen as u64);
 
 
         let mut dec_scl:f64 = 2.0f64.powi(dlen as i32);
-        println!("Scale for decimal:{}", dec_scl);
 
         let mut remain = dlen+ilen;
         let mut bytec = 0;
@@ -957,7 +941,6 @@ impl BuffSliceCompress {
         let mut chunk3:&[u8];
         let mut f_cur = 0f64;
         let num = ceil(remain, 8);
-        println!("Number of chunks:{}", num);
 
         let padding = num*8-ilen-dlen;
         let mut res = BitVec::from_elem(len as usize, false);
@@ -1094,11 +1077,11 @@ impl BuffSliceCompress {
         max_vec.push(max);
 
         // println!("Number of qualified max items:{}", res.cardinality());
-        println!("Max value:{}", max_vec.len());
+        max_vec
     }
 
     /// load all data, deocde and sum
-    pub fn buff_slice_sum(&self, bytes: Vec<u8>){
+    pub fn buff_slice_sum(&self, bytes: Vec<u8>) -> f64 {
         let prec = (self.scale as f32).log10() as i32;
         let prec_delta = get_precision_bound(prec);
 
@@ -1108,17 +1091,13 @@ impl BuffSliceCompress {
         let higher = bitpack.read(32).unwrap();
         let ubase_int= (lower as u64)|((higher as u64)<<32);
         let base_int = unsafe { mem::transmute::<u64, i64>(ubase_int) };
-        println!("base integer: {}",base_int);
         let len = bitpack.read(32).unwrap();
-        println!("total vector size:{}",len);
         let ilen = bitpack.read(32).unwrap();
-        println!("bit packing length:{}",ilen);
         let dlen = bitpack.read(32).unwrap();
         bound.set_length(ilen as u64, dlen as u64);
 
 
         let mut dec_scl:f64 = 2.0f64.powi(dlen as i32);
-        println!("Scale for decimal:{}", dec_scl);
 
         let mut remain = dlen+ilen;
         let mut bytec = 0;
@@ -1128,7 +1107,6 @@ impl BuffSliceCompress {
         let mut chunk3:&[u8];
         let mut f_cur = 0f64;
         let num = ceil(remain, 8);
-        println!("Number of chunks:{}", num);
 
         let padding = num*8-ilen-dlen;
         let mut sum = 0.0;
@@ -1178,7 +1156,7 @@ impl BuffSliceCompress {
             }
             _ => {panic!("bit length greater than 32 is not supported yet.")}
         }
-        println!("sum value:{}",  sum);
+        sum
     }
 
     pub fn buff_slice_decode_bs(&self, bytes: Vec<u8>) -> Vec<f64>{
@@ -1968,6 +1946,16 @@ impl BuffSliceCompress {
     }
 }
 
+impl InferScale for BuffSliceCompress {
+    fn scale(&self) -> usize {
+        self.scale
+    }
+
+    fn set_scale(&mut self, scale: usize) {
+        self.scale = scale;
+    }
+}
+
 impl<'a, T> CompressionMethod<T> for BuffSliceCompress
     where T: Serialize + Clone+ Copy+Into<f64>+ Deserialize<'a>{
     fn get_segments(&self) {
@@ -1981,7 +1969,10 @@ impl<'a, T> CompressionMethod<T> for BuffSliceCompress
     fn run_compress<'b>(&self, segs: &mut Vec<Segment<T>>) {
         let start = Instant::now();
         for seg in segs {
-            self.buff_slice_encode(seg);
+            match self.for_segment(seg) {
+                Some(comp) => { comp.buff_slice_encode(seg); }
+                None => warn!("No scale reproduces the segment, it is not encoded"),
+            }
         }
         let duration = start.elapsed();
         info!("Time elapsed in buff-slice function() is: {:?}", duration);
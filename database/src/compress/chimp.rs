@@ -0,0 +1,418 @@
+use crate::segment::Segment;
+use serde::{Deserialize, Serialize};
+use tsz::stream::{BufferedWriter, BufferedReader, Write, Read, Error};
+use std::time::Instant;
+use std::mem;
+use log::info;
+use croaring::Bitmap;
+use crate::methods::compress::CompressionMethod;
+use std::slice::Iter;
+use num::FromPrimitive;
+use crate::methods::Methods;
+use crate::segment::validity::Nullable;
+
+/*
+ * Overview:
+ * Chimp and Chimp128 (Liakos et al., VLDB 2022), XOR codecs in the
+ * spirit of Gorilla that spend fewer bits on the leading zeros of the
+ * XOR and, in Chimp128, XOR a value with whichever of the last 128
+ * values shares the most trailing bits with it. Neither needs a scale,
+ * so they suit high precision data BUFF and Sprintz can not take.
+ *
+ * Design Choice:
+ * ChimpEncoder and ChimpDecoder implement both codecs, Chimp is the
+ * case of a window of one previous value where no index bits are
+ * written. They only turn u64 bit patterns into bits on a tsz stream,
+ * so the Elf codec puts its erased values through the same XOR stream.
+ * A stream starts with the number of values in 32 bits instead of
+ * ending in a marker, the decoders stop once they read that many.
+ * Per value, after the first one which is stored as is, the two flag
+ * bits are
+ *   00 the value equals the referenced one
+ *   01 the XOR with the referenced one has many trailing zeros, its
+ *      center bits follow the leading zero code and the center length
+ *   10 the XOR with the previous value keeps the last leading zeros
+ *   11 the XOR with the previous value has new leading zeros
+ * where the referenced value is given by the index bits of the window.
+ */
+
+pub const CHIMP_WINDOW: usize = 1;
+pub const CHIMP128_WINDOW: usize = 128;
+
+/* Bits of the values used to find a reference in the window */
+const KEY_BITS: u32 = 14;
+const KEY_MASK: u64 = (1 << KEY_BITS) - 1;
+
+/* Leading zeros are rounded down to one of these, stored as 3 bit codes */
+const LEADING_ROUND: [u32; 8] = [0, 8, 12, 16, 18, 20, 22, 24];
+
+/* No leading zeros are kept after a 00 or 01 value */
+const NO_LEADING: u32 = 65;
+
+fn round_leading(leading: u32) -> (u64, u32) {
+    let code = LEADING_ROUND.iter().rposition(|l| *l <= leading).unwrap();
+    (code as u64, LEADING_ROUND[code])
+}
+
+/* Writes only when there are bits to write, tsz can not shift by 64 */
+fn write_some<W: Write>(w: &mut W, bits: u64, num: u32) {
+    if num > 0 {
+        w.write_bits(bits, num);
+    }
+}
+
+fn read_some<R: Read>(r: &mut R, num: u32) -> Result<u64, Error> {
+    if num > 0 { r.read_bits(num) } else { Ok(0) }
+}
+
+struct Window {
+    size: usize,
+    index_bits: u32,
+    stored: Vec<u64>,
+    count: usize,
+    prev_leading: u32,
+}
+
+impl Window {
+    fn new(size: usize) -> Self {
+        assert!(size.is_power_of_two(), "The chimp window must be a power of two");
+        Window {
+            size: size,
+            index_bits: size.trailing_zeros(),
+            stored: vec![0; size],
+            count: 0,
+            prev_leading: NO_LEADING,
+        }
+    }
+
+    /* A 01 value needs more trailing zeros than its index costs */
+    fn threshold(&self) -> u32 {
+        6 + self.index_bits
+    }
+
+    fn last(&self) -> usize {
+        (self.count - 1) % self.size
+    }
+
+    fn push(&mut self, bits: u64) {
+        self.stored[self.count % self.size] = bits;
+        self.count += 1;
+    }
+}
+
+pub struct ChimpEncoder {
+    window: Window,
+    /* The last position each key was seen at, only kept with a window */
+    positions: Vec<usize>,
+}
+
+impl ChimpEncoder {
+    pub fn new(window: usize) -> Self {
+        let positions = if window > 1 { vec![0; 1 << KEY_BITS] } else { Vec::new() };
+        ChimpEncoder { window: Window::new(window), positions }
+    }
+
+    pub fn encode<W: Write>(&mut self, w: &mut W, bits: u64) {
+        if self.window.count == 0 {
+            w.write_bits(bits, 64);
+            self.push(bits);
+            return;
+        }
+        let threshold = self.window.threshold();
+        let index_bits = self.window.index_bits;
+
+        /* The candidate sharing the low bits wins if it shares enough trailing bits */
+        let mut slot = self.window.last();
+        if !self.positions.is_empty() {
+            let position = self.positions[(bits & KEY_MASK) as usize];
+            if position + self.window.size >= self.window.count {
+                let candidate = position % self.window.size;
+                if (bits ^ self.window.stored[candidate]).trailing_zeros() > threshold {
+                    slot = candidate;
+                }
+            }
+        }
+        let xor = bits ^ self.window.stored[slot];
+
+        if xor == 0 {
+            w.write_bits(0b00, 2);
+            write_some(w, slot as u64, index_bits);
+            self.window.prev_leading = NO_LEADING;
+        } else {
+            let trailing = xor.trailing_zeros();
+            let (code, leading) = round_leading(xor.leading_zeros());
+            if trailing > threshold {
+                let center = 64 - leading - trailing;
+                w.write_bits(0b01, 2);
+                write_some(w, slot as u64, index_bits);
+                w.write_bits(code, 3);
+                w.write_bits(center as u64, 6);
+                w.write_bits(xor >> trailing, center);
+                self.window.prev_leading = NO_LEADING;
+            } else {
+                /* Only a candidate with enough trailing zeros is taken, so this is the previous value */
+                if leading == self.window.prev_leading {
+                    w.write_bits(0b10, 2);
+                } else {
+                    w.write_bits(0b11, 2);
+                    w.write_bits(code, 3);
+                    self.window.prev_leading = leading;
+                }
+                w.write_bits(xor, 64 - leading);
+            }
+        }
+        self.push(bits);
+    }
+
+    fn push(&mut self, bits: u64) {
+        if !self.positions.is_empty() {
+            self.positions[(bits & KEY_MASK) as usize] = self.window.count;
+        }
+        self.window.push(bits);
+    }
+}
+
+pub struct ChimpDecoder {
+    window: Window,
+}
+
+impl ChimpDecoder {
+    pub fn new(window: usize) -> Self {
+        ChimpDecoder { window: Window::new(window) }
+    }
+
+    pub fn decode<R: Read>(&mut self, r: &mut R) -> Result<u64, Error> {
+        if self.window.count == 0 {
+            let bits = r.read_bits(64)?;
+            self.window.push(bits);
+            return Ok(bits);
+        }
+        let index_bits = self.window.index_bits;
+        let bits = match r.read_bits(2)? {
+            0b00 => {
+                let slot = read_some(r, index_bits)? as usize;
+                self.window.prev_leading = NO_LEADING;
+                self.window.stored[slot]
+            }
+            0b01 => {
+                let slot = read_some(r, index_bits)? as usize;
+                let leading = LEADING_ROUND[r.read_bits(3)? as usize];
+                let center = r.read_bits(6)? as u32;
+                let trailing = 64 - leading - center;
+                self.window.prev_leading = NO_LEADING;
+                self.window.stored[slot] ^ (r.read_bits(center)? << trailing)
+            }
+            flag => {
+                if flag == 0b11 {
+                    self.window.prev_leading = LEADING_ROUND[r.read_bits(3)? as usize];
+                }
+                self.window.stored[self.window.last()] ^ r.read_bits(64 - self.window.prev_leading)?
+            }
+        };
+        self.window.push(bits);
+        Ok(bits)
+    }
+}
+
+#[derive(Clone)]
+pub struct ChimpCompress {
+    chunksize: usize,
+    batchsize: usize,
+    window: usize,
+}
+
+impl ChimpCompress {
+    /* CHIMP_WINDOW gives Chimp, CHIMP128_WINDOW gives Chimp128 */
+    pub fn new(chunksize: usize, batchsize: usize, window: usize) -> Self {
+        ChimpCompress { chunksize, batchsize, window }
+    }
+
+    pub fn method(&self) -> Methods {
+        if self.window > 1 { Methods::Chimp128 } else { Methods::Chimp }
+    }
+
+    pub fn encode<'a,T>(&self, seg: &mut Segment<T>) -> Vec<u8>
+        where T: Serialize + Clone+ Copy+Into<f64> + Deserialize<'a>{
+        let mut w = BufferedWriter::new();
+        let mut encoder = ChimpEncoder::new(self.window);
+        let start = Instant::now();
+        w.write_bits(seg.get_data().len() as u64, 32);
+        for val in seg.get_data() {
+            let v: f64 = (*val).into();
+            encoder.encode(&mut w, v.to_bits());
+        }
+        let duration = start.elapsed();
+        info!("Time elapsed in chimp function() is: {:?}", duration);
+        let origin = seg.get_data().len() * mem::size_of::<T>();
+        info!("original size:{}", origin);
+        let byte_vec = w.close().to_vec();
+        info!("compressed size:{}", byte_vec.len());
+        byte_vec
+    }
+
+    /* Hands every value to f in order until f returns false */
+    fn scan<F: FnMut(usize, f64) -> bool>(&self, bytes: &[u8], mut f: F) {
+        let mut r = BufferedReader::new(bytes.to_vec().into_boxed_slice());
+        let mut decoder = ChimpDecoder::new(self.window);
+        let len = match r.read_bits(32) {
+            Ok(len) => len as usize,
+            Err(err) => panic!("Received an error from decoder: {:?}", err),
+        };
+        for i in 0..len {
+            match decoder.decode(&mut r) {
+                Ok(bits) => if !f(i, f64::from_bits(bits)) {
+                    break;
+                },
+                Err(err) => panic!("Received an error from decoder: {:?}", err),
+            }
+        }
+    }
+
+    pub(crate) fn decode(&self, bytes: Vec<u8>) -> Vec<f64> {
+        let mut expected_datapoints = Vec::new();
+        self.scan(&bytes, |_, dp| { expected_datapoints.push(dp); true });
+        expected_datapoints
+    }
+
+    pub(crate) fn decode_general<T>(&self, bytes: &Vec<u8>) -> Vec<T>
+        where T: FromPrimitive{
+        let mut expected_datapoints = Vec::new();
+        self.scan(bytes, |_, dp| { expected_datapoints.push(FromPrimitive::from_f64(dp).unwrap()); true });
+        expected_datapoints
+    }
+
+    pub fn decode_condition(&self, bytes: Vec<u8>, cond:Iter<usize>) -> Vec<f64> {
+        let mut iter = cond.clone();
+        let mut point = iter.next();
+        let mut expected_datapoints:Vec<f64> = Vec::new();
+        self.scan(&bytes, |i, dp| {
+            if Some(&i) == point {
+                expected_datapoints.push(dp);
+                point = iter.next();
+            }
+            point.is_some()
+        });
+        println!("Number of scan items:{}", expected_datapoints.len());
+        expected_datapoints
+    }
+
+    /* The aggregations and filters run on the values as they are
+     * decoded, undefined values are skipped like in CompressedQuery
+     */
+    pub(crate) fn sum(&self, bytes: Vec<u8>) -> f64 {
+        let mut sum = 0f64;
+        self.scan(&bytes, |_, dp| {
+            if !dp.is_undefined() {
+                sum += dp;
+            }
+            true
+        });
+        sum
+    }
+
+    pub(crate) fn count(&self, bytes: Vec<u8>) -> usize {
+        let mut count = 0;
+        self.scan(&bytes, |_, dp| {
+            if !dp.is_undefined() {
+                count += 1;
+            }
+            true
+        });
+        count
+    }
+
+    pub(crate) fn range_filter(&self, bytes: Vec<u8>,pred:f64) -> Bitmap {
+        let mut res = Bitmap::create();
+        self.scan(&bytes, |i, dp| {
+            if dp>pred {
+                res.add(i as u32);
+            }
+            true
+        });
+        res
+    }
+
+    pub(crate) fn equal_filter(&self, bytes: Vec<u8>,pred:f64) -> Bitmap {
+        let mut res = Bitmap::create();
+        self.scan(&bytes, |i, dp| {
+            if dp==pred {
+                res.add(i as u32);
+            }
+            true
+        });
+        res
+    }
+
+    /* None when the segment holds no defined values */
+    pub(crate) fn max(&self, bytes: Vec<u8>) -> Option<f64> {
+        let mut max: Option<f64> = None;
+        self.scan(&bytes, |_, dp| {
+            if !dp.is_undefined() && max.map_or(true, |m| dp > m) {
+                max = Some(dp);
+            }
+            true
+        });
+        max
+    }
+
+    pub(crate) fn min(&self, bytes: Vec<u8>) -> Option<f64> {
+        let mut min: Option<f64> = None;
+        self.scan(&bytes, |_, dp| {
+            if !dp.is_undefined() && min.map_or(true, |m| dp < m) {
+                min = Some(dp);
+            }
+            true
+        });
+        min
+    }
+}
+
+impl<'a, T> CompressionMethod<T> for ChimpCompress
+    where T: Serialize + Clone+ Copy+ FromPrimitive+ Into<f64>+ Deserialize<'a>{
+    fn get_segments(&self) {
+        unimplemented!()
+    }
+
+    fn get_batch(&self) -> usize {
+        self.batchsize
+    }
+
+    fn run_compress<'b>(&self, segs: &mut Vec<Segment<T>>) {
+        for seg in segs {
+            let binary = self.encode(seg);
+            seg.set_comp(Some(binary));
+            seg.set_data(Vec::new());
+            seg.set_method(self.method());
+        }
+    }
+
+    fn run_decompress(&self, seg: &mut Segment<T>) {
+        let vec = self.decode_general(seg.get_comp());
+        seg.set_comp(None);
+        seg.set_data(vec);
+        seg.set_method(self.method());
+    }
+}
+
+#[test]
+fn chimp_round_trip_test() {
+    use std::time::SystemTime;
+
+    let mut data: Vec<f64> = (0..1000).map(|x| (x as f64 * 0.01).sin() * 1234.5678).collect();
+    /* Repeats and values seen a while ago exercise every flag */
+    data.extend(data[..300].to_vec());
+    data.extend(vec![0.0, -0.0, std::f64::MAX, std::f64::MIN_POSITIVE, 1.0, 1.0]);
+    for window in vec![CHIMP_WINDOW, CHIMP128_WINDOW] {
+        let comp = ChimpCompress::new(10, 10, window);
+        let mut seg = Segment::new(None, SystemTime::now(), 0, data.clone(), None, None);
+        let bytes = comp.encode(&mut seg);
+        assert!(bytes.len() < data.len() * 8);
+        let decoded = comp.decode(bytes.clone());
+        assert_eq!(decoded.len(), data.len());
+        for (a, b) in data.iter().zip(decoded.iter()) {
+            assert_eq!(a.to_bits(), b.to_bits());
+        }
+        let picked = comp.decode_condition(bytes, vec![0usize, 5, 999, 1300].iter());
+        assert_eq!(picked, vec![data[0], data[5], data[999], data[1300]]);
+    }
+}
@@ -0,0 +1,471 @@
+use croaring::Bitmap;
+use crate::segment::Segment;
+use crate::segment::validity::Nullable;
+use crate::methods::Methods;
+use crate::methods::bit_packing::BitPack;
+use crate::methods::compress::{GZipCompress, SnappyCompress, ZlibCompress};
+use crate::methods::registry::decode_segment;
+use crate::compress::sprintz::SprintzDoubleCompress;
+use crate::compress::split_double::SplitBDDoubleCompress;
+use crate::compress::gorilla::GorillaCompress;
+use crate::compress::buff_slice::BuffSliceCompress;
+use crate::compress::scaled_slice::ScaledSliceCompress;
+use crate::compress::chimp::{ChimpCompress, CHIMP_WINDOW, CHIMP128_WINDOW};
+use crate::compress::elf::ElfCompress;
+
+/*
+ * Overview:
+ * Aggregations and filters that answer a query on the compressed bytes
+ * of a segment and hand the result back to the caller, instead of
+ * printing it the way the benchmark functions of the compressors do.
+ *
+ * Design Choice:
+ * Only decode_values is required, every other query has a default that
+ * decodes the bytes and computes on the values. A codec overrides the
+ * queries it can answer without materializing the data, so callers get
+ * the same results whichever way the codec answers them.
+ * Filters return the row positions that qualify as a Bitmap, the same
+ * type the compressors already build while scanning.
+ *
+ * Current Implementations:
+ * Sprintz answers sum, max, count and both filters in the compressed domain,
+ * Buff (byte fixed) sum and both filters, BuffSlice and ScaledSlice sum
+ * and max, Gorilla sum. Chimp, Chimp128 and Elf answer every query
+ * while decoding their stream, without collecting the values.
+ * Gzip, Snappy and Zlib fall back to decoding.
+ * The SIMD slice filters skip the tail of a segment that does not fill
+ * a full vector so they are not used here.
+ */
+
+pub trait CompressedQuery {
+    /* Decodes the compressed bytes back to their values */
+    fn decode_values(&self, bytes: Vec<u8>) -> Vec<f64>;
+
+    fn sum(&self, bytes: Vec<u8>) -> f64 {
+        values_sum(&self.decode_values(bytes))
+    }
+
+    /* None when the segment holds no values */
+    fn max(&self, bytes: Vec<u8>) -> Option<f64> {
+        values_max(&self.decode_values(bytes))
+    }
+
+    fn min(&self, bytes: Vec<u8>) -> Option<f64> {
+        values_min(&self.decode_values(bytes))
+    }
+
+    fn count(&self, bytes: Vec<u8>) -> usize {
+        values_count(&self.decode_values(bytes))
+    }
+
+    /* Rows whose value is greater than pred */
+    fn range_filter(&self, bytes: Vec<u8>, pred: f64) -> Bitmap {
+        values_range_filter(&self.decode_values(bytes), pred)
+    }
+
+    /* Rows whose value equals pred */
+    fn equal_filter(&self, bytes: Vec<u8>, pred: f64) -> Bitmap {
+        values_equal_filter(&self.decode_values(bytes), pred)
+    }
+}
+
+/* The aggregations below skip missing values and NaNs, see Nullable::is_undefined */
+pub fn values_sum(values: &[f64]) -> f64 {
+    values.iter().filter(|v| !v.is_undefined()).sum()
+}
+
+pub fn values_count(values: &[f64]) -> usize {
+    values.iter().filter(|v| !v.is_undefined()).count()
+}
+
+pub fn values_max(values: &[f64]) -> Option<f64> {
+    values.iter().cloned().filter(|v| !v.is_undefined()).fold(None, |acc, v| match acc {
+        Some(m) if m >= v => Some(m),
+        _ => Some(v),
+    })
+}
+
+pub fn values_min(values: &[f64]) -> Option<f64> {
+    values.iter().cloned().filter(|v| !v.is_undefined()).fold(None, |acc, v| match acc {
+        Some(m) if m <= v => Some(m),
+        _ => Some(v),
+    })
+}
+
+pub fn values_range_filter(values: &[f64], pred: f64) -> Bitmap {
+    let mut res = Bitmap::create();
+    for (i, v) in values.iter().enumerate() {
+        if *v > pred {
+            res.add(i as u32);
+        }
+    }
+    res
+}
+
+pub fn values_equal_filter(values: &[f64], pred: f64) -> Bitmap {
+    let mut res = Bitmap::create();
+    for (i, v) in values.iter().enumerate() {
+        if *v == pred {
+            res.add(i as u32);
+        }
+    }
+    res
+}
+
+impl CompressedQuery for SprintzDoubleCompress {
+    fn decode_values(&self, bytes: Vec<u8>) -> Vec<f64> {
+        self.decode_general(&bytes)
+    }
+
+    fn sum(&self, bytes: Vec<u8>) -> f64 {
+        SprintzDoubleCompress::sum(self, bytes)
+    }
+
+    fn max(&self, bytes: Vec<u8>) -> Option<f64> {
+        /* The element count follows the 32 bit base value */
+        let mut bitpack = BitPack::<&[u8]>::new(bytes.as_slice());
+        bitpack.read(32).unwrap();
+        if bitpack.read(32).unwrap() == 0 {
+            return None;
+        }
+        Some(SprintzDoubleCompress::max(self, bytes))
+    }
+
+    fn count(&self, bytes: Vec<u8>) -> usize {
+        let mut bitpack = BitPack::<&[u8]>::new(bytes.as_slice());
+        bitpack.read(32).unwrap();
+        bitpack.read(32).unwrap() as usize
+    }
+
+    fn range_filter(&self, bytes: Vec<u8>, pred: f64) -> Bitmap {
+        SprintzDoubleCompress::range_filter(self, bytes, pred)
+    }
+
+    fn equal_filter(&self, bytes: Vec<u8>, pred: f64) -> Bitmap {
+        SprintzDoubleCompress::equal_filter(self, bytes, pred)
+    }
+}
+
+impl CompressedQuery for SplitBDDoubleCompress {
+    fn decode_values(&self, bytes: Vec<u8>) -> Vec<f64> {
+        self.decode_general(&bytes)
+    }
+
+    fn sum(&self, bytes: Vec<u8>) -> f64 {
+        self.byte_fixed_sum(bytes)
+    }
+
+    fn range_filter(&self, bytes: Vec<u8>, pred: f64) -> Bitmap {
+        self.byte_fixed_range_filter(bytes, pred)
+    }
+
+    fn equal_filter(&self, bytes: Vec<u8>, pred: f64) -> Bitmap {
+        self.byte_fixed_equal_filter(bytes, pred)
+    }
+}
+
+impl CompressedQuery for BuffSliceCompress {
+    fn decode_values(&self, bytes: Vec<u8>) -> Vec<f64> {
+        self.buff_slice_decode(bytes)
+    }
+
+    fn sum(&self, bytes: Vec<u8>) -> f64 {
+        self.buff_slice_sum(bytes)
+    }
+
+    fn max(&self, bytes: Vec<u8>) -> Option<f64> {
+        if bytes.is_empty() {
+            return None;
+        }
+        Some(self.buff_slice_max(bytes))
+    }
+}
+
+impl CompressedQuery for ScaledSliceCompress {
+    fn decode_values(&self, bytes: Vec<u8>) -> Vec<f64> {
+        self.scaled_slice_decode(bytes)
+    }
+
+    fn sum(&self, bytes: Vec<u8>) -> f64 {
+        self.scaled_slice_sum(bytes)
+    }
+
+    fn max(&self, bytes: Vec<u8>) -> Option<f64> {
+        if bytes.is_empty() {
+            return None;
+        }
+        Some(self.scaled_slice_max(bytes))
+    }
+}
+
+impl CompressedQuery for GorillaCompress {
+    fn decode_values(&self, bytes: Vec<u8>) -> Vec<f64> {
+        self.decode_general(&bytes)
+    }
+
+    fn sum(&self, bytes: Vec<u8>) -> f64 {
+        GorillaCompress::sum(self, bytes)
+    }
+}
+
+impl CompressedQuery for ChimpCompress {
+    fn decode_values(&self, bytes: Vec<u8>) -> Vec<f64> {
+        self.decode(bytes)
+    }
+
+    fn sum(&self, bytes: Vec<u8>) -> f64 {
+        ChimpCompress::sum(self, bytes)
+    }
+
+    fn max(&self, bytes: Vec<u8>) -> Option<f64> {
+        ChimpCompress::max(self, bytes)
+    }
+
+    fn min(&self, bytes: Vec<u8>) -> Option<f64> {
+        ChimpCompress::min(self, bytes)
+    }
+
+    fn count(&self, bytes: Vec<u8>) -> usize {
+        ChimpCompress::count(self, bytes)
+    }
+
+    fn range_filter(&self, bytes: Vec<u8>, pred: f64) -> Bitmap {
+        ChimpCompress::range_filter(self, bytes, pred)
+    }
+
+    fn equal_filter(&self, bytes: Vec<u8>, pred: f64) -> Bitmap {
+        ChimpCompress::equal_filter(self, bytes, pred)
+    }
+}
+
+impl CompressedQuery for ElfCompress {
+    fn decode_values(&self, bytes: Vec<u8>) -> Vec<f64> {
+        self.decode(bytes)
+    }
+
+    fn sum(&self, bytes: Vec<u8>) -> f64 {
+        ElfCompress::sum(self, bytes)
+    }
+
+    fn max(&self, bytes: Vec<u8>) -> Option<f64> {
+        ElfCompress::max(self, bytes)
+    }
+
+    fn min(&self, bytes: Vec<u8>) -> Option<f64> {
+        ElfCompress::min(self, bytes)
+    }
+
+    fn count(&self, bytes: Vec<u8>) -> usize {
+        ElfCompress::count(self, bytes)
+    }
+
+    fn range_filter(&self, bytes: Vec<u8>, pred: f64) -> Bitmap {
+        ElfCompress::range_filter(self, bytes, pred)
+    }
+
+    fn equal_filter(&self, bytes: Vec<u8>, pred: f64) -> Bitmap {
+        ElfCompress::equal_filter(self, bytes, pred)
+    }
+}
+
+impl CompressedQuery for GZipCompress {
+    fn decode_values(&self, bytes: Vec<u8>) -> Vec<f64> {
+        self.decode(&bytes)
+    }
+}
+
+impl CompressedQuery for SnappyCompress {
+    fn decode_values(&self, bytes: Vec<u8>) -> Vec<f64> {
+        self.decode(&bytes)
+    }
+}
+
+impl CompressedQuery for ZlibCompress {
+    fn decode_values(&self, bytes: Vec<u8>) -> Vec<f64> {
+        self.decode(&bytes)
+    }
+}
+
+/* The query engine for a method whose segments keep their compressed bytes,
+ * None for methods that keep their result in the data of the segment
+ */
+pub fn query_for(method: &Methods) -> Option<Box<dyn CompressedQuery>> {
+    match method {
+        Methods::Gorilla => Some(Box::new(GorillaCompress::new(10, 20))),
+        Methods::Gzip => Some(Box::new(GZipCompress::new(10, 20))),
+        Methods::Snappy => Some(Box::new(SnappyCompress::new(10, 20))),
+        Methods::Zlib => Some(Box::new(ZlibCompress::new(10, 20, 5))),
+        Methods::Sprintz (scale) => Some(Box::new(SprintzDoubleCompress::new(10, 20, *scale))),
+        Methods::Buff (scale) => Some(Box::new(SplitBDDoubleCompress::new(10, 20, *scale))),
+        Methods::Chimp => Some(Box::new(ChimpCompress::new(10, 20, CHIMP_WINDOW))),
+        Methods::Chimp128 => Some(Box::new(ChimpCompress::new(10, 20, CHIMP128_WINDOW))),
+        Methods::Elf => Some(Box::new(ElfCompress::new(10, 20))),
+        _ => None,
+    }
+}
+
+/* Runs queries against a segment whatever its method, through the
+ * compressed bytes when the method has a query engine and otherwise on
+ * the values decoded through the codec registry
+ */
+pub struct SegmentQuery<'a> {
+    seg: &'a Segment<f64>,
+    engine: Option<Box<dyn CompressedQuery>>,
+    values: Vec<f64>,
+}
+
+impl<'a> SegmentQuery<'a> {
+    /* None when the segment has neither a query engine nor a codec to decode it */
+    pub fn new(seg: &'a Segment<f64>) -> Option<SegmentQuery<'a>> {
+        /* The compressed bytes hold fill values where non finite ones were set aside */
+        let engine = match (seg.get_method(), seg.get_validity()) {
+            (Some(m), None) => query_for(m),
+            _ => None,
+        };
+        let values = match engine {
+            Some(_) => Vec::new(),
+            None => decode_segment(seg)?,
+        };
+        Some(SegmentQuery { seg: seg, engine: engine, values: values })
+    }
+
+    /* True when the queries are answered without decoding through the registry */
+    pub fn is_compressed_domain(&self) -> bool {
+        self.engine.is_some()
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        self.seg.get_comp().clone()
+    }
+
+    pub fn sum(&self) -> f64 {
+        match &self.engine {
+            Some(q) => q.sum(self.bytes()),
+            None => values_sum(&self.values),
+        }
+    }
+
+    pub fn max(&self) -> Option<f64> {
+        match &self.engine {
+            Some(q) => q.max(self.bytes()),
+            None => values_max(&self.values),
+        }
+    }
+
+    pub fn min(&self) -> Option<f64> {
+        match &self.engine {
+            Some(q) => q.min(self.bytes()),
+            None => values_min(&self.values),
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        match &self.engine {
+            Some(q) => q.count(self.bytes()),
+            None => values_count(&self.values),
+        }
+    }
+
+    pub fn range_filter(&self, pred: f64) -> Bitmap {
+        match &self.engine {
+            Some(q) => q.range_filter(self.bytes(), pred),
+            None => values_range_filter(&self.values, pred),
+        }
+    }
+
+    pub fn equal_filter(&self, pred: f64) -> Bitmap {
+        match &self.engine {
+            Some(q) => q.equal_filter(self.bytes(), pred),
+            None => values_equal_filter(&self.values, pred),
+        }
+    }
+}
+
+#[test]
+fn test_compressed_query_matches_decoded() {
+    use crate::methods::registry::codec_for;
+
+    let data: Vec<f64> = (0..300).map(|x| ((x as f64 * 0.21).sin() * 200.0).round() / 4.0).collect();
+    let pred = 12.5;
+    let expected_sum = values_sum(&data);
+    let expected_gt = values_range_filter(&data, pred);
+    let expected_eq = values_equal_filter(&data, data[7]);
+
+    for method in vec![Methods::Sprintz (100), Methods::Buff (100), Methods::Gorilla,
+                       Methods::Chimp, Methods::Chimp128, Methods::Elf, Methods::Gzip, Methods::Paa (1)] {
+        let mut seg = Segment::new(None, std::time::SystemTime::now(), 0, data.clone(), None, None);
+        codec_for(&method).unwrap().encode(&mut seg);
+        let query = SegmentQuery::new(&seg).unwrap();
+        assert!((query.sum() - expected_sum).abs() < 0.1, "{} sum", method);
+        assert!((query.max().unwrap() - values_max(&data).unwrap()).abs() < 0.01, "{} max", method);
+        assert!((query.min().unwrap() - values_min(&data).unwrap()).abs() < 0.01, "{} min", method);
+        assert_eq!(query.count(), data.len(), "{} count", method);
+        assert_eq!(query.range_filter(pred), expected_gt, "{} range filter", method);
+        assert_eq!(query.equal_filter(data[7]), expected_eq, "{} equal filter", method);
+    }
+}
+
+#[test]
+fn test_buff_filters_match_decoded() {
+    use crate::methods::registry::codec_for;
+
+    /* Lengths off a multiple of 8 and scales and ranges that leave the
+     * encoded width below a byte, on a byte boundary and with trailing bits
+     */
+    for &len in &[13usize, 1003] {
+        for &(scale, range) in &[(10usize, 3.0), (10, 20.0), (10, 40.0), (10, 200.0), (100, 2.0), (100, 300.0),
+                                   (100, 500.0), (1000, 8.0), (1000, 30.0), (1000, 60.0), (10000, 900.0)] {
+            let data: Vec<f64> = (0..len)
+                .map(|x| (((x as f64 * 0.37).sin() + 1.0) / 2.0 * range * scale as f64).round() / scale as f64)
+                .collect();
+            let method = Methods::Buff (scale);
+            let mut seg = Segment::new(None, std::time::SystemTime::now(), 0, data.clone(), None, None);
+            codec_for(&method).unwrap().encode(&mut seg);
+            let query = SegmentQuery::new(&seg).unwrap();
+            assert!(query.is_compressed_domain());
+            /* Decoded values are only exact to the scale */
+            let decoded: Vec<f64> = decode_segment(&seg).unwrap().iter()
+                .map(|v| (v * scale as f64).round() / scale as f64)
+                .collect();
+            for &i in &[0, 5, len / 2, len - 1] {
+                let pred = data[i];
+                assert_eq!(query.equal_filter(pred), values_equal_filter(&decoded, pred),
+                           "equal {} on {} values at scale {} over {}", pred, len, scale, range);
+                assert_eq!(query.range_filter(pred), values_range_filter(&decoded, pred),
+                           "range {} on {} values at scale {} over {}", pred, len, scale, range);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_aggregations_skip_nan() {
+    let null = f64::null().unwrap();
+    let values = vec![1.5, std::f64::NAN, null, -2.0, std::f64::INFINITY, 4.0];
+    assert_eq!(values_count(&values), 4);
+    assert_eq!(values_max(&values), Some(std::f64::INFINITY));
+    assert_eq!(values_min(&values), Some(-2.0));
+    assert_eq!(values_sum(&values[..4]), -0.5);
+    assert_eq!(values_max(&[std::f64::NAN, null]), None);
+}
+
+#[test]
+fn test_stream_queries_skip_nan() {
+    /* The codec registry sets NaNs aside, so they only reach the engines through their own encoders */
+    let data = vec![2.5, std::f64::NAN, -1.25, 7.0, 2.5];
+    let mut seg = Segment::new(None, std::time::SystemTime::now(), 0, data, None, None);
+    for method in vec![Methods::Chimp, Methods::Chimp128, Methods::Elf] {
+        let bytes = match method {
+            Methods::Chimp => ChimpCompress::new(10, 20, CHIMP_WINDOW).encode(&mut seg),
+            Methods::Chimp128 => ChimpCompress::new(10, 20, CHIMP128_WINDOW).encode(&mut seg),
+            _ => ElfCompress::new(10, 20).encode(&mut seg),
+        };
+        let query = query_for(&method).unwrap();
+        assert_eq!(query.sum(bytes.clone()), 10.75, "{} sum", method);
+        assert_eq!(query.count(bytes.clone()), 4, "{} count", method);
+        assert_eq!(query.max(bytes.clone()), Some(7.0), "{} max", method);
+        assert_eq!(query.min(bytes.clone()), Some(-1.25), "{} min", method);
+        assert_eq!(query.range_filter(bytes.clone(), 2.0).iter().collect::<Vec<u32>>(), vec![0, 3, 4], "{} range filter", method);
+        assert_eq!(query.equal_filter(bytes, 2.5).iter().collect::<Vec<u32>>(), vec![0, 4], "{} equal filter", method);
+    }
+}
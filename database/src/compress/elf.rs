@@ -0,0 +1,309 @@
+use crate::segment::Segment;
+use serde::{Deserialize, Serialize};
+use tsz::stream::{BufferedWriter, BufferedReader, Write, Read};
+use std::time::Instant;
+use std::mem;
+use log::info;
+use croaring::Bitmap;
+use crate::methods::compress::CompressionMethod;
+use crate::compress::chimp::{ChimpEncoder, ChimpDecoder, CHIMP_WINDOW};
+use std::slice::Iter;
+use num::FromPrimitive;
+use crate::methods::Methods;
+use crate::segment::validity::Nullable;
+
+/*
+ * Overview:
+ * Elf (Li et al., VLDB 2023) erases the trailing mantissa bits a value
+ * does not need to be told apart at its decimal precision, so the XOR
+ * with its neighbours ends in long runs of zeros. The decimal places
+ * are found per value, no scale has to be known up front.
+ *
+ * Design Choice:
+ * A value with alpha decimal places is within 10^-alpha of itself with
+ * all but ceil(alpha * log2(10)) + exponent mantissa bits cleared, and
+ * rounding the erased value up at alpha places gives it back. Erasing
+ * is only done when it clears more than 4 set bits and the restored
+ * value has the exact bits of the original, everything else, including
+ * NaN, infinities and subnormals, is kept as is. Per value a flag is
+ * written in front of its bits in the Chimp XOR stream
+ *   0 the value is kept as is
+ *   10 the value is erased with the decimal places of the last erased one
+ *   11 the value is erased with the 5 bit decimal places that follow
+ * after the 32 bit value count the Chimp stream starts with.
+ */
+
+/* Erasing fewer bits does not pay for the flag and decimal places */
+const MIN_ERASED: i32 = 5;
+
+const MAX_DECIMALS: u32 = 31;
+
+/* The decimal places of the shortest representation that reads back as v */
+fn decimal_places(v: f64) -> u32 {
+    let repr = format!("{}", v.abs());
+    match repr.find('.') {
+        Some(idx) => (repr.len() - idx - 1) as u32,
+        None => 0,
+    }
+}
+
+/* Rounds the erased value away from zero at the decimal places */
+fn restore(erased: f64, decimals: u32) -> f64 {
+    let scale = 10f64.powi(decimals as i32);
+    if erased < 0.0 {
+        -((-erased * scale).ceil() / scale)
+    } else {
+        (erased * scale).ceil() / scale
+    }
+}
+
+/* The decimal places and the bits of the erased value, if erasing pays off */
+fn erase(v: f64) -> Option<(u32, u64)> {
+    let bits = v.to_bits();
+    let exponent = ((bits >> 52) & 0x7ff) as i32;
+    if v == 0.0 || exponent == 0 || exponent == 0x7ff {
+        return None;
+    }
+    let decimals = decimal_places(v);
+    if decimals > MAX_DECIMALS {
+        return None;
+    }
+    let keep = (decimals as f64 * std::f64::consts::LOG2_10).ceil() as i32 + exponent - 1023;
+    let erased_bits = 52 - keep.max(0);
+    if erased_bits < MIN_ERASED {
+        return None;
+    }
+    let erased = bits & (!0u64 << erased_bits);
+    if erased == bits || restore(f64::from_bits(erased), decimals).to_bits() != bits {
+        return None;
+    }
+    Some((decimals, erased))
+}
+
+#[derive(Clone)]
+pub struct ElfCompress {
+    chunksize: usize,
+    batchsize: usize,
+}
+
+impl ElfCompress {
+    pub fn new(chunksize: usize, batchsize: usize) -> Self {
+        ElfCompress { chunksize, batchsize }
+    }
+
+    pub fn encode<'a,T>(&self, seg: &mut Segment<T>) -> Vec<u8>
+        where T: Serialize + Clone+ Copy+Into<f64> + Deserialize<'a>{
+        let mut w = BufferedWriter::new();
+        let mut encoder = ChimpEncoder::new(CHIMP_WINDOW);
+        let mut last_decimals = None;
+        let mut erased_count = 0;
+        let start = Instant::now();
+        w.write_bits(seg.get_data().len() as u64, 32);
+        for val in seg.get_data() {
+            let v: f64 = (*val).into();
+            match erase(v) {
+                Some((decimals, erased)) => {
+                    if last_decimals == Some(decimals) {
+                        w.write_bits(0b10, 2);
+                    } else {
+                        w.write_bits(0b11, 2);
+                        w.write_bits(decimals as u64, 5);
+                        last_decimals = Some(decimals);
+                    }
+                    encoder.encode(&mut w, erased);
+                    erased_count += 1;
+                }
+                None => {
+                    w.write_bits(0, 1);
+                    encoder.encode(&mut w, v.to_bits());
+                }
+            }
+        }
+        let duration = start.elapsed();
+        info!("Time elapsed in elf function() is: {:?}, {} values erased", duration, erased_count);
+        let origin = seg.get_data().len() * mem::size_of::<T>();
+        info!("original size:{}", origin);
+        let byte_vec = w.close().to_vec();
+        info!("compressed size:{}", byte_vec.len());
+        byte_vec
+    }
+
+    /* Hands every value to f in order until f returns false */
+    fn scan<F: FnMut(usize, f64) -> bool>(&self, bytes: &[u8], mut f: F) {
+        let mut r = BufferedReader::new(bytes.to_vec().into_boxed_slice());
+        let mut decoder = ChimpDecoder::new(CHIMP_WINDOW);
+        let mut decimals = 0;
+        let len = match r.read_bits(32) {
+            Ok(len) => len as usize,
+            Err(err) => panic!("Received an error from decoder: {:?}", err),
+        };
+        for i in 0..len {
+            let dp = r.read_bits(1)
+                .and_then(|erased| {
+                    if erased == 1 && r.read_bits(1)? == 1 {
+                        decimals = r.read_bits(5)? as u32;
+                    }
+                    let bits = decoder.decode(&mut r)?;
+                    Ok(if erased == 1 { restore(f64::from_bits(bits), decimals) } else { f64::from_bits(bits) })
+                });
+            match dp {
+                Ok(dp) => if !f(i, dp) {
+                    break;
+                },
+                Err(err) => panic!("Received an error from decoder: {:?}", err),
+            }
+        }
+    }
+
+    pub(crate) fn decode(&self, bytes: Vec<u8>) -> Vec<f64> {
+        let mut expected_datapoints = Vec::new();
+        self.scan(&bytes, |_, dp| { expected_datapoints.push(dp); true });
+        expected_datapoints
+    }
+
+    pub(crate) fn decode_general<T>(&self, bytes: &Vec<u8>) -> Vec<T>
+        where T: FromPrimitive{
+        let mut expected_datapoints = Vec::new();
+        self.scan(bytes, |_, dp| { expected_datapoints.push(FromPrimitive::from_f64(dp).unwrap()); true });
+        expected_datapoints
+    }
+
+    pub fn decode_condition(&self, bytes: Vec<u8>, cond:Iter<usize>) -> Vec<f64> {
+        let mut iter = cond.clone();
+        let mut point = iter.next();
+        let mut expected_datapoints:Vec<f64> = Vec::new();
+        self.scan(&bytes, |i, dp| {
+            if Some(&i) == point {
+                expected_datapoints.push(dp);
+                point = iter.next();
+            }
+            point.is_some()
+        });
+        println!("Number of scan items:{}", expected_datapoints.len());
+        expected_datapoints
+    }
+
+    /* The aggregations and filters run on the values as they are
+     * decoded, undefined values are skipped like in CompressedQuery
+     */
+    pub(crate) fn sum(&self, bytes: Vec<u8>) -> f64 {
+        let mut sum = 0f64;
+        self.scan(&bytes, |_, dp| {
+            if !dp.is_undefined() {
+                sum += dp;
+            }
+            true
+        });
+        sum
+    }
+
+    pub(crate) fn count(&self, bytes: Vec<u8>) -> usize {
+        let mut count = 0;
+        self.scan(&bytes, |_, dp| {
+            if !dp.is_undefined() {
+                count += 1;
+            }
+            true
+        });
+        count
+    }
+
+    pub(crate) fn range_filter(&self, bytes: Vec<u8>,pred:f64) -> Bitmap {
+        let mut res = Bitmap::create();
+        self.scan(&bytes, |i, dp| {
+            if dp>pred {
+                res.add(i as u32);
+            }
+            true
+        });
+        res
+    }
+
+    pub(crate) fn equal_filter(&self, bytes: Vec<u8>,pred:f64) -> Bitmap {
+        let mut res = Bitmap::create();
+        self.scan(&bytes, |i, dp| {
+            if dp==pred {
+                res.add(i as u32);
+            }
+            true
+        });
+        res
+    }
+
+    /* None when the segment holds no defined values */
+    pub(crate) fn max(&self, bytes: Vec<u8>) -> Option<f64> {
+        let mut max: Option<f64> = None;
+        self.scan(&bytes, |_, dp| {
+            if !dp.is_undefined() && max.map_or(true, |m| dp > m) {
+                max = Some(dp);
+            }
+            true
+        });
+        max
+    }
+
+    pub(crate) fn min(&self, bytes: Vec<u8>) -> Option<f64> {
+        let mut min: Option<f64> = None;
+        self.scan(&bytes, |_, dp| {
+            if !dp.is_undefined() && min.map_or(true, |m| dp < m) {
+                min = Some(dp);
+            }
+            true
+        });
+        min
+    }
+}
+
+impl<'a, T> CompressionMethod<T> for ElfCompress
+    where T: Serialize + Clone+ Copy+ FromPrimitive+ Into<f64>+ Deserialize<'a>{
+    fn get_segments(&self) {
+        unimplemented!()
+    }
+
+    fn get_batch(&self) -> usize {
+        self.batchsize
+    }
+
+    fn run_compress<'b>(&self, segs: &mut Vec<Segment<T>>) {
+        for seg in segs {
+            let binary = self.encode(seg);
+            seg.set_comp(Some(binary));
+            seg.set_data(Vec::new());
+            seg.set_method(Methods::Elf);
+        }
+    }
+
+    fn run_decompress(&self, seg: &mut Segment<T>) {
+        let vec = self.decode_general(seg.get_comp());
+        seg.set_comp(None);
+        seg.set_data(vec);
+        seg.set_method(Methods::Elf);
+    }
+}
+
+#[test]
+fn elf_round_trip_test() {
+    use std::time::SystemTime;
+    use crate::compress::chimp::ChimpCompress;
+
+    /* Sensor like readings with a few decimal places, and values Elf has to keep as is */
+    let mut data: Vec<f64> = (0..1000).map(|x| ((x as f64 * 0.01).sin() * 1234.5678 * 1000.0).round() / 1000.0).collect();
+    data.extend(vec![0.0, -0.0, 0.1, -2.75, 1.0 / 3.0, std::f64::consts::PI, 1e-310, std::f64::MAX, 123456789.123]);
+    assert_eq!(erase(0.1).map(|(decimals, _)| decimals), Some(1));
+    assert_eq!(erase(1.0 / 3.0), None);
+
+    let comp = ElfCompress::new(10, 10);
+    let mut seg = Segment::new(None, SystemTime::now(), 0, data.clone(), None, None);
+    let bytes = comp.encode(&mut seg);
+    let decoded = comp.decode(bytes.clone());
+    assert_eq!(decoded.len(), data.len());
+    for (a, b) in data.iter().zip(decoded.iter()) {
+        assert_eq!(a.to_bits(), b.to_bits(), "{} came back as {}", a, b);
+    }
+
+    /* The erased mantissas leave Chimp less to store */
+    let chimp = ChimpCompress::new(10, 10, CHIMP_WINDOW).encode(&mut seg);
+    assert!(bytes.len() < chimp.len());
+    let picked = comp.decode_condition(bytes, vec![1usize, 1001, 1003].iter());
+    assert_eq!(picked, vec![data[1], data[1001], data[1003]]);
+}
@@ -8,7 +8,7 @@ use log::info;
 use crate::methods::gorilla_decoder::{GorillaDecoder, SepDecode};
 use tsz::decode::Error;
 use croaring::Bitmap;
-use crate::methods::compress::CompressionMethod;
+use crate::methods::compress::{CompressionMethod, StreamingEncoder};
 use crate::methods::prec_double::{get_precision_bound, PrecisionBound};
 use std::slice::Iter;
 use my_bit_vec::BitVec;
@@ -26,6 +26,11 @@ impl GorillaCompress {
         GorillaCompress { chunksize, batchsize }
     }
 
+    /* Encodes points as they arrive, to the bytes encode gives */
+    pub fn streaming(&self) -> GorillaStreamEncoder {
+        GorillaStreamEncoder::new()
+    }
+
     // Compress a sample string and print it after transformation.
     pub fn encode<'a,T>(&self, seg: &mut Segment<T>) -> Vec<u8>
         where T: Serialize + Clone+ Copy+Into<f64> + Deserialize<'a>{
@@ -196,7 +201,6 @@ impl GorillaCompress {
                 }
             };
         }
-        println!("sum is: {:?}",sum);
         sum
     }
 
@@ -446,6 +450,51 @@ impl<'a, T> CompressionMethod<T> for GorillaCompress
     }
 }
 
+/* Gorilla already encodes one value at a time, the stream encoder keeps
+ * the encoder open between points and closes it on finish.
+ */
+pub struct GorillaStreamEncoder {
+    encoder: GorillaEncoder<BufferedWriter>,
+    count: usize,
+}
+
+impl GorillaStreamEncoder {
+    pub fn new() -> Self {
+        GorillaStreamEncoder {
+            encoder: GorillaEncoder::new(0, BufferedWriter::new()),
+            count: 0,
+        }
+    }
+}
+
+impl Default for GorillaStreamEncoder {
+    fn default() -> Self {
+        GorillaStreamEncoder::new()
+    }
+}
+
+impl<T> StreamingEncoder<T> for GorillaStreamEncoder
+    where T: Into<f64> {
+    fn push(&mut self, value: T) {
+        self.encoder.encode_float(value.into());
+        self.count += 1;
+    }
+
+    fn len(&self) -> usize {
+        self.count
+    }
+
+    fn finish(&mut self) -> Vec<u8> {
+        let encoder = mem::replace(&mut self.encoder, GorillaEncoder::new(0, BufferedWriter::new()));
+        self.count = 0;
+        encoder.close().to_vec()
+    }
+
+    fn method(&self) -> Methods {
+        Methods::Gorilla
+    }
+}
+
 #[derive(Clone)]
 pub struct GorillaBDCompress {
     chunksize: usize,
@@ -634,7 +683,6 @@ impl GorillaBDCompress {
                 }
             };
         }
-        println!("sum is: {:?}",sum);
         sum
     }
 
@@ -845,4 +893,24 @@ impl<'a, T> CompressionMethod<T> for GorillaBDCompress
     }
 }
 
+#[test]
+fn gorilla_stream_test() {
+    use std::time::SystemTime;
+
+    let data: Vec<f64> = (0..500).map(|x| (x as f64 * 0.03).cos() * 87.25).collect();
+    let comp = GorillaCompress::new(10, 10);
+    let mut seg = Segment::new(None, SystemTime::now(), 0, data.clone(), None, None);
+    let batch = comp.encode(&mut seg);
 
+    let mut stream = comp.streaming();
+    /* The encoder is reused, the second segment must not see the first */
+    for _ in 0..2 {
+        for v in data.iter() {
+            stream.push(*v);
+        }
+        assert_eq!(StreamingEncoder::<f64>::len(&stream), data.len());
+        let bytes = StreamingEncoder::<f64>::finish(&mut stream);
+        assert_eq!(bytes, batch);
+        assert_eq!(comp.decode(bytes), data);
+    }
+}
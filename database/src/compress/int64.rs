@@ -0,0 +1,445 @@
+use crate::segment::Segment;
+use std::time::Instant;
+use std::mem;
+use log::info;
+use crate::methods::compress::CompressionMethod;
+use crate::methods::bit_packing::{BitPack, zigzag64, unzigzag64};
+use crate::methods::Methods;
+use crate::segment::header::FormatError;
+
+/*
+ * Overview:
+ * Codecs for signals of 64-bit integers, counters and nanosecond
+ * timestamps that overflow the i32/u32 of FCM, DFCM and the BP
+ * compressors. Delta, delta-of-delta and zigzag pack the zigzag of the
+ * value, its delta to the previous value or the change of that delta,
+ * frame-of-reference (FOR) packs each value as its offset to the
+ * minimum of its block, Simple-8b packs zigzag deltas into 64-bit words.
+ *
+ * Design Choice:
+ * All arithmetic is done on the two's complement bits with wrapping,
+ * so i64 and u64 take the same path and every value, including the
+ * extremes, comes back exactly. Packing is done in blocks of INT_BLOCK
+ * values with a width of their own, so a single outlier only widens
+ * its block.
+ *
+ * Byte Format:
+ * The format is stable, segments written with it are read back by
+ * later versions. Everything is written with BitPack, least
+ * significant bit first.
+ *   32 bits  number of values
+ *   64 bits  per head residual, the first value for Delta, the first
+ *            value and delta for DeltaOfDelta
+ *   Delta, DeltaOfDelta, Zigzag, per block of INT_BLOCK values
+ *     7 bits   width w, 0 to 64
+ *     w bits   per residual
+ *   For, per block of INT_BLOCK values
+ *     7 bits   width w, 0 to 64
+ *     64 bits  minimum of the block
+ *     w bits   per offset to the minimum
+ *   Simple8b, 64 bit words until all values are read
+ *     4 bits on top select the layout of the 60 bits below, see
+ *     SIMPLE8B, the first value in the lowest bits. Selector 1 is
+ *     the escape for a residual of more than 60 bits, it is stored
+ *     in the 64 bits after the word.
+ * Residuals are zigzags, the first value is taken against a previous
+ * value of 0 and the first delta against a previous delta of 0.
+ */
+
+/* Values that share a width */
+pub const INT_BLOCK: usize = 128;
+
+const WIDTH_BITS: usize = 7;
+
+/* Values per word and their bits by selector */
+const SIMPLE8B: [(usize, usize); 16] = [(240, 0), (1, 64), (60, 1), (30, 2), (20, 3), (15, 4), (12, 5), (10, 6),
+                                         (8, 7), (7, 8), (6, 10), (5, 12), (4, 15), (3, 20), (2, 30), (1, 60)];
+
+const SELECTOR_ESCAPE: usize = 1;
+
+const SELECTOR_SHIFT: usize = 60;
+
+/* The 64-bit integers the codecs take, by their two's complement bits */
+pub trait Int64: Copy + Ord {
+    fn to_word(self) -> u64;
+    fn from_word(word: u64) -> Self;
+}
+
+impl Int64 for i64 {
+    fn to_word(self) -> u64 {
+        self as u64
+    }
+
+    fn from_word(word: u64) -> Self {
+        word as i64
+    }
+}
+
+impl Int64 for u64 {
+    fn to_word(self) -> u64 {
+        self
+    }
+
+    fn from_word(word: u64) -> Self {
+        word
+    }
+}
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum IntCodec {
+    Delta,
+    DeltaOfDelta,
+    Zigzag,
+    For,
+    Simple8b,
+}
+
+impl IntCodec {
+    pub fn method(&self) -> Methods {
+        match self {
+            IntCodec::Delta => Methods::Delta64,
+            IntCodec::DeltaOfDelta => Methods::DeltaOfDelta64,
+            IntCodec::Zigzag => Methods::Zigzag64,
+            IntCodec::For => Methods::For64,
+            IntCodec::Simple8b => Methods::Simple8b,
+        }
+    }
+
+    /* Residuals written as is ahead of the blocks, they are as wide as the values */
+    fn head(&self) -> usize {
+        match self {
+            IntCodec::Delta => 1,
+            IntCodec::DeltaOfDelta => 2,
+            _ => 0,
+        }
+    }
+
+    pub fn from_method(method: &Methods) -> Option<IntCodec> {
+        match method {
+            Methods::Delta64 => Some(IntCodec::Delta),
+            Methods::DeltaOfDelta64 => Some(IntCodec::DeltaOfDelta),
+            Methods::Zigzag64 => Some(IntCodec::Zigzag),
+            Methods::For64 => Some(IntCodec::For),
+            Methods::Simple8b => Some(IntCodec::Simple8b),
+            _ => None,
+        }
+    }
+}
+
+fn width(values: &[u64]) -> usize {
+    64 - values.iter().fold(0, |acc, v| acc | v).leading_zeros() as usize
+}
+
+/* What Delta, DeltaOfDelta, Zigzag and Simple8b pack for each value */
+fn residuals(codec: IntCodec, words: &[u64]) -> Vec<u64> {
+    let mut pre = 0u64;
+    let mut pre_delta = 0u64;
+    words.iter().enumerate().map(|(i, &word)| {
+        let delta = word.wrapping_sub(pre);
+        let residual = match codec {
+            IntCodec::Zigzag => word,
+            IntCodec::DeltaOfDelta => delta.wrapping_sub(pre_delta),
+            _ => delta,
+        };
+        pre = word;
+        if i > 0 {
+            pre_delta = delta;
+        }
+        zigzag64(residual as i64)
+    }).collect()
+}
+
+/* Undoes residuals in place */
+fn restore(codec: IntCodec, values: &mut [u64]) {
+    let mut pre = 0u64;
+    let mut pre_delta = 0u64;
+    for (i, value) in values.iter_mut().enumerate() {
+        let residual = unzigzag64(*value) as u64;
+        let word = match codec {
+            IntCodec::Zigzag => residual,
+            IntCodec::DeltaOfDelta => {
+                let delta = pre_delta.wrapping_add(residual);
+                if i > 0 {
+                    pre_delta = delta;
+                }
+                pre.wrapping_add(delta)
+            }
+            _ => pre.wrapping_add(residual),
+        };
+        pre = word;
+        *value = word;
+    }
+}
+
+fn write_block(w: &mut BitPack<Vec<u8>>, base: Option<u64>, values: &[u64]) {
+    let bits = width(values);
+    w.write(bits as u32, WIDTH_BITS).unwrap();
+    if let Some(base) = base {
+        w.write_u64(base, 64).unwrap();
+    }
+    for &value in values {
+        w.write_u64(value, bits).unwrap();
+    }
+}
+
+fn pack_simple8b(w: &mut BitPack<Vec<u8>>, values: &[u64]) {
+    let mut i = 0;
+    while i < values.len() {
+        let rest = &values[i..];
+        /* The densest layout the next values fit, the last word may be padded */
+        let layout = SIMPLE8B.iter().enumerate()
+            .filter(|(selector, _)| *selector != SELECTOR_ESCAPE)
+            .map(|(selector, &(count, bits))| (selector, count.min(rest.len()), bits))
+            .find(|&(_, take, bits)| width(&rest[..take]) <= bits);
+        match layout {
+            Some((selector, take, bits)) => {
+                let mut word = (selector as u64) << SELECTOR_SHIFT;
+                for (j, &value) in rest[..take].iter().enumerate() {
+                    word |= value << (j * bits);
+                }
+                w.write_u64(word, 64).unwrap();
+                i += take;
+            }
+            None => {
+                w.write_u64((SELECTOR_ESCAPE as u64) << SELECTOR_SHIFT, 64).unwrap();
+                w.write_u64(rest[0], 64).unwrap();
+                i += 1;
+            }
+        }
+    }
+}
+
+fn unpack_simple8b(r: &mut BitPack<&[u8]>, len: usize, values: &mut Vec<u64>) -> Result<(), usize> {
+    while values.len() < len {
+        let word = r.read_u64(64)?;
+        let selector = (word >> SELECTOR_SHIFT) as usize;
+        if selector == SELECTOR_ESCAPE {
+            values.push(r.read_u64(64)?);
+            continue;
+        }
+        let (count, bits) = SIMPLE8B[selector];
+        let mask = (1u64 << bits) - 1;
+        for j in 0..count.min(len - values.len()) {
+            values.push((word >> (j * bits)) & mask);
+        }
+    }
+    Ok(())
+}
+
+#[derive(Clone)]
+pub struct IntCompress {
+    chunksize: usize,
+    batchsize: usize,
+    codec: IntCodec,
+}
+
+impl IntCompress {
+    pub fn new(chunksize: usize, batchsize: usize, codec: IntCodec) -> Self {
+        IntCompress { chunksize, batchsize, codec }
+    }
+
+    pub fn method(&self) -> Methods {
+        self.codec.method()
+    }
+
+    pub fn encode<T: Int64>(&self, seg: &mut Segment<T>) -> Vec<u8> {
+        let data = seg.get_data();
+        let mut w = BitPack::<Vec<u8>>::with_capacity(data.len());
+        let start = Instant::now();
+        w.write(data.len() as u32, 32).unwrap();
+        match self.codec {
+            IntCodec::For => for block in data.chunks(INT_BLOCK) {
+                let base = block.iter().min().unwrap().to_word();
+                let offsets: Vec<u64> = block.iter().map(|v| v.to_word().wrapping_sub(base)).collect();
+                write_block(&mut w, Some(base), &offsets);
+            },
+            codec => {
+                let words: Vec<u64> = data.iter().map(|v| v.to_word()).collect();
+                let residuals = residuals(codec, &words);
+                let head = codec.head().min(residuals.len());
+                for &residual in &residuals[..head] {
+                    w.write_u64(residual, 64).unwrap();
+                }
+                if codec == IntCodec::Simple8b {
+                    pack_simple8b(&mut w, &residuals);
+                } else {
+                    for block in residuals[head..].chunks(INT_BLOCK) {
+                        write_block(&mut w, None, block);
+                    }
+                }
+            }
+        }
+        let duration = start.elapsed();
+        info!("Time elapsed in {:?} function() is: {:?}", self.codec, duration);
+        let origin = data.len() * mem::size_of::<T>();
+        info!("original size:{}", origin);
+        let byte_vec = w.into_vec();
+        info!("compressed size:{}", byte_vec.len());
+        byte_vec
+    }
+
+    fn decode_words(&self, bytes: &[u8]) -> Result<Vec<u64>, usize> {
+        let mut r = BitPack::<&[u8]>::new(bytes);
+        let len = r.read(32)? as usize;
+        let mut values = Vec::with_capacity(len);
+        for _ in 0..self.codec.head().min(len) {
+            values.push(r.read_u64(64)?);
+        }
+        if self.codec == IntCodec::Simple8b {
+            unpack_simple8b(&mut r, len, &mut values)?;
+        } else {
+            while values.len() < len {
+                let bits = r.read(WIDTH_BITS)? as usize;
+                let base = if self.codec == IntCodec::For { r.read_u64(64)? } else { 0 };
+                for _ in 0..INT_BLOCK.min(len - values.len()) {
+                    values.push(base.wrapping_add(r.read_u64(bits)?));
+                }
+            }
+        }
+        if self.codec != IntCodec::For {
+            restore(self.codec, &mut values);
+        }
+        Ok(values)
+    }
+
+    /* Truncated when the bytes end before every value is read */
+    pub fn try_decode<T: Int64>(&self, bytes: &[u8]) -> Result<Vec<T>, FormatError> {
+        match self.decode_words(bytes) {
+            Ok(values) => Ok(values.into_iter().map(T::from_word).collect()),
+            Err(_) => Err(FormatError::Truncated),
+        }
+    }
+
+    pub fn decode<T: Int64>(&self, bytes: &[u8]) -> Vec<T> {
+        match self.try_decode(bytes) {
+            Ok(values) => values,
+            Err(err) => panic!("Received an error from decoder: {:?}", err),
+        }
+    }
+}
+
+impl<T: Int64> CompressionMethod<T> for IntCompress {
+    fn get_segments(&self) {
+        unimplemented!()
+    }
+
+    fn get_batch(&self) -> usize {
+        self.batchsize
+    }
+
+    fn run_compress<'a>(&self, segs: &mut Vec<Segment<T>>) {
+        for seg in segs {
+            let binary = self.encode(seg);
+            seg.set_comp(Some(binary));
+            seg.set_data(Vec::new());
+            seg.set_method(self.method());
+        }
+    }
+
+    fn run_single_compress(&self, seg: &mut Segment<T>) {
+        let binary = self.encode(seg);
+        seg.set_comp(Some(binary));
+        seg.set_data(Vec::new());
+        seg.set_method(self.method());
+    }
+
+    fn run_decompress(&self, seg: &mut Segment<T>) {
+        let vec = self.decode(seg.get_comp());
+        seg.set_comp(None);
+        seg.set_data(vec);
+        seg.set_method(Methods::Uncompr);
+    }
+}
+
+/* Decodes an integer segment with the codec its own method names,
+ * UnknownCodec when that method is not one of the integer codecs
+ */
+pub fn decode_int_segment<T: Int64>(seg: &Segment<T>) -> Result<Vec<T>, FormatError> {
+    match seg.get_method() {
+        None | Some(Methods::Uncompr) => Ok(seg.get_data().clone()),
+        Some(m) => match IntCodec::from_method(m) {
+            Some(codec) => IntCompress::new(10, 10, codec).try_decode(seg.get_comp()),
+            None => Err(FormatError::UnknownCodec(m.codec_id())),
+        }
+    }
+}
+
+#[test]
+fn int64_byte_format_test() {
+    use std::time::SystemTime;
+
+    /* The zigzags 2, 1 and 4 in one block of width 3 */
+    let mut seg = Segment::new(None, SystemTime::now(), 0, vec![1i64, -1, 2], None, None);
+    assert_eq!(IntCompress::new(10, 10, IntCodec::Zigzag).encode(&mut seg), vec![3, 0, 0, 0, 0x03, 0x85]);
+
+    /* The zigzag delta 10 in a word of selector 5, 15 values of 4 bits */
+    let mut seg = Segment::new(None, SystemTime::now(), 0, vec![5i64], None, None);
+    assert_eq!(IntCompress::new(10, 10, IntCodec::Simple8b).encode(&mut seg),
+               vec![1, 0, 0, 0, 0x0a, 0, 0, 0, 0, 0, 0, 0x50]);
+
+    /* Nanosecond timestamps a second apart only cost the first value and delta */
+    let stamps: Vec<u64> = (0..1000u64).map(|i| 1_600_000_000_000_000_000 + i * 1_000_000_000).collect();
+    let mut seg = Segment::new(None, SystemTime::now(), 0, stamps.clone(), None, None);
+    let comp = IntCompress::new(10, 10, IntCodec::DeltaOfDelta);
+    let bytes = comp.encode(&mut seg);
+    assert!(bytes.len() < 32);
+    let mut segs = vec![seg];
+    comp.run_compress(&mut segs);
+    assert_eq!(segs[0].get_method(), &Some(Methods::DeltaOfDelta64));
+    assert_eq!(decode_int_segment(&segs[0]), Ok(stamps.clone()));
+
+    /* Segments of other codecs and cut off bytes are errors, not panics */
+    let mut seg = Segment::new(None, SystemTime::now(), 0, stamps.clone(), None, None);
+    seg.set_method(Methods::Gorilla);
+    assert_eq!(decode_int_segment(&seg), Err(FormatError::UnknownCodec(Methods::Gorilla.codec_id())));
+    let mut cut = segs[0].get_comp().clone();
+    cut.truncate(cut.len() - 1);
+    seg.set_comp(Some(cut));
+    seg.set_method(Methods::DeltaOfDelta64);
+    assert_eq!(decode_int_segment(&seg), Err(FormatError::Truncated));
+
+    /* The segment header keeps the codec by its id */
+    for codec in vec![IntCodec::Delta, IntCodec::DeltaOfDelta, IntCodec::Zigzag, IntCodec::For, IntCodec::Simple8b] {
+        let method = codec.method();
+        assert_eq!(Methods::from_codec(method.codec_id(), &method.codec_params()), Some(method.clone()));
+        assert_eq!(IntCodec::from_method(&method), Some(codec));
+    }
+}
+
+#[cfg(test)]
+mod properties {
+    use super::*;
+    use std::time::SystemTime;
+
+    const CODECS: [IntCodec; 5] = [IntCodec::Delta, IntCodec::DeltaOfDelta, IntCodec::Zigzag, IntCodec::For, IntCodec::Simple8b];
+
+    fn round_trips<T: Int64>(data: Vec<T>) -> bool {
+        CODECS.iter().all(|&codec| {
+            let comp = IntCompress::new(10, 10, codec);
+            let mut seg = Segment::new(None, SystemTime::now(), 0, data.clone(), None, None);
+            let bytes = comp.encode(&mut seg);
+            comp.decode::<T>(&bytes) == data
+        })
+    }
+
+    #[quickcheck]
+    fn signed_round_trip(xs: Vec<i64>) -> bool {
+        round_trips(xs)
+    }
+
+    #[quickcheck]
+    fn unsigned_round_trip(xs: Vec<u64>) -> bool {
+        round_trips(xs)
+    }
+
+    /* Counters that grow by small steps from a large start, with the extremes mixed in */
+    #[quickcheck]
+    fn counter_round_trip(start: u64, steps: Vec<u16>, extreme: bool) -> bool {
+        let mut counter = start;
+        let mut xs: Vec<u64> = steps.iter().map(|&s| { counter = counter.wrapping_add(s as u64); counter }).collect();
+        if extreme {
+            xs.extend(vec![std::u64::MAX, 0, std::u64::MAX, 1 << 60, (1 << 60) - 1]);
+        }
+        round_trips(xs.clone()) && round_trips(xs.into_iter().map(|x| x as i64).collect())
+    }
+}
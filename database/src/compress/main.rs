@@ -1,7 +1,10 @@
 use std::env;
-use time_series_start::compress::{run_bpsplit_encoding_decoding, run_gorilla_encoding_decoding, run_gorillabd_encoding_decoding, run_snappy_encoding_decoding, run_gzip_encoding_decoding, run_bp_double_encoding_decoding, run_sprintz_double_encoding_decoding, run_parquet_write_filter, run_splitbd_byte_encoding_decoding, run_splitdouble_byte_encoding_decoding, run_splitdouble_encoding_decoding, run_splitdouble_byte_residue_encoding_decoding, run_splitdouble_byte_residue_majority_encoding_decoding, run_fixed_encoding_decoding, run_fft_encoding_decoding, run_paa_encoding_decoding};
+use time_series_start::compress::{run_bpsplit_encoding_decoding, run_gorilla_encoding_decoding, run_gorillabd_encoding_decoding, run_codec_encoding_decoding, run_int64_encoding_decoding, run_snappy_encoding_decoding, run_gzip_encoding_decoding, run_bp_double_encoding_decoding, run_sprintz_double_encoding_decoding, run_parquet_write_filter, run_splitbd_byte_encoding_decoding, run_splitdouble_byte_encoding_decoding, run_splitdouble_encoding_decoding, run_splitdouble_byte_residue_encoding_decoding, run_splitdouble_byte_residue_majority_encoding_decoding, run_fixed_encoding_decoding, run_fft_encoding_decoding, run_paa_encoding_decoding};
 use log::{error, info, warn};
 use log4rs;
+use time_series_start::methods::prec_double::INFER_SCALE;
+use time_series_start::methods::registry::{method_by_name, codec_by_name};
+use time_series_start::compress::int64::IntCodec;
 use time_series_start::avl::btrarr::run_btr_array_index;
 use time_series_start::compress::buff_simd::{run_buff_simd_encoding_decoding, run_buff_encoding_decoding_mybitvec, run_buff_majority_encoding_decoding};
 use time_series_start::compress::buff_slice::{run_buff_slice_encoding_decoding, run_buff_slice_scalar_encoding_decoding};
@@ -14,7 +17,11 @@ fn main() {
     info!("input args{:?}",args);
     let input_file = &args[1];
     let compression = &args[2];
-    let int_scale = args[3].parse::<usize>().unwrap();
+    /* BUFF infers the scale from the data when it is given as auto */
+    let int_scale = match args[3].as_str() {
+        "auto" => INFER_SCALE,
+        scale => scale.parse::<usize>().unwrap(),
+    };
     let pred = args[4].parse::<f64>().unwrap();
 
     println!("ARGS: {}, {}, {}, {}, ",input_file, compression, int_scale,pred);
@@ -28,6 +35,10 @@ fn main() {
         "gorillabd" => {
             run_gorillabd_encoding_decoding(input_file,int_scale,pred);
         },
+        "delta64" | "dod64" | "zigzag64" | "for64" | "simple8b" => {
+            let codec = method_by_name(compression).and_then(|m| IntCodec::from_method(&m)).unwrap();
+            run_int64_encoding_decoding(input_file,int_scale,codec);
+        },
         "splitdouble" => {
             run_splitdouble_encoding_decoding(input_file,int_scale,pred);
         },
@@ -99,7 +110,11 @@ fn main() {
         "pqgzip" => {run_parquet_write_filter(input_file, int_scale, pred, "pqgzip");},
         "pqsnappy" => {run_parquet_write_filter(input_file, int_scale, pred, "pqsnappy");},
 
-        _ => {panic!("Compression not supported yet.")}
+        /* Any other codec of the registry, with its default parameters */
+        name => match codec_by_name(name, 10) {
+            Some(codec) => run_codec_encoding_decoding(input_file, int_scale, pred, codec),
+            None => panic!("Compression not supported yet."),
+        }
     }
 
 }
\ No newline at end of file
@@ -8,9 +8,13 @@ pub mod scaled_slice;
 pub mod rrd_sample;
 pub mod pla_lttb;
 pub mod buff_lossy;
+pub mod compressed_query;
+pub mod chimp;
+pub mod elf;
+pub mod int64;
 
 use std::{env, fs};
-use crate::client::construct_file_iterator_skip_newline;
+use crate::client::{construct_file_iterator_skip_newline, construct_file_iterator_int64};
 use crate::methods::compress::{SCALE, SplitDoubleCompress, test_split_compress_on_file, BPDoubleCompress, test_BP_double_compress_on_file, test_sprintz_double_compress_on_file, test_splitbd_compress_on_file, test_grillabd_compress_on_file, test_grilla_compress_on_file, GZipCompress, SnappyCompress, PRED, TEST_FILE};
 use std::time::{SystemTime, Instant};
 use crate::segment::{Segment, FourierCompress, PAACompress};
@@ -31,10 +35,13 @@ use parity_snappy::compress;
 use crate::compress::split_double::{SplitBDDoubleCompress, OUTLIER_R};
 use crate::compress::sprintz::SprintzDoubleCompress;
 use crate::compress::gorilla::{GorillaBDCompress, GorillaCompress};
+use crate::compress::compressed_query::SegmentQuery;
+use crate::methods::registry::{MethodCodec, method_name};
+use crate::compress::int64::{IntCompress, IntCodec};
 use std::collections::HashMap;
 use crate::methods::bit_packing::{BitPack, BYTE_BITS};
 use core::mem;
-use crate::methods::prec_double::{PrecisionBound, get_precision_bound};
+use crate::methods::prec_double::{PrecisionBound, get_precision_bound, InferScale};
 
 lazy_static! {
     pub static ref PRECISION_MAP: HashMap<i32, i32> =[(1, 5),
@@ -216,23 +223,27 @@ pub fn run_sprintz_double_encoding_decoding(test_file:&str, scl:usize,pred: f64)
     println!("Time elapsed in sprintz_double decompress function() is: {:?}", duration2);
 
     let start3 = Instant::now();
-    comp.range_filter(comp_cp,pred);
+    let res = comp.range_filter(comp_cp,pred);
     let duration3 = start3.elapsed();
+    println!("Number of qualified items:{}", res.cardinality());
     println!("Time elapsed in sprintz_double range filter function() is: {:?}", duration3);
 
     let start4 = Instant::now();
-    comp.equal_filter(comp_eq,pred);
+    let res = comp.equal_filter(comp_eq,pred);
     let duration4 = start4.elapsed();
+    println!("Number of qualified items for equal:{}", res.cardinality());
     println!("Time elapsed in sprintz equal filter function() is: {:?}", duration4);
 
     let start5 = Instant::now();
-    comp.sum(comp_sum);
+    let sum = comp.sum(comp_sum);
     let duration5 = start5.elapsed();
+    println!("sum is: {:?}", sum);
     println!("Time elapsed in sprintz sum function() is: {:?}", duration5);
 
     let start6 = Instant::now();
-    comp.max(comp_max);
+    let max = comp.max(comp_max);
     let duration6 = start6.elapsed();
+    println!("Max value:{}", max);
     println!("Time elapsed in sprintz max function() is: {:?}", duration6);
 
 
@@ -255,7 +266,7 @@ pub fn run_splitbd_byte_encoding_decoding(test_file:&str, scl:usize,pred: f64) {
 
     let mut seg = Segment::new(None,SystemTime::now(),0,file_vec.clone(),None,None);
     let org_size = seg.get_byte_size().unwrap();
-    let comp = SplitBDDoubleCompress::new(10,10,scl);
+    let comp = SplitBDDoubleCompress::new(10,10,scl).for_segment(&seg).expect("No scale reproduces the test file");
     let start1 = Instant::now();
     let compressed = comp.byte_encode(&mut seg);
     let duration1 = start1.elapsed();
@@ -313,7 +324,7 @@ pub fn run_splitdouble_byte_encoding_decoding(test_file:&str, scl:usize,pred: f6
 
     let mut seg = Segment::new(None,SystemTime::now(),0,file_vec.clone(),None,None);
     let org_size = seg.get_byte_size().unwrap();
-    let comp = SplitBDDoubleCompress::new(10,10,scl);
+    let comp = SplitBDDoubleCompress::new(10,10,scl).for_segment(&seg).expect("No scale reproduces the test file");
     let start1 = Instant::now();
     let mut compressed;
     if FILE_MIN_MAX.contains_key(test_file){
@@ -333,23 +344,27 @@ pub fn run_splitdouble_byte_encoding_decoding(test_file:&str, scl:usize,pred: f6
     println!("Time elapsed in splitbd byte compress function() is: {:?}", duration1);
 
     let start2 = Instant::now();
-    comp.byte_fixed_decode(compressed);
+    let decoded = comp.byte_fixed_decode(compressed);
     let duration2 = start2.elapsed();
+    println!("Number of scan items:{}", decoded.len());
     println!("Time elapsed in splitbd byte decompress function() is: {:?}", duration2);
 
     let start3 = Instant::now();
-    comp.byte_fixed_range_filter(comp_cp,pred);
+    let res = comp.byte_fixed_range_filter(comp_cp,pred);
     let duration3 = start3.elapsed();
+    println!("Number of qualified items:{}", res.cardinality());
     println!("Time elapsed in splitbd byte range filter function() is: {:?}", duration3);
 
     let start4 = Instant::now();
-    comp.byte_fixed_equal_filter(comp_eq,pred);
+    let res = comp.byte_fixed_equal_filter(comp_eq,pred);
     let duration4 = start4.elapsed();
+    println!("Number of qualified items for equal:{}", res.cardinality());
     println!("Time elapsed in splitbd byte equal filter function() is: {:?}", duration4);
 
     let start5 = Instant::now();
-    comp.byte_fixed_sum(comp_sum);
+    let sum = comp.byte_fixed_sum(comp_sum);
     let duration5 = start5.elapsed();
+    println!("sum is: {:?}", sum);
     println!("Time elapsed in byte_splitbd sum function() is: {:?}", duration5);
 
     let start6 = Instant::now();
@@ -379,7 +394,7 @@ pub fn run_splitdouble_byte_residue_majority_encoding_decoding(test_file:&str, s
 
     let mut seg = Segment::new(None,SystemTime::now(),0,file_vec.clone(),None,None);
     let org_size = seg.get_byte_size().unwrap();
-    let comp = SplitBDDoubleCompress::new(10,10,scl);
+    let comp = SplitBDDoubleCompress::new(10,10,scl).for_segment(&seg).expect("No scale reproduces the test file");
     let start1 = Instant::now();
     let compressed = comp.byte_residue_encode_majority(&mut seg);
     let duration1 = start1.elapsed();
@@ -437,7 +452,7 @@ pub fn run_fixed_encoding_decoding(test_file:&str, scl:usize,pred: f64) {
 
     let mut seg = Segment::new(None,SystemTime::now(),0,file_vec.clone(),None,None);
     let org_size = seg.get_byte_size().unwrap();
-    let comp = SplitBDDoubleCompress::new(10,10,scl);
+    let comp = SplitBDDoubleCompress::new(10,10,scl).for_segment(&seg).expect("No scale reproduces the test file");
     let start1 = Instant::now();
     let compressed = comp.fixed_encode(&mut seg);
     let duration1 = start1.elapsed();
@@ -613,7 +628,7 @@ pub fn run_splitdouble_byte_residue_encoding_decoding(test_file:&str, scl:usize,
 
     let mut seg = Segment::new(None,SystemTime::now(),0,file_vec.clone(),None,None);
     let org_size = seg.get_byte_size().unwrap();
-    let comp = SplitBDDoubleCompress::new(10,10,scl);
+    let comp = SplitBDDoubleCompress::new(10,10,scl).for_segment(&seg).expect("No scale reproduces the test file");
     let start1 = Instant::now();
     let compressed = comp.byte_residue_encode(&mut seg);
     let duration1 = start1.elapsed();
@@ -671,7 +686,7 @@ pub fn run_splitdouble_encoding_decoding(test_file:&str, scl:usize,pred: f64) {
 
     let mut seg = Segment::new(None,SystemTime::now(),0,file_vec.clone(),None,None);
     let org_size = seg.get_byte_size().unwrap();
-    let comp = SplitBDDoubleCompress::new(10,10,scl);
+    let comp = SplitBDDoubleCompress::new(10,10,scl).for_segment(&seg).expect("No scale reproduces the test file");
     let start1 = Instant::now();
     let compressed = comp.offset_encode(&mut seg);
     let duration1 = start1.elapsed();
@@ -805,8 +820,9 @@ pub fn run_gorilla_encoding_decoding(test_file:&str, scl:usize, pred: f64) {
     println!("Time elapsed in gorilla equal filter function() is: {:?}", duration4);
 
     let start5 = Instant::now();
-    comp.sum(comp_sum);
+    let sum = comp.sum(comp_sum);
     let duration5 = start5.elapsed();
+    println!("sum is: {:?}", sum);
     println!("Time elapsed in gorilla sum function() is: {:?}", duration5);
 
     let start6 = Instant::now();
@@ -826,6 +842,86 @@ pub fn run_gorilla_encoding_decoding(test_file:&str, scl:usize, pred: f64) {
     )
 }
 
+/* Benchmarks any codec of the registry, the queries run through
+ * SegmentQuery so they use the compressed domain where the codec has it
+ */
+pub fn run_codec_encoding_decoding(test_file:&str, scl:usize, pred: f64, codec: MethodCodec) {
+    let file_iter = construct_file_iterator_skip_newline::<f64>(test_file, 0, ',');
+    let file_vec: Vec<f64> = file_iter.unwrap()
+        .map(|x| (x*SCALE))
+        .collect();
+    let name = method_name(codec.method());
+    let mut seg = Segment::new(None,SystemTime::now(),0,file_vec.clone(),None,None);
+    let org_size = seg.get_byte_size().unwrap();
+    let start1 = Instant::now();
+    codec.encode(&mut seg);
+    let duration1 = start1.elapsed();
+    let comp_size = seg.get_byte_size().unwrap();
+    println!("Time elapsed in {} compress function() is: {:?}", name, duration1);
+
+    let start2 = Instant::now();
+    codec.decode(&seg);
+    let duration2 = start2.elapsed();
+    println!("Time elapsed in {} decompress function() is: {:?}", name, duration2);
+
+    let query = SegmentQuery::new(&seg).unwrap();
+    let start3 = Instant::now();
+    query.range_filter(pred);
+    let duration3 = start3.elapsed();
+    println!("Time elapsed in {} range filter function() is: {:?}", name, duration3);
+
+    let start4 = Instant::now();
+    query.equal_filter(pred);
+    let duration4 = start4.elapsed();
+    println!("Time elapsed in {} equal filter function() is: {:?}", name, duration4);
+
+    let start5 = Instant::now();
+    query.sum();
+    let duration5 = start5.elapsed();
+    println!("Time elapsed in {} sum function() is: {:?}", name, duration5);
+
+    let start6 = Instant::now();
+    query.max();
+    let duration6 = start6.elapsed();
+    println!("Time elapsed in {} max function() is: {:?}", name, duration6);
+
+
+    println!("Performance:{},{},{},{},{},{},{},{},{},{}", test_file, scl, pred,
+             comp_size as f64/ org_size as f64,
+             1000000000.0 * org_size as f64 / duration1.as_nanos() as f64 / 1024.0/1024.0,
+             1000000000.0 * org_size as f64 / duration2.as_nanos() as f64 / 1024.0/1024.0,
+             1000000000.0 * org_size as f64 / duration3.as_nanos() as f64 / 1024.0/1024.0,
+             1000000000.0 * org_size as f64 / duration4.as_nanos() as f64 / 1024.0/1024.0,
+             1000000000.0 * org_size as f64 / duration5.as_nanos() as f64 / 1024.0/1024.0,
+             1000000000.0 * org_size as f64 / duration6.as_nanos() as f64 / 1024.0/1024.0
+    )
+}
+
+pub fn run_int64_encoding_decoding(test_file:&str, scl:usize, codec: IntCodec) {
+    let file_iter = construct_file_iterator_int64(test_file, 0, ',', scl.max(1) as i64);
+    let file_vec: Vec<i64> = file_iter.unwrap().collect();
+    let mut seg = Segment::new(None,SystemTime::now(),0,file_vec.clone(),None,None);
+    let org_size = file_vec.len() * mem::size_of::<i64>();
+    let comp = IntCompress::new(10,10,codec);
+    let start1 = Instant::now();
+    let compressed = comp.encode(&mut seg);
+    let duration1 = start1.elapsed();
+    let comp_size = compressed.len();
+    println!("Time elapsed in {:?} compress function() is: {:?}", codec, duration1);
+
+    let start2 = Instant::now();
+    let decoded = comp.decode::<i64>(&compressed);
+    let duration2 = start2.elapsed();
+    println!("Time elapsed in {:?} decompress function() is: {:?}", codec, duration2);
+    assert_eq!(decoded, file_vec);
+
+    println!("Performance:{},{},{},{},{}", test_file, scl,
+             comp_size as f64/ org_size as f64,
+             1000000000.0 * org_size as f64 / duration1.as_nanos() as f64 / 1024.0/1024.0,
+             1000000000.0 * org_size as f64 / duration2.as_nanos() as f64 / 1024.0/1024.0
+    )
+}
+
 pub fn run_gzip_encoding_decoding(test_file:&str, scl:usize,pred: f64) {
     let file_iter = construct_file_iterator_skip_newline::<f64>(test_file, 0, ',');
     let file_vec: Vec<f64> = file_iter.unwrap()
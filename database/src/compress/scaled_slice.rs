@@ -39,8 +39,9 @@ pub fn run_scaled_slice_encoding_decoding(test_file:&str, scl:usize, pred: f64)
     println!("Time elapsed in scaled slice compress function() is: {:?}", duration1);
 
     let start2 = Instant::now();
-    comp.scaled_slice_decode(compressed);
+    let decoded = comp.scaled_slice_decode(compressed);
     let duration2 = start2.elapsed();
+    println!("Number of scan items:{}", decoded.len());
     println!("Time elapsed in scaled slice decompress function() is: {:?}", duration2);
 
     let start3 = Instant::now();
@@ -54,13 +55,15 @@ pub fn run_scaled_slice_encoding_decoding(test_file:&str, scl:usize, pred: f64)
     println!("Time elapsed in scaled slice equal filter function() is: {:?}", duration4);
 
     let start5 = Instant::now();
-    comp.scaled_slice_sum(comp_sum);
+    let sum = comp.scaled_slice_sum(comp_sum);
     let duration5 = start5.elapsed();
+    println!("sum is: {:?}", sum);
     println!("Time elapsed in scaled slice sum function() is: {:?}", duration5);
 
     let start6 = Instant::now();
-    comp.scaled_slice_max(comp_max);
+    let max = comp.scaled_slice_max(comp_max);
     let duration6 = start6.elapsed();
+    println!("Max value:{}", max);
     println!("Time elapsed in scaled slice max function() is: {:?}", duration6);
 
     // println!("Performance:{},{},{},{},{},{},{},{},{},{}", test_file, scl, pred,
@@ -124,13 +127,15 @@ pub fn run_scaled_slice_scalar_filtering_encoding_decoding(test_file:&str, scl:u
     println!("Time elapsed in scaled slice scalar equal filter function() is: {:?}", duration4);
 
     let start5 = Instant::now();
-    comp.scaled_slice_sum(comp_sum);
+    let sum = comp.scaled_slice_sum(comp_sum);
     let duration5 = start5.elapsed();
+    println!("sum is: {:?}", sum);
     println!("Time elapsed in scaled slice sum function() is: {:?}", duration5);
 
     let start6 = Instant::now();
-    comp.scaled_slice_max(comp_max);
+    let max = comp.scaled_slice_max(comp_max);
     let duration6 = start6.elapsed();
+    println!("Max value:{}", max);
     println!("Time elapsed in scaled slice max function() is: {:?}", duration6);
 
     // println!("Performance:{},{},{},{},{},{},{},{},{},{}", test_file, scl, pred,
@@ -326,9 +331,7 @@ impl ScaledSliceCompress {
         let mut bitpack = BitPack::<&[u8]>::new(bytes.as_slice());
         let ubase_int = bitpack.read(32).unwrap();
         let base_int = unsafe { mem::transmute::<u32, i32>(ubase_int) } as i64;
-        println!("base int:{}",base_int);
         let len = bitpack.read(32).unwrap();
-        println!("total vector size:{}",len);
         let ilen = bitpack.read(8).unwrap();
 
 
@@ -336,7 +339,6 @@ impl ScaledSliceCompress {
         let mut fixed_vec: Vec<u64> = Vec::new();
 
         let scl = self.scale as f64;
-        println!("Scale for decimal:{}", scl);
 
         let mut remain = ilen;
         let mut bytec = 0;
@@ -370,7 +372,6 @@ impl ScaledSliceCompress {
                     fixed_vec.push((flip(*x) as u64) << remain)
                 }
             }
-            println!("read the {}th byte of dec", bytec);
 
             while (remain >= 8) {
                 bytec += 1;
@@ -393,7 +394,6 @@ impl ScaledSliceCompress {
                 }
 
 
-                println!("read the {}th byte of dec", bytec);
             }
             // let duration = start.elapsed();
             // println!("Time elapsed in leading bytes: {:?}", duration);
@@ -401,8 +401,6 @@ impl ScaledSliceCompress {
 
             // let start5 = Instant::now();
             if (remain > 0) {
-                println!("read remaining {} bits of dec", remain);
-                println!("length for fixed:{}", fixed_vec.len());
                 chunk = bitpack.read_n_byte(len as usize).unwrap();
                 let padding = 8 - remain;
                 for (&cur_fixed, &x) in fixed_vec.iter().zip(chunk.iter()) {
@@ -414,17 +412,14 @@ impl ScaledSliceCompress {
         // for i in 0..10{
         //     println!("{}th item:{}",i,expected_datapoints.get(i).unwrap())
         // }
-        println!("Number of scan items:{}", expected_datapoints.len());
         expected_datapoints
     }
 
-    pub fn scaled_slice_sum(&self, bytes: Vec<u8>){
+    pub fn scaled_slice_sum(&self, bytes: Vec<u8>) -> f64 {
         let mut bitpack = BitPack::<&[u8]>::new(bytes.as_slice());
         let ubase_int = bitpack.read(32).unwrap();
         let base_int = unsafe { mem::transmute::<u32, i32>(ubase_int) } as i64;
-        println!("base int:{}",base_int);
         let len = bitpack.read(32).unwrap();
-        println!("total vector size:{}",len);
         let ilen = bitpack.read(8).unwrap();
 
 
@@ -432,7 +427,6 @@ impl ScaledSliceCompress {
         let mut fixed_vec: Vec<u64> = Vec::new();
 
         let scl = self.scale as f64;
-        println!("Scale for decimal:{}", scl);
 
         let mut remain = ilen;
         let mut bytec = 0;
@@ -471,7 +465,6 @@ impl ScaledSliceCompress {
                     fixed_vec.push((flip(*x) as u64) << remain)
                 }
             }
-            println!("read the {}th byte of dec", bytec);
 
             while (remain >= 8) {
                 bytec += 1;
@@ -495,7 +488,6 @@ impl ScaledSliceCompress {
                 }
 
 
-                println!("read the {}th byte of dec", bytec);
             }
             // let duration = start.elapsed();
             // println!("Time elapsed in leading bytes: {:?}", duration);
@@ -503,8 +495,6 @@ impl ScaledSliceCompress {
 
             // let start5 = Instant::now();
             if (remain > 0) {
-                println!("read remaining {} bits of dec", remain);
-                println!("length for fixed:{}", fixed_vec.len());
                 chunk = bitpack.read_n_byte(len as usize).unwrap();
                 let padding = 8 - remain;
                 sum = 0.0;
@@ -517,16 +507,14 @@ impl ScaledSliceCompress {
         // for i in 0..10{
         //     println!("{}th item:{}",i,expected_datapoints.get(i).unwrap())
         // }
-        println!("sum value:{}",  sum);
+        sum
     }
 
-    pub fn scaled_slice_max(&self, bytes: Vec<u8>) {
+    pub fn scaled_slice_max(&self, bytes: Vec<u8>) -> f64 {
         let mut bitpack = BitPack::<&[u8]>::new(bytes.as_slice());
         let ubase_int = bitpack.read(32).unwrap();
         let base_int = unsafe { mem::transmute::<u32, i32>(ubase_int) } as i64;
-        println!("base int:{}",base_int);
         let len = bitpack.read(32).unwrap();
-        println!("total vector size:{}",len);
         let ilen = bitpack.read(8).unwrap();
 
 
@@ -534,7 +522,6 @@ impl ScaledSliceCompress {
         let mut fixed_vec: Vec<u64> = Vec::new();
 
         let scl = self.scale as f64;
-        println!("Scale for decimal:{}", scl);
 
         let mut remain = ilen;
         let mut bytec = 0;
@@ -590,7 +577,6 @@ impl ScaledSliceCompress {
                     fixed_vec.push((flip(*x) as u64) << remain)
                 }
             }
-            println!("read the {}th byte of dec", bytec);
 
             while (remain >= 8) {
                 bytec += 1;
@@ -622,7 +608,6 @@ impl ScaledSliceCompress {
                 }
 
 
-                println!("read the {}th byte of dec", bytec);
             }
             // let duration = start.elapsed();
             // println!("Time elapsed in leading bytes: {:?}", duration);
@@ -630,8 +615,6 @@ impl ScaledSliceCompress {
 
             // let start5 = Instant::now();
             if (remain > 0) {
-                println!("read remaining {} bits of dec", remain);
-                println!("length for fixed:{}", fixed_vec.len());
                 chunk = bitpack.read_n_byte(len as usize).unwrap();
                 let padding = 8 - remain;
                 index=0;
@@ -652,8 +635,7 @@ impl ScaledSliceCompress {
         // for i in 0..10{
         //     println!("{}th item:{}",i,expected_datapoints.get(i).unwrap())
         // }
-        println!("Number of qualified max items:{}", res.cardinality());
-        println!("Max value:{}", max);
+        max
     }
 
 
@@ -806,13 +788,11 @@ impl ScaledSliceCompress {
         expected_datapoints
     }
 
-    pub fn scaled_slice_max_range(&self, bytes: Vec<u8>,s:u32, e:u32, window:u32) {
+    pub fn scaled_slice_max_range(&self, bytes: Vec<u8>,s:u32, e:u32, window:u32) -> Vec<f64> {
         let mut bitpack = BitPack::<&[u8]>::new(bytes.as_slice());
         let ubase_int = bitpack.read(32).unwrap();
         let base_int = unsafe { mem::transmute::<u32, i32>(ubase_int) } as i64;
-        println!("base int:{}",base_int);
         let len = bitpack.read(32).unwrap();
-        println!("total vector size:{}",len);
         let ilen = bitpack.read(8).unwrap();
 
 
@@ -820,7 +800,6 @@ impl ScaledSliceCompress {
         let mut fixed_vec: Vec<u64> = Vec::new();
 
         let dec_scl = self.scale as f64;
-        println!("Scale for decimal:{}", dec_scl);
 
         let mut remain = ilen;
         let mut bytec = 0;
@@ -830,7 +809,6 @@ impl ScaledSliceCompress {
         let mut chunk3:&[u8];
         let mut f_cur = 0f64;
         let num = ceil(remain, 8);
-        println!("Number of chunks:{}", num);
 
         let padding = num*8-ilen;
         let mut res = BitVec::from_elem(len as usize, false);
@@ -963,7 +941,7 @@ impl ScaledSliceCompress {
         max_vec.push(max);
 
         // println!("Number of qualified max items:{}", res.cardinality());
-        println!("Max value:{}", max_vec.len());
+        max_vec
     }
 
     pub(crate) fn scaled_slice_range_filter(&self, bytes: Vec<u8>, pred: f64) {
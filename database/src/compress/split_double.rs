@@ -2,14 +2,15 @@ use crate::segment::Segment;
 use std::time::{Instant, SystemTime};
 use serde::{Serialize, Deserialize};
 use tsz::stream::BufferedWriter;
-use crate::methods::prec_double::{get_precision_bound, PrecisionBound, FIRST_ONE};
-use crate::methods::bit_packing::{BitPack, BYTE_BITS};
+use crate::methods::prec_double::{get_precision_bound, PrecisionBound, FIRST_ONE, INFER_SCALE, MAX_INFERRED_PRECISION, InferScale, infer_scale};
+use crate::methods::bit_packing::{BitPack, BYTE_BITS, GrowingPack, zigzag64, unzigzag64};
 use std::mem;
-use crate::methods::compress::{CompressionMethod, SCALE, TEST_FILE};
+use crate::methods::compress::{CompressionMethod, StreamingEncoder, SCALE, TEST_FILE};
 use croaring::Bitmap;
 use tsz::StdEncoder;
 use rustfft::num_traits::real::Real;
-use log::info;
+use log::{info, warn};
+use crate::compress::gorilla::GorillaCompress;
 use std::collections::HashMap;
 use crate::client::construct_file_iterator_skip_newline;
 use itertools::Itertools;
@@ -40,6 +41,14 @@ impl SplitBDDoubleCompress {
         SplitBDDoubleCompress { chunksize, batchsize, scale}
     }
 
+    /* Encodes points as they arrive, to the bytes byte_fixed_encode gives.
+     * With INFER_SCALE the scale is inferred from the first points of
+     * every segment, see BuffStreamEncoder
+     */
+    pub fn streaming(&self) -> BuffStreamEncoder {
+        BuffStreamEncoder::new(self.scale)
+    }
+
     fn encode<'a,T>(&self, seg: &mut Segment<T>) -> Vec<u8>
         where T: Serialize + Clone+ Copy+Into<f64> + Deserialize<'a>{
 
@@ -450,7 +459,7 @@ impl SplitBDDoubleCompress {
             if fixed<min {
                 min = fixed;
             }
-            else if fixed>max {
+            if fixed>max {
                 max = fixed;
             }
             fixed_vec.push(fixed);
@@ -460,16 +469,9 @@ impl SplitBDDoubleCompress {
         // println!("base integer: {}, max:{}",base_fixed,max);
         let ubase_fixed = unsafe { mem::transmute::<i64, u64>(base_fixed) };
         let base_fixed64:i64 = base_fixed;
-        let mut single_val = false;
-        let mut cal_int_length = 0.0;
-        if delta == 0 {
-            single_val = true;
-        }else {
-            cal_int_length = (delta as f64).log2().ceil();
-        }
 
-        let fixed_len = cal_int_length as usize;
-        bound.set_length((cal_int_length as u64-dec_len), dec_len);
+        let fixed_len = fixed_width(delta, dec_len);
+        bound.set_length((fixed_len as u64-dec_len), dec_len);
         let ilen = fixed_len -dec_len as usize;
         let dlen = dec_len as usize;
         // println!("int_len:{},dec_len:{}",ilen as u64,dec_len);
@@ -1964,11 +1966,8 @@ impl SplitBDDoubleCompress {
         let higher = bitpack.read(32).unwrap();
         let ubase_int= (lower as u64)|((higher as u64)<<32);
         let base_int = unsafe { mem::transmute::<u64, i64>(ubase_int) };
-        println!("base integer: {}",base_int);
         let len = bitpack.read(32).unwrap();
-        println!("total vector size:{}",len);
         let ilen = bitpack.read(32).unwrap();
-        println!("bit packing length:{}",ilen);
         let dlen = bitpack.read(32).unwrap();
         bound.set_length(ilen as u64, dlen as u64);
         // check integer part and update bitmap;
@@ -1978,7 +1977,6 @@ impl SplitBDDoubleCompress {
         let mut fixed_vec:Vec<u64> = Vec::new();
 
         let mut dec_scl:f64 = 2.0f64.powi(dlen as i32);
-        println!("Scale for decimal:{}", dec_scl);
 
         let mut remain = dlen+ilen;
         let mut bytec = 0;
@@ -2013,7 +2011,6 @@ impl SplitBDDoubleCompress {
                     fixed_vec.push(((*x) as u64)<<remain)
                 }
             }
-            println!("read the {}th byte of dec",bytec);
 
             while (remain>=8){
                 bytec+=1;
@@ -2037,7 +2034,6 @@ impl SplitBDDoubleCompress {
                 }
 
 
-                println!("read the {}th byte of dec",bytec);
             }
             // let duration = start.elapsed();
             // println!("Time elapsed in leading bytes: {:?}", duration);
@@ -2046,8 +2042,6 @@ impl SplitBDDoubleCompress {
             // let start5 = Instant::now();
             if (remain>0){
                 bitpack.finish_read_byte();
-                println!("read remaining {} bits of dec",remain);
-                println!("length for fixed:{}", fixed_vec.len());
                 for cur_fixed in fixed_vec.into_iter(){
                     f_cur = (base_int + ((cur_fixed)|(bitpack.read_bits( remain as usize).unwrap() as u64)) as i64) as f64 / dec_scl;
                     // todo: this is for value reconstruction
@@ -2059,7 +2053,6 @@ impl SplitBDDoubleCompress {
         // for i in 0..10{
         //     println!("{}th item:{}",i,expected_datapoints.get(i).unwrap())
         // }
-        println!("Number of scan items:{}", expected_datapoints.len());
         expected_datapoints
     }
 
@@ -3154,11 +3147,8 @@ impl SplitBDDoubleCompress {
         let higher = bitpack.read(32).unwrap();
         let ubase_int= (lower as u64)|((higher as u64)<<32);
         let base_int = unsafe { mem::transmute::<u64, i64>(ubase_int) };
-        println!("base integer: {}",base_int);
         let len = bitpack.read(32).unwrap();
-        println!("total vector size:{}",len);
         let ilen = bitpack.read(32).unwrap();
-        println!("bit packing length:{}",ilen);
         let dlen = bitpack.read(32).unwrap();
         bound.set_length(ilen as u64, dlen as u64);
 
@@ -3187,7 +3177,6 @@ impl SplitBDDoubleCompress {
             sum = sum+(sum_fixed as f64)/dec_scl;
             // println!("sum the {}th byte of fixed number, which is {}",bytec,sum_fixed);
             sum_fixed=0;
-            println!("now sum :{}", sum);
             if remain == 0 {
                 return sum
             }
@@ -3204,7 +3193,6 @@ impl SplitBDDoubleCompress {
             sum = sum+(sum_fixed as f64)/dec_scl;
             // println!("sum the {}th byte of fixed number, which is {}",bytec,sum_fixed);
             sum_fixed=0;
-            println!("now sum :{}", sum);
             if remain == 0 {
                 return sum
             }
@@ -3223,7 +3211,6 @@ impl SplitBDDoubleCompress {
             sum = sum+(sum_fixed as f64)/dec_scl;
         }
         sum+= sum_base as f64;
-        println!("sum is: {:?}",sum);
         sum
     }
 
@@ -4980,7 +4967,7 @@ impl SplitBDDoubleCompress {
         println!("Number of qualified int items:{}", res.cardinality());
     }
 
-    pub(crate) fn byte_fixed_range_filter(&self, bytes: Vec<u8>, pred:f64) {
+    pub(crate) fn byte_fixed_range_filter(&self, bytes: Vec<u8>, pred:f64) -> Bitmap {
         let prec = (self.scale as f32).log10() as i32;
         let prec_delta = get_precision_bound(prec);
 
@@ -4990,11 +4977,8 @@ impl SplitBDDoubleCompress {
         let higher = bitpack.read(32).unwrap();
         let ubase_int= (lower as u64)|((higher as u64)<<32);
         let base_int = unsafe { mem::transmute::<u64, i64>(ubase_int) };
-        println!("base integer: {}",base_int);
         let len = bitpack.read(32).unwrap();
-        println!("total vector size:{}",len);
         let ilen = bitpack.read(32).unwrap();
-        println!("bit packing length:{}",ilen);
         let dlen = bitpack.read(32).unwrap();
         let mut remain = dlen+ilen;
         bound.set_length(ilen as u64, dlen as u64);
@@ -5005,8 +4989,9 @@ impl SplitBDDoubleCompress {
         let target = pred;
         let fixed_part = bound.fetch_fixed_aligned(target);
         if fixed_part<base_int{
-            println!("Number of qualified items:{}", len);
-            return;
+            let mut all = Bitmap::create();
+            all.add_range(0..len as u64);
+            return all;
         }
         let fixed_target = (fixed_part-base_int) as u64;
         let mut byte_count = 0;
@@ -5142,7 +5127,6 @@ impl SplitBDDoubleCompress {
                         it = iterator.next();
                         dec_pre=dec_cur;
                     }
-                    println!("read the remain {} bits of dec",remain);
                     remain = 0;
                 }
                 else{
@@ -5151,7 +5135,7 @@ impl SplitBDDoubleCompress {
             }
         }
 
-        println!("Number of qualified items:{}", res.cardinality());
+        res
     }
 
     pub(crate) fn byte_residue_range_filter(&self, bytes: Vec<u8>, pred:f64) {
@@ -6214,7 +6198,7 @@ impl SplitBDDoubleCompress {
         println!("Number of qualified items:{}", res.cardinality());
     }
 
-    pub(crate) fn byte_fixed_equal_filter(&self, bytes: Vec<u8>, pred:f64) {
+    pub(crate) fn byte_fixed_equal_filter(&self, bytes: Vec<u8>, pred:f64) -> Bitmap {
         let prec = (self.scale as f32).log10() as i32;
         let prec_delta = get_precision_bound(prec);
 
@@ -6224,11 +6208,8 @@ impl SplitBDDoubleCompress {
         let higher = bitpack.read(32).unwrap();
         let ubase_int= (lower as u64)|((higher as u64)<<32);
         let base_int = unsafe { mem::transmute::<u64, i64>(ubase_int) };
-        println!("base integer: {}",base_int);
         let len = bitpack.read(32).unwrap();
-        println!("total vector size:{}",len);
         let ilen = bitpack.read(32).unwrap();
-        println!("bit packing length:{}",ilen);
         let dlen = bitpack.read(32).unwrap();
         let mut remain =dlen+ilen;
         bound.set_length(ilen as u64, dlen as u64);
@@ -6239,17 +6220,18 @@ impl SplitBDDoubleCompress {
         let target = pred;
         let fixed_part = bound.fetch_fixed_aligned(target);
         if fixed_part<base_int {
-            println!("Number of qualified items for equal:{}", 0);
-            return;
+            return res;
         }
         let fixed_target = (fixed_part-base_int ) as u64;
         let mut dec_byte = fixed_target as u8;
         // println!("target value with integer part:{}, decimal part:{}",int_target,dec_target);
         let mut byte_count = 0;
-        let start = Instant::now();
         let mut count = 0;
+        /* the matches end up in res only when the last bits are read one by one */
+        let mut tail_by_bits = false;
 
         if remain<8{
+            tail_by_bits = true;
             for i in 0..len {
                 cur = bitpack.read_bits(remain as usize).unwrap();
                 if cur as u64==fixed_target{
@@ -6272,9 +6254,6 @@ impl SplitBDDoubleCompress {
             }
         }
         // rb1.run_optimize();
-        let duration = start.elapsed();
-        println!("Time elapsed in first byte is: {:?}", duration);
-        println!("Number of qualified items for equal:{}", rb1.cardinality());
 
         while (remain>0){
             // if we can read by byte
@@ -6336,6 +6315,7 @@ impl SplitBDDoubleCompress {
             }
             // else we have to read by bits
             else {
+                tail_by_bits = true;
                 dec_byte =(((fixed_target as u8)<< ((BYTE_BITS - remain as usize) as u8)) >> ((BYTE_BITS - remain as usize) as u8));
                 bitpack.finish_read_byte();
                 if rb1.cardinality()!=0{
@@ -6373,7 +6353,6 @@ impl SplitBDDoubleCompress {
                         it = iterator.next();
                         dec_pre=dec_cur;
                     }
-                    println!("read the remain {} bits of dec",remain);
                     remain = 0;
                 }
                 else{
@@ -6381,7 +6360,7 @@ impl SplitBDDoubleCompress {
                 }
             }
         }
-        println!("Number of qualified int items:{}", res.cardinality());
+        if tail_by_bits { res } else { rb1 }
     }
 
     pub(crate) fn byte_residue_equal_filter(&self, bytes: Vec<u8>, pred:f64) {
@@ -8607,6 +8586,16 @@ impl SplitBDDoubleCompress {
 
 }
 
+impl InferScale for SplitBDDoubleCompress {
+    fn scale(&self) -> usize {
+        self.scale
+    }
+
+    fn set_scale(&mut self, scale: usize) {
+        self.scale = scale;
+    }
+}
+
 impl<'a, T> CompressionMethod<T> for SplitBDDoubleCompress
     where T: Serialize + Clone+ Copy+Into<f64>+ Deserialize<'a>+ FromPrimitive{
     fn get_segments(&self) {
@@ -8620,10 +8609,22 @@ impl<'a, T> CompressionMethod<T> for SplitBDDoubleCompress
     fn run_compress<'b>(&self, segs: &mut Vec<Segment<T>>) {
         // let start = Instant::now();
         for seg in segs {
-            let binary =  self.byte_fixed_encode(seg);
+            /* The scale goes into the method, so the header keeps the inferred one */
+            let comp = match self.for_segment(seg) {
+                Some(comp) => comp,
+                None => {
+                    warn!("No scale reproduces the segment, it is kept with Gorilla instead");
+                    let binary = GorillaCompress::new(self.chunksize, self.batchsize).encode(seg);
+                    seg.set_comp(Some(binary));
+                    seg.set_data(Vec::new());
+                    seg.set_method(Methods::Gorilla);
+                    continue;
+                }
+            };
+            let binary =  comp.byte_fixed_encode(seg);
             seg.set_comp(Some(binary));
             seg.set_data(Vec::new());
-            seg.set_method(Methods::Buff(self.scale));
+            seg.set_method(Methods::Buff(comp.scale));
 
         }
         // let duration = start.elapsed();
@@ -8639,6 +8640,164 @@ impl<'a, T> CompressionMethod<T> for SplitBDDoubleCompress
     }
 }
 
+/* Bits of a fixed point value once the base is taken off. Never fewer
+ * than the decimal bits, so the integer part has a length too.
+ */
+fn fixed_width(delta: i64, dec_len: u64) -> usize {
+    let bits = 64 - (delta as u64).leading_zeros() as usize;
+    bits.max(dec_len as usize)
+}
+
+/* BUFF needs the smallest value as the base and the range for the width
+ * before it writes anything, and both only settle at the end. Until then
+ * the fixed point values are packed as zigzag offsets from the first one,
+ * which the width of the final columns is at most one bit short of, and
+ * finish slices them into byte columns as byte_fixed_encode does.
+ * Built with INFER_SCALE the encoder holds the first STREAM_WARM_UP
+ * points of a segment as they are and infers the scale from them, a
+ * shorter segment gets the scale the batch encoder infers. Later points
+ * that need a finer scale are rounded to it, and a prefix no scale
+ * reproduces gets the finest one BUFF holds.
+ */
+#[derive(Clone, Debug)]
+pub struct BuffStreamEncoder {
+    infer: bool,
+    scale: usize,
+    bound: PrecisionBound,
+    dec_len: u64,
+    warm_up: Vec<f64>,
+    first: i64,
+    min: i64,
+    max: i64,
+    offsets: GrowingPack,
+}
+
+/* Points an inferring stream encoder takes its scale from */
+pub const STREAM_WARM_UP: usize = 256;
+
+impl BuffStreamEncoder {
+    pub fn new(scale: usize) -> Self {
+        let mut stream = BuffStreamEncoder {
+            infer: scale == INFER_SCALE,
+            scale: INFER_SCALE,
+            bound: PrecisionBound::new(0.0),
+            dec_len: 0,
+            warm_up: Vec::new(),
+            first: 0,
+            min: i64::max_value(),
+            max: i64::min_value(),
+            offsets: GrowingPack::new(),
+        };
+        if !stream.infer {
+            stream.set_scale(scale);
+        }
+        stream
+    }
+
+    fn set_scale(&mut self, scale: usize) {
+        let prec = (scale as f32).log10() as i32;
+        self.bound = PrecisionBound::new(get_precision_bound(prec));
+        self.dec_len = *(PRECISION_MAP.get(&prec).unwrap()) as u64;
+        self.bound.set_length(0, self.dec_len);
+        self.scale = scale;
+    }
+
+    /* Infers the scale from the points held so far and encodes them */
+    fn settle(&mut self) {
+        let scale = infer_scale(&self.warm_up)
+            .unwrap_or(10usize.pow(MAX_INFERRED_PRECISION as u32));
+        self.set_scale(scale);
+        for v in mem::replace(&mut self.warm_up, Vec::new()) {
+            self.push_fixed(v);
+        }
+    }
+
+    fn push_fixed(&mut self, value: f64) {
+        let fixed = self.bound.fetch_fixed_aligned(value);
+        if self.offsets.is_empty() {
+            self.first = fixed;
+        }
+        if fixed < self.min {
+            self.min = fixed;
+        }
+        if fixed > self.max {
+            self.max = fixed;
+        }
+        self.offsets.push(zigzag64(fixed - self.first));
+    }
+
+    /* The values pushed so far, less the base */
+    fn aligned<'a>(&'a self, base: i64) -> impl Iterator<Item=u64> + 'a {
+        let shift = self.first - base;
+        self.offsets.values().map(move |z| (unzigzag64(z) + shift) as u64)
+    }
+}
+
+impl<T> StreamingEncoder<T> for BuffStreamEncoder
+    where T: Into<f64> {
+    fn push(&mut self, value: T) {
+        if self.infer && self.offsets.is_empty() {
+            self.warm_up.push(value.into());
+            if self.warm_up.len() == STREAM_WARM_UP {
+                self.settle();
+            }
+            return;
+        }
+        self.push_fixed(value.into());
+    }
+
+    fn len(&self) -> usize {
+        self.warm_up.len() + self.offsets.len()
+    }
+
+    fn finish(&mut self) -> Vec<u8> {
+        if self.infer && !self.warm_up.is_empty() {
+            self.settle();
+        }
+        let count = self.offsets.len();
+        let (base, delta) = if count == 0 { (0, 0) } else { (self.min, self.max - self.min) };
+        let fixed_len = fixed_width(delta, self.dec_len);
+        let ubase_fixed = unsafe { mem::transmute::<i64, u64>(base) };
+
+        let mut bitpack_vec = BitPack::<Vec<u8>>::with_capacity(20 + count * fixed_len / BYTE_BITS);
+        bitpack_vec.write(ubase_fixed as u32, 32).unwrap();
+        bitpack_vec.write((ubase_fixed >> 32) as u32, 32).unwrap();
+        bitpack_vec.write(count as u32, 32).unwrap();
+        bitpack_vec.write((fixed_len - self.dec_len as usize) as u32, 32).unwrap();
+        bitpack_vec.write(self.dec_len as u32, 32).unwrap();
+
+        let mut remain = fixed_len;
+        if remain < BYTE_BITS {
+            for v in self.aligned(base) {
+                bitpack_vec.write_bits(v as u32, remain).unwrap();
+            }
+        }
+        else {
+            while remain >= BYTE_BITS {
+                remain -= BYTE_BITS;
+                for v in self.aligned(base) {
+                    bitpack_vec.write_byte((v >> remain) as u8).unwrap();
+                }
+            }
+            if remain > 0 {
+                bitpack_vec.finish_write_byte();
+                for v in self.aligned(base) {
+                    bitpack_vec.write_bits(v as u32, remain).unwrap();
+                }
+            }
+        }
+
+        self.offsets.take();
+        self.min = i64::max_value();
+        self.max = i64::min_value();
+        bitpack_vec.into_vec()
+    }
+
+    fn method(&self) -> Methods {
+        Methods::Buff(self.scale)
+    }
+}
+
 #[test]
 fn test_sub_column_profiler() {
     let file_iter = construct_file_iterator_skip_newline::<f64>("/home/cc/float_comp/signal/time_series_120rpm-c2-current.csv", 0, ',');
@@ -8767,3 +8926,63 @@ fn test_vec_array() {
     let duration = start.elapsed();
     println!("Time elapsed in sum 100 million usize vec is: {:?}", duration);
 }
+
+#[test]
+fn byte_fixed_range_test() {
+    /* The largest value comes first, a range that is a power of two needs
+     * a bit more than its log, and a single repeated value has no range
+     */
+    let comp = SplitBDDoubleCompress::new(10, 10, 1000);
+    for data in vec![vec![4.0, 0.0, 1.0], vec![2.0, 1.5, 1.0, 0.5, 0.0], vec![0.0, 4.0, 1.0], vec![1.25; 5]] {
+        let mut seg = Segment::new(None, SystemTime::now(), 0, data.clone(), None, None);
+        let decoded: Vec<f64> = comp.decode_general(&comp.byte_fixed_encode(&mut seg));
+        assert_eq!(decoded.len(), data.len());
+        for (a, b) in data.iter().zip(decoded.iter()) {
+            assert!((a - b).abs() <= 0.001);
+        }
+    }
+}
+
+#[test]
+fn buff_stream_test() {
+    /* The first point is neither the smallest nor the largest, a range
+     * that is a power of two, one that is not, and a single repeated value
+     */
+    let mut wide: Vec<f64> = vec![12.5, -3.25, 40.125, 7.0];
+    wide.extend((0..300).map(|x| ((x as f64 * 0.07).sin() * 500.0 * 1000.0).round() / 1000.0));
+    let sets = vec![wide, vec![0.5, 0.0, 2.0, 1.0], vec![1.25; 5]];
+    let comp = SplitBDDoubleCompress::new(10, 10, 1000);
+    let mut stream = comp.streaming();
+    for data in sets.clone() {
+        let mut seg = Segment::new(None, SystemTime::now(), 0, data.clone(), None, None);
+        let batch = comp.byte_fixed_encode(&mut seg);
+        for v in data.iter() {
+            stream.push(*v);
+        }
+        assert_eq!(StreamingEncoder::<f64>::len(&stream), data.len());
+        let bytes = StreamingEncoder::<f64>::finish(&mut stream);
+        assert_eq!(bytes, batch);
+        let decoded: Vec<f64> = comp.decode_general(&bytes);
+        for (a, b) in data.iter().zip(decoded.iter()) {
+            assert!((a - b).abs() <= 0.001);
+        }
+    }
+
+    /* An inferring encoder takes the scale of the batch encoder, from
+     * the warm-up prefix once a segment is longer than that
+     */
+    assert!(sets[0].len() > STREAM_WARM_UP);
+    let infer = SplitBDDoubleCompress::new(10, 10, INFER_SCALE);
+    let mut stream = infer.streaming();
+    for data in sets {
+        let mut seg = Segment::new(None, SystemTime::now(), 0, data.clone(), None, None);
+        let comp = infer.for_segment(&seg).unwrap();
+        let batch = comp.byte_fixed_encode(&mut seg);
+        for v in data.iter() {
+            stream.push(*v);
+        }
+        assert_eq!(StreamingEncoder::<f64>::len(&stream), data.len());
+        assert_eq!(StreamingEncoder::<f64>::finish(&mut stream), batch);
+        assert_eq!(StreamingEncoder::<f64>::method(&stream), Methods::Buff(comp.scale));
+    }
+}
@@ -1,10 +1,10 @@
 use crate::segment::Segment;
 use serde::{Serialize, Deserialize};
-use crate::methods::bit_packing::{sprintz_double_encoder, BitPack, unzigzag};
+use crate::methods::bit_packing::{sprintz_double_encoder, BitPack, GrowingPack, zigzag, unzigzag};
 use std::mem;
 use croaring::Bitmap;
 use std::time::Instant;
-use crate::methods::compress::CompressionMethod;
+use crate::methods::compress::{CompressionMethod, StreamingEncoder};
 use std::slice::Iter;
 use my_bit_vec::BitVec;
 use num::FromPrimitive;
@@ -22,6 +22,11 @@ impl SprintzDoubleCompress {
         SprintzDoubleCompress { chunksize, batchsize, scale }
     }
 
+    /* Encodes points as they arrive, to the bytes encode gives */
+    pub fn streaming(&self) -> SprintzStreamEncoder {
+        SprintzStreamEncoder::new(self.scale)
+    }
+
     pub fn encode<'a,T>(&self, seg: &mut Segment<T>) -> Vec<u8>
         where T: Serialize + Clone+ Copy+Into<f64> + Deserialize<'a>{
         let comp = sprintz_double_encoder(seg.get_data().as_slice(),self.scale);
@@ -135,9 +140,7 @@ impl SprintzDoubleCompress {
         let mut bitpack = BitPack::<&[u8]>::new(bytes.as_slice());
         let ubase_int = bitpack.read(32).unwrap();
         let base_int = unsafe { mem::transmute::<u32, i32>(ubase_int) };
-        println!("base int:{}",base_int);
         let len = bitpack.read(32).unwrap();
-        println!("total vector size:{}",len);
         let ilen = bitpack.read(8).unwrap();
 
         // check integer part and update bitmap;
@@ -157,19 +160,16 @@ impl SprintzDoubleCompress {
             pre = cur_int;
         }
         let sum = sum_int as f64/scl;
-        println!("sum is: {:?}",sum);
         sum
 
     }
 
-    pub(crate) fn max(&self, bytes: Vec<u8>) {
+    pub(crate) fn max(&self, bytes: Vec<u8>) -> f64 {
         let scl = self.scale as f64;
         let mut bitpack = BitPack::<&[u8]>::new(bytes.as_slice());
         let ubase_int = bitpack.read(32).unwrap();
         let base_int = unsafe { mem::transmute::<u32, i32>(ubase_int) };
-        println!("base int:{}",base_int);
         let len = bitpack.read(32).unwrap();
-        println!("total vector size:{}",len);
         let ilen = bitpack.read(8).unwrap();
         let mut res = Bitmap::create();
         // check integer part and update bitmap;
@@ -193,19 +193,16 @@ impl SprintzDoubleCompress {
             pre = cur_int;
         }
         let max_f = max_int as f64/scl;
-        println!("Max: {:?}",max_f);
-        println!("Number of qualified items for max:{}", res.cardinality());
+        max_f
     }
 
 
-    pub(crate) fn max_range(&self, bytes: Vec<u8>,s:u32, e:u32, window:u32) {
+    pub(crate) fn max_range(&self, bytes: Vec<u8>,s:u32, e:u32, window:u32) -> Vec<f64> {
         let scl = self.scale as f64;
         let mut bitpack = BitPack::<&[u8]>::new(bytes.as_slice());
         let ubase_int = bitpack.read(32).unwrap();
         let base_int = unsafe { mem::transmute::<u32, i32>(ubase_int) };
-        println!("base int:{}",base_int);
         let len = bitpack.read(32).unwrap();
-        println!("total vector size:{}",len);
         let ilen = bitpack.read(8).unwrap();
         let mut res = Bitmap::create();
         // check integer part and update bitmap;
@@ -250,18 +247,16 @@ impl SprintzDoubleCompress {
 
         let max_vec_f64 : Vec<f64> = max_vec.iter().map(|&x| x as f64/scl).collect();
 
-        println!("Max: {}",max_vec_f64.len());
         // println!("Number of qualified items for max_groupby:{}", res.cardinality());
+        max_vec_f64
     }
 
 
-    pub fn range_filter(&self, bytes: Vec<u8>,pred:f64) {
+    pub fn range_filter(&self, bytes: Vec<u8>,pred:f64) -> Bitmap {
         let mut bitpack = BitPack::<&[u8]>::new(bytes.as_slice());
         let ubase_int = bitpack.read(32).unwrap();
         let base_int = unsafe { mem::transmute::<u32, i32>(ubase_int) };
-        println!("base int:{}",base_int);
         let len = bitpack.read(32).unwrap();
-        println!("total vector size:{}",len);
         let ilen = bitpack.read(8).unwrap();
         let target = pred;
         let adjust_target = (target*self.scale as f64).ceil() as i32;
@@ -285,7 +280,7 @@ impl SprintzDoubleCompress {
 
         }
         // res.run_optimize();
-        println!("Number of qualified items:{}", res.cardinality());
+        res
     }
 
     pub fn range_filter_condition(&self, bytes: Vec<u8>, pred:f64, mut iter: Iter<usize>) -> BitVec<u32> {
@@ -328,13 +323,11 @@ impl SprintzDoubleCompress {
         return res;
     }
 
-    pub fn equal_filter(&self, bytes: Vec<u8>,pred:f64) {
+    pub fn equal_filter(&self, bytes: Vec<u8>,pred:f64) -> Bitmap {
         let mut bitpack = BitPack::<&[u8]>::new(bytes.as_slice());
         let ubase_int = bitpack.read(32).unwrap();
         let base_int = unsafe { mem::transmute::<u32, i32>(ubase_int) };
-        println!("base int:{}",base_int);
         let len = bitpack.read(32).unwrap();
-        println!("total vector size:{}",len);
         let ilen = bitpack.read(8).unwrap();
         let target = pred;
         let adjust_target = (target*self.scale as f64).ceil() as i32;
@@ -358,7 +351,7 @@ impl SprintzDoubleCompress {
 
         }
         //res.run_optimize();
-        println!("Number of qualified items for equal:{}", res.cardinality());
+        res
     }
 }
 
@@ -390,3 +383,80 @@ impl<'a, T> CompressionMethod<T> for SprintzDoubleCompress
         seg.set_method(Methods::Uncompr);
     }
 }
+
+/* The zigzag deltas are packed as they arrive, at the widest delta seen so
+ * far. The header ahead of them fills whole bytes, so finish writes it and
+ * appends the packed deltas unchanged.
+ */
+#[derive(Clone, Debug)]
+pub struct SprintzStreamEncoder {
+    scale: usize,
+    base: i32,
+    pre: i32,
+    deltas: GrowingPack,
+}
+
+impl SprintzStreamEncoder {
+    pub fn new(scale: usize) -> Self {
+        SprintzStreamEncoder { scale, base: 0, pre: 0, deltas: GrowingPack::new() }
+    }
+}
+
+impl<T> StreamingEncoder<T> for SprintzStreamEncoder
+    where T: Into<f64> {
+    fn push(&mut self, value: T) {
+        let cur = (value.into() * self.scale as f64).ceil() as i32;
+        if self.deltas.is_empty() {
+            self.base = cur;
+            self.pre = cur;
+        }
+        self.deltas.push(zigzag(cur - self.pre) as u64);
+        self.pre = cur;
+    }
+
+    fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    fn finish(&mut self) -> Vec<u8> {
+        let ubase_int = unsafe { mem::transmute::<i32, u32>(self.base) };
+        let mut bitpack_vec = BitPack::<Vec<u8>>::with_capacity(9 + self.deltas.len() * self.deltas.width() / 8);
+        bitpack_vec.write(ubase_int, 32).unwrap();
+        bitpack_vec.write(self.deltas.len() as u32, 32).unwrap();
+        bitpack_vec.write(self.deltas.width() as u32, 8).unwrap();
+        bitpack_vec.write_bytes(&mut self.deltas.take()).unwrap();
+        self.base = 0;
+        self.pre = 0;
+        bitpack_vec.into_vec()
+    }
+
+    fn method(&self) -> Methods {
+        Methods::Sprintz(self.scale)
+    }
+}
+
+#[test]
+fn sprintz_stream_test() {
+    use std::time::SystemTime;
+
+    /* The first point is neither the smallest nor the largest, and the
+     * delta width grows part way through
+     */
+    let mut data: Vec<f64> = vec![3.25, 1.5, 1.75, 2.0];
+    data.extend((0..300).map(|x| ((x as f64 * 0.05).sin() * 40.0 * 1000.0).round() / 1000.0));
+    let comp = SprintzDoubleCompress::new(10, 10, 1000);
+    let mut seg = Segment::new(None, SystemTime::now(), 0, data.clone(), None, None);
+    let batch = comp.encode(&mut seg);
+
+    let mut stream = comp.streaming();
+    for _ in 0..2 {
+        for v in data.iter() {
+            stream.push(*v);
+        }
+        assert_eq!(StreamingEncoder::<f64>::len(&stream), data.len());
+        let bytes = StreamingEncoder::<f64>::finish(&mut stream);
+        assert_eq!(bytes, batch);
+        let decoded: Vec<f64> = comp.decode_general(&bytes);
+        assert_eq!(decoded, comp.decode_general::<f64>(&batch));
+    }
+}
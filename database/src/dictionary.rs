@@ -1,7 +1,6 @@
 use std::slice::Chunks;
 use std::collections::HashMap;
 use std::sync::RwLock;
-use rocksdb::DBVector;
 
 /* 
  * Changes: removed is_implmented and implement method from dictionary
@@ -111,7 +110,7 @@ impl<T> Dictionary<T> {
 		unimplemented!()
 	}
 
-	pub fn convert_from_bytes(bytes: DBVector) -> Dictionary<T> {
+	pub fn convert_from_bytes(bytes: &[u8]) -> Dictionary<T> {
 		unimplemented!()
 	}
 
@@ -1,9 +1,16 @@
 use std::time::SystemTime;
 use std::fmt::Debug;
+use std::collections::BTreeMap;
+use std::ops::Bound;
+use std::fs::{self,File,OpenOptions};
+use std::io::{self,Read,Write,Seek,SeekFrom};
+use std::path::{Path,PathBuf};
+use std::sync::{Arc,Mutex};
 
 use serde::{Serialize,Deserialize};
 
-use rocksdb::{Options,DBVector};
+#[cfg(feature = "rocksdb")]
+use rocksdb::{Options,WriteBatch,IteratorMode,Direction};
 
 use segment::{Segment,random_f32signal,compare_vectors};
 use crate::segment;
@@ -22,7 +29,12 @@ use compression::prelude::*;
  * as an easier time comparing performances with different managers.
  *
  * Current Implementations:
- * The current implementation uses RocksDB to implement the file manager.
+ * 1. RocksDB, selected with file_manager = "Rocks". Only built with
+ *    the rocksdb cargo feature, which is on by default.
+ * 2. LogFileManager, an append-only log with a sorted key index
+ *    kept in a local directory, selected with file_manager = "Local".
+ *    It is written in plain Rust, so building without default features
+ *    drops librocksdb-sys and leaves this as the only file manager.
  */
 
 /* Think about truncating results when memory is gotten too large */
@@ -50,20 +62,142 @@ pub trait FileManager<T,U>
 	 */
 	fn fm_get(&self, key: T) -> Result<Option<U>,Error>;
 
-	/* add batch write support */
+	/* Takes a collection of key/value pairs and writes all of them
+	 * to the file controlled by the FileManager in a single operation.
+	 * Either every pair is written or none of them are.
+	 * Will return =>
+	 *   Ok(()): Indicating every pair was succesfully written
+	 *   Err(e): Indicating an error prevented the batch from being written
+	 */
+	fn fm_write_batch(&self, batch: Vec<(T,T)>) -> Result<(),Error>;
+
+	/* Takes the bytes representing a key and removes the entry
+	 * from the file controlled by the FileManager.
+	 * Deleting a key that is not present is not an error.
+	 * Will return =>
+	 *   Ok(()): Indicating the entry is no longer present
+	 *   Err(e): Indicating some failure
+	 */
+	fn fm_delete(&self, key: T) -> Result<(),Error>;
+
+	/* Takes the bytes of a start and an end key and returns an iterator
+	 * over every key/value pair in [start_key, end_key), in ascending
+	 * byte order of the keys.
+	 * Will return =>
+	 *   Ok(iter): An iterator yielding (key bytes, value bytes)
+	 *   Err(e): Indicating some failure
+	 */
+	fn fm_scan<'a>(&'a self, start_key: T, end_key: T) -> Result<Box<dyn Iterator<Item=(Vec<u8>,Vec<u8>)> + 'a>,Error>;
+
+	/* Same as fm_scan, but only the first len bytes of every value
+	 * are returned, for callers that only need what a value starts with.
+	 * Backends that can avoid reading the rest of the values should.
+	 * Will return =>
+	 *   Ok(iter): An iterator yielding (key bytes, value prefix bytes)
+	 *   Err(e): Indicating some failure
+	 */
+	fn fm_scan_prefix<'a>(&'a self, start_key: T, end_key: T, len: usize) -> Result<Box<dyn Iterator<Item=(Vec<u8>,Vec<u8>)> + 'a>,Error> {
+		Ok(Box::new(self.fm_scan(start_key,end_key)?.map(move |(k,mut v)| {
+			v.truncate(len);
+			(k,v)
+		})))
+	}
 }
 
-/* Error enum used by the FileManager to wrap rocksdb errors */
+/* Error enum used by the FileManager to wrap backend errors */
 #[derive(Debug)]
 pub enum Error {
+	#[cfg(feature = "rocksdb")]
 	DbError(rocksdb::Error),
 	ColumnError(&'static str),
+	IoError(io::Error),
+	CorruptLog(u64),
+	LockError,
+}
+
+/* A file manager whose backend is only known at runtime,
+ * this is what the TOML config produces so the buffers
+ * do not need to be duplicated for every backend
+ */
+pub type BoxedFileManager = Box<dyn FileManager<Vec<u8>,Vec<u8>> + Send + Sync>;
+
+impl<T,U> FileManager<T,U> for Box<dyn FileManager<T,U> + Send + Sync>
+	where T: AsRef<[u8]>,
+		  U: AsRef<[u8]>,
+{
+	#[inline]
+	fn fm_write(&self, key: T, value: T) -> Result<(),Error> {
+		(**self).fm_write(key,value)
+	}
+
+	#[inline]
+	fn fm_get(&self, key: T) -> Result<Option<U>,Error> {
+		(**self).fm_get(key)
+	}
+
+	#[inline]
+	fn fm_write_batch(&self, batch: Vec<(T,T)>) -> Result<(),Error> {
+		(**self).fm_write_batch(batch)
+	}
+
+	#[inline]
+	fn fm_delete(&self, key: T) -> Result<(),Error> {
+		(**self).fm_delete(key)
+	}
+
+	#[inline]
+	fn fm_scan<'a>(&'a self, start_key: T, end_key: T) -> Result<Box<dyn Iterator<Item=(Vec<u8>,Vec<u8>)> + 'a>,Error> {
+		(**self).fm_scan(start_key,end_key)
+	}
+
+	#[inline]
+	fn fm_scan_prefix<'a>(&'a self, start_key: T, end_key: T, len: usize) -> Result<Box<dyn Iterator<Item=(Vec<u8>,Vec<u8>)> + 'a>,Error> {
+		(**self).fm_scan_prefix(start_key,end_key,len)
+	}
 }
 
 
+/* Lets the shards of a buffer pool write through a single file manager */
+impl<T,U,F> FileManager<T,U> for Arc<F>
+	where T: AsRef<[u8]>,
+		  U: AsRef<[u8]>,
+		  F: FileManager<T,U> + ?Sized,
+{
+	#[inline]
+	fn fm_write(&self, key: T, value: T) -> Result<(),Error> {
+		(**self).fm_write(key,value)
+	}
+
+	#[inline]
+	fn fm_get(&self, key: T) -> Result<Option<U>,Error> {
+		(**self).fm_get(key)
+	}
+
+	#[inline]
+	fn fm_write_batch(&self, batch: Vec<(T,T)>) -> Result<(),Error> {
+		(**self).fm_write_batch(batch)
+	}
+
+	#[inline]
+	fn fm_delete(&self, key: T) -> Result<(),Error> {
+		(**self).fm_delete(key)
+	}
+
+	#[inline]
+	fn fm_scan<'a>(&'a self, start_key: T, end_key: T) -> Result<Box<dyn Iterator<Item=(Vec<u8>,Vec<u8>)> + 'a>,Error> {
+		(**self).fm_scan(start_key,end_key)
+	}
+
+	#[inline]
+	fn fm_scan_prefix<'a>(&'a self, start_key: T, end_key: T, len: usize) -> Result<Box<dyn Iterator<Item=(Vec<u8>,Vec<u8>)> + 'a>,Error> {
+		(**self).fm_scan_prefix(start_key,end_key,len)
+	}
+}
+
+#[cfg(feature = "rocksdb")]
 impl<T,U> FileManager<T,U> for rocksdb::DB
 	where T: AsRef<[u8]>,
-	      U: AsRef<[u8]> + From<DBVector>,
+	      U: AsRef<[u8]> + From<Vec<u8>>,
 {
 	#[inline]
 	fn fm_write(&self, key: T, value: T) -> Result<(),Error> {
@@ -76,21 +210,493 @@ impl<T,U> FileManager<T,U> for rocksdb::DB
 	#[inline]
 	fn fm_get(&self, key: T) -> Result<Option<U>,Error> {
 		match self.get(key) {
-			Ok(Some(x)) => Ok(Some(x.into())),
+			Ok(Some(x)) => Ok(Some(x.to_vec().into())),
 			Ok(None)    => Ok(None),
 			Err(e)      => Err(Error::DbError(e)),
 		}
 
 	}
+
+	fn fm_write_batch(&self, batch: Vec<(T,T)>) -> Result<(),Error> {
+		let mut write_batch = WriteBatch::default();
+		for (key,value) in batch {
+			if let Err(e) = write_batch.put(key,value) {
+				return Err(Error::DbError(e));
+			}
+		}
+		match self.write(write_batch) {
+			Err(e) => Err(Error::DbError(e)),
+			Ok(_)  => Ok(()),
+		}
+	}
+
+	#[inline]
+	fn fm_delete(&self, key: T) -> Result<(),Error> {
+		match self.delete(key) {
+			Err(e) => Err(Error::DbError(e)),
+			Ok(_)  => Ok(()),
+		}
+	}
+
+	fn fm_scan<'a>(&'a self, start_key: T, end_key: T) -> Result<Box<dyn Iterator<Item=(Vec<u8>,Vec<u8>)> + 'a>,Error> {
+		let end = end_key.as_ref().to_vec();
+		let iter = self.iterator(IteratorMode::From(start_key.as_ref(), Direction::Forward));
+		Ok(Box::new(iter.take_while(move |(k,_)| k.as_ref() < end.as_slice())
+						.map(|(k,v)| (k.to_vec(), v.to_vec()))))
+	}
+
+	fn fm_scan_prefix<'a>(&'a self, start_key: T, end_key: T, len: usize) -> Result<Box<dyn Iterator<Item=(Vec<u8>,Vec<u8>)> + 'a>,Error> {
+		let end = end_key.as_ref().to_vec();
+		let iter = self.iterator(IteratorMode::From(start_key.as_ref(), Direction::Forward));
+		Ok(Box::new(iter.take_while(move |(k,_)| k.as_ref() < end.as_slice())
+						.map(move |(k,v)| (k.to_vec(), v[..len.min(v.len())].to_vec()))))
+	}
+}
+
+/***************************************************************
+ **********************LogFileManager***************************
+ ***************************************************************/
+
+/* Layout of the directory owned by a LogFileManager:
+ *   segments.log: every write appended as a record
+ *       [op: u8][key len: u32][value len: u32][key][value][crc32: u32]
+ *       op is RECORD_PUT or RECORD_DELETE, deletes carry no value.
+ *   segments.idx: a checkpoint of the sorted key index
+ *       [magic: u32][log length covered: u64][entries: u64]
+ *       entries of [key len: u32][key][offset: u64][value len: u32]
+ *       followed by a crc32 of everything before it.
+ *
+ * Every write is fsynced before it is acknowledged. On open the index
+ * checkpoint is loaded and the log is replayed past the covered length.
+ * A torn record at the end of the log (from a crash mid write) fails its
+ * checksum and is truncated away, anything before it is kept.
+ * The index checkpoint is replaced atomically through a rename. Compaction
+ * removes it before the compacted log replaces the old one, its offsets
+ * would point into the wrong records, so a crash in between replays
+ * the whole log instead.
+ * Scans look up one key at a time and read its value on next.
+ */
+
+const LOG_FILE: &str = "segments.log";
+const INDEX_FILE: &str = "segments.idx";
+const INDEX_MAGIC: u32 = 0x5453_4958;
+const RECORD_PUT: u8 = 0;
+const RECORD_DELETE: u8 = 1;
+const RECORD_HEADER: u64 = 9;
+const CHECKPOINT_INTERVAL: usize = 1024;
+
+lazy_static! {
+	static ref CRC32_TABLE: [u32; 256] = {
+		let mut table = [0u32; 256];
+		for i in 0..256 {
+			let mut c = i as u32;
+			for _ in 0..8 {
+				c = if c & 1 != 0 { 0xEDB8_8320 ^ (c >> 1) } else { c >> 1 };
+			}
+			table[i] = c;
+		}
+		table
+	};
+}
+
+/* CRC-32 (IEEE) of the provided bytes */
+pub fn crc32(bytes: &[u8]) -> u32 {
+	let mut crc = 0xFFFF_FFFFu32;
+	for b in bytes {
+		crc = CRC32_TABLE[((crc ^ *b as u32) & 0xFF) as usize] ^ (crc >> 8);
+	}
+	crc ^ 0xFFFF_FFFF
+}
+
+/* Location of a value inside the log */
+#[derive(Clone,Copy,Debug,PartialEq)]
+struct LogEntry {
+	offset: u64,
+	len: u32,
+}
+
+struct LogState {
+	log: File,
+	log_len: u64,
+	index: BTreeMap<Vec<u8>,LogEntry>,
+	since_checkpoint: usize,
+}
+
+pub struct LogFileManager {
+	dir: PathBuf,
+	state: Mutex<LogState>,
+}
+
+impl LogFileManager {
+	/* Opens (or creates) the store in the provided directory
+	 * and recovers the key index from the checkpoint and the log
+	 */
+	pub fn open<P: AsRef<Path>>(dir: P) -> Result<LogFileManager,Error> {
+		let dir = dir.as_ref().to_path_buf();
+		fs::create_dir_all(&dir).map_err(Error::IoError)?;
+
+		let mut log = OpenOptions::new().read(true).append(true).create(true)
+							.open(dir.join(LOG_FILE)).map_err(Error::IoError)?;
+		let file_len = log.metadata().map_err(Error::IoError)?.len();
+
+		let (mut index, covered) = match LogFileManager::read_checkpoint(&dir.join(INDEX_FILE)) {
+			Some((index,covered)) if covered <= file_len => (index,covered),
+			_ => (BTreeMap::new(), 0),
+		};
+
+		let log_len = LogFileManager::replay(&mut log, covered, file_len, &mut index)?;
+		if log_len < file_len {
+			println!("Truncating {} bytes of incomplete records from {:?}", file_len - log_len, dir.join(LOG_FILE));
+			log.set_len(log_len).map_err(Error::IoError)?;
+			log.sync_all().map_err(Error::IoError)?;
+		}
+
+		Ok(LogFileManager {
+			dir: dir,
+			state: Mutex::new(LogState {
+				log: log,
+				log_len: log_len,
+				index: index,
+				since_checkpoint: 0,
+			}),
+		})
+	}
+
+	/* Removes every file owned by the store in the provided directory */
+	pub fn destroy<P: AsRef<Path>>(dir: P) -> Result<(),Error> {
+		match fs::remove_dir_all(dir) {
+			Ok(()) => Ok(()),
+			Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+			Err(e) => Err(Error::IoError(e)),
+		}
+	}
+
+	/* Number of live keys */
+	pub fn len(&self) -> usize {
+		self.state.lock().map(|st| st.index.len()).unwrap_or(0)
+	}
+
+	/* Bytes occupied by the log, including overwritten and deleted values */
+	pub fn log_size(&self) -> u64 {
+		self.state.lock().map(|st| st.log_len).unwrap_or(0)
+	}
+
+	/* Rewrites the log keeping only live values, reclaiming the space
+	 * of overwritten and deleted entries. Returns the bytes reclaimed.
+	 */
+	pub fn compact(&self) -> Result<u64,Error> {
+		let mut st = self.state.lock().map_err(|_| Error::LockError)?;
+		let tmp_path = self.dir.join(format!("{}.compact", LOG_FILE));
+		let mut tmp = OpenOptions::new().read(true).write(true).create(true).truncate(true)
+							.open(&tmp_path).map_err(Error::IoError)?;
+
+		let mut new_index = BTreeMap::new();
+		let mut new_len = 0u64;
+		let entries: Vec<(Vec<u8>,LogEntry)> = st.index.iter().map(|(k,e)| (k.clone(),*e)).collect();
+		for (key, entry) in entries {
+			let value = LogFileManager::read_value(&mut st.log, entry)?;
+			let record = LogFileManager::encode_record(RECORD_PUT, &key, &value);
+			tmp.write_all(&record).map_err(Error::IoError)?;
+			new_index.insert(key.clone(), LogEntry { offset: new_len + RECORD_HEADER + key.len() as u64, len: value.len() as u32 });
+			new_len += record.len() as u64;
+		}
+		tmp.sync_all().map_err(Error::IoError)?;
+		drop(tmp);
+
+		match fs::remove_file(self.dir.join(INDEX_FILE)) {
+			Ok(()) => (),
+			Err(ref e) if e.kind() == io::ErrorKind::NotFound => (),
+			Err(e) => return Err(Error::IoError(e)),
+		}
+		sync_dir(&self.dir)?;
+		fs::rename(&tmp_path, self.dir.join(LOG_FILE)).map_err(Error::IoError)?;
+		sync_dir(&self.dir)?;
+
+		let reclaimed = st.log_len - new_len;
+		st.log = OpenOptions::new().read(true).append(true).open(self.dir.join(LOG_FILE)).map_err(Error::IoError)?;
+		st.log_len = new_len;
+		st.index = new_index;
+		self.checkpoint(&mut st)?;
+		Ok(reclaimed)
+	}
+
+	fn encode_record(op: u8, key: &[u8], value: &[u8]) -> Vec<u8> {
+		let mut record = Vec::with_capacity(RECORD_HEADER as usize + key.len() + value.len() + 4);
+		record.push(op);
+		record.extend_from_slice(&(key.len() as u32).to_le_bytes());
+		record.extend_from_slice(&(value.len() as u32).to_le_bytes());
+		record.extend_from_slice(key);
+		record.extend_from_slice(value);
+		let crc = crc32(&record);
+		record.extend_from_slice(&crc.to_le_bytes());
+		record
+	}
+
+	/* Replays the records in [start, end) into the index and returns
+	 * the offset just past the last complete record
+	 */
+	fn replay(log: &mut File, start: u64, end: u64, index: &mut BTreeMap<Vec<u8>,LogEntry>) -> Result<u64,Error> {
+		let mut bytes = Vec::with_capacity((end - start) as usize);
+		log.seek(SeekFrom::Start(start)).map_err(Error::IoError)?;
+		log.read_to_end(&mut bytes).map_err(Error::IoError)?;
+
+		let mut pos = 0usize;
+		loop {
+			if pos + RECORD_HEADER as usize > bytes.len() {
+				break;
+			}
+			let op = bytes[pos];
+			let key_len = read_u32(&bytes[pos+1..pos+5]) as usize;
+			let val_len = read_u32(&bytes[pos+5..pos+9]) as usize;
+			let body_end = pos + RECORD_HEADER as usize + key_len + val_len;
+			if body_end + 4 > bytes.len() || read_u32(&bytes[body_end..body_end+4]) != crc32(&bytes[pos..body_end]) {
+				break;
+			}
+			let key_start = pos + RECORD_HEADER as usize;
+			let key = bytes[key_start..key_start+key_len].to_vec();
+			match op {
+				RECORD_PUT => {
+					let offset = start + (key_start + key_len) as u64;
+					index.insert(key, LogEntry { offset: offset, len: val_len as u32 });
+				}
+				RECORD_DELETE => {
+					index.remove(&key);
+				}
+				_ => break,
+			}
+			pos = body_end + 4;
+		}
+		Ok(start + pos as u64)
+	}
+
+	fn read_value(log: &mut File, entry: LogEntry) -> Result<Vec<u8>,Error> {
+		let mut value = vec![0u8; entry.len as usize];
+		log.seek(SeekFrom::Start(entry.offset)).map_err(Error::IoError)?;
+		log.read_exact(&mut value).map_err(Error::IoError)?;
+		Ok(value)
+	}
+
+	/* Appends the records and fsyncs the log before updating the index */
+	fn append(&self, records: Vec<(u8,Vec<u8>,Vec<u8>)>) -> Result<(),Error> {
+		let mut st = self.state.lock().map_err(|_| Error::LockError)?;
+		let mut buf = Vec::new();
+		let mut updates = Vec::with_capacity(records.len());
+		let mut pos = st.log_len;
+		for (op, key, value) in records {
+			let record = LogFileManager::encode_record(op, &key, &value);
+			updates.push((op, key.clone(), LogEntry { offset: pos + RECORD_HEADER + key.len() as u64, len: value.len() as u32 }));
+			pos += record.len() as u64;
+			buf.extend_from_slice(&record);
+		}
+
+		if let Err(e) = st.log.write_all(&buf).and_then(|_| st.log.sync_data()) {
+			/* Drop whatever part of the batch made it to disk */
+			let len = st.log_len;
+			let _ = st.log.set_len(len);
+			return Err(Error::IoError(e));
+		}
+		st.log_len = pos;
+
+		for (op, key, entry) in updates {
+			if op == RECORD_PUT {
+				st.index.insert(key, entry);
+			} else {
+				st.index.remove(&key);
+			}
+		}
+
+		st.since_checkpoint += 1;
+		if st.since_checkpoint >= CHECKPOINT_INTERVAL {
+			self.checkpoint(&mut st)?;
+		}
+		Ok(())
+	}
+
+	fn checkpoint(&self, st: &mut LogState) -> Result<(),Error> {
+		let mut bytes = Vec::new();
+		bytes.extend_from_slice(&INDEX_MAGIC.to_le_bytes());
+		bytes.extend_from_slice(&st.log_len.to_le_bytes());
+		bytes.extend_from_slice(&(st.index.len() as u64).to_le_bytes());
+		for (key, entry) in st.index.iter() {
+			bytes.extend_from_slice(&(key.len() as u32).to_le_bytes());
+			bytes.extend_from_slice(key);
+			bytes.extend_from_slice(&entry.offset.to_le_bytes());
+			bytes.extend_from_slice(&entry.len.to_le_bytes());
+		}
+		let crc = crc32(&bytes);
+		bytes.extend_from_slice(&crc.to_le_bytes());
+
+		let tmp_path = self.dir.join(format!("{}.tmp", INDEX_FILE));
+		let mut tmp = File::create(&tmp_path).map_err(Error::IoError)?;
+		tmp.write_all(&bytes).and_then(|_| tmp.sync_all()).map_err(Error::IoError)?;
+		fs::rename(&tmp_path, self.dir.join(INDEX_FILE)).map_err(Error::IoError)?;
+		sync_dir(&self.dir)?;
+		st.since_checkpoint = 0;
+		Ok(())
+	}
+
+	/* Returns None if the checkpoint is missing or does not pass its checksum */
+	fn read_checkpoint(path: &Path) -> Option<(BTreeMap<Vec<u8>,LogEntry>,u64)> {
+		let bytes = fs::read(path).ok()?;
+		if bytes.len() < 24 {
+			return None;
+		}
+		let (body, crc) = bytes.split_at(bytes.len() - 4);
+		if read_u32(crc) != crc32(body) || read_u32(&body[0..4]) != INDEX_MAGIC {
+			return None;
+		}
+		let covered = read_u64(&body[4..12]);
+		let count = read_u64(&body[12..20]);
+		let mut index = BTreeMap::new();
+		let mut pos = 20;
+		for _ in 0..count {
+			let key_len = read_u32(body.get(pos..pos+4)?) as usize;
+			pos += 4;
+			let key = body.get(pos..pos+key_len)?.to_vec();
+			pos += key_len;
+			let offset = read_u64(body.get(pos..pos+8)?);
+			let len = read_u32(body.get(pos+8..pos+12)?);
+			pos += 12;
+			index.insert(key, LogEntry { offset: offset, len: len });
+		}
+		Some((index, covered))
+	}
+}
+
+impl Drop for LogFileManager {
+	fn drop(&mut self) {
+		if let Ok(mut st) = self.state.lock() {
+			if st.since_checkpoint > 0 {
+				if let Err(e) = self.checkpoint(&mut st) {
+					println!("Failed to checkpoint the log index: {:?}", e);
+				}
+			}
+		}
+	}
+}
+
+impl<T,U> FileManager<T,U> for LogFileManager
+	where T: AsRef<[u8]>,
+		  U: AsRef<[u8]> + From<Vec<u8>>,
+{
+	#[inline]
+	fn fm_write(&self, key: T, value: T) -> Result<(),Error> {
+		self.append(vec![(RECORD_PUT, key.as_ref().to_vec(), value.as_ref().to_vec())])
+	}
+
+	fn fm_get(&self, key: T) -> Result<Option<U>,Error> {
+		let mut st = self.state.lock().map_err(|_| Error::LockError)?;
+		let entry = match st.index.get(key.as_ref()) {
+			Some(entry) => *entry,
+			None => return Ok(None),
+		};
+		let value = LogFileManager::read_value(&mut st.log, entry)?;
+		Ok(Some(value.into()))
+	}
+
+	fn fm_write_batch(&self, batch: Vec<(T,T)>) -> Result<(),Error> {
+		self.append(batch.into_iter()
+						 .map(|(k,v)| (RECORD_PUT, k.as_ref().to_vec(), v.as_ref().to_vec()))
+						 .collect())
+	}
+
+	#[inline]
+	fn fm_delete(&self, key: T) -> Result<(),Error> {
+		self.append(vec![(RECORD_DELETE, key.as_ref().to_vec(), Vec::new())])
+	}
+
+	fn fm_scan<'a>(&'a self, start_key: T, end_key: T) -> Result<Box<dyn Iterator<Item=(Vec<u8>,Vec<u8>)> + 'a>,Error> {
+		Ok(Box::new(LogScan::new(self, start_key.as_ref(), end_key.as_ref(), usize::max_value())))
+	}
+
+	/* Only the prefix of every value is read from the log */
+	fn fm_scan_prefix<'a>(&'a self, start_key: T, end_key: T, len: usize) -> Result<Box<dyn Iterator<Item=(Vec<u8>,Vec<u8>)> + 'a>,Error> {
+		Ok(Box::new(LogScan::new(self, start_key.as_ref(), end_key.as_ref(), len)))
+	}
+}
+
+/* Ordered scan over [start, end) of a LogFileManager. Each call to next
+ * takes the lock, finds the key after the last one returned and reads
+ * at most len bytes of its value, so writes and compactions in between
+ * are seen and only one value is held at a time.
+ */
+struct LogScan<'a> {
+	fm: &'a LogFileManager,
+	from: Bound<Vec<u8>>,
+	end: Vec<u8>,
+	len: usize,
+}
+
+impl<'a> LogScan<'a> {
+	fn new(fm: &'a LogFileManager, start: &[u8], end: &[u8], len: usize) -> LogScan<'a> {
+		/* An empty range starts past its end */
+		let from = if start < end { Bound::Included(start.to_vec()) } else { Bound::Excluded(end.to_vec()) };
+		LogScan { fm: fm, from: from, end: end.to_vec(), len: len }
+	}
+}
+
+impl<'a> Iterator for LogScan<'a> {
+	type Item = (Vec<u8>,Vec<u8>);
+
+	fn next(&mut self) -> Option<(Vec<u8>,Vec<u8>)> {
+		if let Bound::Excluded(ref key) = self.from {
+			if *key == self.end {
+				return None;
+			}
+		}
+		let mut st = self.fm.state.lock().ok()?;
+		let (key, entry) = match st.index.range((self.from.clone(), Bound::Excluded(self.end.clone()))).next() {
+			Some((k,e)) => (k.clone(), LogEntry { offset: e.offset, len: e.len.min(self.len as u32) }),
+			None => {
+				self.from = Bound::Excluded(self.end.clone());
+				return None;
+			}
+		};
+		match LogFileManager::read_value(&mut st.log, entry) {
+			Ok(value) => {
+				self.from = Bound::Excluded(key.clone());
+				Some((key, value))
+			}
+			Err(e) => {
+				println!("Failed to read {:?} from the log: {:?}", key, e);
+				self.from = Bound::Excluded(self.end.clone());
+				None
+			}
+		}
+	}
+}
+
+#[inline]
+pub(crate) fn read_u32(bytes: &[u8]) -> u32 {
+	let mut buf = [0u8; 4];
+	buf.copy_from_slice(&bytes[..4]);
+	u32::from_le_bytes(buf)
+}
+
+#[inline]
+pub(crate) fn read_u64(bytes: &[u8]) -> u64 {
+	let mut buf = [0u8; 8];
+	buf.copy_from_slice(&bytes[..8]);
+	u64::from_le_bytes(buf)
+}
+
+/* Makes renames inside the directory durable */
+pub(crate) fn sync_dir(dir: &Path) -> Result<(),Error> {
+	match File::open(dir) {
+		Ok(d) => d.sync_all().map_err(Error::IoError),
+		Err(e) => Err(Error::IoError(e)),
+	}
 }
 
 /***************************************************************
  ****************************Testing****************************
  ***************************************************************/
 
+#[cfg(feature = "rocksdb")]
 const FILEPATH: &str = "../rocksdb";
 
-fn read_write_validate<'a,T:Send>(fm: &FileManager<Vec<u8>,DBVector>, seg: &mut Segment<T>) -> DBVector
+#[cfg(feature = "rocksdb")]
+fn read_write_validate<'a,T:Send>(fm: &dyn FileManager<Vec<u8>,Vec<u8>>, seg: &mut Segment<T>) -> Vec<u8>
 	where T: Clone + Serialize + Deserialize<'a> + Debug + PartialEq
 {
 	let seg_key = seg.get_key();
@@ -129,6 +735,7 @@ fn read_write_validate<'a,T:Send>(fm: &FileManager<Vec<u8>,DBVector>, seg: &mut
 
 
 #[test]
+#[cfg(feature = "rocksdb")]
 fn read_write_test() {
 	let mut db_opts = Options::default();
 	db_opts.create_if_missing(true);
@@ -144,7 +751,7 @@ fn read_write_test() {
 		)}).collect();
 
 	for mut seg in segs {
-		let inflated_seg_bytes = read_write_validate(&fm, &mut seg).as_ref().iter()
+		let inflated_seg_bytes = read_write_validate(&fm, &mut seg).iter()
         .cloned()
         .decode(&mut BZip2Decoder::new())
         .collect::<Result<Vec<_>, _>>()
@@ -165,4 +772,101 @@ fn read_write_test() {
 		Ok(()) => (),
 		Err(e) => panic!("Failed to destroy db: {:?}", e),
 	}
-}
\ No newline at end of file
+}
+
+
+#[test]
+#[cfg(feature = "rocksdb")]
+fn batch_scan_delete_test() {
+	let path = "../rocksdb_scan";
+	let mut db_opts = Options::default();
+	db_opts.create_if_missing(true);
+	let fm = match rocksdb::DB::open(&db_opts, path) {
+		Ok(x) => x,
+		Err(e) => panic!("Failed to create database: {:?}", e),
+	};
+
+	let batch: Vec<(Vec<u8>,Vec<u8>)> = (0..10u8).map(|i| (vec![1,i], vec![i;4])).collect();
+	match FileManager::<Vec<u8>,Vec<u8>>::fm_write_batch(&fm, batch) {
+		Ok(_) => (),
+		Err(e) => panic!("Failed to write batch: {:?}", e),
+	}
+
+	let scanned: Vec<(Vec<u8>,Vec<u8>)> = match FileManager::<Vec<u8>,Vec<u8>>::fm_scan(&fm, vec![1,2], vec![1,6]) {
+		Ok(iter) => iter.collect(),
+		Err(e) => panic!("Failed to scan: {:?}", e),
+	};
+	assert_eq!(scanned.len(), 4);
+	for (i,(k,v)) in scanned.iter().enumerate() {
+		assert_eq!(*k, vec![1,i as u8 + 2]);
+		assert_eq!(*v, vec![i as u8 + 2;4]);
+	}
+
+	match FileManager::<Vec<u8>,Vec<u8>>::fm_delete(&fm, vec![1,3]) {
+		Ok(_) => (),
+		Err(e) => panic!("Failed to delete key: {:?}", e),
+	}
+	match FileManager::<Vec<u8>,Vec<u8>>::fm_get(&fm, vec![1,3]) {
+		Ok(None) => (),
+		_ => panic!("Deleted key is still present"),
+	}
+	let remaining = FileManager::<Vec<u8>,Vec<u8>>::fm_scan(&fm, vec![1,0], vec![2]).unwrap().count();
+	assert_eq!(remaining, 9);
+
+	drop(fm);
+
+	match rocksdb::DB::destroy(&db_opts, path) {
+		Ok(()) => (),
+		Err(e) => panic!("Failed to destroy db: {:?}", e),
+	}
+}
+
+
+#[test]
+fn log_file_manager_recovery_test() {
+	let path = "../localfm_test";
+	let _ = LogFileManager::destroy(path);
+
+	{
+		let fm = LogFileManager::open(path).expect("Failed to open log file manager");
+		let batch: Vec<(Vec<u8>,Vec<u8>)> = (0..10u8).map(|i| (vec![i], vec![i;16])).collect();
+		FileManager::<Vec<u8>,Vec<u8>>::fm_write_batch(&fm, batch).expect("Failed to write batch");
+		FileManager::<Vec<u8>,Vec<u8>>::fm_write(&fm, vec![3], vec![42;8]).expect("Failed to overwrite");
+		FileManager::<Vec<u8>,Vec<u8>>::fm_delete(&fm, vec![4]).expect("Failed to delete");
+	}
+
+	/* Simulate a crash in the middle of appending a record */
+	{
+		let mut log = OpenOptions::new().append(true).open(Path::new(path).join(LOG_FILE)).unwrap();
+		log.write_all(&[RECORD_PUT, 1, 0, 0, 0, 200, 0]).unwrap();
+	}
+
+	let fm = LogFileManager::open(path).expect("Failed to reopen log file manager");
+	assert_eq!(fm.len(), 9);
+	let value: Option<Vec<u8>> = FileManager::<Vec<u8>,Vec<u8>>::fm_get(&fm, vec![3]).unwrap();
+	assert_eq!(value, Some(vec![42;8]));
+	let value: Option<Vec<u8>> = FileManager::<Vec<u8>,Vec<u8>>::fm_get(&fm, vec![4]).unwrap();
+	assert_eq!(value, None);
+
+	let keys: Vec<Vec<u8>> = FileManager::<Vec<u8>,Vec<u8>>::fm_scan(&fm, vec![2], vec![7]).unwrap().map(|(k,_)| k).collect();
+	assert_eq!(keys, vec![vec![2],vec![3],vec![5],vec![6]]);
+	let prefixes: Vec<Vec<u8>> = FileManager::<Vec<u8>,Vec<u8>>::fm_scan_prefix(&fm, vec![2], vec![4], 10).unwrap().map(|(_,v)| v).collect();
+	assert_eq!(prefixes, vec![vec![2;10],vec![42;8]]);
+
+	/* The scan reads each value when it gets there, after the compaction below */
+	let mut scan = FileManager::<Vec<u8>,Vec<u8>>::fm_scan(&fm, vec![0], vec![10]).unwrap();
+	assert_eq!(scan.next(), Some((vec![0], vec![0;16])));
+
+	let before = fm.log_size();
+	let reclaimed = fm.compact().expect("Failed to compact");
+	let rest: Vec<(Vec<u8>,Vec<u8>)> = scan.collect();
+	assert_eq!(rest.len(), 8);
+	assert_eq!(rest[2], (vec![3], vec![42;8]));
+	assert_eq!(rest[7], (vec![9], vec![9;16]));
+	assert_eq!(before - reclaimed, fm.log_size());
+	let value: Option<Vec<u8>> = FileManager::<Vec<u8>,Vec<u8>>::fm_get(&fm, vec![9]).unwrap();
+	assert_eq!(value, Some(vec![9;16]));
+
+	drop(fm);
+	LogFileManager::destroy(path).expect("Failed to destroy log file manager");
+}
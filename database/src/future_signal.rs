@@ -6,8 +6,13 @@ use serde::de::DeserializeOwned;
 use crate::file_handler::FileManager;
 use crate::client::{construct_file_client_skip_newline,Amount,RunPeriod,Frequency};
 use std::sync::{Arc,Mutex};
-use crate::buffer_pool::{SegmentBuffer,ClockBuffer};
+use crate::buffer_pool::{SegmentBuffer,ClockBuffer,BufErr};
+use crate::buffer_pool::governor::{governor,MemoryGovernor};
+use crate::shutdown::Shutdown;
+use crate::wal::WriteAheadLog;
+use crate::catalog::{SignalCatalog,codec_of};
 use crate::segment::{Segment,SegmentKey};
+use crate::segment::columnar::ColumnarSegment;
 use std::time::SystemTime;
 use std::time::{Duration,Instant};
 use std::{mem, thread};
@@ -18,7 +23,7 @@ use ndarray::Array2;
 use nalgebra::Matrix2;
 use crate::kernel::Kernel;
 use rustfft::FFTnum;
-use num::Float;
+use num::{Float, Num, FromPrimitive};
 use ndarray_linalg::Lapack;
 use std::ptr::null;
 use futures::sync::oneshot;
@@ -46,7 +51,11 @@ pub struct BufferedSignal<T,U,F,G>
 	compress_on_segmentation: bool,
 	compression_percentage: f64,
 	segments_produced: u32,
-	kernel: Option<Kernel<T>>
+	kernel: Option<Kernel<T>>,
+	governor: Arc<MemoryGovernor>,
+	shutdown: Shutdown,
+	wal: Option<Arc<WriteAheadLog>>,
+	catalog: Option<Arc<Mutex<SignalCatalog>>>,
 }
 
 /* Fix the buffer to not reuqire broad locking it */
@@ -92,9 +101,115 @@ impl<T,U,F,G> BufferedSignal<T,U,F,G>
 			compression_percentage: 0.0,
 			segments_produced: 0,
 			kernel: kernel,
+			governor: governor(),
+			shutdown: Shutdown::new(),
+			wal: None,
+			catalog: None,
 		}
 	}
 
+	/* Lets the signal be stopped before its stream runs out */
+	pub fn with_shutdown(mut self, shutdown: Shutdown) -> BufferedSignal<T,U,F,G> {
+		self.shutdown = shutdown;
+		self
+	}
+
+	/* Holds the signal back by another governor than the process wide one */
+	pub fn with_governor(mut self, governor: Arc<MemoryGovernor>) -> BufferedSignal<T,U,F,G> {
+		self.governor = governor;
+		self
+	}
+
+	/* Logs every value before it is added to a segment, so the
+	 * segments not yet persisted can be recovered after a crash
+	 */
+	pub fn with_wal(mut self, wal: Option<Arc<WriteAheadLog>>) -> BufferedSignal<T,U,F,G> {
+		self.wal = wal;
+		self
+	}
+
+	/* Records every segment in the catalog. A signal the catalog already
+	 * holds links its first segment to the newest one stored, unless that
+	 * one lies ahead of the clock, which would break the chain.
+	 */
+	pub fn with_catalog(mut self, catalog: Option<Arc<Mutex<SignalCatalog>>>) -> BufferedSignal<T,U,F,G> {
+		if let Some(catalog) = &catalog {
+			if let Some(key) = catalog.lock().expect("Failed to acquire the catalog lock").get_last_key(self.signal_id) {
+				if key.get_timestamp() <= SystemTime::now() {
+					self.prev_seg_offset = Some(key.get_timestamp());
+				}
+			}
+		}
+		self.catalog = catalog;
+		self
+	}
+
+}
+
+impl<T,U,F,G> BufferedSignal<T,U,F,G> 
+	where T: Copy + Send+ FFTnum+ Float+Lapack,
+		  U: Stream<Item=T,Error=()>,
+		  F: Fn(usize,usize) -> bool,
+		  G: Fn(&mut Segment<T>)
+{
+	/* Seals the values received so far into a segment and hands it to the buffer,
+	 * a failure is reported here and ends the signal
+	 */
+	fn seal(&mut self, cur_time: SystemTime) -> Result<(),()> {
+		let data = mem::replace(&mut self.data, Vec::with_capacity(self.seg_size));
+		let time_lapse = mem::replace(&mut self.time_lapse, Vec::with_capacity(self.seg_size));
+		let old_timestamp = mem::replace(&mut self.timestamp, Some(cur_time));
+		let prev_seg_offset = mem::replace(&mut self.prev_seg_offset, old_timestamp);
+		let dur_offset = match prev_seg_offset {
+			Some(t) => match old_timestamp.unwrap().duration_since(t) {
+				Ok(d) => Some(d),
+				Err(_) => panic!("Hard Failure, since messes up implicit chain"),
+			}
+			None => None,
+		};
+		let mut seg = Segment::new(None,old_timestamp.unwrap(),self.signal_id,
+							   data, Some(time_lapse), dur_offset);
+		
+		if self.compress_on_segmentation {
+			let before = self.data.len() as f64;
+			(self.compress_func)(&mut seg);
+			let after = self.data.len() as f64;
+			self.compression_percentage += after/before;
+		}
+
+		if let Some(wal) = &self.wal {
+			if let Err(e) = wal.seal(self.signal_id, old_timestamp.unwrap()) {
+				println!("Signal {} failed to seal its segment in the write ahead log: {:?}", self.signal_id, e);
+				return Err(());
+			}
+		}
+
+		if let Some(catalog) = &self.catalog {
+			match catalog.lock() {
+				Ok(mut catalog) => catalog.record(seg.get_key(), codec_of(seg.get_method()), seg.get_size() as u64),
+				Err(_) => panic!("Failed to acquire the catalog lock"),
+			}
+		}
+
+		match self.buffer.lock() {
+			Ok(mut buf) => match buf.put(seg) {
+				Ok(()) => Ok(()),
+				Err(e) => {
+					println!("Signal {} failed to put its segment in the buffer: {:?}", self.signal_id, e);
+					Err(())
+				}
+			},
+			Err(_)  => panic!("Failed to acquire buffer write lock"),
+		}
+	}
+
+	/* Seals the values received so far, a partial segment included */
+	fn finish(&mut self) -> Result<Option<SystemTime>,()> {
+		if !self.data.is_empty() {
+			self.seal(SystemTime::now())?;
+		}
+		Ok(self.prev_seg_offset)
+	}
 }
 
 /* Currently just creates the segment and writes it to a buffer,
@@ -106,8 +221,6 @@ impl<T,U,F,G> BufferedSignal<T,U,F,G>
    		4. Allow function that determines what method to apply,
    			Like a hashmap from signal id to a method enum that should
    				be applied for that signal
-   		5. Allow early return/way for user to kill a signal without 
-   			having the signal need to exhaust the stream
  */
 impl<T,U,F,G> Future for BufferedSignal<T,U,F,G> 
 	where T: Copy + Send+ FFTnum+ Float+Lapack,
@@ -122,9 +235,20 @@ impl<T,U,F,G> Future for BufferedSignal<T,U,F,G>
 		let mut batch_vec: Vec<T> = Vec::new();
 		let mut bsize = 0;
 		loop {
+			/* A shut down signal keeps its partial segment, like one whose stream ran out */
+			if self.shutdown.is_stopping() {
+				return self.finish().map(Async::Ready);
+			}
+			/* Stop reading while the buffers hold more than the memory budget,
+			 * the governor wakes the task once the daemons released enough
+			 */
+			if let Async::NotReady = self.governor.poll_admit() {
+				return Ok(Async::NotReady);
+			}
 			match self.signal.poll() {
 				Ok(Async::NotReady) => return Ok(Async::NotReady),
 				Ok(Async::Ready(None)) => {
+					let last = self.finish()?;
 					let elapse: Duration = self.start.unwrap().elapsed();
 					if self.compress_on_segmentation {
 						let percentage = self.compression_percentage / (self.segments_produced as f64);
@@ -134,7 +258,7 @@ impl<T,U,F,G> Future for BufferedSignal<T,U,F,G>
 						println!("Signal: {}\n Segments produced: {}\n Data points in total {} \n Time: {:?}\n Throughput: {:?} points/second", self.signal_id, (self.segments_produced as usize)/self.seg_size, self.segments_produced, elapse, (self.segments_produced as f64) / ((elapse.as_nanos() as f64) / (1_000_000_000 as f64)));
 					}
 					
-					return Ok(Async::Ready(self.prev_seg_offset))
+					return Ok(Async::Ready(last))
 				}
 				Err(e) => {
 					println!("The client signal produced an error: {:?}", e);
@@ -152,17 +276,6 @@ impl<T,U,F,G> Future for BufferedSignal<T,U,F,G>
 
 					/* case where the value reaches split size */
 					if (self.split_decider)(self.data.len(), self.seg_size) {
-						let data = mem::replace(&mut self.data, Vec::with_capacity(self.seg_size));
-						let time_lapse = mem::replace(&mut self.time_lapse, Vec::with_capacity(self.seg_size));
-						let old_timestamp = mem::replace(&mut self.timestamp, Some(cur_time));
-						let prev_seg_offset = mem::replace(&mut self.prev_seg_offset, old_timestamp);
-						let dur_offset = match prev_seg_offset {
-							Some(t) => match old_timestamp.unwrap().duration_since(t) {
-								Ok(d) => Some(d),
-								Err(_) => panic!("Hard Failure, since messes up implicit chain"),
-							}
-							None => None,
-						};
 						//todo: adjust logics here to fix kernel method.
 						// we should remove compression logic here.
 						if bsize<DEFAULT_BATCH_SIZE{
@@ -181,27 +294,16 @@ impl<T,U,F,G> Future for BufferedSignal<T,U,F,G>
 							};
 							// println!("new vec for matrix length: {}", batch_vec.len());
 						}
-						// todo: remove time lapse and dur_offset
-						// let mut seg = Segment::new(None,old_timestamp.unwrap(),self.signal_id, data, Some(time_lapse), dur_offset);
-						let mut seg = Segment::new(None,old_timestamp.unwrap(),self.signal_id,
-											   data, None, None);
-						
-						if self.compress_on_segmentation {
-							let before = self.data.len() as f64;
-							(self.compress_func)(&mut seg);
-							let after = self.data.len() as f64;
-							self.compression_percentage += after/before;
-						}
-
-
-						match self.buffer.lock() {
-							Ok(mut buf) => match buf.put(seg) {
-								Ok(()) => (),
-								Err(e) => panic!("Failed to put segment in buffer: {:?}", e),
-							},
-							Err(_)  => panic!("Failed to acquire buffer write lock"),
-						}; /* Currently panics if can't get it */
+						self.seal(cur_time)?;
+					}
 
+					/* The value is only acknowledged once it is logged */
+					if let Some(wal) = &self.wal {
+						let logged = num::cast::<T,f64>(value).unwrap_or(std::f64::NAN);
+						if let Err(e) = wal.append(self.signal_id, cur_time, logged) {
+							println!("Signal {} failed to log a value in the write ahead log: {:?}", self.signal_id, e);
+							return Err(());
+						}
 					}
 
 					/* Always add the newly received data  */
@@ -505,7 +607,176 @@ impl<T,U,F,G,V> Future for StoredSignal<T,U,F,G,V>
 	}
 }
 
+pub struct ColumnarSignal<T,U,F,G> 
+	where T: Copy + Send,
+	      U: Stream,
+	      F: Fn(usize,usize) -> bool,
+	      G: Fn(&mut ColumnarSegment<T>),
+{
+	timestamp: Option<SystemTime>,
+	prev_seg_offset: Option<SystemTime>,
+	seg_size: usize,
+	signal_id: SignalId,
+	names: Vec<String>,
+	rows: Vec<Vec<T>>,
+	time_lapse: Vec<Duration>,
+	signal: U,
+	buffer: Arc<Mutex<SegmentBuffer<T> + Send + Sync>>,
+	split_decider: F,
+	compress_func: G,
+	segments_produced: u64,
+	governor: Arc<MemoryGovernor>,
+	shutdown: Shutdown,
+}
+
+/* Ingests tuples of a multivariate signal, one value per column name,
+   into columnar segments that share a single timestamp column. Every
+   sealed segment is compressed by compress_func and handed to the buffer,
+   which writes it through its file manager under one key per segment.
+   The rows of the open segment are not logged to the write ahead log,
+   whose records hold a single value per point.
+ */
+impl<T,U,F,G> ColumnarSignal<T,U,F,G> 
+	where T: Copy + Send,
+		  U: Stream,
+		  F: Fn(usize,usize) -> bool,
+		  G: Fn(&mut ColumnarSegment<T>),
+{
+
+	pub fn new(signal_id: u64, signal: U, names: Vec<String>, seg_size: usize, 
+		buffer: Arc<Mutex<SegmentBuffer<T> + Send + Sync>>,
+		split_decider: F, compress_func: G) 
+		-> ColumnarSignal<T,U,F,G> 
+	{
+		ColumnarSignal {
+			timestamp: None,
+			prev_seg_offset: None,
+			seg_size: seg_size,
+			signal_id: signal_id,
+			names: names,
+			rows: Vec::with_capacity(seg_size),
+			time_lapse: Vec::with_capacity(seg_size),
+			signal: signal,
+			buffer: buffer,
+			split_decider: split_decider,
+			compress_func: compress_func,
+			segments_produced: 0,
+			governor: governor(),
+			shutdown: Shutdown::new(),
+		}
+	}
+
+	/* Lets the signal be stopped before its stream runs out */
+	pub fn with_shutdown(mut self, shutdown: Shutdown) -> ColumnarSignal<T,U,F,G> {
+		self.shutdown = shutdown;
+		self
+	}
+}
+
+impl<T,U,F,G> ColumnarSignal<T,U,F,G> 
+	where T: Num + FromPrimitive + Copy + Send + FFTnum + Into<f64> + Serialize + DeserializeOwned,
+		  U: Stream,
+		  F: Fn(usize,usize) -> bool,
+		  G: Fn(&mut ColumnarSegment<T>),
+{
+	fn flush(&mut self, cur_time: SystemTime) -> Result<(),BufErr> {
+		let rows = mem::replace(&mut self.rows, Vec::with_capacity(self.seg_size));
+		let time_lapse = mem::replace(&mut self.time_lapse, Vec::with_capacity(self.seg_size));
+		let old_timestamp = mem::replace(&mut self.timestamp, Some(cur_time));
+		let prev_seg_offset = mem::replace(&mut self.prev_seg_offset, old_timestamp);
+		let dur_offset = match prev_seg_offset {
+			Some(t) => match old_timestamp.unwrap().duration_since(t) {
+				Ok(d) => Some(d),
+				Err(_) => panic!("Hard Failure, since messes up implicit chain"),
+			}
+			None => None,
+		};
+
+		let mut seg = ColumnarSegment::from_rows(old_timestamp.unwrap(), self.signal_id,
+									self.names.clone(), rows, Some(time_lapse), dur_offset);
+		(self.compress_func)(&mut seg);
+		self.segments_produced += 1;
+
+		let bytes = seg.convert_to_bytes().map_err(|_| BufErr::FailedSegSer)?;
+		match self.buffer.lock() {
+			Ok(mut buf) => buf.put_columnar(seg.get_key(), bytes),
+			Err(_) => Err(BufErr::CantGrabMutex),
+		}
+	}
+
+	/* Seals the rows received so far, a partial segment included */
+	fn finish(&mut self) -> Poll<Option<SystemTime>,()> {
+		if !self.rows.is_empty() {
+			if let Err(e) = self.flush(SystemTime::now()) {
+				println!("Signal {} failed to store a columnar segment: {:?}", self.signal_id, e);
+				return Err(());
+			}
+		}
+		Ok(Async::Ready(self.prev_seg_offset))
+	}
+}
+
+impl<T,U,F,G> Future for ColumnarSignal<T,U,F,G> 
+	where T: Num + FromPrimitive + Copy + Send + FFTnum + Into<f64> + Serialize + DeserializeOwned,
+		  U: Stream<Item=Vec<T>,Error=()>,
+		  F: Fn(usize,usize) -> bool,
+		  G: Fn(&mut ColumnarSegment<T>),
+{
+	type Item  = Option<SystemTime>;
+	type Error = ();
+
+	fn poll(&mut self) -> Poll<Option<SystemTime>,()> {
+		loop {
+			/* A shut down signal keeps its partial segment, like one whose stream ran out */
+			if self.shutdown.is_stopping() {
+				return self.finish();
+			}
+			if let Async::NotReady = self.governor.poll_admit() {
+				return Ok(Async::NotReady);
+			}
+			match self.signal.poll() {
+				Ok(Async::NotReady) => return Ok(Async::NotReady),
+				Ok(Async::Ready(None)) => {
+					let res = self.finish();
+					println!("Signal {} produced {} columnar segments", self.signal_id, self.segments_produced);
+					return res;
+				}
+				Err(e) => {
+					println!("The client signal produced an error: {:?}", e);
+					return Err(e);
+				}
+				Ok(Async::Ready(Some(row))) => {
+					if row.len() != self.names.len() {
+						println!("Signal {} dropped a tuple of {} values, expected {}", self.signal_id, row.len(), self.names.len());
+						continue;
+					}
+
+					let cur_time    = SystemTime::now();
+					if let None = self.timestamp {
+						self.timestamp = Some(cur_time);
+					};
+
+					/* case where the value reaches split size */
+					if (self.split_decider)(self.rows.len(), self.seg_size) {
+						if let Err(e) = self.flush(cur_time) {
+							println!("Signal {} failed to store a columnar segment: {:?}", self.signal_id, e);
+							return Err(());
+						}
+					}
+
+					self.rows.push(row);
+					match cur_time.duration_since(self.timestamp.unwrap()) {
+						Ok(d)  => self.time_lapse.push(d),
+						Err(_) => self.time_lapse.push(Duration::default()),
+					}
+				}
+			}	
+		}
+	}
+}
+
 #[test]
+#[cfg(feature = "rocksdb")]
 fn run_dual_signals() {
 	let mut db_opts = rocksdb::Options::default();
 	db_opts.create_if_missing(true);
@@ -602,6 +873,7 @@ fn run_dual_signals() {
 
 
 #[test]
+#[cfg(feature = "rocksdb")]
 fn run_single_signals() {
     let mut db_opts = rocksdb::Options::default();
     db_opts.create_if_missing(true);
@@ -713,3 +985,115 @@ fn run_single_signals() {
 
 }
 
+
+#[test]
+fn columnar_signal_test() {
+	use futures::stream;
+	use crate::file_handler::LogFileManager;
+	use crate::segment::columnar::KEY_TAG_COLUMNAR;
+
+	let path = "../columnar_signal_test";
+	let _ = LogFileManager::destroy(path);
+	let fm = Arc::new(LogFileManager::open(path).expect("Failed to open log file manager"));
+	let buffer: Arc<Mutex<SegmentBuffer<f64> + Send + Sync>> = Arc::new(Mutex::new(ClockBuffer::<f64,_>::new(16, fm.clone())));
+	let names = vec!["x".to_string(), "y".to_string()];
+	let stored = |fm: &Arc<LogFileManager>| -> Vec<ColumnarSegment<f64>> {
+		FileManager::<Vec<u8>,Vec<u8>>::fm_scan(fm, vec![KEY_TAG_COLUMNAR], vec![KEY_TAG_COLUMNAR + 1])
+			.expect("Failed to scan the file manager")
+			.map(|(_, bytes)| ColumnarSegment::convert_from_bytes(&bytes).expect("The columnar segment should be readable"))
+			.collect()
+	};
+
+	/* The partial last segment is stored along with the full ones */
+	let rows: Vec<Vec<f64>> = (0..25).map(|i| vec![i as f64, 2.0 * i as f64]).collect();
+	let sig = ColumnarSignal::new(1, stream::iter_ok::<_,()>(rows), names.clone(), 10,
+		buffer.clone(), |i,j| i >= j, |_| ());
+	assert!(sig.wait().expect("The signal should run to its end").is_some());
+	let sizes: Vec<usize> = stored(&fm).iter().map(|seg| seg.get_size()).collect();
+	assert_eq!(sizes, vec![10, 10, 5]);
+
+	/* An error of the stream ends the signal instead of being skipped */
+	let failing = stream::iter_result(vec![Ok(vec![1.0, 2.0]), Err(())]);
+	let sig = ColumnarSignal::new(2, failing, names.clone(), 10, buffer.clone(), |i,j| i >= j, |_| ());
+	assert_eq!(sig.wait(), Err(()));
+
+	/* A shut down signal stores the rows it received before stopping */
+	let shutdown = Shutdown::new();
+	let stop = shutdown.clone();
+	let mut sent = 0;
+	let endless = stream::poll_fn(move || -> Poll<Option<Vec<f64>>,()> {
+		sent += 1;
+		if sent == 4 {
+			stop.trigger();
+		}
+		Ok(Async::Ready(Some(vec![sent as f64, 0.0])))
+	});
+	let sig = ColumnarSignal::new(3, endless, names, 10, buffer, |i,j| i >= j, |_| ())
+		.with_shutdown(shutdown);
+	assert!(sig.wait().expect("The signal should stop cleanly").is_some());
+	let stopped: Vec<ColumnarSegment<f64>> = stored(&fm).into_iter().filter(|seg| seg.get_signal() == 3).collect();
+	assert_eq!(stopped.len(), 1);
+	assert_eq!(stopped[0].column("x"), Some(vec![1.0, 2.0, 3.0, 4.0]));
+
+	drop(fm);
+	LogFileManager::destroy(path).expect("Failed to destroy log file manager");
+}
+
+#[test]
+fn buffered_signal_governor_test() {
+	use futures::stream;
+	use futures::executor::{self, Notify};
+	use crate::buffer_pool::NoFmClockBuffer;
+
+	struct Noop;
+	impl Notify for Noop {
+		fn notify(&self, _id: usize) {}
+	}
+
+	let governor = Arc::new(MemoryGovernor::new(Some(100)));
+	let held = MemoryGovernor::register(&governor, "held");
+	held.charge(200);
+
+	let buffer: Arc<Mutex<SegmentBuffer<f64> + Send + Sync>> = Arc::new(Mutex::new(NoFmClockBuffer::<f64>::new(16)));
+	let values: Vec<f64> = (0..25).map(|i| i as f64).collect();
+	let sig = BufferedSignal::new(1, stream::iter_ok::<_,()>(values), 10, buffer.clone(), |i,j| i >= j, |_| (), false, None)
+		.with_governor(governor.clone());
+
+	/* Over the budget the signal is parked before it reads a value */
+	let mut task = executor::spawn(sig);
+	assert_eq!(task.poll_future_notify(&Arc::new(Noop), 0), Ok(Async::NotReady));
+	assert!(buffer.lock().unwrap().copy().is_empty());
+
+	/* Once the memory is released it resumes and runs to the end of its stream */
+	held.release(200);
+	assert!(task.wait_future().expect("The signal should resume").is_some());
+	assert_eq!(buffer.lock().unwrap().copy().len(), 3);
+}
+
+#[test]
+fn buffered_signal_shutdown_test() {
+	use futures::stream;
+	use crate::buffer_pool::NoFmClockBuffer;
+
+	let buffer: Arc<Mutex<SegmentBuffer<f64> + Send + Sync>> = Arc::new(Mutex::new(NoFmClockBuffer::<f64>::new(16)));
+	let shutdown = Shutdown::new();
+	let stop = shutdown.clone();
+	let mut sent = 0;
+	let endless = stream::poll_fn(move || -> Poll<Option<f64>,()> {
+		sent += 1;
+		if sent == 14 {
+			stop.trigger();
+		}
+		Ok(Async::Ready(Some(sent as f64)))
+	});
+
+	/* A shut down signal stores the values it received before stopping */
+	let sig = BufferedSignal::new(1, endless, 10, buffer.clone(), |i,j| i >= j, |_| (), false, None)
+		.with_shutdown(shutdown);
+	let last = sig.wait().expect("The signal should stop cleanly");
+	let mut segs = buffer.lock().unwrap().copy();
+	segs.sort_by_key(|seg| seg.get_key());
+	assert_eq!(segs.iter().map(|seg| seg.get_size()).collect::<Vec<usize>>(), vec![10, 4]);
+	assert_eq!(segs[1].get_data(), &vec![11.0, 12.0, 13.0, 14.0]);
+	assert_eq!(last, Some(segs[1].get_key().get_timestamp()));
+}
@@ -19,28 +19,30 @@ use time_series_start::client::{construct_normal_gen_client, read_dict};
 use time_series_start::client::construct_gen_client;
 use std::time::SystemTime;
 use time_series_start::client::construct_file_client;
-use time_series_start::segment::{ FourierCompress, PAACompress};
 use std::str::FromStr;
 use std::fmt::Debug;
 use ndarray::Array2;
 use rustfft::FFTnum;
 use num::Float;
-use time_series_start::compression_daemon::CompressionDaemon;
+use time_series_start::compression_daemon::{CompressionDaemon, DRAIN_THRESHOLD};
 use std::{fs, thread};
 use time_series_start::kernel::Kernel;
-use time_series_start::methods::compress::{GZipCompress, ZlibCompress, DeflateCompress, SnappyCompress, CompressionMethod};
+use time_series_start::methods::compress::{ZlibCompress, DeflateCompress, CompressionMethod};
+use time_series_start::methods::registry::codec_by_name;
+use time_series_start::predict::codec_selector::{CodecSelector,QueryMix,AutoCompress};
 use time_series_start::methods::Methods::Fourier;
 use time_series_start::methods::gorilla_encoder::GorillaEncoder;
-use time_series_start::compress::gorilla::GorillaCompress;
-use time_series_start::compress::sprintz::SprintzDoubleCompress;
 
 use time_series_start::methods::prec_double::{PrecisionBound, get_precision_bound};
 use time_series_start::knn::fft_ifft_ratio;
-use time_series_start::client::{construct_file_client_skip_newline,Amount,RunPeriod,Frequency};
-use time_series_start::buffer_pool::{SegmentBuffer,ClockBuffer,LRUBuffer, NoFmClockBuffer};
+use time_series_start::client::{construct_file_client_with_gaps,Amount,RunPeriod,Frequency};
+use time_series_start::buffer_pool::{SegmentBuffer,LRUBuffer, share_buffer};
+use time_series_start::buffer_pool::governor::governor;
+use time_series_start::buffer_pool::workload::evaluator_for_task;
+use time_series_start::{build_file_manager, build_buffer, build_wal, build_catalog, register_client, build_retention, set_memory_budget};
+use time_series_start::file_handler::BoxedFileManager;
 use time_series_start::future_signal::{BufferedSignal};
 use toml_loader::{Loader};
-use rocksdb::{DB};
 use ndarray_linalg::Lapack;
 use std::path::Path;
 use std::time::{Duration,Instant};
@@ -50,11 +52,13 @@ use tokio::runtime::{Builder};
 use futures::sync::oneshot;
 use std::sync::{Arc,Mutex};
 use serde::Serialize;
-use time_series_start::compress::split_double::SplitBDDoubleCompress;
 use time_series_start::MABcompression_daemon::MABCompressionDaemon;
 use time_series_start::MABrecoding_daemon::MABRecodingDaemon;
 use time_series_start::methods::Methods;
 use time_series_start::recoding_daemon::RecodingDaemon;
+use time_series_start::shutdown::ShutdownHandle;
+use time_series_start::catalog::CatalogCodec;
+use time_series_start::segment::header::element_type_id;
 
 
 const DEFAULT_BUF_SIZE: usize = 150;
@@ -75,119 +79,36 @@ pub fn run_single_test(config_file: &str, task:&str, comp:&str, recode:&str, num
 		.expect("The segment size argument must be provided as an integer") as usize;
 
 
-	/* Construct the file manager to be used */
-	let fm = match config.lookup("file_handler") {
-		Some (config) => {
-			let fm_type = config.lookup("file_manager").expect("A file manager must be provided");
-			match fm_type.as_str().expect("A file manager must be provided as a string") {
-				"Rocks" => {
-					let params = config.lookup("params").expect("A RocksDB file manager requires parameters");
-					let path = params.lookup("path").expect("RocksDB requires a path be provided").as_str().expect("Rocks file path must be provided as string");
-					let mut db_opts = rocksdb::Options::default();
-					db_opts.create_if_missing(true);
-					match rocksdb::DB::open(&db_opts, path) {
-						Ok(x) => Some(Box::new(x)),
-						Err(e) => panic!("Failed to create RocksFM object: {:?}", e),
-					}
-				}
-				x => panic!("File manager type, {:?}, not supported yet", x),
-			}
-		}
-		None => None,
-	};
-
+	/* Construct the file managers to be used, the compressed segments get one of their own */
+	let fm = build_file_manager(&config, "");
+	let fm_comp = build_file_manager(&config, "comp");
 
-	/* Construct the file manager for compression to be used */
-	let fm_comp = match config.lookup("file_handler") {
-		Some (config) => {
-			let fm_type = config.lookup("file_manager").expect("A file manager must be provided");
-			match fm_type.as_str().expect("A file manager must be provided as a string") {
-				"Rocks" => {
-					let params = config.lookup("params").expect("A RocksDB file manager requires parameters");
-					let path = params.lookup("path").expect("RocksDB requires a path be provided").as_str().expect("Rocks file path must be provided as string");
-					let mut comp_path = String::from(path);
-					comp_path.push_str("comp");
-					let new_path = comp_path.as_str();
-					let mut db_opts = rocksdb::Options::default();
-					db_opts.create_if_missing(true);
-					match rocksdb::DB::open(&db_opts, new_path) {
-						Ok(x) => Some(Box::new(x)),
-						Err(e) => panic!("Failed to create RocksFM object: {:?}", e),
-					}
-				}
-				x => panic!("File manager type, {:?}, not supported yet", x),
-			}
-		}
-		None => None,
-	};
-
-	/* Construct the buffer to be used */
-	let buffer_size = match config.lookup("buffer") {
-		Some(value) => value.lookup("buffer_size").map_or(DEFAULT_BUF_SIZE, |v| v.as_integer().expect("The buffer size should be provided as an integer") as usize),
-		None => DEFAULT_BUF_SIZE,
-	};
+	/* Rebuild what the store holds of every signal before the buffers take the file managers */
+	let catalog = build_catalog(&config, &fm, &fm_comp);
 
+	/* Construct the buffers to be used */
 	let buffer_budget = match config.lookup("comp_buffer") {
 		Some(value) => value.lookup("buffer_budget").map_or(DEFAULT_BUF_SIZE, |v| v.as_integer().expect("The buffer size should be provided as an integer") as usize),
 		None => DEFAULT_BUF_SIZE,
 	};
 
-	let buf_option: Option<Box<Arc<Mutex<(SegmentBuffer<f64> + Send + Sync)>>>> = match fm {
-		Some(fm) => {
-			match config.lookup("buffer") {
-				Some(config) => {
-					let buf_type = config.lookup("type").expect("A buffer type must be provided");
-					match buf_type.as_str().expect("Buffer type must be provided as string") {
-						"Clock" => Some(Box::new(Arc::new(Mutex::new(ClockBuffer::<f64,rocksdb::DB>::new(buffer_size,*fm))))),
-						x => panic!("The buffer type, {:?}, is not currently supported to run with a file manager", x),
-					}
-				}
-				None => None,
-			}
-		}
-		None => {
-			match config.lookup("buffer") {
-				Some(config) => {
-					let buf_type = config.lookup("type").expect("A buffer type must be provided");
-					match buf_type.as_str().expect("Buffer type must be provided as a string") {
-						"NoFmClock" => Some(Box::new(Arc::new(Mutex::new(NoFmClockBuffer::<f64>::new(buffer_size))))),
-						x => panic!("The buffer type, {:?}, is not currently supported to run without a file manager", x),
-					}
-				}
-				None => None,
-			}
-		}
-	};
+	let buf_option: Option<Box<Arc<Mutex<(SegmentBuffer<f64> + Send + Sync)>>>> = build_buffer(&config, "buffer", fm).map(Box::new);
 
-	/* Create buffer for compression segments*/
-	let compre_buf_option: Option<Box<Arc<Mutex<(SegmentBuffer<f64> + Send + Sync)>>>> = match fm_comp {
-		Some(fm) => {
-			match config.lookup("comp_buffer") {
-				Some(config) => {
-					let buf_type = config.lookup("type").expect("A buffer type must be provided");
-					match buf_type.as_str().expect("Buffer type must be provided as string") {
-						"Clock" => Some(Box::new(Arc::new(Mutex::new(ClockBuffer::<f64,rocksdb::DB>::new(buffer_size,*fm))))),
-						"LRU" => Some(Box::new(Arc::new(Mutex::new(LRUBuffer::<f64,rocksdb::DB>::new(buffer_budget,*fm, task))))),
-						x => panic!("The buffer type, {:?}, is not currently supported to run with a file manager", x),
-					}
-				}
-				None => None,
-			}
-		}
-		None => {
-			match config.lookup("buffer") {
-				Some(config) => {
-					let buf_type = config.lookup("type").expect("A buffer type must be provided");
-					match buf_type.as_str().expect("Buffer type must be provided as a string") {
-						"NoFmClock" => Some(Box::new(Arc::new(Mutex::new(NoFmClockBuffer::<f64>::new(buffer_size))))),
-						x => panic!("The buffer type, {:?}, is not currently supported to run without a file manager", x),
-					}
-				}
-				None => None,
-			}
+	/* Create buffer for compression segments, the LRU buffer is only built here since it needs the task */
+	let compre_buf_option: Option<Box<Arc<Mutex<(SegmentBuffer<f64> + Send + Sync)>>>> = match (fm_comp, config.lookup("comp_buffer.type").and_then(|v| v.as_str())) {
+		(Some(fm), Some("LRU")) => {
+			let model_dir = config.lookup("comp_buffer.model_dir").map(|v| v.as_str().expect("The model directory must be provided as a string"));
+			Some(Box::new(Arc::new(Mutex::new(LRUBuffer::<f64,BoxedFileManager>::new(buffer_budget,fm,evaluator_for_task::<f64>(model_dir,task))))))
 		}
+		(Some(fm), _) => build_buffer(&config, "comp_buffer", Some(fm)).map(Box::new),
+		(None, _) => build_buffer(&config, "buffer", None).map(Box::new),
 	};
 
+	let mut shutdown = ShutdownHandle::new();
+
+	/* Put back what the write ahead log still holds before new points arrive */
+	let wal = build_wal(&config, buf_option.as_ref().map(|buf| &**buf), compre_buf_option.as_ref().map(|buf| &**buf), &catalog, &mut shutdown);
+
 	/* Construct the clients */
 	let mut signals: Vec<Box<(Future<Item=Option<SystemTime>,Error=()> + Send + Sync)>> = Vec::new();
 	let mut rng = thread_rng();
@@ -205,6 +126,9 @@ pub fn run_single_test(config_file: &str, task:&str, comp:&str, recode:&str, num
 				signal_id = x.as_integer().expect("If an ID for a client is provided it must be supplied as an integer") as u64;
 			}
 
+			/* A named client keeps the id the catalog gave its name in an earlier run */
+			signal_id = register_client(&catalog, signal_id, client_config, element_type_id::<f64>());
+
 			let client_type = client_config.lookup("type").expect("The client type must be provided");
 
 			let amount = match client_config.lookup("amount") {
@@ -304,7 +228,7 @@ pub fn run_single_test(config_file: &str, task:&str, comp:&str, recode:&str, num
 								Some(skip_val) => skip_val.as_integer().expect("The skip value must be provided as an integer") as usize,
 								None => 0,
 							};
-							Box::new(construct_file_client_skip_newline::<f64>(path, skip_val, delim, amount, run_period, frequency).expect("Client could not be properly produced"))
+							Box::new(construct_file_client_with_gaps::<f64>(path, skip_val, delim, amount, run_period, frequency).expect("Client could not be properly produced"))
 						}
 						"DeserializeDelim" => Box::new(construct_file_client::<f64>(path, delim as u8, amount, run_period, frequency).expect("Client could not be properly produced")),
 						x => panic!("The specified file reader, {:?}, is not supported yet", x),
@@ -313,7 +237,7 @@ pub fn run_single_test(config_file: &str, task:&str, comp:&str, recode:&str, num
 
 
 					match &buf_option {
-						Some(buf) => signals.push(Box::new(BufferedSignal::new(signal_id, client, seg_size, *buf.clone(), |i,j| i >= j, |_| (), false,dict))),
+						Some(buf) => signals.push(Box::new(BufferedSignal::new(signal_id, client, seg_size, share_buffer(buf), |i,j| i >= j, |_| (), false,dict).with_shutdown(shutdown.signal()).with_wal(wal.clone()).with_catalog(Some(catalog.clone())))),
 						None => panic!("Buffer and File manager provided not supported yet"),
 					}
 				}
@@ -362,7 +286,7 @@ pub fn run_single_test(config_file: &str, task:&str, comp:&str, recode:&str, num
 							x => panic!("The provided generator type, {:?}, is not currently supported", x),
 						};
 					match &buf_option {
-						Some(buf) => signals.push(Box::new(BufferedSignal::new(signal_id, client, seg_size, *buf.clone(), |i,j| i >= j, |_| (), false, None))),
+						Some(buf) => signals.push(Box::new(BufferedSignal::new(signal_id, client, seg_size, share_buffer(buf), |i,j| i >= j, |_| (), false, None).with_shutdown(shutdown.signal()).with_wal(wal.clone()).with_catalog(Some(catalog.clone())))),
 						None => panic!("Buffer and File manager provided not supported yet"),
 					}
 				}
@@ -374,7 +298,37 @@ pub fn run_single_test(config_file: &str, task:&str, comp:&str, recode:&str, num
 
 	let batch = 5;
 
-
+	/* A budget over the heap bytes of every buffer, the signals wait while it is exceeded */
+	set_memory_budget::<f64>(&config, seg_size, batch);
+
+	/* Expire the segments that are past the retention rules of their signals */
+	build_retention(&config, buf_option.as_ref().map(|buf| &**buf), compre_buf_option.as_ref().map(|buf| &**buf), &catalog, &mut shutdown);
+
+	/* Query mixes the auto compression weighs the codecs of each signal with */
+	let mut codec_selector = CodecSelector::new(QueryMix::default());
+	if let Some(params) = config.lookup("selector") {
+		let default = QueryMix::parse(
+			params.lookup("storage").map(|v| v.as_float().expect("Query mix weights must be provided as floats")),
+			params.lookup("ingest").map(|v| v.as_float().expect("Query mix weights must be provided as floats")),
+			params.lookup("read").map(|v| v.as_float().expect("Query mix weights must be provided as floats")),
+			QueryMix::default())
+			.expect("Failed to parse the default query mix");
+		codec_selector = CodecSelector::new(default);
+		if let Some(mixes) = params.lookup("signals") {
+			/* Mixes are keyed by signal selectors, the weights they leave out come from the default mix */
+			for (selector, mix) in mixes.as_table().expect("Per signal query mixes must be provided as a TOML table") {
+				let mix = QueryMix::parse(
+					mix.lookup("storage").map(|v| v.as_float().expect("Query mix weights must be provided as floats")),
+					mix.lookup("ingest").map(|v| v.as_float().expect("Query mix weights must be provided as floats")),
+					mix.lookup("read").map(|v| v.as_float().expect("Query mix weights must be provided as floats")),
+					default)
+					.expect("Failed to parse a signal query mix");
+				for signal in catalog.lock().unwrap().resolve(selector) {
+					codec_selector.set(signal, mix);
+				}
+			}
+		}
+	}
 
 	/* Construct the runtime */
 	let rt = match config.lookup("runtime") {
@@ -415,92 +369,53 @@ pub fn run_single_test(config_file: &str, task:&str, comp:&str, recode:&str, num
 
 	for _x in 0..num_comp {
 		match comp{
-			"paa" => {
-				let mut compress_daemon:CompressionDaemon<_,DB,_> = CompressionDaemon::new(*(buf_option.clone().unwrap()),*(compre_buf_option.clone().unwrap()),None,0.1,0.0,PAACompress::new(10,batch));
-				let handle = thread::spawn(move || {
-					println!("Run paa compression demon");
-					compress_daemon.run();
-					println!("segment commpressed: {}", compress_daemon.get_processed() );
-				});
-				comp_handlers.push(handle);
-			},
-			"buff" => {
-				let mut compress_daemon:CompressionDaemon<_,DB,_> = CompressionDaemon::new(*(buf_option.clone().unwrap()),*(compre_buf_option.clone().unwrap()),None,0.1,0.0,SplitBDDoubleCompress::new(10,batch, 10000));
-				let handle = thread::spawn(move || {
-					println!("Run buff compression demon");
-					compress_daemon.run();
-					println!("segment commpressed: {}", compress_daemon.get_processed() );
-				});
-				comp_handlers.push(handle);
-			},
-			"fourier" => {
-				let mut compress_daemon: CompressionDaemon<_, DB, _> = CompressionDaemon::new(*(buf_option.clone().unwrap()), *(compre_buf_option.clone().unwrap()), None, 0.1, 0.0, FourierCompress::new(10, batch,1.0));
-				let handle = thread::spawn(move || {
-					println!("Run fourier compression demon");
-					compress_daemon.run();
-					println!("segment commpressed: {}", compress_daemon.get_processed() );
-				});
-				comp_handlers.push(handle);
-			}
-			"snappy" => {
-				let mut compress_daemon: CompressionDaemon<_, DB, _> = CompressionDaemon::new(*(buf_option.clone().unwrap()), *(compre_buf_option.clone().unwrap()), None, 0.1, 0.0, SnappyCompress::new(10, batch));
-				let handle = thread::spawn(move || {
-					println!("Run snappy compression demon");
-					compress_daemon.run();
-					println!("segment commpressed: {}", compress_daemon.get_processed() );
-				});
-				comp_handlers.push(handle);
-			}
-			"gorilla" => {
-				let mut compress_daemon: CompressionDaemon<_, DB, _> = CompressionDaemon::new(*(buf_option.clone().unwrap()), *(compre_buf_option.clone().unwrap()), None, 0.1, 0.0, GorillaCompress::new(10, batch));
-				let handle = thread::spawn(move || {
-					println!("Run gotilla compression demon");
-					compress_daemon.run();
-					println!("segment commpressed: {}", compress_daemon.get_processed() );
-				});
-				comp_handlers.push(handle);
-			}
-			"sprintz" => {
-				let mut compress_daemon: CompressionDaemon<_, DB, _> = CompressionDaemon::new(*(buf_option.clone().unwrap()), *(compre_buf_option.clone().unwrap()), None, 0.1, 0.0, SprintzDoubleCompress::new(10, batch,10000));
+
+			"kernel" => {
+				let mut knl = Kernel::new(array![[1.0, 1.0],[1.0, 1.0]],1,4,30);
+				if testdict != None{
+					knl = Kernel::new(testdict.clone().unwrap(),1,4,30);
+					knl.rbfdict_pre_process();
+				}
+				let mut compress_daemon:CompressionDaemon<_,BoxedFileManager,_> = CompressionDaemon::new(share_buffer(buf_option.as_ref().unwrap()),share_buffer(compre_buf_option.as_ref().unwrap()),None,DRAIN_THRESHOLD,0.0,CatalogCodec::new(knl, catalog.clone()));
+				compress_daemon.set_shutdown(shutdown.signal());
 				let handle = thread::spawn(move || {
-					println!("Run sprintz compression demon");
+					println!("Run kernel compression demon");
 					compress_daemon.run();
 					println!("segment commpressed: {}", compress_daemon.get_processed() );
 				});
 				comp_handlers.push(handle);
 			}
-			"gzip" => {
-				let mut compress_daemon:CompressionDaemon<_,DB,_> = CompressionDaemon::new(*(buf_option.clone().unwrap()),*(compre_buf_option.clone().unwrap()),None,0.1,0.0,GZipCompress::new(10,batch));
+			"auto" => {
+				let codec = AutoCompress::new(batch, codec_selector.clone());
+				let mut compress_daemon:CompressionDaemon<_,BoxedFileManager,_> = CompressionDaemon::new(share_buffer(buf_option.as_ref().unwrap()),share_buffer(compre_buf_option.as_ref().unwrap()),None,DRAIN_THRESHOLD,0.0,CatalogCodec::new(codec, catalog.clone()));
+				compress_daemon.set_shutdown(shutdown.signal());
 				let handle = thread::spawn(move || {
-					println!("Run gzip compression demon");
+					println!("Run auto compression demon");
 					compress_daemon.run();
 					println!("segment commpressed: {}", compress_daemon.get_processed() );
 				});
 				comp_handlers.push(handle);
 			}
-
-			"kernel" => {
-				let mut knl = Kernel::new(array![[1.0, 1.0],[1.0, 1.0]],1,4,30);
-				if testdict != None{
-					knl = Kernel::new(testdict.clone().unwrap(),1,4,30);
-					knl.rbfdict_pre_process();
-				}
-				let mut compress_daemon:CompressionDaemon<_,DB,_> = CompressionDaemon::new(*(buf_option.clone().unwrap()),*(compre_buf_option.clone().unwrap()),None,0.1,0.0,knl);
+			name => {
+				let codec = codec_by_name(name, batch).expect("Compression not supported yet.");
+				let label = codec.method().to_string();
+				let mut compress_daemon:CompressionDaemon<_,BoxedFileManager,_> = CompressionDaemon::new(share_buffer(buf_option.as_ref().unwrap()),share_buffer(compre_buf_option.as_ref().unwrap()),None,DRAIN_THRESHOLD,0.0,CatalogCodec::new(codec, catalog.clone()));
+				compress_daemon.set_shutdown(shutdown.signal());
 				let handle = thread::spawn(move || {
-					println!("Run kernel compression demon");
+					println!("Run {} compression demon", label);
 					compress_daemon.run();
 					println!("segment commpressed: {}", compress_daemon.get_processed() );
 				});
 				comp_handlers.push(handle);
 			}
-			_ => {panic!("Compression not supported yet.")}
 		}
 
 	}
 
 	for _x in 0..num_recode {
 		let rec_name = recoding.clone();
-		let mut rec:RecodingDaemon<_,DB> = RecodingDaemon::new(*(compre_buf_option.clone().unwrap()),*(compre_buf_option.clone().unwrap()),None,0.8,0.8,batch, recoding.clone());
+		let mut rec:RecodingDaemon<_,BoxedFileManager> = RecodingDaemon::new(share_buffer(compre_buf_option.as_ref().unwrap()),share_buffer(compre_buf_option.as_ref().unwrap()),None,0.8,0.8,batch, recoding.clone());
+		rec.set_shutdown(shutdown.signal());
 		let handle = thread::spawn(move || {
 			println!("Run recoding demon with lossy {}", rec_name );
 			rec.run();
@@ -518,8 +433,8 @@ pub fn run_single_test(config_file: &str, task:&str, comp:&str, recode:&str, num
 		spawn_handles.push(oneshot::spawn(sig, &executor))
 	}
 
-	for _comp in comp_handlers {
-		// comp.join().unwrap();
+	for comp in comp_handlers {
+		shutdown.add_daemon(comp);
 	}
 
 
@@ -531,12 +446,31 @@ pub fn run_single_test(config_file: &str, task:&str, comp:&str, recode:&str, num
 		}
 	}
 
+	/* Drain the daemons and persist both pools so a restart finds every segment */
+	for buf in vec![&buf_option, &compre_buf_option] {
+		if let Some(buf) = buf {
+			shutdown.add_pool(buf);
+		}
+	}
+	match shutdown.shutdown() {
+		Ok(()) => (),
+		Err(e) => println!("Failed to persist the buffers: {:?}", e),
+	}
+
 	// Wait until the runtime becomes idle and shut it down.
 	match rt.shutdown_on_idle().wait() {
 		Ok(_) => (),
 		Err(_) => panic!("Failed to shutdown properly"),
 	}
 
+	/* Lookups each buffer answered from memory, to compare the eviction policies */
+	for (name, buf) in vec![("buffer", &buf_option), ("comp buffer", &compre_buf_option)] {
+		if let Some(buf) = buf {
+			println!("{} {}", name, buf.lock().unwrap().get_cache_stats());
+		}
+	}
+	println!("{:?}", governor());
+
 }
 
 pub fn run_mab_test(config_file: &str, task:&str, comp:&str, recode:&str, num_comp:i32, num_recode: i32){
@@ -554,119 +488,36 @@ pub fn run_mab_test(config_file: &str, task:&str, comp:&str, recode:&str, num_co
 		.expect("The segment size argument must be provided as an integer") as usize;
 
 
-	/* Construct the file manager to be used */
-	let fm = match config.lookup("file_handler") {
-		Some (config) => {
-			let fm_type = config.lookup("file_manager").expect("A file manager must be provided");
-			match fm_type.as_str().expect("A file manager must be provided as a string") {
-				"Rocks" => {
-					let params = config.lookup("params").expect("A RocksDB file manager requires parameters");
-					let path = params.lookup("path").expect("RocksDB requires a path be provided").as_str().expect("Rocks file path must be provided as string");
-					let mut db_opts = rocksdb::Options::default();
-					db_opts.create_if_missing(true);
-					match rocksdb::DB::open(&db_opts, path) {
-						Ok(x) => Some(Box::new(x)),
-						Err(e) => panic!("Failed to create RocksFM object: {:?}", e),
-					}
-				}
-				x => panic!("File manager type, {:?}, not supported yet", x),
-			}
-		}
-		None => None,
-	};
-
-
-	/* Construct the file manager for compression to be used */
-	let fm_comp = match config.lookup("file_handler") {
-		Some (config) => {
-			let fm_type = config.lookup("file_manager").expect("A file manager must be provided");
-			match fm_type.as_str().expect("A file manager must be provided as a string") {
-				"Rocks" => {
-					let params = config.lookup("params").expect("A RocksDB file manager requires parameters");
-					let path = params.lookup("path").expect("RocksDB requires a path be provided").as_str().expect("Rocks file path must be provided as string");
-					let mut comp_path = String::from(path);
-					comp_path.push_str("comp");
-					let new_path = comp_path.as_str();
-					let mut db_opts = rocksdb::Options::default();
-					db_opts.create_if_missing(true);
-					match rocksdb::DB::open(&db_opts, new_path) {
-						Ok(x) => Some(Box::new(x)),
-						Err(e) => panic!("Failed to create RocksFM object: {:?}", e),
-					}
-				}
-				x => panic!("File manager type, {:?}, not supported yet", x),
-			}
-		}
-		None => None,
-	};
+	/* Construct the file managers to be used, the compressed segments get one of their own */
+	let fm = build_file_manager(&config, "");
+	let fm_comp = build_file_manager(&config, "comp");
 
-	/* Construct the buffer to be used */
-	let buffer_size = match config.lookup("buffer") {
-		Some(value) => value.lookup("buffer_size").map_or(DEFAULT_BUF_SIZE, |v| v.as_integer().expect("The buffer size should be provided as an integer") as usize),
-		None => DEFAULT_BUF_SIZE,
-	};
+	/* Rebuild what the store holds of every signal before the buffers take the file managers */
+	let catalog = build_catalog(&config, &fm, &fm_comp);
 
+	/* Construct the buffers to be used */
 	let buffer_budget = match config.lookup("comp_buffer") {
 		Some(value) => value.lookup("buffer_budget").map_or(DEFAULT_BUF_SIZE, |v| v.as_integer().expect("The buffer size should be provided as an integer") as usize),
 		None => DEFAULT_BUF_SIZE,
 	};
 
-	let buf_option: Option<Box<Arc<Mutex<(SegmentBuffer<f64> + Send + Sync)>>>> = match fm {
-		Some(fm) => {
-			match config.lookup("buffer") {
-				Some(config) => {
-					let buf_type = config.lookup("type").expect("A buffer type must be provided");
-					match buf_type.as_str().expect("Buffer type must be provided as string") {
-						"Clock" => Some(Box::new(Arc::new(Mutex::new(ClockBuffer::<f64,rocksdb::DB>::new(buffer_size,*fm))))),
-						x => panic!("The buffer type, {:?}, is not currently supported to run with a file manager", x),
-					}
-				}
-				None => None,
-			}
-		}
-		None => {
-			match config.lookup("buffer") {
-				Some(config) => {
-					let buf_type = config.lookup("type").expect("A buffer type must be provided");
-					match buf_type.as_str().expect("Buffer type must be provided as a string") {
-						"NoFmClock" => Some(Box::new(Arc::new(Mutex::new(NoFmClockBuffer::<f64>::new(buffer_size))))),
-						x => panic!("The buffer type, {:?}, is not currently supported to run without a file manager", x),
-					}
-				}
-				None => None,
-			}
-		}
-	};
+	let buf_option: Option<Box<Arc<Mutex<(SegmentBuffer<f64> + Send + Sync)>>>> = build_buffer(&config, "buffer", fm).map(Box::new);
 
-	/* Create buffer for compression segments*/
-	let compre_buf_option: Option<Box<Arc<Mutex<(SegmentBuffer<f64> + Send + Sync)>>>> = match fm_comp {
-		Some(fm) => {
-			match config.lookup("comp_buffer") {
-				Some(config) => {
-					let buf_type = config.lookup("type").expect("A buffer type must be provided");
-					match buf_type.as_str().expect("Buffer type must be provided as string") {
-						"Clock" => Some(Box::new(Arc::new(Mutex::new(ClockBuffer::<f64,rocksdb::DB>::new(buffer_size,*fm))))),
-						"LRU" => Some(Box::new(Arc::new(Mutex::new(LRUBuffer::<f64,rocksdb::DB>::new(buffer_budget,*fm,task))))),
-						x => panic!("The buffer type, {:?}, is not currently supported to run with a file manager", x),
-					}
-				}
-				None => None,
-			}
-		}
-		None => {
-			match config.lookup("buffer") {
-				Some(config) => {
-					let buf_type = config.lookup("type").expect("A buffer type must be provided");
-					match buf_type.as_str().expect("Buffer type must be provided as a string") {
-						"NoFmClock" => Some(Box::new(Arc::new(Mutex::new(NoFmClockBuffer::<f64>::new(buffer_size))))),
-						x => panic!("The buffer type, {:?}, is not currently supported to run without a file manager", x),
-					}
-				}
-				None => None,
-			}
+	/* Create buffer for compression segments, the LRU buffer is only built here since it needs the task */
+	let compre_buf_option: Option<Box<Arc<Mutex<(SegmentBuffer<f64> + Send + Sync)>>>> = match (fm_comp, config.lookup("comp_buffer.type").and_then(|v| v.as_str())) {
+		(Some(fm), Some("LRU")) => {
+			let model_dir = config.lookup("comp_buffer.model_dir").map(|v| v.as_str().expect("The model directory must be provided as a string"));
+			Some(Box::new(Arc::new(Mutex::new(LRUBuffer::<f64,BoxedFileManager>::new(buffer_budget,fm,evaluator_for_task::<f64>(model_dir,task))))))
 		}
+		(Some(fm), _) => build_buffer(&config, "comp_buffer", Some(fm)).map(Box::new),
+		(None, _) => build_buffer(&config, "buffer", None).map(Box::new),
 	};
 
+	let mut shutdown = ShutdownHandle::new();
+
+	/* Put back what the write ahead log still holds before new points arrive */
+	let wal = build_wal(&config, buf_option.as_ref().map(|buf| &**buf), compre_buf_option.as_ref().map(|buf| &**buf), &catalog, &mut shutdown);
+
 	/* Construct the clients */
 	let mut signals: Vec<Box<(Future<Item=Option<SystemTime>,Error=()> + Send + Sync)>> = Vec::new();
 	let mut rng = thread_rng();
@@ -684,6 +535,9 @@ pub fn run_mab_test(config_file: &str, task:&str, comp:&str, recode:&str, num_co
 			signal_id = x.as_integer().expect("If an ID for a client is provided it must be supplied as an integer") as u64;
 		}
 
+		/* A named client keeps the id the catalog gave its name in an earlier run */
+		signal_id = register_client(&catalog, signal_id, client_config, element_type_id::<f64>());
+
 		let client_type = client_config.lookup("type").expect("The client type must be provided");
 
 		let amount = match client_config.lookup("amount") {
@@ -783,7 +637,7 @@ pub fn run_mab_test(config_file: &str, task:&str, comp:&str, recode:&str, num_co
 							Some(skip_val) => skip_val.as_integer().expect("The skip value must be provided as an integer") as usize,
 							None => 0,
 						};
-						Box::new(construct_file_client_skip_newline::<f64>(path, skip_val, delim, amount, run_period, frequency).expect("Client could not be properly produced"))
+						Box::new(construct_file_client_with_gaps::<f64>(path, skip_val, delim, amount, run_period, frequency).expect("Client could not be properly produced"))
 					}
 					"DeserializeDelim" => Box::new(construct_file_client::<f64>(path, delim as u8, amount, run_period, frequency).expect("Client could not be properly produced")),
 					x => panic!("The specified file reader, {:?}, is not supported yet", x),
@@ -792,7 +646,7 @@ pub fn run_mab_test(config_file: &str, task:&str, comp:&str, recode:&str, num_co
 
 
 				match &buf_option {
-					Some(buf) => signals.push(Box::new(BufferedSignal::new(signal_id, client, seg_size, *buf.clone(), |i,j| i >= j, |_| (), false,dict))),
+					Some(buf) => signals.push(Box::new(BufferedSignal::new(signal_id, client, seg_size, share_buffer(buf), |i,j| i >= j, |_| (), false,dict).with_shutdown(shutdown.signal()).with_wal(wal.clone()).with_catalog(Some(catalog.clone())))),
 					None => panic!("Buffer and File manager provided not supported yet"),
 				}
 			}
@@ -841,7 +695,7 @@ pub fn run_mab_test(config_file: &str, task:&str, comp:&str, recode:&str, num_co
 					x => panic!("The provided generator type, {:?}, is not currently supported", x),
 				};
 				match &buf_option {
-					Some(buf) => signals.push(Box::new(BufferedSignal::new(signal_id, client, seg_size, *buf.clone(), |i,j| i >= j, |_| (), false, None))),
+					Some(buf) => signals.push(Box::new(BufferedSignal::new(signal_id, client, seg_size, share_buffer(buf), |i,j| i >= j, |_| (), false, None).with_shutdown(shutdown.signal()).with_wal(wal.clone()).with_catalog(Some(catalog.clone())))),
 					None => panic!("Buffer and File manager provided not supported yet"),
 				}
 			}
@@ -853,7 +707,11 @@ pub fn run_mab_test(config_file: &str, task:&str, comp:&str, recode:&str, num_co
 
 	let batch = 5;
 
+	/* A budget over the heap bytes of every buffer, the signals wait while it is exceeded */
+	set_memory_budget::<f64>(&config, seg_size, batch);
 
+	/* Expire the segments that are past the retention rules of their signals */
+	build_retention(&config, buf_option.as_ref().map(|buf| &**buf), compre_buf_option.as_ref().map(|buf| &**buf), &catalog, &mut shutdown);
 
 	/* Construct the runtime */
 	let rt = match config.lookup("runtime") {
@@ -895,69 +753,6 @@ pub fn run_mab_test(config_file: &str, task:&str, comp:&str, recode:&str, num_co
 	for _x in 0..num_comp {
 		println!("Init MAB compression demon {}", _x);
 		match comp{
-			"paa" => {
-				let mut compress_daemon:MABCompressionDaemon<_,DB,_> = MABCompressionDaemon::new(*(buf_option.clone().unwrap()),*(compre_buf_option.clone().unwrap()),None,0.1,0.0,PAACompress::new(10,batch));
-				let handle = thread::spawn(move || {
-					println!("Run paa compression demon");
-					compress_daemon.run();
-					println!("segment commpressed: {}", compress_daemon.get_processed() );
-				});
-				comp_handlers.push(handle);
-			},
-			"buff" => {
-				let mut compress_daemon:MABCompressionDaemon<_,DB,_> = MABCompressionDaemon::new(*(buf_option.clone().unwrap()),*(compre_buf_option.clone().unwrap()),None,0.1,0.0,SplitBDDoubleCompress::new(10,batch, 10000));
-				let handle = thread::spawn(move || {
-					println!("Run buff compression demon");
-					compress_daemon.run();
-					println!("segment commpressed: {}", compress_daemon.get_processed() );
-				});
-				comp_handlers.push(handle);
-			},
-			"fourier" => {
-				let mut compress_daemon: MABCompressionDaemon<_, DB, _> = MABCompressionDaemon::new(*(buf_option.clone().unwrap()), *(compre_buf_option.clone().unwrap()), None, 0.1, 0.0, FourierCompress::new(10, batch,1.0));
-				let handle = thread::spawn(move || {
-					println!("Run fourier compression demon");
-					compress_daemon.run();
-					println!("segment commpressed: {}", compress_daemon.get_processed() );
-				});
-				comp_handlers.push(handle);
-			}
-			"snappy" => {
-				let mut compress_daemon: MABCompressionDaemon<_, DB, _> = MABCompressionDaemon::new(*(buf_option.clone().unwrap()), *(compre_buf_option.clone().unwrap()), None, 0.1, 0.0, SnappyCompress::new(10, batch));
-				let handle = thread::spawn(move || {
-					println!("Run snappy compression demon");
-					compress_daemon.run();
-					println!("segment commpressed: {}", compress_daemon.get_processed() );
-				});
-				comp_handlers.push(handle);
-			}
-			"gorilla" => {
-				let mut compress_daemon: MABCompressionDaemon<_, DB, _> = MABCompressionDaemon::new(*(buf_option.clone().unwrap()), *(compre_buf_option.clone().unwrap()), None, 0.1, 0.0, GorillaCompress::new(10, batch));
-				let handle = thread::spawn(move || {
-					println!("Run gotilla compression demon");
-					compress_daemon.run();
-					println!("segment commpressed: {}", compress_daemon.get_processed() );
-				});
-				comp_handlers.push(handle);
-			}
-			"sprintz" => {
-				let mut compress_daemon: MABCompressionDaemon<_, DB, _> = MABCompressionDaemon::new(*(buf_option.clone().unwrap()), *(compre_buf_option.clone().unwrap()), None, 0.1, 0.0, SprintzDoubleCompress::new(10, batch,10000));
-				let handle = thread::spawn(move || {
-					println!("Run sprintz compression demon");
-					compress_daemon.run();
-					println!("segment commpressed: {}", compress_daemon.get_processed() );
-				});
-				comp_handlers.push(handle);
-			}
-			"gzip" => {
-				let mut compress_daemon:MABCompressionDaemon<_,DB,_> = MABCompressionDaemon::new(*(buf_option.clone().unwrap()),*(compre_buf_option.clone().unwrap()),None,0.1,0.0,GZipCompress::new(10,batch));
-				let handle = thread::spawn(move || {
-					println!("Run gzip compression demon");
-					compress_daemon.run();
-					println!("segment commpressed: {}", compress_daemon.get_processed() );
-				});
-				comp_handlers.push(handle);
-			}
 
 			"kernel" => {
 				let mut knl = Kernel::new(array![[1.0, 1.0],[1.0, 1.0]],1,4,30);
@@ -965,7 +760,8 @@ pub fn run_mab_test(config_file: &str, task:&str, comp:&str, recode:&str, num_co
 					knl = Kernel::new(testdict.clone().unwrap(),1,4,30);
 					knl.rbfdict_pre_process();
 				}
-				let mut compress_daemon:MABCompressionDaemon<_,DB,_> = MABCompressionDaemon::new(*(buf_option.clone().unwrap()),*(compre_buf_option.clone().unwrap()),None,0.1,0.0,knl);
+				let mut compress_daemon:MABCompressionDaemon<_,BoxedFileManager,_> = MABCompressionDaemon::new(share_buffer(buf_option.as_ref().unwrap()),share_buffer(compre_buf_option.as_ref().unwrap()),None,DRAIN_THRESHOLD,0.0,knl);
+				compress_daemon.set_shutdown(shutdown.signal());
 				let handle = thread::spawn(move || {
 					println!("Run kernel compression demon");
 					compress_daemon.run();
@@ -973,14 +769,26 @@ pub fn run_mab_test(config_file: &str, task:&str, comp:&str, recode:&str, num_co
 				});
 				comp_handlers.push(handle);
 			}
-			_ => {panic!("Compression not supported yet.")}
+			name => {
+				let codec = codec_by_name(name, batch).expect("Compression not supported yet.");
+				let label = codec.method().to_string();
+				let mut compress_daemon:MABCompressionDaemon<_,BoxedFileManager,_> = MABCompressionDaemon::new(share_buffer(buf_option.as_ref().unwrap()),share_buffer(compre_buf_option.as_ref().unwrap()),None,DRAIN_THRESHOLD,0.0,codec);
+				compress_daemon.set_shutdown(shutdown.signal());
+				let handle = thread::spawn(move || {
+					println!("Run {} compression demon", label);
+					compress_daemon.run();
+					println!("segment commpressed: {}", compress_daemon.get_processed() );
+				});
+				comp_handlers.push(handle);
+			}
 		}
 
 	}
 
 	for _x in 0..num_recode {
 		let rec_name = recoding.clone();
-		let mut rec:MABRecodingDaemon<_,DB> = MABRecodingDaemon::new(*(compre_buf_option.clone().unwrap()),*(compre_buf_option.clone().unwrap()),None,0.8,0.8,batch, recoding.clone());
+		let mut rec:MABRecodingDaemon<_,BoxedFileManager> = MABRecodingDaemon::new(share_buffer(compre_buf_option.as_ref().unwrap()),share_buffer(compre_buf_option.as_ref().unwrap()),None,0.8,0.8,batch, recoding.clone());
+		rec.set_shutdown(shutdown.signal());
 		let handle = thread::spawn(move || {
 			println!("Run recoding demon with lossy {}", rec_name );
 			rec.run();
@@ -998,8 +806,8 @@ pub fn run_mab_test(config_file: &str, task:&str, comp:&str, recode:&str, num_co
 		spawn_handles.push(oneshot::spawn(sig, &executor))
 	}
 
-	for _comp in comp_handlers {
-		// comp.join().unwrap();
+	for comp in comp_handlers {
+		shutdown.add_daemon(comp);
 	}
 
 
@@ -1011,12 +819,31 @@ pub fn run_mab_test(config_file: &str, task:&str, comp:&str, recode:&str, num_co
 		}
 	}
 
+	/* Drain the daemons and persist both pools so a restart finds every segment */
+	for buf in vec![&buf_option, &compre_buf_option] {
+		if let Some(buf) = buf {
+			shutdown.add_pool(buf);
+		}
+	}
+	match shutdown.shutdown() {
+		Ok(()) => (),
+		Err(e) => println!("Failed to persist the buffers: {:?}", e),
+	}
+
 	// Wait until the runtime becomes idle and shut it down.
 	match rt.shutdown_on_idle().wait() {
 		Ok(_) => (),
 		Err(_) => panic!("Failed to shutdown properly"),
 	}
 
+	/* Lookups each buffer answered from memory, to compare the eviction policies */
+	for (name, buf) in vec![("buffer", &buf_option), ("comp buffer", &compre_buf_option)] {
+		if let Some(buf) = buf {
+			println!("{} {}", name, buf.lock().unwrap().get_cache_stats());
+		}
+	}
+	println!("{:?}", governor());
+
 }
 
 pub fn run_online_mab_test(config_file: &str, task:&str, comp:&str, recode:&str, num_comp:i32, num_recode: i32, tcr:f64){
@@ -1034,119 +861,36 @@ pub fn run_online_mab_test(config_file: &str, task:&str, comp:&str, recode:&str,
 		.expect("The segment size argument must be provided as an integer") as usize;
 
 
-	/* Construct the file manager to be used */
-	let fm = match config.lookup("file_handler") {
-		Some (config) => {
-			let fm_type = config.lookup("file_manager").expect("A file manager must be provided");
-			match fm_type.as_str().expect("A file manager must be provided as a string") {
-				"Rocks" => {
-					let params = config.lookup("params").expect("A RocksDB file manager requires parameters");
-					let path = params.lookup("path").expect("RocksDB requires a path be provided").as_str().expect("Rocks file path must be provided as string");
-					let mut db_opts = rocksdb::Options::default();
-					db_opts.create_if_missing(true);
-					match rocksdb::DB::open(&db_opts, path) {
-						Ok(x) => Some(Box::new(x)),
-						Err(e) => panic!("Failed to create RocksFM object: {:?}", e),
-					}
-				}
-				x => panic!("File manager type, {:?}, not supported yet", x),
-			}
-		}
-		None => None,
-	};
-
-
-	/* Construct the file manager for compression to be used */
-	let fm_comp = match config.lookup("file_handler") {
-		Some (config) => {
-			let fm_type = config.lookup("file_manager").expect("A file manager must be provided");
-			match fm_type.as_str().expect("A file manager must be provided as a string") {
-				"Rocks" => {
-					let params = config.lookup("params").expect("A RocksDB file manager requires parameters");
-					let path = params.lookup("path").expect("RocksDB requires a path be provided").as_str().expect("Rocks file path must be provided as string");
-					let mut comp_path = String::from(path);
-					comp_path.push_str("comp");
-					let new_path = comp_path.as_str();
-					let mut db_opts = rocksdb::Options::default();
-					db_opts.create_if_missing(true);
-					match rocksdb::DB::open(&db_opts, new_path) {
-						Ok(x) => Some(Box::new(x)),
-						Err(e) => panic!("Failed to create RocksFM object: {:?}", e),
-					}
-				}
-				x => panic!("File manager type, {:?}, not supported yet", x),
-			}
-		}
-		None => None,
-	};
+	/* Construct the file managers to be used, the compressed segments get one of their own */
+	let fm = build_file_manager(&config, "");
+	let fm_comp = build_file_manager(&config, "comp");
 
-	/* Construct the buffer to be used */
-	let buffer_size = match config.lookup("buffer") {
-		Some(value) => value.lookup("buffer_size").map_or(DEFAULT_BUF_SIZE, |v| v.as_integer().expect("The buffer size should be provided as an integer") as usize),
-		None => DEFAULT_BUF_SIZE,
-	};
+	/* Rebuild what the store holds of every signal before the buffers take the file managers */
+	let catalog = build_catalog(&config, &fm, &fm_comp);
 
+	/* Construct the buffers to be used */
 	let buffer_budget = match config.lookup("comp_buffer") {
 		Some(value) => value.lookup("buffer_budget").map_or(DEFAULT_BUF_SIZE, |v| v.as_integer().expect("The buffer size should be provided as an integer") as usize),
 		None => DEFAULT_BUF_SIZE,
 	};
 
-	let buf_option: Option<Box<Arc<Mutex<(SegmentBuffer<f64> + Send + Sync)>>>> = match fm {
-		Some(fm) => {
-			match config.lookup("buffer") {
-				Some(config) => {
-					let buf_type = config.lookup("type").expect("A buffer type must be provided");
-					match buf_type.as_str().expect("Buffer type must be provided as string") {
-						"Clock" => Some(Box::new(Arc::new(Mutex::new(ClockBuffer::<f64,rocksdb::DB>::new(buffer_size,*fm))))),
-						x => panic!("The buffer type, {:?}, is not currently supported to run with a file manager", x),
-					}
-				}
-				None => None,
-			}
-		}
-		None => {
-			match config.lookup("buffer") {
-				Some(config) => {
-					let buf_type = config.lookup("type").expect("A buffer type must be provided");
-					match buf_type.as_str().expect("Buffer type must be provided as a string") {
-						"NoFmClock" => Some(Box::new(Arc::new(Mutex::new(NoFmClockBuffer::<f64>::new(buffer_size))))),
-						x => panic!("The buffer type, {:?}, is not currently supported to run without a file manager", x),
-					}
-				}
-				None => None,
-			}
-		}
-	};
+	let buf_option: Option<Box<Arc<Mutex<(SegmentBuffer<f64> + Send + Sync)>>>> = build_buffer(&config, "buffer", fm).map(Box::new);
 
-	/* Create buffer for compression segments*/
-	let compre_buf_option: Option<Box<Arc<Mutex<(SegmentBuffer<f64> + Send + Sync)>>>> = match fm_comp {
-		Some(fm) => {
-			match config.lookup("comp_buffer") {
-				Some(config) => {
-					let buf_type = config.lookup("type").expect("A buffer type must be provided");
-					match buf_type.as_str().expect("Buffer type must be provided as string") {
-						"Clock" => Some(Box::new(Arc::new(Mutex::new(ClockBuffer::<f64,rocksdb::DB>::new(buffer_size,*fm))))),
-						"LRU" => Some(Box::new(Arc::new(Mutex::new(LRUBuffer::<f64,rocksdb::DB>::new(buffer_budget,*fm,task))))),
-						x => panic!("The buffer type, {:?}, is not currently supported to run with a file manager", x),
-					}
-				}
-				None => None,
-			}
-		}
-		None => {
-			match config.lookup("buffer") {
-				Some(config) => {
-					let buf_type = config.lookup("type").expect("A buffer type must be provided");
-					match buf_type.as_str().expect("Buffer type must be provided as a string") {
-						"NoFmClock" => Some(Box::new(Arc::new(Mutex::new(NoFmClockBuffer::<f64>::new(buffer_size))))),
-						x => panic!("The buffer type, {:?}, is not currently supported to run without a file manager", x),
-					}
-				}
-				None => None,
-			}
+	/* Create buffer for compression segments, the LRU buffer is only built here since it needs the task */
+	let compre_buf_option: Option<Box<Arc<Mutex<(SegmentBuffer<f64> + Send + Sync)>>>> = match (fm_comp, config.lookup("comp_buffer.type").and_then(|v| v.as_str())) {
+		(Some(fm), Some("LRU")) => {
+			let model_dir = config.lookup("comp_buffer.model_dir").map(|v| v.as_str().expect("The model directory must be provided as a string"));
+			Some(Box::new(Arc::new(Mutex::new(LRUBuffer::<f64,BoxedFileManager>::new(buffer_budget,fm,evaluator_for_task::<f64>(model_dir,task))))))
 		}
+		(Some(fm), _) => build_buffer(&config, "comp_buffer", Some(fm)).map(Box::new),
+		(None, _) => build_buffer(&config, "buffer", None).map(Box::new),
 	};
 
+	let mut shutdown = ShutdownHandle::new();
+
+	/* Put back what the write ahead log still holds before new points arrive */
+	let wal = build_wal(&config, buf_option.as_ref().map(|buf| &**buf), compre_buf_option.as_ref().map(|buf| &**buf), &catalog, &mut shutdown);
+
 	/* Construct the clients */
 	let mut signals: Vec<Box<(Future<Item=Option<SystemTime>,Error=()> + Send + Sync)>> = Vec::new();
 	let mut rng = thread_rng();
@@ -1164,6 +908,9 @@ pub fn run_online_mab_test(config_file: &str, task:&str, comp:&str, recode:&str,
 			signal_id = x.as_integer().expect("If an ID for a client is provided it must be supplied as an integer") as u64;
 		}
 
+		/* A named client keeps the id the catalog gave its name in an earlier run */
+		signal_id = register_client(&catalog, signal_id, client_config, element_type_id::<f64>());
+
 		let client_type = client_config.lookup("type").expect("The client type must be provided");
 
 		let amount = match client_config.lookup("amount") {
@@ -1263,7 +1010,7 @@ pub fn run_online_mab_test(config_file: &str, task:&str, comp:&str, recode:&str,
 							Some(skip_val) => skip_val.as_integer().expect("The skip value must be provided as an integer") as usize,
 							None => 0,
 						};
-						Box::new(construct_file_client_skip_newline::<f64>(path, skip_val, delim, amount, run_period, frequency).expect("Client could not be properly produced"))
+						Box::new(construct_file_client_with_gaps::<f64>(path, skip_val, delim, amount, run_period, frequency).expect("Client could not be properly produced"))
 					}
 					"DeserializeDelim" => Box::new(construct_file_client::<f64>(path, delim as u8, amount, run_period, frequency).expect("Client could not be properly produced")),
 					x => panic!("The specified file reader, {:?}, is not supported yet", x),
@@ -1272,7 +1019,7 @@ pub fn run_online_mab_test(config_file: &str, task:&str, comp:&str, recode:&str,
 
 
 				match &buf_option {
-					Some(buf) => signals.push(Box::new(BufferedSignal::new(signal_id, client, seg_size, *buf.clone(), |i,j| i >= j, |_| (), false,dict))),
+					Some(buf) => signals.push(Box::new(BufferedSignal::new(signal_id, client, seg_size, share_buffer(buf), |i,j| i >= j, |_| (), false,dict).with_shutdown(shutdown.signal()).with_wal(wal.clone()).with_catalog(Some(catalog.clone())))),
 					None => panic!("Buffer and File manager provided not supported yet"),
 				}
 			}
@@ -1321,7 +1068,7 @@ pub fn run_online_mab_test(config_file: &str, task:&str, comp:&str, recode:&str,
 					x => panic!("The provided generator type, {:?}, is not currently supported", x),
 				};
 				match &buf_option {
-					Some(buf) => signals.push(Box::new(BufferedSignal::new(signal_id, client, seg_size, *buf.clone(), |i,j| i >= j, |_| (), false, None))),
+					Some(buf) => signals.push(Box::new(BufferedSignal::new(signal_id, client, seg_size, share_buffer(buf), |i,j| i >= j, |_| (), false, None).with_shutdown(shutdown.signal()).with_wal(wal.clone()).with_catalog(Some(catalog.clone())))),
 					None => panic!("Buffer and File manager provided not supported yet"),
 				}
 			}
@@ -1333,7 +1080,11 @@ pub fn run_online_mab_test(config_file: &str, task:&str, comp:&str, recode:&str,
 
 	let batch = 1;
 
+	/* A budget over the heap bytes of every buffer, the signals wait while it is exceeded */
+	set_memory_budget::<f64>(&config, seg_size, batch);
 
+	/* Expire the segments that are past the retention rules of their signals */
+	build_retention(&config, buf_option.as_ref().map(|buf| &**buf), compre_buf_option.as_ref().map(|buf| &**buf), &catalog, &mut shutdown);
 
 	/* Construct the runtime */
 	let rt = match config.lookup("runtime") {
@@ -1384,71 +1135,9 @@ pub fn run_online_mab_test(config_file: &str, task:&str, comp:&str, recode:&str,
 	for _x in 0..num_comp {
 		println!("Init MAB compression demon {}", _x);
 		match comp{
-			"paa" => {
-				let mut compress_daemon:MABCompressionDaemon<_,DB,_> = MABCompressionDaemon::new(*(buf_option.clone().unwrap()),*(compre_buf_option.clone().unwrap()),None,0.1,0.0,PAACompress::new(10,batch));
-				let handle = thread::spawn(move || {
-					println!("Run paa compression demon");
-					compress_daemon.run();
-					println!("segment commpressed: {}", compress_daemon.get_processed() );
-				});
-				comp_handlers.push(handle);
-			},
-			"buff" => {
-				let mut compress_daemon:MABCompressionDaemon<_,DB,_> = MABCompressionDaemon::new(*(buf_option.clone().unwrap()),*(compre_buf_option.clone().unwrap()),None,0.1,0.0,SplitBDDoubleCompress::new(10,batch, 10000));
-				let handle = thread::spawn(move || {
-					println!("Run buff compression demon");
-					compress_daemon.run();
-					println!("segment commpressed: {}", compress_daemon.get_processed() );
-				});
-				comp_handlers.push(handle);
-			},
-			"fourier" => {
-				let mut compress_daemon: MABCompressionDaemon<_, DB, _> = MABCompressionDaemon::new(*(buf_option.clone().unwrap()), *(compre_buf_option.clone().unwrap()), None, 0.1, 0.0, FourierCompress::new(10, batch,1.0));
-				let handle = thread::spawn(move || {
-					println!("Run fourier compression demon");
-					compress_daemon.run();
-					println!("segment commpressed: {}", compress_daemon.get_processed() );
-				});
-				comp_handlers.push(handle);
-			}
-			"snappy" => {
-				let mut compress_daemon: MABCompressionDaemon<_, DB, _> = MABCompressionDaemon::new(*(buf_option.clone().unwrap()), *(compre_buf_option.clone().unwrap()), None, 0.1, 0.0, SnappyCompress::new(10, batch));
-				let handle = thread::spawn(move || {
-					println!("Run snappy compression demon");
-					compress_daemon.run();
-					println!("segment commpressed: {}", compress_daemon.get_processed() );
-				});
-				comp_handlers.push(handle);
-			}
-			"gorilla" => {
-				let mut compress_daemon: MABCompressionDaemon<_, DB, _> = MABCompressionDaemon::new(*(buf_option.clone().unwrap()), *(compre_buf_option.clone().unwrap()), None, 0.1, 0.0, GorillaCompress::new(10, batch));
-				let handle = thread::spawn(move || {
-					println!("Run gotilla compression demon");
-					compress_daemon.run();
-					println!("segment commpressed: {}", compress_daemon.get_processed() );
-				});
-				comp_handlers.push(handle);
-			}
-			"sprintz" => {
-				let mut compress_daemon: MABCompressionDaemon<_, DB, _> = MABCompressionDaemon::new(*(buf_option.clone().unwrap()), *(compre_buf_option.clone().unwrap()), None, 0.1, 0.0, SprintzDoubleCompress::new(10, batch,10000));
-				let handle = thread::spawn(move || {
-					println!("Run sprintz compression demon");
-					compress_daemon.run();
-					println!("segment commpressed: {}", compress_daemon.get_processed() );
-				});
-				comp_handlers.push(handle);
-			}
-			"gzip" => {
-				let mut compress_daemon:MABCompressionDaemon<_,DB,_> = MABCompressionDaemon::new(*(buf_option.clone().unwrap()),*(compre_buf_option.clone().unwrap()),None,0.1,0.0,GZipCompress::new(10,batch));
-				let handle = thread::spawn(move || {
-					println!("Run gzip compression demon");
-					compress_daemon.run();
-					println!("segment commpressed: {}", compress_daemon.get_processed() );
-				});
-				comp_handlers.push(handle);
-			}
 			"zlib" => {
-				let mut compress_daemon:MABCompressionDaemon<_,DB,_> = MABCompressionDaemon::new(*(buf_option.clone().unwrap()),*(compre_buf_option.clone().unwrap()),None,0.1,0.0,ZlibCompress::new(10,batch, level));
+				let mut compress_daemon:MABCompressionDaemon<_,BoxedFileManager,_> = MABCompressionDaemon::new(share_buffer(buf_option.as_ref().unwrap()),share_buffer(compre_buf_option.as_ref().unwrap()),None,DRAIN_THRESHOLD,0.0,ZlibCompress::new(10,batch, level));
+				compress_daemon.set_shutdown(shutdown.signal());
 				let handle = thread::spawn(move || {
 					println!("Run zlib compression demon with level {}", level);
 					compress_daemon.run();
@@ -1462,7 +1151,8 @@ pub fn run_online_mab_test(config_file: &str, task:&str, comp:&str, recode:&str,
 					knl = Kernel::new(testdict.clone().unwrap(),1,4,30);
 					knl.rbfdict_pre_process();
 				}
-				let mut compress_daemon:MABCompressionDaemon<_,DB,_> = MABCompressionDaemon::new(*(buf_option.clone().unwrap()),*(compre_buf_option.clone().unwrap()),None,0.1,0.0,knl);
+				let mut compress_daemon:MABCompressionDaemon<_,BoxedFileManager,_> = MABCompressionDaemon::new(share_buffer(buf_option.as_ref().unwrap()),share_buffer(compre_buf_option.as_ref().unwrap()),None,DRAIN_THRESHOLD,0.0,knl);
+				compress_daemon.set_shutdown(shutdown.signal());
 				let handle = thread::spawn(move || {
 					println!("Run kernel compression demon");
 					compress_daemon.run();
@@ -1470,14 +1160,26 @@ pub fn run_online_mab_test(config_file: &str, task:&str, comp:&str, recode:&str,
 				});
 				comp_handlers.push(handle);
 			}
-			_ => {panic!("Compression not supported yet.")}
+			name => {
+				let codec = codec_by_name(name, batch).expect("Compression not supported yet.");
+				let label = codec.method().to_string();
+				let mut compress_daemon:MABCompressionDaemon<_,BoxedFileManager,_> = MABCompressionDaemon::new(share_buffer(buf_option.as_ref().unwrap()),share_buffer(compre_buf_option.as_ref().unwrap()),None,DRAIN_THRESHOLD,0.0,codec);
+				compress_daemon.set_shutdown(shutdown.signal());
+				let handle = thread::spawn(move || {
+					println!("Run {} compression demon", label);
+					compress_daemon.run();
+					println!("segment commpressed: {}", compress_daemon.get_processed() );
+				});
+				comp_handlers.push(handle);
+			}
 		}
 
 	}
 
 	for _x in 0..num_recode {
 		let rec_name = recoding.clone();
-		let mut rec:MABRecodingDaemon<_,DB> = MABRecodingDaemon::new(*(compre_buf_option.clone().unwrap()),*(compre_buf_option.clone().unwrap()),None,0.01,0.01,batch, recoding.clone());
+		let mut rec:MABRecodingDaemon<_,BoxedFileManager> = MABRecodingDaemon::new(share_buffer(compre_buf_option.as_ref().unwrap()),share_buffer(compre_buf_option.as_ref().unwrap()),None,0.01,0.01,batch, recoding.clone());
+		rec.set_shutdown(shutdown.signal());
 		rec.set_targetCR(tcr);
 		let handle = thread::spawn(move || {
 			println!("Run recoding demon with lossy {}", rec_name );
@@ -1496,8 +1198,8 @@ pub fn run_online_mab_test(config_file: &str, task:&str, comp:&str, recode:&str,
 		spawn_handles.push(oneshot::spawn(sig, &executor))
 	}
 
-	for _comp in comp_handlers {
-		// comp.join().unwrap();
+	for comp in comp_handlers {
+		shutdown.add_daemon(comp);
 	}
 
 
@@ -1509,12 +1211,31 @@ pub fn run_online_mab_test(config_file: &str, task:&str, comp:&str, recode:&str,
 		}
 	}
 
+	/* Drain the daemons and persist both pools so a restart finds every segment */
+	for buf in vec![&buf_option, &compre_buf_option] {
+		if let Some(buf) = buf {
+			shutdown.add_pool(buf);
+		}
+	}
+	match shutdown.shutdown() {
+		Ok(()) => (),
+		Err(e) => println!("Failed to persist the buffers: {:?}", e),
+	}
+
 	// Wait until the runtime becomes idle and shut it down.
 	match rt.shutdown_on_idle().wait() {
 		Ok(_) => (),
 		Err(_) => panic!("Failed to shutdown properly"),
 	}
 
+	/* Lookups each buffer answered from memory, to compare the eviction policies */
+	for (name, buf) in vec![("buffer", &buf_option), ("comp buffer", &compre_buf_option)] {
+		if let Some(buf) = buf {
+			println!("{} {}", name, buf.lock().unwrap().get_cache_stats());
+		}
+	}
+	println!("{:?}", governor());
+
 }
 
 pub fn run_online_test(config_file: &str, task:&str, comp:&str, recode:&str, num_comp:i32, num_recode: i32, tcr:f64)
@@ -1533,119 +1254,36 @@ pub fn run_online_test(config_file: &str, task:&str, comp:&str, recode:&str, num
 		.expect("The segment size argument must be provided as an integer") as usize;
 
 
-	/* Construct the file manager to be used */
-	let fm = match config.lookup("file_handler") {
-		Some (config) => {
-			let fm_type = config.lookup("file_manager").expect("A file manager must be provided");
-			match fm_type.as_str().expect("A file manager must be provided as a string") {
-				"Rocks" => {
-					let params = config.lookup("params").expect("A RocksDB file manager requires parameters");
-					let path = params.lookup("path").expect("RocksDB requires a path be provided").as_str().expect("Rocks file path must be provided as string");
-					let mut db_opts = rocksdb::Options::default();
-					db_opts.create_if_missing(true);
-					match rocksdb::DB::open(&db_opts, path) {
-						Ok(x) => Some(Box::new(x)),
-						Err(e) => panic!("Failed to create RocksFM object: {:?}", e),
-					}
-				}
-				x => panic!("File manager type, {:?}, not supported yet", x),
-			}
-		}
-		None => None,
-	};
+	/* Construct the file managers to be used, the compressed segments get one of their own */
+	let fm = build_file_manager(&config, "");
+	let fm_comp = build_file_manager(&config, "comp");
 
+	/* Rebuild what the store holds of every signal before the buffers take the file managers */
+	let catalog = build_catalog(&config, &fm, &fm_comp);
 
-	/* Construct the file manager for compression to be used */
-	let fm_comp = match config.lookup("file_handler") {
-		Some (config) => {
-			let fm_type = config.lookup("file_manager").expect("A file manager must be provided");
-			match fm_type.as_str().expect("A file manager must be provided as a string") {
-				"Rocks" => {
-					let params = config.lookup("params").expect("A RocksDB file manager requires parameters");
-					let path = params.lookup("path").expect("RocksDB requires a path be provided").as_str().expect("Rocks file path must be provided as string");
-					let mut comp_path = String::from(path);
-					comp_path.push_str("comp");
-					let new_path = comp_path.as_str();
-					let mut db_opts = rocksdb::Options::default();
-					db_opts.create_if_missing(true);
-					match rocksdb::DB::open(&db_opts, new_path) {
-						Ok(x) => Some(Box::new(x)),
-						Err(e) => panic!("Failed to create RocksFM object: {:?}", e),
-					}
-				}
-				x => panic!("File manager type, {:?}, not supported yet", x),
-			}
-		}
-		None => None,
-	};
-
-	/* Construct the buffer to be used */
-	let buffer_size = match config.lookup("buffer") {
-		Some(value) => value.lookup("buffer_size").map_or(DEFAULT_BUF_SIZE, |v| v.as_integer().expect("The buffer size should be provided as an integer") as usize),
-		None => DEFAULT_BUF_SIZE,
-	};
-
+	/* Construct the buffers to be used */
 	let buffer_budget = match config.lookup("comp_buffer") {
 		Some(value) => value.lookup("buffer_budget").map_or(DEFAULT_BUF_SIZE, |v| v.as_integer().expect("The buffer size should be provided as an integer") as usize),
 		None => DEFAULT_BUF_SIZE,
 	};
 
-	let buf_option: Option<Box<Arc<Mutex<(SegmentBuffer<f64> + Send + Sync)>>>> = match fm {
-		Some(fm) => {
-			match config.lookup("buffer") {
-				Some(config) => {
-					let buf_type = config.lookup("type").expect("A buffer type must be provided");
-					match buf_type.as_str().expect("Buffer type must be provided as string") {
-						"Clock" => Some(Box::new(Arc::new(Mutex::new(ClockBuffer::<f64,rocksdb::DB>::new(buffer_size,*fm))))),
-						x => panic!("The buffer type, {:?}, is not currently supported to run with a file manager", x),
-					}
-				}
-				None => None,
-			}
-		}
-		None => {
-			match config.lookup("buffer") {
-				Some(config) => {
-					let buf_type = config.lookup("type").expect("A buffer type must be provided");
-					match buf_type.as_str().expect("Buffer type must be provided as a string") {
-						"NoFmClock" => Some(Box::new(Arc::new(Mutex::new(NoFmClockBuffer::<f64>::new(buffer_size))))),
-						x => panic!("The buffer type, {:?}, is not currently supported to run without a file manager", x),
-					}
-				}
-				None => None,
-			}
-		}
-	};
+	let buf_option: Option<Box<Arc<Mutex<(SegmentBuffer<f64> + Send + Sync)>>>> = build_buffer(&config, "buffer", fm).map(Box::new);
 
-	/* Create buffer for compression segments*/
-	let compre_buf_option: Option<Box<Arc<Mutex<(SegmentBuffer<f64> + Send + Sync)>>>> = match fm_comp {
-		Some(fm) => {
-			match config.lookup("comp_buffer") {
-				Some(config) => {
-					let buf_type = config.lookup("type").expect("A buffer type must be provided");
-					match buf_type.as_str().expect("Buffer type must be provided as string") {
-						"Clock" => Some(Box::new(Arc::new(Mutex::new(ClockBuffer::<f64,rocksdb::DB>::new(buffer_size,*fm))))),
-						"LRU" => Some(Box::new(Arc::new(Mutex::new(LRUBuffer::<f64,rocksdb::DB>::new(buffer_budget,*fm, task))))),
-						x => panic!("The buffer type, {:?}, is not currently supported to run with a file manager", x),
-					}
-				}
-				None => None,
-			}
-		}
-		None => {
-			match config.lookup("buffer") {
-				Some(config) => {
-					let buf_type = config.lookup("type").expect("A buffer type must be provided");
-					match buf_type.as_str().expect("Buffer type must be provided as a string") {
-						"NoFmClock" => Some(Box::new(Arc::new(Mutex::new(NoFmClockBuffer::<f64>::new(buffer_size))))),
-						x => panic!("The buffer type, {:?}, is not currently supported to run without a file manager", x),
-					}
-				}
-				None => None,
-			}
+	/* Create buffer for compression segments, the LRU buffer is only built here since it needs the task */
+	let compre_buf_option: Option<Box<Arc<Mutex<(SegmentBuffer<f64> + Send + Sync)>>>> = match (fm_comp, config.lookup("comp_buffer.type").and_then(|v| v.as_str())) {
+		(Some(fm), Some("LRU")) => {
+			let model_dir = config.lookup("comp_buffer.model_dir").map(|v| v.as_str().expect("The model directory must be provided as a string"));
+			Some(Box::new(Arc::new(Mutex::new(LRUBuffer::<f64,BoxedFileManager>::new(buffer_budget,fm,evaluator_for_task::<f64>(model_dir,task))))))
 		}
+		(Some(fm), _) => build_buffer(&config, "comp_buffer", Some(fm)).map(Box::new),
+		(None, _) => build_buffer(&config, "buffer", None).map(Box::new),
 	};
 
+	let mut shutdown = ShutdownHandle::new();
+
+	/* Put back what the write ahead log still holds before new points arrive */
+	let wal = build_wal(&config, buf_option.as_ref().map(|buf| &**buf), compre_buf_option.as_ref().map(|buf| &**buf), &catalog, &mut shutdown);
+
 	/* Construct the clients */
 	let mut signals: Vec<Box<(Future<Item=Option<SystemTime>,Error=()> + Send + Sync)>> = Vec::new();
 	let mut rng = thread_rng();
@@ -1663,6 +1301,9 @@ pub fn run_online_test(config_file: &str, task:&str, comp:&str, recode:&str, num
 			signal_id = x.as_integer().expect("If an ID for a client is provided it must be supplied as an integer") as u64;
 		}
 
+		/* A named client keeps the id the catalog gave its name in an earlier run */
+		signal_id = register_client(&catalog, signal_id, client_config, element_type_id::<f64>());
+
 		let client_type = client_config.lookup("type").expect("The client type must be provided");
 
 		let amount = match client_config.lookup("amount") {
@@ -1762,7 +1403,7 @@ pub fn run_online_test(config_file: &str, task:&str, comp:&str, recode:&str, num
 							Some(skip_val) => skip_val.as_integer().expect("The skip value must be provided as an integer") as usize,
 							None => 0,
 						};
-						Box::new(construct_file_client_skip_newline::<f64>(path, skip_val, delim, amount, run_period, frequency).expect("Client could not be properly produced"))
+						Box::new(construct_file_client_with_gaps::<f64>(path, skip_val, delim, amount, run_period, frequency).expect("Client could not be properly produced"))
 					}
 					"DeserializeDelim" => Box::new(construct_file_client::<f64>(path, delim as u8, amount, run_period, frequency).expect("Client could not be properly produced")),
 					x => panic!("The specified file reader, {:?}, is not supported yet", x),
@@ -1771,7 +1412,7 @@ pub fn run_online_test(config_file: &str, task:&str, comp:&str, recode:&str, num
 
 
 				match &buf_option {
-					Some(buf) => signals.push(Box::new(BufferedSignal::new(signal_id, client, seg_size, *buf.clone(), |i,j| i >= j, |_| (), false,dict))),
+					Some(buf) => signals.push(Box::new(BufferedSignal::new(signal_id, client, seg_size, share_buffer(buf), |i,j| i >= j, |_| (), false,dict).with_shutdown(shutdown.signal()).with_wal(wal.clone()).with_catalog(Some(catalog.clone())))),
 					None => panic!("Buffer and File manager provided not supported yet"),
 				}
 			}
@@ -1820,7 +1461,7 @@ pub fn run_online_test(config_file: &str, task:&str, comp:&str, recode:&str, num
 					x => panic!("The provided generator type, {:?}, is not currently supported", x),
 				};
 				match &buf_option {
-					Some(buf) => signals.push(Box::new(BufferedSignal::new(signal_id, client, seg_size, *buf.clone(), |i,j| i >= j, |_| (), false, None))),
+					Some(buf) => signals.push(Box::new(BufferedSignal::new(signal_id, client, seg_size, share_buffer(buf), |i,j| i >= j, |_| (), false, None).with_shutdown(shutdown.signal()).with_wal(wal.clone()).with_catalog(Some(catalog.clone())))),
 					None => panic!("Buffer and File manager provided not supported yet"),
 				}
 			}
@@ -1832,7 +1473,37 @@ pub fn run_online_test(config_file: &str, task:&str, comp:&str, recode:&str, num
 
 	let batch = 5;
 
-
+	/* A budget over the heap bytes of every buffer, the signals wait while it is exceeded */
+	set_memory_budget::<f64>(&config, seg_size, batch);
+
+	/* Expire the segments that are past the retention rules of their signals */
+	build_retention(&config, buf_option.as_ref().map(|buf| &**buf), compre_buf_option.as_ref().map(|buf| &**buf), &catalog, &mut shutdown);
+
+	/* Query mixes the auto compression weighs the codecs of each signal with */
+	let mut codec_selector = CodecSelector::new(QueryMix::default());
+	if let Some(params) = config.lookup("selector") {
+		let default = QueryMix::parse(
+			params.lookup("storage").map(|v| v.as_float().expect("Query mix weights must be provided as floats")),
+			params.lookup("ingest").map(|v| v.as_float().expect("Query mix weights must be provided as floats")),
+			params.lookup("read").map(|v| v.as_float().expect("Query mix weights must be provided as floats")),
+			QueryMix::default())
+			.expect("Failed to parse the default query mix");
+		codec_selector = CodecSelector::new(default);
+		if let Some(mixes) = params.lookup("signals") {
+			/* Mixes are keyed by signal selectors, the weights they leave out come from the default mix */
+			for (selector, mix) in mixes.as_table().expect("Per signal query mixes must be provided as a TOML table") {
+				let mix = QueryMix::parse(
+					mix.lookup("storage").map(|v| v.as_float().expect("Query mix weights must be provided as floats")),
+					mix.lookup("ingest").map(|v| v.as_float().expect("Query mix weights must be provided as floats")),
+					mix.lookup("read").map(|v| v.as_float().expect("Query mix weights must be provided as floats")),
+					default)
+					.expect("Failed to parse a signal query mix");
+				for signal in catalog.lock().unwrap().resolve(selector) {
+					codec_selector.set(signal, mix);
+				}
+			}
+		}
+	}
 
 	/* Construct the runtime */
 	let rt = match config.lookup("runtime") {
@@ -1882,101 +1553,63 @@ pub fn run_online_test(config_file: &str, task:&str, comp:&str, recode:&str, num
 
 	for _x in 0..num_comp {
 		match comp{
-			"paa" => {
-				let mut compress_daemon:CompressionDaemon<_,DB,_> = CompressionDaemon::new(*(buf_option.clone().unwrap()),*(compre_buf_option.clone().unwrap()),None,0.1,0.0,PAACompress::new(10,batch));
-				let handle = thread::spawn(move || {
-					println!("Run paa compression demon");
-					compress_daemon.run();
-					println!("segment commpressed: {}", compress_daemon.get_processed() );
-				});
-				comp_handlers.push(handle);
-			},
-			"buff" => {
-				let mut compress_daemon:CompressionDaemon<_,DB,_> = CompressionDaemon::new(*(buf_option.clone().unwrap()),*(compre_buf_option.clone().unwrap()),None,0.1,0.0,SplitBDDoubleCompress::new(10,batch, 10000));
-				let handle = thread::spawn(move || {
-					println!("Run buff compression demon");
-					compress_daemon.run();
-					println!("segment commpressed: {}", compress_daemon.get_processed() );
-				});
-				comp_handlers.push(handle);
-			},
-			"fourier" => {
-				let mut compress_daemon: CompressionDaemon<_, DB, _> = CompressionDaemon::new(*(buf_option.clone().unwrap()), *(compre_buf_option.clone().unwrap()), None, 0.1, 0.0, FourierCompress::new(10, batch,1.0));
-				let handle = thread::spawn(move || {
-					println!("Run fourier compression demon");
-					compress_daemon.run();
-					println!("segment commpressed: {}", compress_daemon.get_processed() );
-				});
-				comp_handlers.push(handle);
-			}
-			"snappy" => {
-				let mut compress_daemon: CompressionDaemon<_, DB, _> = CompressionDaemon::new(*(buf_option.clone().unwrap()), *(compre_buf_option.clone().unwrap()), None, 0.1, 0.0, SnappyCompress::new(10, batch));
-				let handle = thread::spawn(move || {
-					println!("Run snappy compression demon");
-					compress_daemon.run();
-					println!("segment commpressed: {}", compress_daemon.get_processed() );
-				});
-				comp_handlers.push(handle);
-			}
-			"gorilla" => {
-				let mut compress_daemon: CompressionDaemon<_, DB, _> = CompressionDaemon::new(*(buf_option.clone().unwrap()), *(compre_buf_option.clone().unwrap()), None, 0.1, 0.0, GorillaCompress::new(10, batch));
-				let handle = thread::spawn(move || {
-					println!("Run gotilla compression demon");
-					compress_daemon.run();
-					println!("segment commpressed: {}", compress_daemon.get_processed() );
-				});
-				comp_handlers.push(handle);
-			}
-			"sprintz" => {
-				let mut compress_daemon: CompressionDaemon<_, DB, _> = CompressionDaemon::new(*(buf_option.clone().unwrap()), *(compre_buf_option.clone().unwrap()), None, 0.1, 0.0, SprintzDoubleCompress::new(10, batch,10000));
+			"zlib" => {
+				let mut compress_daemon:CompressionDaemon<_,BoxedFileManager,_> = CompressionDaemon::new(share_buffer(buf_option.as_ref().unwrap()),share_buffer(compre_buf_option.as_ref().unwrap()),None,DRAIN_THRESHOLD,0.0,CatalogCodec::new(ZlibCompress::new(10,batch, level), catalog.clone()));
+				compress_daemon.set_shutdown(shutdown.signal());
 				let handle = thread::spawn(move || {
-					println!("Run sprintz compression demon");
+					println!("Run zlib compression demon with level {}", level);
 					compress_daemon.run();
 					println!("segment commpressed: {}", compress_daemon.get_processed() );
 				});
 				comp_handlers.push(handle);
 			}
-			"gzip" => {
-				let mut compress_daemon:CompressionDaemon<_,DB,_> = CompressionDaemon::new(*(buf_option.clone().unwrap()),*(compre_buf_option.clone().unwrap()),None,0.1,0.0,GZipCompress::new(10,batch));
+
+			"kernel" => {
+				let mut knl = Kernel::new(array![[1.0, 1.0],[1.0, 1.0]],1,4,30);
+				if testdict != None{
+					knl = Kernel::new(testdict.clone().unwrap(),1,4,30);
+					knl.rbfdict_pre_process();
+				}
+				let mut compress_daemon:CompressionDaemon<_,BoxedFileManager,_> = CompressionDaemon::new(share_buffer(buf_option.as_ref().unwrap()),share_buffer(compre_buf_option.as_ref().unwrap()),None,DRAIN_THRESHOLD,0.0,CatalogCodec::new(knl, catalog.clone()));
+				compress_daemon.set_shutdown(shutdown.signal());
 				let handle = thread::spawn(move || {
-					println!("Run gzip compression demon");
+					println!("Run kernel compression demon");
 					compress_daemon.run();
 					println!("segment commpressed: {}", compress_daemon.get_processed() );
 				});
 				comp_handlers.push(handle);
 			}
-			"zlib" => {
-				let mut compress_daemon:CompressionDaemon<_,DB,_> = CompressionDaemon::new(*(buf_option.clone().unwrap()),*(compre_buf_option.clone().unwrap()),None,0.1,0.0,ZlibCompress::new(10,batch, level));
+			"auto" => {
+				let codec = AutoCompress::new(batch, codec_selector.clone());
+				let mut compress_daemon:CompressionDaemon<_,BoxedFileManager,_> = CompressionDaemon::new(share_buffer(buf_option.as_ref().unwrap()),share_buffer(compre_buf_option.as_ref().unwrap()),None,DRAIN_THRESHOLD,0.0,CatalogCodec::new(codec, catalog.clone()));
+				compress_daemon.set_shutdown(shutdown.signal());
 				let handle = thread::spawn(move || {
-					println!("Run zlib compression demon with level {}", level);
+					println!("Run auto compression demon");
 					compress_daemon.run();
 					println!("segment commpressed: {}", compress_daemon.get_processed() );
 				});
 				comp_handlers.push(handle);
 			}
-
-			"kernel" => {
-				let mut knl = Kernel::new(array![[1.0, 1.0],[1.0, 1.0]],1,4,30);
-				if testdict != None{
-					knl = Kernel::new(testdict.clone().unwrap(),1,4,30);
-					knl.rbfdict_pre_process();
-				}
-				let mut compress_daemon:CompressionDaemon<_,DB,_> = CompressionDaemon::new(*(buf_option.clone().unwrap()),*(compre_buf_option.clone().unwrap()),None,0.1,0.0,knl);
+			name => {
+				let codec = codec_by_name(name, batch).expect("Compression not supported yet.");
+				let label = codec.method().to_string();
+				let mut compress_daemon:CompressionDaemon<_,BoxedFileManager,_> = CompressionDaemon::new(share_buffer(buf_option.as_ref().unwrap()),share_buffer(compre_buf_option.as_ref().unwrap()),None,DRAIN_THRESHOLD,0.0,CatalogCodec::new(codec, catalog.clone()));
+				compress_daemon.set_shutdown(shutdown.signal());
 				let handle = thread::spawn(move || {
-					println!("Run kernel compression demon");
+					println!("Run {} compression demon", label);
 					compress_daemon.run();
 					println!("segment commpressed: {}", compress_daemon.get_processed() );
 				});
 				comp_handlers.push(handle);
 			}
-			_ => {panic!("Compression not supported yet.")}
 		}
 
 	}
 
 	for _x in 0..num_recode {
 		let rec_name = recoding.clone();
-		let mut rec:RecodingDaemon<_,DB> = RecodingDaemon::new(*(compre_buf_option.clone().unwrap()),*(compre_buf_option.clone().unwrap()),None,0.01,0.01,batch, recoding.clone());
+		let mut rec:RecodingDaemon<_,BoxedFileManager> = RecodingDaemon::new(share_buffer(compre_buf_option.as_ref().unwrap()),share_buffer(compre_buf_option.as_ref().unwrap()),None,0.01,0.01,batch, recoding.clone());
+		rec.set_shutdown(shutdown.signal());
 		rec.set_targetCR(tcr);
 		let handle = thread::spawn(move || {
 			println!("Run recoding demon with lossy {}", rec_name );
@@ -1995,8 +1628,8 @@ pub fn run_online_test(config_file: &str, task:&str, comp:&str, recode:&str, num
 		spawn_handles.push(oneshot::spawn(sig, &executor))
 	}
 
-	for _comp in comp_handlers {
-		// comp.join().unwrap();
+	for comp in comp_handlers {
+		shutdown.add_daemon(comp);
 	}
 
 
@@ -2008,12 +1641,31 @@ pub fn run_online_test(config_file: &str, task:&str, comp:&str, recode:&str, num
 		}
 	}
 
+	/* Drain the daemons and persist both pools so a restart finds every segment */
+	for buf in vec![&buf_option, &compre_buf_option] {
+		if let Some(buf) = buf {
+			shutdown.add_pool(buf);
+		}
+	}
+	match shutdown.shutdown() {
+		Ok(()) => (),
+		Err(e) => println!("Failed to persist the buffers: {:?}", e),
+	}
+
 	// Wait until the runtime becomes idle and shut it down.
 	match rt.shutdown_on_idle().wait() {
 		Ok(_) => (),
 		Err(_) => panic!("Failed to shutdown properly"),
 	}
 
+	/* Lookups each buffer answered from memory, to compare the eviction policies */
+	for (name, buf) in vec![("buffer", &buf_option), ("comp buffer", &compre_buf_option)] {
+		if let Some(buf) = buf {
+			println!("{} {}", name, buf.lock().unwrap().get_cache_stats());
+		}
+	}
+	println!("{:?}", governor());
+
 }
 
 
@@ -17,7 +17,7 @@ extern crate quickcheck_macros;
 
 pub mod buffer_pool;
 mod dictionary;
-mod file_handler;
+pub mod file_handler;
 pub mod segment;
 pub mod methods;
 pub mod predict;
@@ -25,7 +25,7 @@ pub mod simd;
 pub mod benchmark;
 pub mod future_signal;
 pub mod client;
-mod query;
+pub mod query;
 pub mod compress;
 pub mod pscan;
 pub mod avl;
@@ -41,6 +41,10 @@ pub mod compression_daemon;
 pub mod recoding_daemon;
 pub mod MABcompression_daemon;
 pub mod MABrecoding_daemon;
+pub mod shutdown;
+pub mod wal;
+pub mod catalog;
+pub mod retention;
 
 use rand::prelude::*;
 use rand::distributions::Uniform;
@@ -49,13 +53,16 @@ use crate::client::construct_gen_client;
 use std::time::SystemTime;
 use crate::client::construct_file_client;
 use crate::segment::{ FourierCompress, PAACompress};
-use rocksdb::{DB};
 use std::str::FromStr;
 use serde::Serialize;
 use std::fmt::Debug;
 use serde::de::DeserializeOwned;
-use crate::buffer_pool::{SegmentBuffer,ClockBuffer,NoFmClockBuffer};
-use crate::future_signal::{BufferedSignal};
+use crate::buffer_pool::{SegmentBuffer,ClockBuffer,NoFmClockBuffer,share_buffer};
+use crate::buffer_pool::governor::governor;
+use crate::buffer_pool::sharded::{ShardedBuffer,DEFAULT_SHARDS,shard_size};
+use crate::buffer_pool::scan_resistant::{ArcBuffer,TwoQueueBuffer};
+use crate::file_handler::{BoxedFileManager,LogFileManager};
+use crate::future_signal::{BufferedSignal,SignalId};
 use std::path::Path;
 use toml_loader::{Loader};
 use std::time::{Duration,Instant};
@@ -64,12 +71,18 @@ use tokio::prelude::*;
 use tokio::runtime::{Builder};
 use futures::sync::oneshot;
 use std::sync::{Arc,Mutex};
-use client::{construct_file_client_skip_newline,Amount,RunPeriod,Frequency};
+use client::{construct_file_client_with_gaps,Amount,RunPeriod,Frequency};
 use ndarray::Array2;
 use rustfft::FFTnum;
 use num::Float;
 use ndarray_linalg::Lapack;
-use crate::compression_daemon::CompressionDaemon;
+use crate::compression_daemon::{CompressionDaemon, DRAIN_THRESHOLD};
+use crate::shutdown::ShutdownHandle;
+use crate::wal::{WriteAheadLog, start_wal, DEFAULT_TRUNCATE_INTERVAL};
+use crate::retention::{RetentionPolicy,start_retention,DEFAULT_RETENTION_INTERVAL};
+use crate::catalog::{SignalCatalog,SignalMeta,CatalogCodec};
+use crate::segment::header::element_type_id;
+use crate::segment::validity::Nullable;
 use std::thread;
 use crate::kernel::Kernel;
 use crate::methods::compress::{GZipCompress, ZlibCompress, DeflateCompress, SnappyCompress, CompressionMethod};
@@ -77,12 +90,196 @@ use crate::methods::Methods::Fourier;
 use crate::methods::gorilla_encoder::GorillaEncoder;
 use crate::compress::gorilla::GorillaCompress;
 use crate::compress::sprintz::SprintzDoubleCompress;
+use crate::methods::registry::{codec_by_name, method_with_param};
+use crate::predict::codec_selector::{CodecSelector,QueryMix,AutoCompress};
 
 const DEFAULT_BUF_SIZE: usize = 150;
 const DEFAULT_DELIM: char = '\n';
 
+/* Constructs the file manager the file_handler table of the config
+ * describes, or None when there is no such table. Every file manager
+ * after the first is opened next to it with a suffix on its path, the
+ * compressed segments are kept apart from the raw ones that way.
+ */
+pub fn build_file_manager(config: &toml::Value, suffix: &str) -> Option<BoxedFileManager> {
+	let config = config.lookup("file_handler")?;
+	let fm_type = config.lookup("file_manager").expect("A file manager must be provided");
+	let params = config.lookup("params").expect("A file manager requires parameters");
+	let mut path = String::from(params.lookup("path").expect("The file manager requires a path be provided").as_str().expect("The file path must be provided as string"));
+	path.push_str(suffix);
+	match fm_type.as_str().expect("A file manager must be provided as a string") {
+		#[cfg(feature = "rocksdb")]
+		"Rocks" => {
+			let mut db_opts = rocksdb::Options::default();
+			db_opts.create_if_missing(true);
+			match rocksdb::DB::open(&db_opts, path) {
+				Ok(x) => Some(Box::new(x) as BoxedFileManager),
+				Err(e) => panic!("Failed to create RocksFM object: {:?}", e),
+			}
+		}
+		#[cfg(not(feature = "rocksdb"))]
+		"Rocks" => panic!("The RocksDB file manager needs the rocksdb feature, use file_manager = \"Local\" without it"),
+		"Local" => match LogFileManager::open(path) {
+			Ok(x) => Some(Box::new(x) as BoxedFileManager),
+			Err(e) => panic!("Failed to create LocalFM object: {:?}", e),
+		},
+		x => panic!("File manager type, {:?}, not supported yet", x),
+	}
+}
+
+/* Opens the write ahead log the wal table of the config asks for and
+ * puts back what it still holds. The log is only truncated once a pool
+ * persisted its segments, which pools without a file manager never do,
+ * so a log without one is rejected instead of growing for the whole run.
+ */
+pub fn build_wal<T: Float + Send + 'static>(config: &toml::Value,
+		raw: Option<&Arc<Mutex<SegmentBuffer<T> + Send + Sync>>>,
+		comp: Option<&Arc<Mutex<SegmentBuffer<T> + Send + Sync>>>,
+		catalog: &Arc<Mutex<SignalCatalog>>, shutdown: &mut ShutdownHandle<T>) -> Option<Arc<WriteAheadLog>>
+{
+	let params = config.lookup("wal")?;
+	if config.lookup("file_handler").is_none() {
+		panic!("The write ahead log requires a file manager, the buffers never persist a segment without one so the log is never truncated");
+	}
+	let path = params.lookup("path").expect("The write ahead log requires a path").as_str().expect("The write ahead log path must be provided as a string");
+	let interval = params.lookup("truncate_interval").map_or(DEFAULT_TRUNCATE_INTERVAL, |v| v.as_integer().expect("The truncate interval should be provided as an integer") as u64);
+	Some(start_wal(path, Duration::from_millis(interval), raw.expect("The write ahead log requires a buffer"), comp, Some(catalog), shutdown))
+}
+
+/* Rebuilds what the store holds of every signal before the buffers take
+ * the file managers, with the metadata kept in the file the catalog table
+ * of the config points at
+ */
+pub fn build_catalog(config: &toml::Value, fm: &Option<BoxedFileManager>, fm_comp: &Option<BoxedFileManager>) -> Arc<Mutex<SignalCatalog>> {
+	let mut catalog = SignalCatalog::recover(&fm.iter().chain(fm_comp.iter()).collect::<Vec<_>>()).expect("Failed to recover the signal catalog");
+	if let Some(params) = config.lookup("catalog") {
+		let path = params.lookup("path").expect("The signal catalog requires a path").as_str().expect("The signal catalog path must be provided as a string");
+		catalog = catalog.with_meta_file(path).expect("Failed to load the signal metadata");
+	}
+	println!("{}", catalog);
+	Arc::new(Mutex::new(catalog))
+}
+
+/* Registers the name, tags and codec of a client with the catalog and
+ * returns the id the client goes by, a client without a name keeps its id
+ */
+pub fn register_client(catalog: &Arc<Mutex<SignalCatalog>>, signal_id: SignalId, client_config: &toml::Value, value_type: u8) -> SignalId {
+	let name = match client_config.lookup("name") {
+		Some(name) => name.as_str().expect("The client name must be provided as a string"),
+		None => return signal_id,
+	};
+	let mut meta = SignalMeta::new(name, value_type);
+	if let Some(tags) = client_config.lookup("tags") {
+		for (key, value) in tags.as_table().expect("The client tags must be provided as a TOML table") {
+			meta = meta.with_tag(key, value.as_str().expect("The client tags must be provided as strings"));
+		}
+	}
+	if let Some(codec) = client_config.lookup("codec") {
+		let codec_name = codec.lookup("name").expect("The client codec requires a name").as_str().expect("The client codec name must be provided as a string");
+		let param = codec.lookup("param").map(|v| v.as_float().or(v.as_integer().map(|i| i as f64)).expect("The client codec parameter must be provided as a number"));
+		meta = meta.with_codec(method_with_param(codec_name, param).expect("The client codec is not supported"));
+	}
+	catalog.lock().unwrap().register(signal_id, meta).expect("Failed to register the client in the signal catalog")
+}
+
+/* Starts the retention daemon the retention table of the config asks for */
+pub fn build_retention<T: Copy + Send + 'static>(config: &toml::Value,
+		raw: Option<&Arc<Mutex<SegmentBuffer<T> + Send + Sync>>>,
+		comp: Option<&Arc<Mutex<SegmentBuffer<T> + Send + Sync>>>,
+		catalog: &Arc<Mutex<SignalCatalog>>, shutdown: &mut ShutdownHandle<T>)
+{
+	let params = match config.lookup("retention") {
+		Some(params) => params,
+		None => return,
+	};
+	let interval = params.lookup("interval").map_or(DEFAULT_RETENTION_INTERVAL, |v| v.as_integer().expect("The retention interval should be provided as an integer") as u64);
+	let policy = RetentionPolicy::from_config(params, &catalog.lock().unwrap());
+	start_retention(policy, Duration::from_millis(interval), raw, comp, catalog, shutdown);
+}
+
+fn buffer_type<'a>(config: &'a toml::Value, table: &'a str) -> Option<&'a str> {
+	config.lookup(table).map(|v| v
+					.lookup("type").expect("A buffer type must be provided")
+					.as_str().expect("Buffer type must be provided as a string"))
+}
+
+/* The segments a buffer holds, the shards it is split into and the byte
+ * budget of the sharded buffers, all read from the buffer table
+ */
+fn buffer_sizing(config: &toml::Value) -> (usize, usize, Option<usize>) {
+	let sizing = config.lookup("buffer");
+	let buffer_size = sizing.and_then(|v| v.lookup("buffer_size"))
+					.map_or(DEFAULT_BUF_SIZE, |v| v.as_integer().expect("The buffer size should be provided as an integer") as usize);
+	let shards = sizing.and_then(|v| v.lookup("shards"))
+					.map_or(DEFAULT_SHARDS, |v| v.as_integer().expect("The shard count should be provided as an integer") as usize);
+	let budget = sizing.and_then(|v| v.lookup("budget"))
+					.map(|v| v.as_integer().expect("The buffer budget should be provided as an integer") as usize);
+	(buffer_size, shards, budget)
+}
+
+/* Constructs the buffer the table of the config names, over the file
+ * manager when there is one, or None when there is no such table.
+ * Every buffer is sized by the buffer table, the sharded ones split
+ * buffer_size over their shards and keep to its budget in bytes.
+ */
+pub fn build_buffer<T>(config: &toml::Value, table: &str, fm: Option<BoxedFileManager>) -> Option<Arc<Mutex<SegmentBuffer<T> + Send + Sync>>>
+	where T: Copy + Send + Sync + Serialize + DeserializeOwned + Debug + 'static,
+{
+	let buf_type = buffer_type(config, table)?;
+	let (buffer_size, shards, budget) = buffer_sizing(config);
+	let per_shard = shard_size(buffer_size, shards);
+
+	let buf: Arc<Mutex<SegmentBuffer<T> + Send + Sync>> = match (fm, buf_type) {
+		(Some(fm), "Clock") => Arc::new(Mutex::new(ClockBuffer::<T,BoxedFileManager>::new(buffer_size,fm))),
+		(Some(fm), "ShardedClock") => {
+			let fm = Arc::new(fm);
+			Arc::new(Mutex::new(ShardedBuffer::<T,_>::with_shards(shards, budget, |_| ClockBuffer::<T,Arc<BoxedFileManager>>::new(per_shard,fm.clone()))))
+		}
+		(Some(fm), "ARC") => Arc::new(Mutex::new(ArcBuffer::<T,BoxedFileManager>::new(buffer_size,fm))),
+		(Some(fm), "2Q") => Arc::new(Mutex::new(TwoQueueBuffer::<T,BoxedFileManager>::new(buffer_size,fm))),
+		(None, "NoFmClock") => Arc::new(Mutex::new(NoFmClockBuffer::<T>::new(buffer_size))),
+		(None, "ShardedNoFmClock") => Arc::new(Mutex::new(ShardedBuffer::<T,_>::with_shards(shards, budget, |_| NoFmClockBuffer::<T>::new(per_shard)))),
+		(Some(_), x) => panic!("The buffer type, {:?}, is not currently supported to run with a file manager", x),
+		(None, x) => panic!("The buffer type, {:?}, is not currently supported to run without a file manager", x),
+	};
+	Some(buf)
+}
+
+/* The bytes the raw buffer holds once the compression daemons drain it,
+ * they wait for it to be DRAIN_THRESHOLD full and to hold a batch
+ */
+fn drain_bytes<T>(config: &toml::Value, seg_size: usize, batch: usize) -> usize {
+	let (buffer_size, _, budget) = buffer_sizing(config);
+	let seg_bytes = seg_size * std::mem::size_of::<T>();
+	let sharded = buffer_type(config, "buffer").map_or(false, |t| t.starts_with("Sharded"));
+	let fill_bytes = match budget {
+		/* The sharded buffers measure their fill against the byte budget */
+		Some(budget) if sharded => (DRAIN_THRESHOLD * budget as f32).ceil() as usize,
+		_ => (DRAIN_THRESHOLD * buffer_size as f32).ceil() as usize * seg_bytes,
+	};
+	std::cmp::max(fill_bytes, batch * seg_bytes)
+}
+
+/* Sets the memory budget of the governor from the buffer table. Signals
+ * are parked while the budget is exceeded and only the compression
+ * daemons release memory, so a budget the raw buffer exceeds before
+ * they start draining it would never be released and is rejected.
+ */
+pub fn set_memory_budget<T>(config: &toml::Value, seg_size: usize, batch: usize) {
+	let memory_budget = config.lookup("buffer")
+					.and_then(|v| v.lookup("memory_budget"))
+					.map(|v| v.as_integer().expect("The memory budget should be provided as an integer") as usize);
+	if let Some(memory_budget) = memory_budget {
+		let drain_bytes = drain_bytes::<T>(config, seg_size, batch);
+		if memory_budget < drain_bytes {
+			panic!("The memory budget, {} bytes, is below the {} bytes the raw buffer holds before the compression daemons drain it", memory_budget, drain_bytes);
+		}
+	}
+	governor().set_budget(memory_budget);
+}
+
 pub fn run_test<T: 'static>(config_file: &str)
-	where T: Copy + Send + Sync + Serialize + DeserializeOwned + Debug + FFTnum + Into<f64> + Float + Lapack + FromStr + From<f32>,
+	where T: Copy + Send + Sync + Serialize + DeserializeOwned + Debug + FFTnum + Into<f64> + Float + Lapack + FromStr + From<f32> + Nullable,
 //		  f64: std::convert::From<T>,
 //		  f32: std::convert::From<T>
 
@@ -101,112 +298,21 @@ pub fn run_test<T: 'static>(config_file: &str)
 					.expect("The segment size argument must be provided as an integer") as usize;
 
 
-	/* Construct the file manager to be used */
-	let fm = match config.lookup("file_handler") {
-		Some (config) => {
-			let fm_type = config.lookup("file_manager").expect("A file manager must be provided");
-			match fm_type.as_str().expect("A file manager must be provided as a string") {
-				"Rocks" => {
-					let params = config.lookup("params").expect("A RocksDB file manager requires parameters");
-					let path = params.lookup("path").expect("RocksDB requires a path be provided").as_str().expect("Rocks file path must be provided as string");
-					let mut db_opts = rocksdb::Options::default();
-					db_opts.create_if_missing(true);
-					match rocksdb::DB::open(&db_opts, path) {
-						Ok(x) => Some(Box::new(x)),
-						Err(e) => panic!("Failed to create RocksFM object: {:?}", e),
-					}
-				}
-				x => panic!("File manager type, {:?}, not supported yet", x),
-			}
-		}
-		None => None,
-	};
+	/* Construct the file managers to be used, the compressed segments get one of their own */
+	let fm = build_file_manager(&config, "");
+	let fm_comp = build_file_manager(&config, "comp");
 
+	/* Rebuild what the store holds of every signal before the buffers take the file managers */
+	let catalog = build_catalog(&config, &fm, &fm_comp);
 
-	/* Construct the file manager for compression to be used */
-	let fm_comp = match config.lookup("file_handler") {
-		Some (config) => {
-			let fm_type = config.lookup("file_manager").expect("A file manager must be provided");
-			match fm_type.as_str().expect("A file manager must be provided as a string") {
-				"Rocks" => {
-					let params = config.lookup("params").expect("A RocksDB file manager requires parameters");
-					let path = params.lookup("path").expect("RocksDB requires a path be provided").as_str().expect("Rocks file path must be provided as string");
-					let mut comp_path = String::from(path);
-					comp_path.push_str("comp");
-					let new_path = comp_path.as_str();
-					let mut db_opts = rocksdb::Options::default();
-					db_opts.create_if_missing(true);
-					match rocksdb::DB::open(&db_opts, new_path) {
-						Ok(x) => Some(Box::new(x)),
-						Err(e) => panic!("Failed to create RocksFM object: {:?}", e),
-					}
-				}
-				x => panic!("File manager type, {:?}, not supported yet", x),
-			}
-		}
-		None => None,
-	};
+	/* Construct the buffers to be used, the compressed segments get one of their own */
+	let buf_option: Option<Box<Arc<Mutex<(SegmentBuffer<T> + Send + Sync)>>>> = build_buffer(&config, "buffer", fm).map(Box::new);
+	let compre_buf_option: Option<Box<Arc<Mutex<(SegmentBuffer<T> + Send + Sync)>>>> = build_buffer(&config, "buffer", fm_comp).map(Box::new);
 
-	/* Construct the buffer to be used */
-	let buffer_size = match config.lookup("buffer") {
-		Some(value) => value.lookup("buffer_size").map_or(DEFAULT_BUF_SIZE, |v| v.as_integer().expect("The buffer size should be provided as an integer") as usize),
-		None => DEFAULT_BUF_SIZE,
-	};
+	let mut shutdown = ShutdownHandle::new();
 
-	let buf_option: Option<Box<Arc<Mutex<(SegmentBuffer<T> + Send + Sync)>>>> = match fm {
-		Some(fm) => {
-			match config.lookup("buffer") {
-					Some(config) => {
-						let buf_type = config.lookup("type").expect("A buffer type must be provided");
-						match buf_type.as_str().expect("Buffer type must be provided as string") {
-							"Clock" => Some(Box::new(Arc::new(Mutex::new(ClockBuffer::<T,rocksdb::DB>::new(buffer_size,*fm))))),
-							x => panic!("The buffer type, {:?}, is not currently supported to run with a file manager", x),
-						}
-					}
-					None => None,
-			}
-		}
-		None => {
-			match config.lookup("buffer") {
-				Some(config) => {
-					let buf_type = config.lookup("type").expect("A buffer type must be provided");
-					match buf_type.as_str().expect("Buffer type must be provided as a string") {
-						"NoFmClock" => Some(Box::new(Arc::new(Mutex::new(NoFmClockBuffer::<T>::new(buffer_size))))),
-						x => panic!("The buffer type, {:?}, is not currently supported to run without a file manager", x),
-					}
-				}
-				None => None,
-			}
-		}
-	};
-
-    /* Create buffer for compression segments*/
-    let compre_buf_option: Option<Box<Arc<Mutex<(SegmentBuffer<T> + Send + Sync)>>>> = match fm_comp {
-        Some(fm) => {
-            match config.lookup("buffer") {
-                Some(config) => {
-                    let buf_type = config.lookup("type").expect("A buffer type must be provided");
-                    match buf_type.as_str().expect("Buffer type must be provided as string") {
-                        "Clock" => Some(Box::new(Arc::new(Mutex::new(ClockBuffer::<T,rocksdb::DB>::new(buffer_size,*fm))))),
-                        x => panic!("The buffer type, {:?}, is not currently supported to run with a file manager", x),
-                    }
-                }
-                None => None,
-            }
-        }
-        None => {
-            match config.lookup("buffer") {
-                Some(config) => {
-                    let buf_type = config.lookup("type").expect("A buffer type must be provided");
-                    match buf_type.as_str().expect("Buffer type must be provided as a string") {
-                        "NoFmClock" => Some(Box::new(Arc::new(Mutex::new(NoFmClockBuffer::<T>::new(buffer_size))))),
-                        x => panic!("The buffer type, {:?}, is not currently supported to run without a file manager", x),
-                    }
-                }
-                None => None,
-            }
-        }
-    };
+	/* Put back what the write ahead log still holds before new points arrive */
+	let wal = build_wal(&config, buf_option.as_ref().map(|buf| &**buf), compre_buf_option.as_ref().map(|buf| &**buf), &catalog, &mut shutdown);
 
 	/* Construct the clients */
 	let mut signals: Vec<Box<(Future<Item=Option<SystemTime>,Error=()> + Send + Sync)>> = Vec::new();
@@ -225,6 +331,9 @@ pub fn run_test<T: 'static>(config_file: &str)
 			signal_id = x.as_integer().expect("If an ID for a client is provided it must be supplied as an integer") as u64;
 		}
 
+		/* A named client keeps the id the catalog gave its name in an earlier run */
+		signal_id = register_client(&catalog, signal_id, client_config, element_type_id::<T>());
+
 		let client_type = client_config.lookup("type").expect("The client type must be provided");
 
 		let amount = match client_config.lookup("amount") {
@@ -325,7 +434,7 @@ pub fn run_test<T: 'static>(config_file: &str)
 							None => 0,
 						};
 					;
-						Box::new(construct_file_client_skip_newline::<T>(path, skip_val, delim, amount, run_period, frequency).expect("Client could not be properly produced"))
+						Box::new(construct_file_client_with_gaps::<T>(path, skip_val, delim, amount, run_period, frequency).expect("Client could not be properly produced"))
 					}
 					"DeserializeDelim" => Box::new(construct_file_client::<T>(path, delim as u8, amount, run_period, frequency).expect("Client could not be properly produced")),
 					x => panic!("The specified file reader, {:?}, is not supported yet", x),
@@ -334,7 +443,7 @@ pub fn run_test<T: 'static>(config_file: &str)
 
 
 				match &buf_option {
-					Some(buf) => signals.push(Box::new(BufferedSignal::new(signal_id, client, seg_size, *buf.clone(), |i,j| i >= j, |_| (), false,dict))),
+					Some(buf) => signals.push(Box::new(BufferedSignal::new(signal_id, client, seg_size, share_buffer(buf), |i,j| i >= j, |_| (), false,dict).with_shutdown(shutdown.signal()).with_wal(wal.clone()).with_catalog(Some(catalog.clone())))),
 					None => panic!("Buffer and File manager provided not supported yet"),
 				}
 			}
@@ -383,7 +492,7 @@ pub fn run_test<T: 'static>(config_file: &str)
 					x => panic!("The provided generator type, {:?}, is not currently supported", x),
 				};
 				match &buf_option {
-					Some(buf) => signals.push(Box::new(BufferedSignal::new(signal_id, client, seg_size, *buf.clone(), |i,j| i >= j, |_| (), false, None))),
+					Some(buf) => signals.push(Box::new(BufferedSignal::new(signal_id, client, seg_size, share_buffer(buf), |i,j| i >= j, |_| (), false, None).with_shutdown(shutdown.signal()).with_wal(wal.clone()).with_catalog(Some(catalog.clone())))),
 					None => panic!("Buffer and File manager provided not supported yet"),
 				}
 			}
@@ -402,11 +511,18 @@ pub fn run_test<T: 'static>(config_file: &str)
 	let mut kernel = Kernel::new(testdict.clone().unwrap(),1,4,30);
 	kernel.dict_pre_process();
 
-//    let mut compress_daemon:CompressionDaemon<_,DB,_> = CompressionDaemon::new(*buf_option.unwrap().clone(),*compre_buf_option.unwrap().clone(),None,0.1,0.1,|x|(paa_compress(x,50)));
-// 	let mut compress_daemon:CompressionDaemon<_,DB,_> = CompressionDaemon::new(*buf.unwrap(),*comp_buf.unwrap(),None,0.1,0.1,kernel);
-	// let mut compress_daemon:CompressionDaemon<_,DB,_> = CompressionDaemon::new(*buf.unwrap(),*comp_buf.unwrap(),None,0.1,0.1,PAACompress::new(10,10));
-	let mut compress_daemon:CompressionDaemon<_,DB,_> = CompressionDaemon::new(*buf.unwrap(),*comp_buf.unwrap(),None,0.1,0.1,FourierCompress::new(10,1,1.0));
-//	let mut compress_daemon2:CompressionDaemon<_,DB,_> = CompressionDaemon::new(*buf2.unwrap(),*comp_buf2.unwrap(),None,0.1,0.1,FourierCompress::new(10,1));
+//    let mut compress_daemon:CompressionDaemon<_,BoxedFileManager,_> = CompressionDaemon::new(*buf_option.unwrap().clone(),*compre_buf_option.unwrap().clone(),None,0.1,0.1,|x|(paa_compress(x,50)));
+// 	let mut compress_daemon:CompressionDaemon<_,BoxedFileManager,_> = CompressionDaemon::new(*buf.unwrap(),*comp_buf.unwrap(),None,0.1,0.1,kernel);
+	// let mut compress_daemon:CompressionDaemon<_,BoxedFileManager,_> = CompressionDaemon::new(*buf.unwrap(),*comp_buf.unwrap(),None,0.1,0.1,PAACompress::new(10,10));
+	let batch = 1;
+	let mut compress_daemon:CompressionDaemon<_,BoxedFileManager,_> = CompressionDaemon::new(share_buffer(&buf.unwrap()),share_buffer(&comp_buf.unwrap()),None,DRAIN_THRESHOLD,0.1,CatalogCodec::new(FourierCompress::new(10,batch,1.0), catalog.clone()));
+	compress_daemon.set_shutdown(shutdown.signal());
+	/* A budget over the heap bytes of every buffer, the signals wait while it is exceeded */
+	set_memory_budget::<T>(&config, seg_size, batch);
+//	let mut compress_daemon2:CompressionDaemon<_,BoxedFileManager,_> = CompressionDaemon::new(*buf2.unwrap(),*comp_buf2.unwrap(),None,0.1,0.1,FourierCompress::new(10,1));
+
+	/* Expire the segments that are past the retention rules of their signals */
+	build_retention(&config, buf_option.as_ref().map(|buf| &**buf), compre_buf_option.as_ref().map(|buf| &**buf), &catalog, &mut shutdown);
 
 	/* Construct the runtime */
 	let rt = match config.lookup("runtime") {
@@ -440,6 +556,7 @@ pub fn run_test<T: 'static>(config_file: &str)
 		compress_daemon.run();
 		println!("segment commpressed: {}", compress_daemon.get_processed() );
 	});
+	shutdown.add_daemon(handle);
 
 	let executor = rt.executor();
 
@@ -470,20 +587,38 @@ pub fn run_test<T: 'static>(config_file: &str)
 		}
 	}
 
-	// handle.join().unwrap();
 	//handle1.join().unwrap();
 	//handle2.join().unwrap();
 
+	/* Drain the daemons and persist both pools so a restart finds every segment */
+	for buf in vec![&buf_option, &compre_buf_option] {
+		if let Some(buf) = buf {
+			shutdown.add_pool(buf);
+		}
+	}
+	match shutdown.shutdown() {
+		Ok(()) => (),
+		Err(e) => println!("Failed to persist the buffers: {:?}", e),
+	}
+
 	match rt.shutdown_on_idle().wait() {
 		Ok(_) => (),
 		Err(_) => panic!("Failed to shutdown properly"),
 	}
 
+	/* Lookups each buffer answered from memory, to compare the eviction policies */
+	for (name, buf) in vec![("buffer", &buf_option), ("comp buffer", &compre_buf_option)] {
+		if let Some(buf) = buf {
+			println!("{} {}", name, buf.lock().unwrap().get_cache_stats());
+		}
+	}
+	println!("{:?}", governor());
+
 }
 
 
 pub fn run_single_test<T: 'static>(config_file: &str, comp:&str, num_comp:i32)
-	where T: Copy + Send + Sync + Serialize + DeserializeOwned + Debug + FFTnum + Into<f64>  + Float + Lapack + FromStr + From<f32>,
+	where T: Copy + Send + Sync + Serialize + DeserializeOwned + Debug + FFTnum + Into<f64>  + Float + Lapack + FromStr + From<f32> + Nullable,
 {
 
 	let config = match Loader::from_file(Path::new(config_file)) {
@@ -499,112 +634,21 @@ pub fn run_single_test<T: 'static>(config_file: &str, comp:&str, num_comp:i32)
 		.expect("The segment size argument must be provided as an integer") as usize;
 
 
-	/* Construct the file manager to be used */
-	let fm = match config.lookup("file_handler") {
-		Some (config) => {
-			let fm_type = config.lookup("file_manager").expect("A file manager must be provided");
-			match fm_type.as_str().expect("A file manager must be provided as a string") {
-				"Rocks" => {
-					let params = config.lookup("params").expect("A RocksDB file manager requires parameters");
-					let path = params.lookup("path").expect("RocksDB requires a path be provided").as_str().expect("Rocks file path must be provided as string");
-					let mut db_opts = rocksdb::Options::default();
-					db_opts.create_if_missing(true);
-					match rocksdb::DB::open(&db_opts, path) {
-						Ok(x) => Some(Box::new(x)),
-						Err(e) => panic!("Failed to create RocksFM object: {:?}", e),
-					}
-				}
-				x => panic!("File manager type, {:?}, not supported yet", x),
-			}
-		}
-		None => None,
-	};
-
+	/* Construct the file managers to be used, the compressed segments get one of their own */
+	let fm = build_file_manager(&config, "");
+	let fm_comp = build_file_manager(&config, "comp");
 
-	/* Construct the file manager for compression to be used */
-	let fm_comp = match config.lookup("file_handler") {
-		Some (config) => {
-			let fm_type = config.lookup("file_manager").expect("A file manager must be provided");
-			match fm_type.as_str().expect("A file manager must be provided as a string") {
-				"Rocks" => {
-					let params = config.lookup("params").expect("A RocksDB file manager requires parameters");
-					let path = params.lookup("path").expect("RocksDB requires a path be provided").as_str().expect("Rocks file path must be provided as string");
-					let mut comp_path = String::from(path);
-					comp_path.push_str("comp");
-					let new_path = comp_path.as_str();
-					let mut db_opts = rocksdb::Options::default();
-					db_opts.create_if_missing(true);
-					match rocksdb::DB::open(&db_opts, new_path) {
-						Ok(x) => Some(Box::new(x)),
-						Err(e) => panic!("Failed to create RocksFM object: {:?}", e),
-					}
-				}
-				x => panic!("File manager type, {:?}, not supported yet", x),
-			}
-		}
-		None => None,
-	};
+	/* Rebuild what the store holds of every signal before the buffers take the file managers */
+	let catalog = build_catalog(&config, &fm, &fm_comp);
 
-	/* Construct the buffer to be used */
-	let buffer_size = match config.lookup("buffer") {
-		Some(value) => value.lookup("buffer_size").map_or(DEFAULT_BUF_SIZE, |v| v.as_integer().expect("The buffer size should be provided as an integer") as usize),
-		None => DEFAULT_BUF_SIZE,
-	};
+	/* Construct the buffers to be used, the compressed segments get one of their own */
+	let buf_option: Option<Box<Arc<Mutex<(SegmentBuffer<T> + Send + Sync)>>>> = build_buffer(&config, "buffer", fm).map(Box::new);
+	let compre_buf_option: Option<Box<Arc<Mutex<(SegmentBuffer<T> + Send + Sync)>>>> = build_buffer(&config, "buffer", fm_comp).map(Box::new);
 
-	let buf_option: Option<Box<Arc<Mutex<(SegmentBuffer<T> + Send + Sync)>>>> = match fm {
-		Some(fm) => {
-			match config.lookup("buffer") {
-				Some(config) => {
-					let buf_type = config.lookup("type").expect("A buffer type must be provided");
-					match buf_type.as_str().expect("Buffer type must be provided as string") {
-						"Clock" => Some(Box::new(Arc::new(Mutex::new(ClockBuffer::<T,rocksdb::DB>::new(buffer_size,*fm))))),
-						x => panic!("The buffer type, {:?}, is not currently supported to run with a file manager", x),
-					}
-				}
-				None => None,
-			}
-		}
-		None => {
-			match config.lookup("buffer") {
-				Some(config) => {
-					let buf_type = config.lookup("type").expect("A buffer type must be provided");
-					match buf_type.as_str().expect("Buffer type must be provided as a string") {
-						"NoFmClock" => Some(Box::new(Arc::new(Mutex::new(NoFmClockBuffer::<T>::new(buffer_size))))),
-						x => panic!("The buffer type, {:?}, is not currently supported to run without a file manager", x),
-					}
-				}
-				None => None,
-			}
-		}
-	};
+	let mut shutdown = ShutdownHandle::new();
 
-	/* Create buffer for compression segments*/
-	let compre_buf_option: Option<Box<Arc<Mutex<(SegmentBuffer<T> + Send + Sync)>>>> = match fm_comp {
-		Some(fm) => {
-			match config.lookup("buffer") {
-				Some(config) => {
-					let buf_type = config.lookup("type").expect("A buffer type must be provided");
-					match buf_type.as_str().expect("Buffer type must be provided as string") {
-						"Clock" => Some(Box::new(Arc::new(Mutex::new(ClockBuffer::<T,rocksdb::DB>::new(buffer_size,*fm))))),
-						x => panic!("The buffer type, {:?}, is not currently supported to run with a file manager", x),
-					}
-				}
-				None => None,
-			}
-		}
-		None => {
-			match config.lookup("buffer") {
-				Some(config) => {
-					let buf_type = config.lookup("type").expect("A buffer type must be provided");
-					match buf_type.as_str().expect("Buffer type must be provided as a string") {
-						"NoFmClock" => Some(Box::new(Arc::new(Mutex::new(NoFmClockBuffer::<T>::new(buffer_size))))),
-						x => panic!("The buffer type, {:?}, is not currently supported to run without a file manager", x),
-					}
-				}
-				None => None,
-			}
-		}
-	};
+	/* Put back what the write ahead log still holds before new points arrive */
+	let wal = build_wal(&config, buf_option.as_ref().map(|buf| &**buf), compre_buf_option.as_ref().map(|buf| &**buf), &catalog, &mut shutdown);
 
 	/* Construct the clients */
 	let mut signals: Vec<Box<(Future<Item=Option<SystemTime>,Error=()> + Send + Sync)>> = Vec::new();
@@ -623,6 +667,9 @@ pub fn run_single_test<T: 'static>(config_file: &str, comp:&str, num_comp:i32)
 				signal_id = x.as_integer().expect("If an ID for a client is provided it must be supplied as an integer") as u64;
 			}
 
+			/* A named client keeps the id the catalog gave its name in an earlier run */
+			signal_id = register_client(&catalog, signal_id, client_config, element_type_id::<T>());
+
 			let client_type = client_config.lookup("type").expect("The client type must be provided");
 
 			let amount = match client_config.lookup("amount") {
@@ -722,7 +769,7 @@ pub fn run_single_test<T: 'static>(config_file: &str, comp:&str, num_comp:i32)
 								Some(skip_val) => skip_val.as_integer().expect("The skip value must be provided as an integer") as usize,
 								None => 0,
 							};
-							Box::new(construct_file_client_skip_newline::<T>(path, skip_val, delim, amount, run_period, frequency).expect("Client could not be properly produced"))
+							Box::new(construct_file_client_with_gaps::<T>(path, skip_val, delim, amount, run_period, frequency).expect("Client could not be properly produced"))
 						}
 						"DeserializeDelim" => Box::new(construct_file_client::<T>(path, delim as u8, amount, run_period, frequency).expect("Client could not be properly produced")),
 						x => panic!("The specified file reader, {:?}, is not supported yet", x),
@@ -731,7 +778,7 @@ pub fn run_single_test<T: 'static>(config_file: &str, comp:&str, num_comp:i32)
 
 
 					match &buf_option {
-						Some(buf) => signals.push(Box::new(BufferedSignal::new(signal_id, client, seg_size, *buf.clone(), |i,j| i >= j, |_| (), false,dict))),
+						Some(buf) => signals.push(Box::new(BufferedSignal::new(signal_id, client, seg_size, share_buffer(buf), |i,j| i >= j, |_| (), false,dict).with_shutdown(shutdown.signal()).with_wal(wal.clone()).with_catalog(Some(catalog.clone())))),
 						None => panic!("Buffer and File manager provided not supported yet"),
 					}
 				}
@@ -780,7 +827,7 @@ pub fn run_single_test<T: 'static>(config_file: &str, comp:&str, num_comp:i32)
 							x => panic!("The provided generator type, {:?}, is not currently supported", x),
 						};
 					match &buf_option {
-						Some(buf) => signals.push(Box::new(BufferedSignal::new(signal_id, client, seg_size, *buf.clone(), |i,j| i >= j, |_| (), false, None))),
+						Some(buf) => signals.push(Box::new(BufferedSignal::new(signal_id, client, seg_size, share_buffer(buf), |i,j| i >= j, |_| (), false, None).with_shutdown(shutdown.signal()).with_wal(wal.clone()).with_catalog(Some(catalog.clone())))),
 						None => panic!("Buffer and File manager provided not supported yet"),
 					}
 				}
@@ -792,7 +839,37 @@ pub fn run_single_test<T: 'static>(config_file: &str, comp:&str, num_comp:i32)
 
 	let batch = 20;
 
-
+	/* A budget over the heap bytes of every buffer, the signals wait while it is exceeded */
+	set_memory_budget::<T>(&config, seg_size, batch);
+
+	/* Expire the segments that are past the retention rules of their signals */
+	build_retention(&config, buf_option.as_ref().map(|buf| &**buf), compre_buf_option.as_ref().map(|buf| &**buf), &catalog, &mut shutdown);
+
+	/* Query mixes the auto compression weighs the codecs of each signal with */
+	let mut codec_selector = CodecSelector::new(QueryMix::default());
+	if let Some(params) = config.lookup("selector") {
+		let default = QueryMix::parse(
+			params.lookup("storage").map(|v| v.as_float().expect("Query mix weights must be provided as floats")),
+			params.lookup("ingest").map(|v| v.as_float().expect("Query mix weights must be provided as floats")),
+			params.lookup("read").map(|v| v.as_float().expect("Query mix weights must be provided as floats")),
+			QueryMix::default())
+			.expect("Failed to parse the default query mix");
+		codec_selector = CodecSelector::new(default);
+		if let Some(mixes) = params.lookup("signals") {
+			/* Mixes are keyed by signal selectors, the weights they leave out come from the default mix */
+			for (selector, mix) in mixes.as_table().expect("Per signal query mixes must be provided as a TOML table") {
+				let mix = QueryMix::parse(
+					mix.lookup("storage").map(|v| v.as_float().expect("Query mix weights must be provided as floats")),
+					mix.lookup("ingest").map(|v| v.as_float().expect("Query mix weights must be provided as floats")),
+					mix.lookup("read").map(|v| v.as_float().expect("Query mix weights must be provided as floats")),
+					default)
+					.expect("Failed to parse a signal query mix");
+				for signal in catalog.lock().unwrap().resolve(selector) {
+					codec_selector.set(signal, mix);
+				}
+			}
+		}
+	}
 
 	/* Construct the runtime */
 	let rt = match config.lookup("runtime") {
@@ -825,26 +902,14 @@ pub fn run_single_test<T: 'static>(config_file: &str, comp:&str, num_comp:i32)
 
 	for x in 0..num_comp {
 		match comp{
-			"paa" => {
-				let mut compress_daemon:CompressionDaemon<_,DB,_> = CompressionDaemon::new(*(buf_option.clone().unwrap()),*(compre_buf_option.clone().unwrap()),None,0.1,0.0,PAACompress::new(10,batch));
-				let handle = thread::spawn(move || {
-					println!("Run compression demon" );
-					compress_daemon.run();
-					println!("segment commpressed: {}", compress_daemon.get_processed() );
-				});
-				comp_handlers.push(handle);
-			},
-			"fourier" => {
-				let mut compress_daemon: CompressionDaemon<_, DB, _> = CompressionDaemon::new(*(buf_option.clone().unwrap()), *(compre_buf_option.clone().unwrap()), None, 0.1, 0.0, FourierCompress::new(10, batch,1.0));
-				let handle = thread::spawn(move || {
-					println!("Run compression demon" );
-					compress_daemon.run();
-					println!("segment commpressed: {}", compress_daemon.get_processed() );
-				});
-				comp_handlers.push(handle);
-			}
-			"snappy" => {
-				let mut compress_daemon: CompressionDaemon<_, DB, _> = CompressionDaemon::new(*(buf_option.clone().unwrap()), *(compre_buf_option.clone().unwrap()), None, 0.1, 0.0, SnappyCompress::new(10, batch));
+			"kernel" => {
+				let mut knl = Kernel::new(array![[T::one(), T::one()],[T::one(), T::one()]],1,4,30);
+				if testdict != None{
+					knl = Kernel::new(testdict.clone().unwrap(),1,4,30);
+					knl.rbfdict_pre_process();
+				}
+				let mut compress_daemon:CompressionDaemon<_,BoxedFileManager,_> = CompressionDaemon::new(share_buffer(buf_option.as_ref().unwrap()),share_buffer(compre_buf_option.as_ref().unwrap()),None,DRAIN_THRESHOLD,0.0,CatalogCodec::new(knl, catalog.clone()));
+				compress_daemon.set_shutdown(shutdown.signal());
 				let handle = thread::spawn(move || {
 					println!("Run compression demon" );
 					compress_daemon.run();
@@ -852,40 +917,29 @@ pub fn run_single_test<T: 'static>(config_file: &str, comp:&str, num_comp:i32)
 				});
 				comp_handlers.push(handle);
 			}
-			// "sprintz" => {
-			// 	let mut compress_daemon: CompressionDaemon<_, DB, _> = CompressionDaemon::new(*(buf_option.clone().unwrap()), *(compre_buf_option.clone().unwrap()), None, 0.1, 0.0, SprintzDoubleCompress::new(10, batch,10000));
-			// 	let handle = thread::spawn(move || {
-			// 		println!("Run compression demon" );
-			// 		compress_daemon.run();
-			// 		println!("segment commpressed: {}", compress_daemon.get_processed() );
-			// 	});
-			// 	comp_handlers.push(handle);
-			// }
-			"gzip" => {
-				let mut compress_daemon:CompressionDaemon<_,DB,_> = CompressionDaemon::new(*(buf_option.clone().unwrap()),*(compre_buf_option.clone().unwrap()),None,0.1,0.0,GZipCompress::new(10,batch));
+			"auto" => {
+				let codec = AutoCompress::new(batch, codec_selector.clone());
+				let mut compress_daemon:CompressionDaemon<_,BoxedFileManager,_> = CompressionDaemon::new(share_buffer(buf_option.as_ref().unwrap()),share_buffer(compre_buf_option.as_ref().unwrap()),None,DRAIN_THRESHOLD,0.0,CatalogCodec::new(codec, catalog.clone()));
+				compress_daemon.set_shutdown(shutdown.signal());
 				let handle = thread::spawn(move || {
-					println!("Run compression demon" );
+					println!("Run auto compression demon");
 					compress_daemon.run();
 					println!("segment commpressed: {}", compress_daemon.get_processed() );
 				});
 				comp_handlers.push(handle);
 			}
-
-			"kernel" => {
-				let mut knl = Kernel::new(array![[T::one(), T::one()],[T::one(), T::one()]],1,4,30);
-				if testdict != None{
-					knl = Kernel::new(testdict.clone().unwrap(),1,4,30);
-					knl.rbfdict_pre_process();
-				}
-				let mut compress_daemon:CompressionDaemon<_,DB,_> = CompressionDaemon::new(*(buf_option.clone().unwrap()),*(compre_buf_option.clone().unwrap()),None,0.1,0.0,knl);
+			name => {
+				let codec = codec_by_name(name, batch).expect("Compression not supported yet.");
+				let label = codec.method().to_string();
+				let mut compress_daemon:CompressionDaemon<_,BoxedFileManager,_> = CompressionDaemon::new(share_buffer(buf_option.as_ref().unwrap()),share_buffer(compre_buf_option.as_ref().unwrap()),None,DRAIN_THRESHOLD,0.0,CatalogCodec::new(codec, catalog.clone()));
+				compress_daemon.set_shutdown(shutdown.signal());
 				let handle = thread::spawn(move || {
-					println!("Run compression demon" );
+					println!("Run {} compression demon", label);
 					compress_daemon.run();
 					println!("segment commpressed: {}", compress_daemon.get_processed() );
 				});
 				comp_handlers.push(handle);
 			}
-			_ => {panic!("Compression not supported yet.")}
 		}
 
 	}
@@ -900,7 +954,7 @@ pub fn run_single_test<T: 'static>(config_file: &str, comp:&str, num_comp:i32)
 	}
 
 	for comp in comp_handlers {
-		// comp.join().unwrap();
+		shutdown.add_daemon(comp);
 	}
 
 
@@ -912,10 +966,29 @@ pub fn run_single_test<T: 'static>(config_file: &str, comp:&str, num_comp:i32)
 		}
 	}
 
+	/* Drain the daemons and persist both pools so a restart finds every segment */
+	for buf in vec![&buf_option, &compre_buf_option] {
+		if let Some(buf) = buf {
+			shutdown.add_pool(buf);
+		}
+	}
+	match shutdown.shutdown() {
+		Ok(()) => (),
+		Err(e) => println!("Failed to persist the buffers: {:?}", e),
+	}
+
 	// Wait until the runtime becomes idle and shut it down.
 	match rt.shutdown_on_idle().wait() {
 		Ok(_) => (),
 		Err(_) => panic!("Failed to shutdown properly"),
 	}
 
+	/* Lookups each buffer answered from memory, to compare the eviction policies */
+	for (name, buf) in vec![("buffer", &buf_option), ("comp buffer", &compre_buf_option)] {
+		if let Some(buf) = buf {
+			println!("{} {}", name, buf.lock().unwrap().get_cache_stats());
+		}
+	}
+	println!("{:?}", governor());
+
 }
\ No newline at end of file
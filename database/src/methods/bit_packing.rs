@@ -76,7 +76,7 @@ impl<'a> BitPack<&'a mut [u8]> {
             return Err(bits);
         }
         if bits < MAX_BITS {
-            value &= ((1 << bits) - 1) as u32;
+            value &= ((1u64 << bits) - 1) as u32;
         }
 
         loop {
@@ -187,6 +187,22 @@ impl<'a> BitPack<&'a [u8]> {
         Ok(output)
     }
 
+    /* Reads up to 64 bits written by write_u64 */
+    pub fn read_u64(&mut self, bits: usize) -> Result<u64, usize> {
+        if bits > 2 * MAX_BITS {
+            return Err(bits);
+        }
+        if bits == 0 {
+            return Ok(0);
+        }
+        if bits <= MAX_BITS {
+            return Ok(self.read(bits)? as u64);
+        }
+        let low = self.read(MAX_BITS)? as u64;
+        let high = self.read(bits - MAX_BITS)? as u64;
+        Ok(low | high << MAX_BITS)
+    }
+
     /***
     read bits less than BYTE_BITS
      */
@@ -360,6 +376,21 @@ impl BitPack<Vec<u8>> {
 
 
 
+    /* Writes up to 64 bits, the low 32 bits first */
+    pub fn write_u64(&mut self, value: u64, bits: usize) -> Result<(), usize> {
+        if bits > 2 * MAX_BITS {
+            return Err(bits);
+        }
+        if bits == 0 {
+            return Ok(());
+        }
+        if bits <= MAX_BITS {
+            return self.write(value as u32, bits);
+        }
+        self.write(value as u32, MAX_BITS)?;
+        self.write((value >> MAX_BITS) as u32, bits - MAX_BITS)
+    }
+
     #[inline]
     pub fn write_bytes(&mut self, value: &mut Vec<u8>) -> Result<(), usize> {
         self.buff.append(value);
@@ -388,6 +419,65 @@ impl BitPack<Vec<u8>> {
     }
 }
 
+/* Values bit packed as they arrive, at the width of the widest one so far.
+ * A wider value repacks what is there, so the width only grows and the
+ * buffer always holds the values at the width they end up with. Widening
+ * is rare once the first points have set the range.
+ */
+#[derive(Clone, Debug, Default)]
+pub struct GrowingPack {
+    pack: BitPack<Vec<u8>>,
+    width: usize,
+    count: usize,
+}
+
+impl GrowingPack {
+    pub fn new() -> Self {
+        GrowingPack::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn push(&mut self, value: u64) {
+        let width = 64 - value.leading_zeros() as usize;
+        if width > self.width {
+            self.widen(width);
+        }
+        self.pack.write_u64(value, self.width).unwrap();
+        self.count += 1;
+    }
+
+    fn widen(&mut self, width: usize) {
+        let mut wider = BitPack::<Vec<u8>>::with_capacity((self.count + 1) * width / BYTE_BITS + 1);
+        for value in self.values() {
+            wider.write_u64(value, width).unwrap();
+        }
+        self.pack = wider;
+        self.width = width;
+    }
+
+    pub fn values<'a>(&'a self) -> impl Iterator<Item=u64> + 'a {
+        let mut r = BitPack::<&[u8]>::new(self.pack.as_slice());
+        let width = self.width;
+        (0..self.count).map(move |_| r.read_u64(width).unwrap())
+    }
+
+    /* The packed bytes, leaves the pack empty */
+    pub fn take(&mut self) -> Vec<u8> {
+        mem::replace(self, GrowingPack::new()).pack.into_vec()
+    }
+}
+
 pub(crate) fn num_bits(mydata: &[u32]) -> u8{
     let mut xor:u32 = 0;
     for &b in mydata {
@@ -439,6 +529,16 @@ pub fn unzigzag(origin: u32) -> i32{
     zzu
 }
 
+#[inline]
+pub fn zigzag64(origin: i64) -> u64{
+    ((origin << 1) ^ (origin >> 63)) as u64
+}
+
+#[inline]
+pub fn unzigzag64(origin: u64) -> i64{
+    (origin >> 1) as i64 ^ -((origin & 1) as i64)
+}
+
 // delta calculation for sprintz
 pub fn zigzag_delta_num_bits(mydata: &[i32]) -> (i32, u8,Vec<u32>){
     info!("10th vec: {},{},{},{}", mydata[0],mydata[1],mydata[2],mydata[3]);
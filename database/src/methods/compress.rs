@@ -42,6 +42,7 @@ use std::collections::HashMap;
 use crate::compress::split_double::SplitBDDoubleCompress;
 use crate::compress::sprintz::SprintzDoubleCompress;
 use crate::compress::gorilla::{GorillaBDCompress, GorillaCompress};
+use crate::methods::registry::{MethodCodec, method_name};
 use crate::knn::{grail_file, get_gamma};
 use std::path::Path;
 use std::fmt::Debug;
@@ -79,6 +80,31 @@ pub trait CompressionMethod<T> {
     }
 }
 
+/* Encodes the points of a segment as they arrive instead of the finished
+ * segment, so a caller can hold an open segment compressed rather than
+ * as a Vec<T>. BufferedSignal does not use them yet, it still collects
+ * its points in a Vec<T> and leaves them to the compression daemons.
+ * finish returns the same bytes the batch encoder of the method produces
+ * for those points, and leaves the encoder empty for the next segment.
+ * Like the batch encoders the points must be finite, gaps are for the
+ * caller to set aside.
+ */
+pub trait StreamingEncoder<T> {
+    fn push(&mut self, value: T);
+
+    /* Points pushed since the last finish */
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn finish(&mut self) -> Vec<u8>;
+
+    /* The method the finished bytes decode with */
+    fn method(&self) -> Methods;
+}
+
 #[derive(Clone)]
 pub struct FCMCompress {
     chunksize: usize,
@@ -1866,6 +1892,24 @@ pub fn test_grilla_compress_on_file<'a,T>(file: &str)
     println!(",    {}", throughput);
 }
 
+/* Ratio and throughput of any codec of the registry on a file */
+pub fn test_codec_compress_on_file<'a,T>(file: &str, codec: &MethodCodec)
+    where T: FromStr + Serialize + Clone + Copy + Into<f64> + Deserialize<'a> + Num + FromPrimitive + Send + FFTnum{
+    let file_iter = construct_file_iterator_skip_newline::<T>(file, 1, ',');
+    let file_vec: Vec<T> = file_iter.unwrap().collect();
+    let mut seg = Segment::new(None,SystemTime::now(),0,file_vec.clone(),None,None);
+    let start = Instant::now();
+    codec.encode(&mut seg);
+    let duration = start.elapsed();
+    info!("Time elapsed in {} compress function() is: {:?}", method_name(codec.method()), duration);
+    let org_size = file_vec.len() * (mem::size_of::<T>());
+    /* Not every codec keeps its result in the binary, so count the whole segment */
+    let comp_size = seg.get_byte_size().unwrap();
+    let throughput = 1000000000.0 * org_size as f64 / duration.as_nanos() as f64 / 1024.0/1024.0;
+    print!("{}", comp_size as f64 / org_size as f64);
+    println!(",    {}", throughput);
+}
+
 pub fn test_grilla_compress_on_int_file(file: &str, scl:i32) {
     let file_iter = construct_file_iterator_int_signed(file, 1, ',',scl);
     let file_vec: Vec<i32> = file_iter.unwrap().collect();
@@ -1,7 +1,8 @@
 use std::env;
-use time_series_start::methods::compress::{test_grilla_compress_on_file, test_grilla_compress_on_int_file, test_zlib_compress_on_file, test_zlib_compress_on_int_file, test_BP_compress_on_int, test_paa_compress_on_file, test_paa_compress_on_int_file, test_fourier_compress_on_file, test_snappy_compress_on_file, test_snappy_compress_on_int_file, test_deflate_compress_on_file, test_deflate_compress_on_int_file, test_gzip_compress_on_file, test_gzip_compress_on_int_file, test_FCM_compress_on_int, test_deltaBP_compress_on_int, test_DFCM_compress_on_int, test_offsetgrilla_compress_on_file, test_offsetgrilla_compress_on_int_file, test_split_compress_on_int, test_splitbd_compress_on_file, test_grillabd_compress_on_file, test_split_compress_on_file, test_grail_compress_on_file, test_fourier_compress_on_file_per_line};
+use time_series_start::methods::compress::{test_grilla_compress_on_file, test_codec_compress_on_file, test_grilla_compress_on_int_file, test_zlib_compress_on_file, test_zlib_compress_on_int_file, test_BP_compress_on_int, test_paa_compress_on_file, test_paa_compress_on_int_file, test_fourier_compress_on_file, test_snappy_compress_on_file, test_snappy_compress_on_int_file, test_deflate_compress_on_file, test_deflate_compress_on_int_file, test_gzip_compress_on_file, test_gzip_compress_on_int_file, test_FCM_compress_on_int, test_deltaBP_compress_on_int, test_DFCM_compress_on_int, test_offsetgrilla_compress_on_file, test_offsetgrilla_compress_on_int_file, test_split_compress_on_int, test_splitbd_compress_on_file, test_grillabd_compress_on_file, test_split_compress_on_file, test_grail_compress_on_file, test_fourier_compress_on_file_per_line};
 use log::{error, info, warn};
 use log4rs;
+use time_series_start::methods::registry::codec_by_name;
 
 fn main() {
     log4rs::init_file("config/log4rs.yaml", Default::default()).unwrap();
@@ -137,7 +138,15 @@ fn main() {
                 _ => panic!("Data type not supported yet for FCM."),
             }
         },
-        _ => {panic!("Compression not supported yet.")}
+        /* Any other codec of the registry, with its default parameters */
+        name => match codec_by_name(name, 10) {
+            Some(codec) => match data_type.as_str() {
+                "f32" => test_codec_compress_on_file::<f32>(input_file, &codec),
+                "f64" => test_codec_compress_on_file::<f64>(input_file, &codec),
+                _ => panic!("Data type not supported yet for {}.", name),
+            },
+            None => panic!("Compression not supported yet."),
+        }
     }
 
 }
\ No newline at end of file
@@ -6,6 +6,7 @@ pub mod bit_packing;
 pub mod fcm_encoder;
 pub mod prec_double;
 pub mod parquet;
+pub mod registry;
 use std::fmt;
 use crate::dictionary::{DictionaryId};
 
@@ -56,7 +57,15 @@ pub enum Methods {
     Bufflossy (usize,usize),
     Paa (usize),
     Fourier (f64),
-    Pla (f64)
+    Pla (f64),
+    Chimp,
+    Chimp128,
+    Elf,
+    Delta64,
+    DeltaOfDelta64,
+    Zigzag64,
+    For64,
+    Simple8b
 }
 
 pub fn IsLossless(m: &Methods) -> bool {
@@ -73,25 +82,133 @@ pub fn IsLossless(m: &Methods) -> bool {
         Methods::Paa (_) => false,
         Methods::Fourier (_) => false,
         Methods::Pla(_) => false,
+        Methods::Chimp | Methods::Chimp128 | Methods::Elf => true,
+        Methods::Delta64 | Methods::DeltaOfDelta64 | Methods::Zigzag64 | Methods::For64 | Methods::Simple8b => true,
         _ => {  false },
     }
 }
 
+/* Stable numeric ids of the codecs, these are what the segment header
+ * stores so the ids must never be reused or renumbered.
+ * New methods take the next free id.
+ */
+pub const CODEC_NONE: u8 = 255;
+
+impl Methods {
+    pub fn codec_id(&self) -> u8 {
+        match self {
+            Methods::Uncompr => 0,
+            Methods::Gorilla => 1,
+            Methods::Gzip => 2,
+            Methods::Snappy => 3,
+            Methods::Zlib => 4,
+            Methods::Sprintz (_) => 5,
+            Methods::Buff (_) => 6,
+            Methods::Kernel (_) => 7,
+            Methods::SparseLearning (_) => 8,
+            Methods::DeepLearning (_) => 9,
+            Methods::Rrd_sample => 10,
+            Methods::Bufflossy (_,_) => 11,
+            Methods::Paa (_) => 12,
+            Methods::Fourier (_) => 13,
+            Methods::Pla (_) => 14,
+            Methods::Chimp => 15,
+            Methods::Chimp128 => 16,
+            Methods::Elf => 17,
+            Methods::Delta64 => 18,
+            Methods::DeltaOfDelta64 => 19,
+            Methods::Zigzag64 => 20,
+            Methods::For64 => 21,
+            Methods::Simple8b => 22,
+        }
+    }
+
+    /* The parameters of the codec as little endian bytes */
+    pub fn codec_params(&self) -> Vec<u8> {
+        match self {
+            Methods::Sprintz (scale) | Methods::Buff (scale) | Methods::Paa (scale) => (*scale as u64).to_le_bytes().to_vec(),
+            Methods::Bufflossy (scale, bits) => {
+                let mut params = (*scale as u64).to_le_bytes().to_vec();
+                params.extend_from_slice(&(*bits as u64).to_le_bytes());
+                params
+            }
+            Methods::Kernel (id) | Methods::SparseLearning (id) => id.to_le_bytes().to_vec(),
+            Methods::DeepLearning (file) => file.as_bytes().to_vec(),
+            Methods::Fourier (ratio) | Methods::Pla (ratio) => ratio.to_bits().to_le_bytes().to_vec(),
+            _ => Vec::new(),
+        }
+    }
+
+    /* Rebuilds the method from the id and parameters produced above,
+     * returns None for unknown ids or malformed parameters
+     */
+    pub fn from_codec(id: u8, params: &[u8]) -> Option<Methods> {
+        fn u64_at(params: &[u8], idx: usize) -> Option<u64> {
+            let mut buf = [0u8; 8];
+            buf.copy_from_slice(params.get(idx*8..idx*8+8)?);
+            Some(u64::from_le_bytes(buf))
+        }
+        fn u32_at(params: &[u8]) -> Option<u32> {
+            let mut buf = [0u8; 4];
+            buf.copy_from_slice(params.get(0..4)?);
+            Some(u32::from_le_bytes(buf))
+        }
+        match id {
+            0 => Some(Methods::Uncompr),
+            1 => Some(Methods::Gorilla),
+            2 => Some(Methods::Gzip),
+            3 => Some(Methods::Snappy),
+            4 => Some(Methods::Zlib),
+            5 => Some(Methods::Sprintz (u64_at(params, 0)? as usize)),
+            6 => Some(Methods::Buff (u64_at(params, 0)? as usize)),
+            7 => Some(Methods::Kernel (u32_at(params)?)),
+            8 => Some(Methods::SparseLearning (u32_at(params)?)),
+            9 => Some(Methods::DeepLearning (String::from_utf8(params.to_vec()).ok()?)),
+            10 => Some(Methods::Rrd_sample),
+            11 => Some(Methods::Bufflossy (u64_at(params, 0)? as usize, u64_at(params, 1)? as usize)),
+            12 => Some(Methods::Paa (u64_at(params, 0)? as usize)),
+            13 => Some(Methods::Fourier (f64::from_bits(u64_at(params, 0)?))),
+            14 => Some(Methods::Pla (f64::from_bits(u64_at(params, 0)?))),
+            15 => Some(Methods::Chimp),
+            16 => Some(Methods::Chimp128),
+            17 => Some(Methods::Elf),
+            18 => Some(Methods::Delta64),
+            19 => Some(Methods::DeltaOfDelta64),
+            20 => Some(Methods::Zigzag64),
+            21 => Some(Methods::For64),
+            22 => Some(Methods::Simple8b),
+            _ => None,
+        }
+    }
+}
+
 impl fmt::Display for Methods {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Methods::Fourier (ratio) => write!(f,"{}", format!("Fourier w/ ratio {:?}", ratio)),
             Methods::Pla (ratio) => write!(f,"{}", format!("PLA lttb w/ ratio {:?}", ratio)),
+            Methods::Buff (0) => write!(f,"{}", format!("BUFF w/ inferred scale")),
             Methods::Buff (scale) => write!(f,"{}", format!("BUFF w/ scale {:?}", scale)),
             Methods::Sprintz (scale) => write!(f,"{}", format!("Sprintz w/ scale {:?}", scale)),
             Methods::Bufflossy (scale, bits) => write!(f,"{}", format!("BUFF w/ scale {:?}, bits {:?}", scale, bits)),
             Methods::Paa (ws) => write!(f,"{}", format!("Paa w/ window size {:?}", ws)),
             Methods::Rrd_sample => write!(f,"{}", format!("Round robin data management")),
             Methods::Uncompr => write!(f,"{}", format!("No compression applied")),
+            Methods::Gorilla => write!(f, "Gorilla"),
+            Methods::Gzip => write!(f, "Gzip"),
+            Methods::Snappy => write!(f, "Snappy"),
+            Methods::Zlib => write!(f, "Zlib"),
             Methods::Kernel (id) => write!(f, "{}", format!("Kernel w/ DictionaryId {:?}", id)),
             Methods::SparseLearning (id) => write!(f, "{}", format!("Sparse Learning w/ DictionaryId {:?}", id)),
             Methods::DeepLearning (file) => write!(f, "{}", format!("Deep Learning w/ file {:?}", file)),
-            _ => todo!()
+            Methods::Chimp => write!(f, "Chimp"),
+            Methods::Chimp128 => write!(f, "Chimp128"),
+            Methods::Elf => write!(f, "Elf"),
+            Methods::Delta64 => write!(f, "Delta (64-bit)"),
+            Methods::DeltaOfDelta64 => write!(f, "Delta-of-delta (64-bit)"),
+            Methods::Zigzag64 => write!(f, "Zigzag (64-bit)"),
+            Methods::For64 => write!(f, "Frame-of-reference (64-bit)"),
+            Methods::Simple8b => write!(f, "Simple-8b"),
         }
     }
 }
@@ -9,12 +9,14 @@ use std::io::{LineWriter, Write};
 use croaring::Bitmap;
 use rust_decimal::prelude::*;
 use histogram::Histogram;
+use crate::compress::PRECISION_MAP;
 
 /// END_MARKER is a special bit sequence used to indicate the end of the stream
 pub const EXP_MASK: u64 = 0b0111111111110000000000000000000000000000000000000000000000000000;
 pub const FIRST_ONE: u64 = 0b1000000000000000000000000000000000000000000000000000000000000000;
 pub const NEG_ONE: u64 = 0b1111111111111111111111111111111111111111111111111111111111111111;
 
+#[derive(Clone, Debug)]
 pub struct PrecisionBound {
     position: u64,
     precision: f64,
@@ -281,6 +283,77 @@ pub fn get_precision_bound(precision: i32) -> f64{
 }
 
 
+/* A scale of 0 asks the BUFF encoders to infer the scale from the segment */
+pub const INFER_SCALE: usize = 0;
+
+/* Beyond this the fixed point form of BUFF no longer fits its bit lengths */
+pub const MAX_INFERRED_PRECISION: i32 = 12;
+
+/* Finds the smallest decimal precision at which BUFF reproduces every
+ * value exactly. cal_length gives the binary fraction the values need,
+ * once the fixed point form keeps that many bits nothing is lost.
+ * Below that a precision is enough if the fixed point form rounded at
+ * that many decimal places reads back as the original value. Every
+ * finite value is checked, a single one BUFF would round is enough to
+ * need a finer precision. None when no precision is enough, BUFF can
+ * not hold such values without loss.
+ */
+pub fn infer_precision(values: &[f64]) -> Option<i32> {
+    let finite: Vec<f64> = values.iter().cloned().filter(|v| v.is_finite()).collect();
+    let mut exact = PrecisionBound::new(get_precision_bound(MAX_INFERRED_PRECISION));
+    for v in finite.iter() {
+        exact.cal_length(*v);
+    }
+    let (_, binary_length) = exact.get_length();
+
+    for prec in 1..=MAX_INFERRED_PRECISION {
+        let dec_len = *PRECISION_MAP.get(&prec).unwrap() as u64;
+        if dec_len >= binary_length {
+            return Some(prec);
+        }
+        let mut bound = PrecisionBound::new(get_precision_bound(prec));
+        bound.set_length(0, dec_len);
+        let dec_scl = 2.0f64.powi(dec_len as i32);
+        let dec_pow = 10.0f64.powi(prec);
+        let reproduced = finite.iter().all(|v| {
+            let fixed = bound.fetch_fixed_aligned(*v) as f64 / dec_scl;
+            (fixed * dec_pow).round() / dec_pow == *v
+        });
+        if reproduced {
+            return Some(prec);
+        }
+    }
+    None
+}
+
+/* The scale BUFF takes for the inferred precision */
+pub fn infer_scale(values: &[f64]) -> Option<usize> {
+    infer_precision(values).map(|prec| 10usize.pow(prec as u32))
+}
+
+/* The BUFF compressors, built with INFER_SCALE they take the scale of
+ * every segment they encode from its values
+ */
+pub trait InferScale: Clone {
+    fn scale(&self) -> usize;
+
+    fn set_scale(&mut self, scale: usize);
+
+    /* The compressor with the scale inferred from the segment if it was
+     * built with INFER_SCALE, otherwise the compressor itself. None when
+     * no scale reproduces the segment.
+     */
+    fn for_segment<T: Copy + Into<f64>>(&self, seg: &Segment<T>) -> Option<Self> {
+        let mut comp = self.clone();
+        if self.scale() == INFER_SCALE {
+            let values: Vec<f64> = seg.get_data().iter().map(|v| (*v).into()).collect();
+            comp.set_scale(infer_scale(&values)?);
+            info!("inferred scale:{}", comp.scale());
+        }
+        Some(comp)
+    }
+}
+
 
 
 #[test]
@@ -668,4 +741,22 @@ fn test_varible_length_hist() {
     );
 
 
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_infer_precision() {
+    let mut cents: Vec<f64> = (0..5000).map(|x| ((x * 7 % 1000) as f64 - 300.0) / 100.0).collect();
+    assert_eq!(infer_precision(&cents), Some(2));
+    assert_eq!(infer_scale(&cents), Some(100));
+    /* One finer value anywhere in the segment needs its precision */
+    cents[4999] = 1.2345;
+    assert_eq!(infer_precision(&cents), Some(4));
+    let ints: Vec<f64> = (0..100).map(|x| (x * x) as f64).collect();
+    assert_eq!(infer_precision(&ints), Some(1));
+    let halves: Vec<f64> = (0..100).map(|x| x as f64 * 0.5).collect();
+    assert_eq!(infer_precision(&halves), Some(1));
+    let mut micro = vec![1.234567, -20.5, std::f64::NAN];
+    micro.extend(vec![0.000001; 10]);
+    assert_eq!(infer_precision(&micro), Some(6));
+    assert_eq!(infer_precision(&[1.0 / 3.0, 2.0]), None);
+}
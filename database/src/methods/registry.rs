@@ -0,0 +1,449 @@
+use std::mem;
+use std::time::UNIX_EPOCH;
+use num::{FromPrimitive, Num};
+use rustfft::FFTnum;
+use serde::{Serialize, Deserialize};
+use crate::segment::{Segment, PAACompress, FourierCompress};
+use crate::segment::validity;
+use crate::segment::header::FormatError;
+use crate::methods::{IsLossless, Methods};
+use crate::methods::compress::{CompressionMethod, GZipCompress, SnappyCompress, ZlibCompress};
+use crate::compress::sprintz::SprintzDoubleCompress;
+use crate::compress::split_double::SplitBDDoubleCompress;
+use crate::compress::gorilla::GorillaCompress;
+use crate::compress::chimp::{ChimpCompress, CHIMP_WINDOW, CHIMP128_WINDOW};
+use crate::compress::elf::ElfCompress;
+use crate::compress::int64::{IntCodec, IntCompress, Int64};
+use crate::compress::buff_lossy::BUFFlossy;
+use crate::methods::prec_double::{INFER_SCALE, InferScale};
+use crate::compress::pla_lttb::PLACompress;
+use crate::compress::rrd_sample::RRDsample;
+
+/*
+ * Overview:
+ * The single place that knows which compressor struct implements
+ * which Methods variant. Every caller that needs to encode a segment,
+ * decode the data back out of it or ask whether a method loses
+ * precision goes through a MethodCodec instead of matching on the
+ * variant itself.
+ *
+ * Design Choice:
+ * MethodCodec is a plain value wrapping the Methods it stands for,
+ * the compressor structs are built on demand from the parameters the
+ * variant carries. This keeps the codec cheap to clone into daemons
+ * and means a segment's own method is always enough to decode it.
+ * It also implements CompressionMethod so it can drive the daemons.
+ *
+ * Current Implementations:
+ * Every variant except Kernel, SparseLearning and DeepLearning, which
+ * need a dictionary or trained model that is not carried by the
+ * method, codec_for returns None for those. The 64-bit integer codecs
+ * take i64 and u64 segments through encode_int and decode_int, float
+ * segments given to them are packed by the bits of their values.
+ */
+
+/* Chunk size the byte oriented compressors are built with */
+const CHUNK_SIZE: usize = 10;
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct MethodCodec {
+    method: Methods,
+    batchsize: usize,
+}
+
+/* Looks up the codec for the method, None if it can not be built from the method alone */
+pub fn codec_for(method: &Methods) -> Option<MethodCodec> {
+    codec_with_batch(method, 20)
+}
+
+pub fn codec_with_batch(method: &Methods, batchsize: usize) -> Option<MethodCodec> {
+    match method {
+        Methods::Kernel (_) | Methods::SparseLearning (_) | Methods::DeepLearning (_) => None,
+        _ => Some(MethodCodec { method: method.clone(), batchsize: batchsize }),
+    }
+}
+
+/* Maps the compression names used in the configs and on the command line
+ * to a codec with the default parameters for that method
+ */
+pub fn codec_by_name(name: &str, batchsize: usize) -> Option<MethodCodec> {
+    codec_with_batch(&method_by_name(name)?, batchsize)
+}
+
+/* The method a compression name stands for, with its default parameters */
+pub fn method_by_name(name: &str) -> Option<Methods> {
+    let method = match name {
+        "uncompr" => Methods::Uncompr,
+        "gorilla" => Methods::Gorilla,
+        "gzip" => Methods::Gzip,
+        "snappy" => Methods::Snappy,
+        "zlib" => Methods::Zlib,
+        "sprintz" => Methods::Sprintz (10000),
+        "buff" => Methods::Buff (INFER_SCALE),
+        "rrd" => Methods::Rrd_sample,
+        "bufflossy" => Methods::Bufflossy (10000, 32),
+        "paa" => Methods::Paa (10),
+        "fourier" => Methods::Fourier (1.0),
+        "pla" => Methods::Pla (0.25),
+        "chimp" => Methods::Chimp,
+        "chimp128" => Methods::Chimp128,
+        "elf" => Methods::Elf,
+        "delta64" => Methods::Delta64,
+        "dod64" => Methods::DeltaOfDelta64,
+        "zigzag64" => Methods::Zigzag64,
+        "for64" => Methods::For64,
+        "simple8b" => Methods::Simple8b,
+        _ => return None,
+    };
+    Some(method)
+}
+
+/* Like method_by_name, with the scale, window size or ratio of the method
+ * replaced by the given parameter. Methods without one ignore it.
+ */
+pub fn method_with_param(name: &str, param: Option<f64>) -> Option<Methods> {
+    let method = method_by_name(name)?;
+    let param = match param {
+        Some(p) => p,
+        None => return Some(method),
+    };
+    Some(match method {
+        Methods::Sprintz (_) => Methods::Sprintz (param as usize),
+        Methods::Buff (_) => Methods::Buff (param as usize),
+        Methods::Bufflossy (_, bits) => Methods::Bufflossy (param as usize, bits),
+        Methods::Paa (_) => Methods::Paa (param as usize),
+        Methods::Fourier (_) => Methods::Fourier (param),
+        Methods::Pla (_) => Methods::Pla (param),
+        m => m,
+    })
+}
+
+/* The name a method goes by in the configs, the inverse of codec_by_name */
+pub fn method_name(method: &Methods) -> &'static str {
+    match method {
+        Methods::Uncompr => "uncompr",
+        Methods::Gorilla => "gorilla",
+        Methods::Gzip => "gzip",
+        Methods::Snappy => "snappy",
+        Methods::Zlib => "zlib",
+        Methods::Sprintz (_) => "sprintz",
+        Methods::Buff (_) => "buff",
+        Methods::Kernel (_) => "kernel",
+        Methods::SparseLearning (_) => "sparse",
+        Methods::DeepLearning (_) => "deep",
+        Methods::Rrd_sample => "rrd",
+        Methods::Bufflossy (_, _) => "bufflossy",
+        Methods::Paa (_) => "paa",
+        Methods::Fourier (_) => "fourier",
+        Methods::Pla (_) => "pla",
+        Methods::Chimp => "chimp",
+        Methods::Chimp128 => "chimp128",
+        Methods::Elf => "elf",
+        Methods::Delta64 => "delta64",
+        Methods::DeltaOfDelta64 => "dod64",
+        Methods::Zigzag64 => "zigzag64",
+        Methods::For64 => "for64",
+        Methods::Simple8b => "simple8b",
+    }
+}
+
+/* Runs a batch compressor over a single segment in place */
+fn compress_one<T, C: CompressionMethod<T>>(comp: C, seg: &mut Segment<T>) {
+    let placeholder = Segment::new(None, UNIX_EPOCH, 0, Vec::new(), None, None);
+    let mut segs = vec![mem::replace(seg, placeholder)];
+    comp.run_compress(&mut segs);
+    *seg = segs.pop().unwrap();
+}
+
+/* Runs an integer codec over the bits of the values of a float segment */
+fn encode_bits<T: Copy + Into<f64>>(comp: IntCompress, seg: &mut Segment<T>) {
+    let bits: Vec<u64> = seg.get_data().iter().map(|v| (*v).into().to_bits()).collect();
+    let mut words = Segment::new(None, UNIX_EPOCH, 0, bits, None, None);
+    seg.set_comp(Some(comp.encode(&mut words)));
+    seg.set_data(Vec::new());
+    seg.set_method(comp.method());
+}
+
+impl MethodCodec {
+    pub fn method(&self) -> &Methods {
+        &self.method
+    }
+
+    pub fn is_lossless(&self) -> bool {
+        IsLossless(&self.method)
+    }
+
+    /* Compresses the segment in place with this codec */
+    pub fn encode<'a, T>(&self, seg: &mut Segment<T>)
+        where T: Num + FromPrimitive + Copy + Send + FFTnum + Into<f64> + Serialize + Deserialize<'a>
+    {
+        /* The codecs only take finite values, the rest is set aside until decode */
+        match &self.method {
+            Methods::Uncompr => (),
+            _ => if let Some((filled, kept)) = validity::split(seg.get_data()) {
+                seg.set_data(filled);
+                seg.set_validity(Some(kept));
+            }
+        }
+        match &self.method {
+            Methods::Uncompr => (),
+            Methods::Gorilla => compress_one(GorillaCompress::new(CHUNK_SIZE, self.batchsize), seg),
+            Methods::Gzip => compress_one(GZipCompress::new(CHUNK_SIZE, self.batchsize), seg),
+            Methods::Snappy => compress_one(SnappyCompress::new(CHUNK_SIZE, self.batchsize), seg),
+            Methods::Zlib => compress_one(ZlibCompress::new(CHUNK_SIZE, self.batchsize, 5), seg),
+            Methods::Sprintz (scale) => compress_one(SprintzDoubleCompress::new(CHUNK_SIZE, self.batchsize, *scale), seg),
+            Methods::Buff (scale) => match SplitBDDoubleCompress::new(CHUNK_SIZE, self.batchsize, *scale).for_segment(seg) {
+                Some(comp) => compress_one(comp, seg),
+                /* BUFF would round a segment no scale reproduces, it is kept lossless instead */
+                None => return MethodCodec { method: Methods::Gorilla, batchsize: self.batchsize }.encode(seg),
+            },
+            Methods::Rrd_sample => compress_one(RRDsample::new(self.batchsize), seg),
+            Methods::Bufflossy (scale, bits) => compress_one(BUFFlossy::new(self.batchsize, *scale, *bits), seg),
+            Methods::Paa (wsize) => compress_one(PAACompress::new(*wsize, self.batchsize), seg),
+            Methods::Fourier (ratio) => compress_one(FourierCompress::new(CHUNK_SIZE, self.batchsize, *ratio), seg),
+            Methods::Pla (ratio) => compress_one(PLACompress::new(self.batchsize, *ratio), seg),
+            Methods::Chimp => compress_one(ChimpCompress::new(CHUNK_SIZE, self.batchsize, CHIMP_WINDOW), seg),
+            Methods::Chimp128 => compress_one(ChimpCompress::new(CHUNK_SIZE, self.batchsize, CHIMP128_WINDOW), seg),
+            Methods::Elf => compress_one(ElfCompress::new(CHUNK_SIZE, self.batchsize), seg),
+            m => match IntCodec::from_method(m) {
+                Some(codec) => encode_bits(IntCompress::new(CHUNK_SIZE, self.batchsize, codec), seg),
+                /* codec_with_batch never builds a codec for the dictionary methods */
+                None => unreachable!("no codec for {}", m),
+            },
+        }
+        /* Some compressors leave the method to the caller, lossy ones may refine its parameters */
+        let encoded = match seg.get_method() {
+            Some(m) => m.codec_id() == self.method.codec_id(),
+            None    => false,
+        };
+        if !encoded {
+            seg.set_method(self.method.clone());
+        }
+    }
+
+    /* Decodes the data of a segment compressed with this codec */
+    pub fn decode<T>(&self, seg: &Segment<T>) -> Vec<T>
+        where T: Num + FromPrimitive + Copy + Send + FFTnum + Into<f64>
+    {
+        let mut vec = self.decode_finite(seg);
+        if let Some(kept) = seg.get_validity() {
+            kept.restore(&mut vec);
+        }
+        vec
+    }
+
+    fn decode_finite<T>(&self, seg: &Segment<T>) -> Vec<T>
+        where T: Num + FromPrimitive + Copy + Send + FFTnum + Into<f64>
+    {
+        match &self.method {
+            Methods::Uncompr => seg.get_data().clone(),
+            Methods::Gorilla => GorillaCompress::new(CHUNK_SIZE, self.batchsize).decode_general(seg.get_comp()),
+            Methods::Gzip => GZipCompress::new(CHUNK_SIZE, self.batchsize).decode(seg.get_comp()),
+            Methods::Snappy => SnappyCompress::new(CHUNK_SIZE, self.batchsize).decode(seg.get_comp()),
+            Methods::Zlib => ZlibCompress::new(CHUNK_SIZE, self.batchsize, 5).decode(seg.get_comp()),
+            Methods::Sprintz (scale) => SprintzDoubleCompress::new(CHUNK_SIZE, self.batchsize, *scale).decode_general(seg.get_comp()),
+            Methods::Buff (scale) => SplitBDDoubleCompress::new(CHUNK_SIZE, self.batchsize, *scale).decode_general(seg.get_comp()),
+            Methods::Rrd_sample => RRDsample::new(self.batchsize).decode(seg),
+            Methods::Bufflossy (scale, bits) => BUFFlossy::new(self.batchsize, *scale, *bits).decode_general(seg.get_comp()),
+            Methods::Paa (wsize) => {
+                let mut vec = PAACompress::new(*wsize, self.batchsize).decodeVec(seg.get_data());
+                vec.truncate(seg.get_size());
+                vec
+            }
+            Methods::Fourier (ratio) => FourierCompress::new(2, self.batchsize, *ratio).decodeVec(seg.get_data(), seg.get_size()),
+            Methods::Pla (ratio) => PLACompress::new(self.batchsize, *ratio).decode(seg),
+            Methods::Chimp => ChimpCompress::new(CHUNK_SIZE, self.batchsize, CHIMP_WINDOW).decode_general(seg.get_comp()),
+            Methods::Chimp128 => ChimpCompress::new(CHUNK_SIZE, self.batchsize, CHIMP128_WINDOW).decode_general(seg.get_comp()),
+            Methods::Elf => ElfCompress::new(CHUNK_SIZE, self.batchsize).decode_general(seg.get_comp()),
+            m => match IntCodec::from_method(m) {
+                Some(codec) => IntCompress::new(CHUNK_SIZE, self.batchsize, codec).decode::<u64>(seg.get_comp())
+                    .into_iter().map(|bits| FromPrimitive::from_f64(f64::from_bits(bits)).unwrap()).collect(),
+                None => unreachable!("no codec for {}", m),
+            },
+        }
+    }
+
+    /* Compresses a segment of 64-bit integers in place, only the integer
+     * codecs and Uncompr take those
+     */
+    pub fn encode_int<T: Int64>(&self, seg: &mut Segment<T>) -> Result<(), FormatError> {
+        match (&self.method, IntCodec::from_method(&self.method)) {
+            (Methods::Uncompr, _) => Ok(()),
+            (_, Some(codec)) => {
+                IntCompress::new(CHUNK_SIZE, self.batchsize, codec).run_single_compress(seg);
+                Ok(())
+            }
+            (m, None) => Err(FormatError::UnknownCodec(m.codec_id())),
+        }
+    }
+
+    /* Decodes the data of an integer segment compressed with this codec */
+    pub fn decode_int<T: Int64>(&self, seg: &Segment<T>) -> Result<Vec<T>, FormatError> {
+        match (&self.method, IntCodec::from_method(&self.method)) {
+            (Methods::Uncompr, _) => Ok(seg.get_data().clone()),
+            (_, Some(codec)) => IntCompress::new(CHUNK_SIZE, self.batchsize, codec).try_decode(seg.get_comp()),
+            (m, None) => Err(FormatError::UnknownCodec(m.codec_id())),
+        }
+    }
+
+    /* Replaces the compressed form of the segment with its decoded data */
+    pub fn decompress<T>(&self, seg: &mut Segment<T>)
+        where T: Num + FromPrimitive + Copy + Send + FFTnum + Into<f64>
+    {
+        let vec = self.decode(seg);
+        seg.set_comp(None);
+        seg.set_data(vec);
+        seg.set_validity(None);
+        seg.set_method(Methods::Uncompr);
+    }
+}
+
+/* Decodes a segment with the codec its own method names,
+ * None if no codec is registered for that method
+ */
+pub fn decode_segment<T>(seg: &Segment<T>) -> Option<Vec<T>>
+    where T: Num + FromPrimitive + Copy + Send + FFTnum + Into<f64>
+{
+    match seg.get_method() {
+        None => Some(seg.get_data().clone()),
+        Some(m) => codec_for(m).map(|codec| codec.decode(seg)),
+    }
+}
+
+impl<'a, T> CompressionMethod<T> for MethodCodec
+    where T: Num + FromPrimitive + Copy + Send + FFTnum + Into<f64> + Serialize + Deserialize<'a>
+{
+    fn get_segments(&self) {
+        unimplemented!()
+    }
+
+    fn get_batch(&self) -> usize {
+        self.batchsize
+    }
+
+    fn run_compress<'b>(&self, segs: &mut Vec<Segment<T>>) {
+        for seg in segs {
+            self.encode(seg);
+        }
+    }
+
+    fn run_single_compress(&self, seg: &mut Segment<T>) {
+        self.encode(seg);
+    }
+
+    fn run_decompress(&self, seg: &mut Segment<T>) {
+        self.decompress(seg);
+    }
+}
+
+/* Sixteenths have four decimals and scale to exact integers, so the scaled codecs get every value back bit for bit */
+#[cfg(test)]
+fn sixteenths() -> Vec<f64> {
+    (0..200).map(|x| ((x as f64 * 0.37).sin() * 1600.0).round() / 16.0).collect()
+}
+
+#[test]
+fn test_registry_round_trip() {
+    let data = sixteenths();
+    for method in vec![Methods::Uncompr, Methods::Gorilla, Methods::Gzip, Methods::Snappy,
+                       Methods::Zlib, Methods::Sprintz (10000), Methods::Buff (10000),
+                       Methods::Chimp, Methods::Chimp128, Methods::Elf] {
+        let codec = codec_for(&method).unwrap();
+        assert!(codec.is_lossless());
+        let mut seg = Segment::new(None, std::time::SystemTime::now(), 0, data.clone(), None, None);
+        codec.encode(&mut seg);
+        assert_eq!(seg.get_method().as_ref().unwrap().codec_id(), method.codec_id());
+        let decoded = decode_segment(&seg).unwrap();
+        assert_eq!(decoded.len(), data.len(), "{} length", method);
+        for (a, b) in data.iter().zip(decoded.iter()) {
+            assert_eq!(a.to_bits(), b.to_bits(), "{} decoded {} as {}", method, a, b);
+        }
+    }
+
+    let codec = codec_for(&Methods::Paa (4)).unwrap();
+    assert!(!codec.is_lossless());
+    let mut seg = Segment::new(None, std::time::SystemTime::now(), 0, data.clone(), None, None);
+    codec.encode(&mut seg);
+    assert_eq!(decode_segment(&seg).unwrap().len(), data.len());
+
+    assert_eq!(codec_for(&Methods::Kernel (0)), None);
+    let mut seg = Segment::new(None, std::time::SystemTime::now(), 0, data.clone(), None, None);
+    seg.set_method(Methods::Kernel (0));
+    assert_eq!(decode_segment(&seg), None);
+    assert_eq!(codec_by_name("paa", 10).unwrap().method(), &Methods::Paa (10));
+}
+
+#[test]
+fn test_registry_gaps() {
+    /* Gaps, NaN and infinities come back with their exact bits */
+    use crate::segment::validity::Nullable;
+    let mut gappy = sixteenths();
+    gappy[3] = f64::null().unwrap();
+    gappy[50] = std::f64::NAN;
+    gappy[51] = std::f64::INFINITY;
+    gappy[199] = std::f64::NEG_INFINITY;
+    for method in vec![Methods::Gorilla, Methods::Sprintz (10000), Methods::Buff (10000), Methods::Snappy, Methods::Elf] {
+        let codec = codec_for(&method).unwrap();
+        let mut seg = Segment::new(None, std::time::SystemTime::now(), 0, gappy.clone(), None, None);
+        codec.encode(&mut seg);
+        let bytes = seg.convert_to_bytes().unwrap();
+        let decoded = decode_segment(&Segment::<f64>::convert_from_bytes(&bytes).unwrap()).unwrap();
+        assert!(decoded[3].is_null(), "{} lost the gap", method);
+        for i in vec![50, 51, 199] {
+            assert_eq!(decoded[i].to_bits(), gappy[i].to_bits(), "{} changed row {}", method, i);
+        }
+        assert_eq!(decoded[10].to_bits(), gappy[10].to_bits(), "{} changed row 10", method);
+    }
+}
+
+#[test]
+fn test_registry_inferred_scale() {
+    /* BUFF without a scale keeps the one it inferred in the header */
+    let readings: Vec<f64> = (0..500).map(|x| ((x as f64 * 0.37).sin() * 100000.0).round() / 1000.0).collect();
+    let mut seg = Segment::new(None, std::time::SystemTime::now(), 0, readings.clone(), None, None);
+    codec_by_name("buff", 10).unwrap().encode(&mut seg);
+    let bytes = seg.convert_to_bytes().unwrap();
+    let seg = Segment::<f64>::convert_from_bytes(&bytes).unwrap();
+    assert_eq!(seg.get_method(), &Some(Methods::Buff (1000)));
+    for (a, b) in readings.iter().zip(decode_segment(&seg).unwrap().iter()) {
+        assert_eq!(*a, (b * 1000.0).round() / 1000.0);
+    }
+
+    /* A segment no scale reproduces is kept lossless */
+    let mut thirds = readings.clone();
+    thirds[321] = 1.0 / 3.0;
+    let mut seg = Segment::new(None, std::time::SystemTime::now(), 0, thirds.clone(), None, None);
+    codec_by_name("buff", 10).unwrap().encode(&mut seg);
+    assert_eq!(seg.get_method(), &Some(Methods::Gorilla));
+    assert_eq!(decode_segment(&seg).unwrap(), thirds);
+}
+
+#[test]
+fn test_registry_method_names() {
+    for id in 0..=u8::MAX {
+        if let Some(method) = Methods::from_codec(id, &[0; 16]) {
+            assert!(!format!("{}", method).is_empty(), "codec {} has no name", id);
+        }
+    }
+    assert_eq!(format!("{}", Methods::Zlib), "Zlib");
+}
+
+#[test]
+fn test_registry_int_codecs() {
+    /* The integer codecs take integer segments and the bits of float ones */
+    let data = sixteenths();
+    let stamps: Vec<i64> = (0..300).map(|x| 1_600_000_000_000 + x * 1000 + x % 7).collect();
+    for method in vec![Methods::Delta64, Methods::DeltaOfDelta64, Methods::Zigzag64, Methods::For64, Methods::Simple8b] {
+        let codec = codec_for(&method).unwrap();
+        let mut seg = Segment::new(None, std::time::SystemTime::now(), 0, stamps.clone(), None, None);
+        codec.encode_int(&mut seg).unwrap();
+        assert_eq!(seg.get_method(), &Some(method.clone()));
+        assert_eq!(codec.decode_int(&seg), Ok(stamps.clone()), "{} integers", method);
+        let mut seg = Segment::new(None, std::time::SystemTime::now(), 0, data.clone(), None, None);
+        codec.encode(&mut seg);
+        assert_eq!(decode_segment(&seg), Some(data.clone()), "{} floats", method);
+    }
+    let mut seg = Segment::new(None, std::time::SystemTime::now(), 0, stamps.clone(), None, None);
+    assert_eq!(codec_for(&Methods::Gorilla).unwrap().encode_int(&mut seg), Err(FormatError::UnknownCodec(Methods::Gorilla.codec_id())));
+    assert_eq!(method_by_name(method_name(&Methods::For64)), Some(Methods::For64));
+}
@@ -177,23 +177,27 @@ pub fn outlier_byteall_encoding_decoding(vec_u8:Vec<u8>, size:u64, ratio:f64, sc
     println!("Time elapsed in splitbd byte compress function() is: {:?}", duration1);
 
     let start2 = Instant::now();
-    comp.byte_fixed_decode(compressed);
+    let decoded = comp.byte_fixed_decode(compressed);
     let duration2 = start2.elapsed();
+    println!("Number of scan items:{}", decoded.len());
     println!("Time elapsed in splitbd byte decompress function() is: {:?}", duration2);
 
     let start3 = Instant::now();
-    comp.byte_fixed_range_filter(comp_cp,pred);
+    let res = comp.byte_fixed_range_filter(comp_cp,pred);
     let duration3 = start3.elapsed();
+    println!("Number of qualified items:{}", res.cardinality());
     println!("Time elapsed in splitbd byte range filter function() is: {:?}", duration3);
 
     let start4 = Instant::now();
-    comp.byte_fixed_equal_filter(comp_eq,pred);
+    let res = comp.byte_fixed_equal_filter(comp_eq,pred);
     let duration4 = start4.elapsed();
+    println!("Number of qualified items for equal:{}", res.cardinality());
     println!("Time elapsed in splitbd byte equal filter function() is: {:?}", duration4);
 
     let start5 = Instant::now();
-    comp.byte_fixed_sum(comp_sum);
+    let sum = comp.byte_fixed_sum(comp_sum);
     let duration5 = start5.elapsed();
+    println!("sum is: {:?}", sum);
     println!("Time elapsed in byte_splitbd sum function() is: {:?}", duration5);
 
     let start6 = Instant::now();
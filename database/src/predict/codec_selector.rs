@@ -0,0 +1,385 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+use log::info;
+use num::{FromPrimitive, Num};
+use rustfft::FFTnum;
+use serde::{Serialize, Deserialize};
+use crate::segment::Segment;
+use crate::future_signal::SignalId;
+use crate::methods::Methods;
+use crate::methods::compress::CompressionMethod;
+use crate::methods::registry::{codec_for, codec_with_batch};
+use crate::methods::prec_double::{infer_precision, MAX_INFERRED_PRECISION};
+use crate::predict::cr_predictor::{est_buff_cr, est_sprintz_cr, est_gzip_cr, est_gorilla_cr, est_snappy_cr};
+
+/*
+ * Overview:
+ * The CodecSelector picks the compression method of every segment on its
+ * own, so a compression daemon no longer runs one fixed codec for all the
+ * signals it sees. What the best codec is depends on the data as much as
+ * on how a signal is used: a signal that is mostly archived wants the
+ * smallest segments, one that is read all the time wants a codec that
+ * decodes fast.
+ *
+ * Design Choice:
+ * The decimal precision of the segment is inferred, which gives the
+ * scales BUFF and Sprintz are tried with when they get every value back,
+ * and a sample of it is taken. The cr_predictor
+ * estimators give the ratio of every candidate on the sample. Throughput
+ * can not be read off the values, so the candidates are timed encoding
+ * and decoding the sample, every REMEASURE_EVERY segments, and the times
+ * are kept as a moving average. A candidate costs
+ *   storage * ratio + ingest * encode time + read * decode time
+ * weighted by the query mix of the signal, with the times taken relative
+ * to the slowest candidate so all three terms lie between 0 and 1. The
+ * candidate with the lowest cost is chosen. AutoCompress times the
+ * candidates between two locks of its selector, not while holding it.
+ *
+ * Current Implementations:
+ * Buff, Sprintz, Gorilla, Gzip and Snappy, the methods cr_predictor has
+ * an estimator for. The dictionary estimator is left out, there is no
+ * dictionary codec the daemons can run.
+ */
+
+/* Values of a segment the estimates and timings are taken on */
+pub const SELECTOR_SAMPLE: usize = 1024;
+
+/* Segments selected between two timings of the candidates */
+pub const REMEASURE_EVERY: usize = 64;
+
+/* Weight of a new timing in the moving average */
+const TIMING_WEIGHT: f64 = 0.3;
+
+/* Used when the segment has no finite value to sample */
+const FALLBACK: Methods = Methods::Gorilla;
+
+/* How the segments of a signal are used, the weights need not add up to 1 */
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct QueryMix {
+    pub storage: f64,
+    pub ingest: f64,
+    pub read: f64,
+}
+
+impl QueryMix {
+    pub fn new(storage: f64, ingest: f64, read: f64) -> QueryMix {
+        QueryMix { storage, ingest, read }
+    }
+
+    /* Takes the weights that are not given from the other mix */
+    pub fn parse(storage: Option<f64>, ingest: Option<f64>, read: Option<f64>, other: QueryMix) -> Result<QueryMix,String> {
+        let mix = QueryMix {
+            storage: storage.unwrap_or(other.storage),
+            ingest: ingest.unwrap_or(other.ingest),
+            read: read.unwrap_or(other.read),
+        };
+        if mix.storage < 0.0 || mix.ingest < 0.0 || mix.read < 0.0 {
+            return Err(format!("Query mix weights can not be negative: {:?}", mix));
+        }
+        if mix.storage + mix.ingest + mix.read == 0.0 {
+            return Err(format!("Query mix needs at least one weight: {:?}", mix));
+        }
+        Ok(mix)
+    }
+}
+
+impl Default for QueryMix {
+    fn default() -> Self {
+        QueryMix::new(1.0, 0.5, 0.5)
+    }
+}
+
+/* Nanoseconds a codec takes per value */
+#[derive(Clone,Copy,Debug,Default,PartialEq)]
+pub struct CodecTiming {
+    pub encode: f64,
+    pub decode: f64,
+}
+
+impl CodecTiming {
+    fn blend(&mut self, other: CodecTiming) {
+        self.encode = (1.0 - TIMING_WEIGHT) * self.encode + TIMING_WEIGHT * other.encode;
+        self.decode = (1.0 - TIMING_WEIGHT) * self.decode + TIMING_WEIGHT * other.decode;
+    }
+}
+
+fn per_value(elapsed: Duration, count: usize) -> f64 {
+    elapsed.as_nanos() as f64 / count as f64
+}
+
+/* Every step-th value up to SELECTOR_SAMPLE, the codecs set the non finite ones aside */
+pub fn sample<T: Copy + Into<f64>>(data: &[T]) -> Vec<f64> {
+    let step = ((data.len() + SELECTOR_SAMPLE - 1) / SELECTOR_SAMPLE).max(1);
+    data.iter().step_by(step).map(|v| (*v).into()).filter(|v: &f64| v.is_finite()).collect()
+}
+
+/* Sprintz rounds the scaled values up, so it only gets back the values
+ * that scale to whole numbers
+ */
+fn sprintz_reproduces(values: &[f64], scale: usize) -> bool {
+    let scl = scale as f64;
+    values.iter().filter(|v| v.is_finite()).all(|v| (v * scl).ceil() / scl == *v)
+}
+
+/* The candidates for the sample with their estimated compression ratio.
+ * The scaled codecs are only tried when they reproduce every value of
+ * the segment, a precision inferred from the sample could round the rest.
+ */
+pub fn candidates(sample: &[f64], values: &[f64]) -> Vec<(Methods,f64)> {
+    let count = sample.len();
+    let prec = infer_precision(values);
+    let mut candidates = Vec::new();
+    if let Some(prec) = prec {
+        let scale = 10usize.pow(prec as u32);
+        candidates.push((Methods::Buff (scale), est_buff_cr(sample.to_vec(), count, prec as usize)));
+        if sprintz_reproduces(values, scale) {
+            candidates.push((Methods::Sprintz (scale), est_sprintz_cr(sample.to_vec(), count, prec as usize)));
+        }
+    }
+    /* The lossless estimators do not look at the precision */
+    let prec = prec.unwrap_or(MAX_INFERRED_PRECISION) as usize;
+    candidates.push((Methods::Gorilla, est_gorilla_cr(sample.to_vec(), count, prec)));
+    candidates.push((Methods::Gzip, est_gzip_cr(sample.to_vec(), count, prec)));
+    candidates.push((Methods::Snappy, est_snappy_cr(sample.to_vec(), count, prec)));
+    candidates
+}
+
+#[derive(Clone)]
+pub struct CodecSelector {
+    default: QueryMix,
+    signals: HashMap<SignalId,QueryMix>,
+    timings: HashMap<u8,CodecTiming>,
+    selected: usize,
+}
+
+impl CodecSelector {
+    pub fn new(default: QueryMix) -> CodecSelector {
+        CodecSelector {
+            default: default,
+            signals: HashMap::new(),
+            timings: HashMap::new(),
+            selected: 0,
+        }
+    }
+
+    pub fn set(&mut self, signal: SignalId, mix: QueryMix) {
+        self.signals.insert(signal, mix);
+    }
+
+    pub fn mix_for(&self, signal: SignalId) -> QueryMix {
+        *self.signals.get(&signal).unwrap_or(&self.default)
+    }
+
+    pub fn get_timing(&self, method: &Methods) -> Option<CodecTiming> {
+        self.timings.get(&method.codec_id()).cloned()
+    }
+
+    /* The sample and candidates of the segment, None when it has no finite
+     * value to sample. The candidates need timing when remeasure is set.
+     */
+    pub fn prepare<T: Copy + Into<f64>>(&mut self, seg: &Segment<T>) -> Option<Selection> {
+        let sample = sample(seg.get_data());
+        if sample.is_empty() {
+            return None;
+        }
+        let values: Vec<f64> = seg.get_data().iter().map(|v| (*v).into()).collect();
+        let candidates = candidates(&sample, &values);
+        let untimed = candidates.iter().any(|(method, _)| self.get_timing(method).is_none());
+        let remeasure = untimed || self.selected % REMEASURE_EVERY == 0;
+        self.selected += 1;
+        Some(Selection { signal: seg.get_signal(), sample, candidates, remeasure })
+    }
+
+    /* Folds the timings into the moving averages and picks the candidate with the lowest cost */
+    pub fn choose(&mut self, selection: Selection, measured: Vec<(u8,CodecTiming)>) -> Methods {
+        for (codec_id, timing) in measured {
+            self.timings.entry(codec_id)
+                .and_modify(|avg| avg.blend(timing))
+                .or_insert(timing);
+        }
+
+        let mix = self.mix_for(selection.signal);
+        let timings: Vec<CodecTiming> = match selection.candidates.iter().map(|(method, _)| self.get_timing(method)).collect() {
+            Some(timings) => timings,
+            None => return FALLBACK,
+        };
+        let slowest_encode = timings.iter().fold(0.0f64, |max, t| max.max(t.encode));
+        let slowest_decode = timings.iter().fold(0.0f64, |max, t| max.max(t.decode));
+        let relative = |time: f64, slowest: f64| if slowest > 0.0 { time / slowest } else { 0.0 };
+
+        let mut best = (FALLBACK, std::f64::MAX);
+        for ((method, ratio), timing) in selection.candidates.into_iter().zip(timings) {
+            let cost = mix.storage * ratio
+                + mix.ingest * relative(timing.encode, slowest_encode)
+                + mix.read * relative(timing.decode, slowest_decode);
+            if cost < best.1 {
+                best = (method, cost);
+            }
+        }
+        info!("Selected {} for signal {} at cost {}", best.0, selection.signal, best.1);
+        best.0
+    }
+
+    /* The method the cost model picks for the segment */
+    pub fn select<T: Copy + Into<f64>>(&mut self, seg: &Segment<T>) -> Methods {
+        match self.prepare(seg) {
+            Some(selection) => {
+                let measured = selection.measure();
+                self.choose(selection, measured)
+            }
+            None => FALLBACK,
+        }
+    }
+}
+
+/* What the selector needs to pick a method for one segment */
+pub struct Selection {
+    signal: SignalId,
+    sample: Vec<f64>,
+    candidates: Vec<(Methods,f64)>,
+    remeasure: bool,
+}
+
+impl Selection {
+    /* Times every candidate encoding and decoding the sample if the
+     * selector asked for it, the selector is not needed while it runs
+     */
+    pub fn measure(&self) -> Vec<(u8,CodecTiming)> {
+        if !self.remeasure {
+            return Vec::new();
+        }
+        let sample = &self.sample;
+        self.candidates.iter().map(|(method, _)| {
+            let codec = codec_for(method).unwrap();
+            let mut seg = Segment::new(None, SystemTime::now(), 0, sample.to_vec(), None, None);
+            let start = Instant::now();
+            codec.encode(&mut seg);
+            let encode = per_value(start.elapsed(), sample.len());
+            let start = Instant::now();
+            codec.decode(&seg);
+            let decode = per_value(start.elapsed(), sample.len());
+            (method.codec_id(), CodecTiming { encode, decode })
+        }).collect()
+    }
+}
+
+/* Runs the daemons with the method the selector picks for each segment */
+pub struct AutoCompress {
+    batchsize: usize,
+    selector: Mutex<CodecSelector>,
+}
+
+impl AutoCompress {
+    pub fn new(batchsize: usize, selector: CodecSelector) -> Self {
+        AutoCompress { batchsize, selector: Mutex::new(selector) }
+    }
+
+    pub fn encode<'a, T>(&self, seg: &mut Segment<T>)
+        where T: Num + FromPrimitive + Copy + Send + FFTnum + Into<f64> + Serialize + Deserialize<'a>
+    {
+        /* The candidates are timed without holding the selector, so other daemons can select meanwhile */
+        let selection = match self.selector.lock() {
+            Ok(mut selector) => selector.prepare(seg),
+            Err(_) => None,
+        };
+        let method = match selection {
+            Some(selection) => {
+                let measured = selection.measure();
+                match self.selector.lock() {
+                    Ok(mut selector) => selector.choose(selection, measured),
+                    Err(_) => FALLBACK,
+                }
+            }
+            None => FALLBACK,
+        };
+        codec_with_batch(&method, self.batchsize).unwrap().encode(seg);
+    }
+}
+
+impl<'a, T> CompressionMethod<T> for AutoCompress
+    where T: Num + FromPrimitive + Copy + Send + FFTnum + Into<f64> + Serialize + Deserialize<'a>
+{
+    fn get_segments(&self) {
+        unimplemented!()
+    }
+
+    fn get_batch(&self) -> usize {
+        self.batchsize
+    }
+
+    fn run_compress<'b>(&self, segs: &mut Vec<Segment<T>>) {
+        for seg in segs {
+            self.encode(seg);
+        }
+    }
+
+    fn run_single_compress(&self, seg: &mut Segment<T>) {
+        self.encode(seg);
+    }
+
+    /* The segment's own method says how it was encoded */
+    fn run_decompress(&self, seg: &mut Segment<T>) {
+        let codec = match seg.get_method() {
+            Some(method) => codec_for(method),
+            None => None,
+        };
+        if let Some(codec) = codec {
+            codec.decompress(seg);
+        }
+    }
+}
+
+#[test]
+fn codec_selector_test() {
+    use crate::methods::registry::decode_segment;
+
+    /* Readings with two decimal places */
+    let data: Vec<f64> = (0..2000).map(|x| ((x as f64 * 0.01).sin() * 5000.0).round() / 100.0).collect();
+    let sampled = sample(&data);
+    assert!(sampled.len() <= SELECTOR_SAMPLE);
+    let estimates = candidates(&sampled, &data);
+    assert!(estimates.contains(&(Methods::Buff (100), est_buff_cr(sampled.clone(), sampled.len(), 2))));
+    /* Sprintz rounds up, so it would turn 8.46 into 8.47 */
+    assert!(!sprintz_reproduces(&[8.46], 100));
+    assert!(estimates.iter().all(|(m, _)| m != &Methods::Sprintz (100)));
+    assert!(candidates(&[0.5, -1.5, 2.0], &[0.5, -1.5, 2.0]).iter().any(|(m, _)| m == &Methods::Sprintz (10)));
+
+    /* Without a precision that reproduces the segment only the lossless codecs are tried */
+    let mut thirds = data.clone();
+    thirds[1999] = 1.0 / 3.0;
+    let lossless: Vec<Methods> = candidates(&sampled, &thirds).into_iter().map(|(m, _)| m).collect();
+    assert_eq!(lossless, vec![Methods::Gorilla, Methods::Gzip, Methods::Snappy]);
+
+    /* Only storage counts, the smallest estimate wins */
+    let smallest = estimates.iter().fold((FALLBACK, std::f64::MAX), |best, (m, r)| if *r < best.1 { (m.clone(), *r) } else { best }).0;
+    let mut selector = CodecSelector::new(QueryMix::new(1.0, 0.0, 0.0));
+    let seg = Segment::new(None, SystemTime::now(), 1, data.clone(), None, None);
+    assert_eq!(selector.select(&seg), smallest);
+    assert!(selector.get_timing(&Methods::Gzip).is_some());
+
+    /* A signal that is only read gets the candidate that decoded fastest */
+    selector.set(2, QueryMix::parse(Some(0.0), None, Some(1.0), QueryMix::new(0.0, 0.0, 0.0)).unwrap());
+    let seg = Segment::new(None, SystemTime::now(), 2, data.clone(), None, None);
+    let chosen = selector.select(&seg);
+    let fastest = estimates.iter()
+        .map(|(m, _)| (m.clone(), selector.get_timing(m).unwrap().decode))
+        .fold((FALLBACK, std::f64::MAX), |best, (m, d)| if d < best.1 { (m, d) } else { best }).0;
+    assert_eq!(chosen, fastest);
+    assert!(QueryMix::parse(Some(0.0), Some(0.0), Some(0.0), QueryMix::default()).is_err());
+
+    /* The daemon codec encodes each segment with what was selected for it */
+    let auto = AutoCompress::new(10, CodecSelector::new(QueryMix::default()));
+    let mut segs = vec![Segment::new(None, SystemTime::now(), 1, data.clone(), None, None),
+                        Segment::new(None, SystemTime::now(), 1, vec![std::f64::NAN; 10], None, None)];
+    auto.run_compress(&mut segs);
+    assert_eq!(segs[1].get_method(), &Some(FALLBACK));
+    /* The scaled codecs give the values back at their scale */
+    let at_scale = |b: f64| match segs[0].get_method() {
+        Some(Methods::Buff (scale)) | Some(Methods::Sprintz (scale)) => (b * *scale as f64).round() / *scale as f64,
+        _ => b,
+    };
+    for (a, b) in data.iter().zip(decode_segment(&segs[0]).unwrap().iter()) {
+        assert_eq!(*a, at_scale(*b), "{} came back as {}", a, b);
+    }
+}
@@ -20,7 +20,8 @@ pub fn est_buff_cr(vec:Vec<f64>, sample_count:usize, prec:usize) -> f64{
     for &val in sample{
         if val<min{
             min = val;
-        }else if val>max {
+        }
+        if val>max {
             max = val;
         }
     }
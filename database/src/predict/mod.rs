@@ -1 +1,2 @@
-pub mod cr_predictor;
\ No newline at end of file
+pub mod cr_predictor;
+pub mod codec_selector;
@@ -1,17 +1,122 @@
 pub mod bit_vec_iter;
 
-use std::time::SystemTime;
+use std::time::{SystemTime, UNIX_EPOCH, Duration};
 use std::fmt;
-use crate::buffer_pool::SegmentBuffer;
-use crate::segment::Segment;
+use std::sync::{Arc, Mutex};
+use crate::buffer_pool::{SegmentBuffer, BufErr};
+use crate::segment::{Segment, SegmentIter};
+use crate::segment::validity::Nullable;
+use crate::future_signal::SignalId;
+use crate::catalog::SignalCatalog;
+use crate::methods::Methods;
+use crate::methods::registry::codec_for;
+use rustfft::FFTnum;
 use num::{FromPrimitive, abs, Signed};
 use std::ops::Div;
 use std::ops::Add;
 use num::Num;
 use std::cmp::Ord;
+use itertools::process_results;
 
 /* will contain a struct containing all of statistics for each block*/
 
+/* Upper bound used when a query has no end timestamp */
+const OPEN_END_SECS: u64 = 1 << 40;
+
+/* Streams the segments of the signal holding points between the provided
+ * timestamps, a missing start or end leaves that side of the range open.
+ * The segment that starts before the range and runs into it is included.
+ */
+pub fn segments_in_range<T: Copy + Send>(signal: SignalId, start: Option<SystemTime>, end: Option<SystemTime>,
+                                         buffer: Arc<Mutex<SegmentBuffer<T>>>) -> Result<SegmentIter<T>, BufErr> {
+    let start = start.unwrap_or(UNIX_EPOCH);
+    let end = end.unwrap_or(UNIX_EPOCH + Duration::from_secs(OPEN_END_SECS));
+    SegmentIter::covering(signal, start, end, buffer)
+}
+
+/* Runs a query over every signal the selector addresses in the catalog,
+ * by name, tags or id, see SignalSelector
+ */
+pub fn run_selected<R, Q>(catalog: &SignalCatalog, selector: &str, mut query: Q) -> Result<Vec<(SignalId, R)>, BufErr>
+    where Q: FnMut(SignalId) -> Result<R, BufErr>
+{
+    catalog.resolve(selector).into_iter()
+        .map(|signal| query(signal).map(|r| (signal, r)))
+        .collect()
+}
+
+/* Restores the values of a compressed segment through the codec registry,
+ * segments whose codec needs a dictionary are passed through unchanged
+ */
+fn decoded<T: Num + FromPrimitive + Copy + Send + FFTnum + Into<f64>>(mut seg: Segment<T>) -> Segment<T> {
+    let codec = match seg.get_method() {
+        Some(Methods::Uncompr) | None => None,
+        Some(m) => codec_for(m),
+    };
+    if let Some(codec) = codec {
+        codec.decompress(&mut seg);
+    }
+    seg
+}
+
+/* Drops the points of a decoded segment whose own time falls outside
+ * the range along with their times and set aside values, segments
+ * without a time per value are left whole
+ */
+fn clipped<T: Copy>(mut seg: Segment<T>, start: Option<SystemTime>, end: Option<SystemTime>) -> Segment<T> {
+    let points = match seg.get_time_lapse() {
+        Some(lapse) if lapse.len() == seg.get_data().len() => lapse.len(),
+        _ => return seg,
+    };
+    let start = start.unwrap_or(UNIX_EPOCH);
+    let end = end.unwrap_or(UNIX_EPOCH + Duration::from_secs(OPEN_END_SECS));
+    let rows = seg.time_filter(start, end);
+    if rows.cardinality() as usize == points {
+        return seg;
+    }
+    seg.retain_rows(&rows);
+    seg
+}
+
+/* Like segments_in_range but yields the decoded segments, clipped to
+ * the points whose timestamps lie in the range. The aggregations stop
+ * at the first segment that fails to load.
+ */
+fn decoded_in_range<T: Num + FromPrimitive + Copy + Send + FFTnum + Into<f64>>(signal: SignalId, start: Option<SystemTime>, end: Option<SystemTime>,
+                    buffer: Arc<Mutex<SegmentBuffer<T>>>) -> Result<impl Iterator<Item=Result<Segment<T>, BufErr>>, BufErr> {
+    Ok(segments_in_range(signal, start, end, buffer)?.map(move |seg| seg.map(decoded).map(|seg| clipped(seg, start, end))))
+}
+
+/* The aggregations skip missing values and NaNs, see Nullable::is_undefined */
+fn count_of<T: Nullable, I: Iterator<Item=Segment<T>>>(segs: I) -> usize {
+    segs.map(|x| x.get_data().iter().filter(|v| !v.is_undefined()).count()).sum()
+}
+
+fn min_of<T: Copy + PartialOrd + Nullable, I: Iterator<Item=Segment<T>>>(segs: I) -> Option<T> {
+    segs.filter_map(|s| s.get_data().iter().filter(|v| !v.is_undefined()).fold(None, |min, x| match min {
+        None => Some(*x),
+        Some(y) => Some(if *x < y { *x } else { y }),
+    })).fold(None, |min, x| match min {
+        None => Some(x),
+        Some(y) => Some(if x < y { x } else { y }),
+    })
+}
+
+fn max_of<T: Copy + PartialOrd + Nullable, I: Iterator<Item=Segment<T>>>(segs: I) -> Option<T> {
+    segs.filter_map(|s| s.get_data().iter().filter(|v| !v.is_undefined()).fold(None, |max, x| match max {
+        None => Some(*x),
+        Some(y) => Some(if *x > y { *x } else { y }),
+    })).fold(None, |max, x| match max {
+        None => Some(x),
+        Some(y) => Some(if x > y { x } else { y }),
+    })
+}
+
+fn sum_of<T: Num + Copy + Nullable, I: Iterator<Item=Segment<T>>>(segs: I) -> T {
+    let zero = T::zero();
+    segs.map(|x| x.get_data().iter().filter(|v| !v.is_undefined()).fold(zero, |sum, &i| sum + i)).fold(zero, |sum, i| sum + i)
+}
+
 
 //
 ///* An enum holding every supported aggregation query
@@ -55,8 +160,16 @@ impl Count {
     *   run query on whole signal.
     */
 
-    pub fn run<T: Copy + Send + Num + Add>(signals: &SegmentBuffer<T>) -> usize{
-        signals.copy().iter().map(|x|x.get_data().len()).sum()
+    pub fn run<T: Copy + Send + Num + Add + Nullable>(signals: &SegmentBuffer<T>) -> usize{
+        count_of(signals.copy().into_iter())
+    }
+
+    /*
+    *   run query on the segments of one signal within the time range.
+    */
+
+    pub fn run_range<T: Copy + Send + Num + Add + FFTnum + Into<f64> + Nullable>(&self, signal: SignalId, buffer: Arc<Mutex<SegmentBuffer<T>>>) -> Result<usize, BufErr> {
+        process_results(decoded_in_range(signal, self.star_timestamp, self.end_timestamp, buffer)?, |segs| count_of(segs))
     }
 
 }
@@ -81,16 +194,17 @@ impl Min {
     *   run query on whole signal.
     */
 
-    pub fn run<T: Num + Copy + Send + FromPrimitive + PartialOrd>(signals: &SegmentBuffer<T>) -> T {
+    pub fn run<T: Num + Copy + Send + FromPrimitive + PartialOrd + Nullable>(signals: &SegmentBuffer<T>) -> T {
+        min_of(signals.copy().into_iter()).unwrap()
+    }
 
-        signals.copy().iter().map(|s| s.get_data().iter().fold(None, |min, x| match min {
-            None => Some(x),
-            Some(y) => Some(if x < y { x } else { y }),
-        })).fold(None, |min, x| match min {
-            None => Some(x),
-            Some(y) => Some(if x < y { x } else { y }),
-        }).unwrap().unwrap().to_owned()
+    /*
+    *   run query on the segments of one signal within the time range,
+    *   returns None when the range holds no values.
+    */
 
+    pub fn run_range<T: Num + Copy + Send + FromPrimitive + PartialOrd + FFTnum + Into<f64> + Nullable>(&self, signal: SignalId, buffer: Arc<Mutex<SegmentBuffer<T>>>) -> Result<Option<T>, BufErr> {
+        process_results(decoded_in_range(signal, self.star_timestamp, self.end_timestamp, buffer)?, |segs| min_of(segs))
     }
 }
 
@@ -113,16 +227,17 @@ impl Max {
     *   run query on whole signal.
     */
 
-    pub fn run<T: Num + Copy + Send + FromPrimitive + PartialOrd>(signals: &SegmentBuffer<T>) -> T {
+    pub fn run<T: Num + Copy + Send + FromPrimitive + PartialOrd + Nullable>(signals: &SegmentBuffer<T>) -> T {
+        max_of(signals.copy().into_iter()).unwrap()
+    }
 
-        signals.copy().iter().map(|s| s.get_data().iter().fold(None, |min, x| match min {
-            None => Some(x),
-            Some(y) => Some(if x > y { x } else { y }),
-        })).fold(None, |min, x| match min {
-            None => Some(x),
-            Some(y) => Some(if x > y { x } else { y }),
-        }).unwrap().unwrap().to_owned()
+    /*
+    *   run query on the segments of one signal within the time range,
+    *   returns None when the range holds no values.
+    */
 
+    pub fn run_range<T: Num + Copy + Send + FromPrimitive + PartialOrd + FFTnum + Into<f64> + Nullable>(&self, signal: SignalId, buffer: Arc<Mutex<SegmentBuffer<T>>>) -> Result<Option<T>, BufErr> {
+        process_results(decoded_in_range(signal, self.star_timestamp, self.end_timestamp, buffer)?, |segs| max_of(segs))
     }
 }
 
@@ -144,9 +259,16 @@ impl Sum {
     *   run query on whole signal.
     */
 
-    pub fn run<T: Num + Div + Copy + Send + Add<T, Output = T> + Signed + FromPrimitive>(signals: &SegmentBuffer<T>) -> T{
-        let zero = T::zero();
-        signals.copy().iter().map(|x|x.get_data().iter().fold(zero, |sum, &i| sum + i)).fold(zero, |sum, i| sum + i)
+    pub fn run<T: Num + Div + Copy + Send + Add<T, Output = T> + Signed + FromPrimitive + Nullable>(signals: &SegmentBuffer<T>) -> T{
+        sum_of(signals.copy().into_iter())
+    }
+
+    /*
+    *   run query on the segments of one signal within the time range.
+    */
+
+    pub fn run_range<T: Num + Div + Copy + Send + Add<T, Output = T> + Signed + FromPrimitive + FFTnum + Into<f64> + Nullable>(&self, signal: SignalId, buffer: Arc<Mutex<SegmentBuffer<T>>>) -> Result<T, BufErr> {
+        process_results(decoded_in_range(signal, self.star_timestamp, self.end_timestamp, buffer)?, |segs| sum_of(segs))
     }
 }
 
@@ -165,9 +287,59 @@ impl Average {
         }
     }
 
-    pub fn run< T:  Num + Div + Copy + Send + Add<T, Output = T> + Signed + FromPrimitive>(signals: &SegmentBuffer<T>) -> T{
+    pub fn run< T:  Num + Div + Copy + Send + Add<T, Output = T> + Signed + FromPrimitive + Nullable>(signals: &SegmentBuffer<T>) -> T{
         let sum  = Sum::run(signals);
         let count = Count::run(signals);
         (sum)/FromPrimitive::from_usize(count).unwrap()
     }
-}
\ No newline at end of file
+
+    /*
+    *   run query on the segments of one signal within the time range,
+    *   returns None when the range holds no values.
+    */
+
+    pub fn run_range<T: Num + Div + Copy + Send + Add<T, Output = T> + Signed + FromPrimitive + FFTnum + Into<f64> + Nullable>(&self, signal: SignalId, buffer: Arc<Mutex<SegmentBuffer<T>>>) -> Result<Option<T>, BufErr> {
+        let segs: Vec<Segment<T>> = decoded_in_range(signal, self.star_timestamp, self.end_timestamp, buffer)?.collect::<Result<_, _>>()?;
+        let count = count_of(segs.iter().cloned());
+        if count == 0 {
+            return Ok(None);
+        }
+        let sum = sum_of(segs.into_iter());
+        Ok(Some(sum / FromPrimitive::from_usize(count).unwrap()))
+    }
+}
+#[test]
+fn test_run_range_boundaries() {
+    use crate::buffer_pool::NoFmClockBuffer;
+
+    /* Two segments a second apart with a point every 100ms */
+    let buffer: Arc<Mutex<SegmentBuffer<f64>>> = Arc::new(Mutex::new(NoFmClockBuffer::<f64>::new(2)));
+    for i in 1..3u64 {
+        let lapse = (0..10).map(|j| Duration::from_millis(j * 100)).collect();
+        let seg = Segment::new(None, UNIX_EPOCH + Duration::from_secs(i), 0, vec![1.0f64; 10], Some(lapse), None);
+        buffer.lock().unwrap().put(seg).unwrap();
+    }
+    let at = |ms| Some(UNIX_EPOCH + Duration::from_millis(ms));
+
+    /* The start is in the range and the end is not, for segments and points alike */
+    assert_eq!(Count::new(at(1000), at(2000)).run_range(0, buffer.clone()).unwrap(), 10);
+    assert_eq!(Count::new(at(1000), at(1500)).run_range(0, buffer.clone()).unwrap(), 5);
+    assert_eq!(Count::new(at(1000), at(2001)).run_range(0, buffer.clone()).unwrap(), 11);
+    assert_eq!(Count::new(at(1000), at(1000)).run_range(0, buffer.clone()).unwrap(), 0);
+    assert_eq!(Sum::new(at(2000), None).run_range(0, buffer.clone()).unwrap(), 10.0);
+    /* A range that starts in the middle of a segment counts the rest of it */
+    assert_eq!(Count::new(at(1500), at(2500)).run_range(0, buffer.clone()).unwrap(), 10);
+    assert_eq!(Sum::new(at(1950), None).run_range(0, buffer).unwrap(), 10.0);
+
+    /* Clipping keeps the point times and set aside values of the kept rows */
+    let null = f64::null().unwrap();
+    let lapse: Vec<Duration> = (0..6).map(|j| Duration::from_millis(j * 100)).collect();
+    let mut seg = Segment::new(None, UNIX_EPOCH, 0, vec![1.0, null, 3.0, std::f64::NAN, 5.0, 6.0], Some(lapse), None);
+    let (filled, kept) = crate::segment::validity::split(seg.get_data()).unwrap();
+    seg.set_data(filled);
+    seg.set_validity(Some(kept));
+    let seg = clipped(seg, at(200), at(500));
+    assert_eq!(seg.get_data(), &vec![3.0, 3.0, 5.0]);
+    assert_eq!(seg.get_time_lapse(), &Some(vec![Duration::from_millis(200), Duration::from_millis(300), Duration::from_millis(400)]));
+    assert_eq!(seg.get_validity().as_ref().unwrap().get_rows().iter().collect::<Vec<u32>>(), vec![1]);
+}
@@ -1,5 +1,6 @@
 use std::env;
-use std::time::{Instant, SystemTime};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use time_series_start::outlier::gen_vector_indices;
 use time_series_start::client::construct_file_iterator_skip_newline;
 use time_series_start::compress::split_double::SplitBDDoubleCompress;
@@ -7,6 +8,8 @@ use time_series_start::compress::gorilla::{GorillaCompress, GorillaBDCompress};
 use time_series_start::methods::compress::{SnappyCompress, GZipCompress};
 use time_series_start::compress::sprintz::SprintzDoubleCompress;
 use time_series_start::segment::Segment;
+use time_series_start::buffer_pool::{SegmentBuffer, NoFmClockBuffer};
+use time_series_start::query::{Count, Min, Max, Sum, Average};
 use log::{error, info, warn};
 
 fn main() {
@@ -17,6 +20,10 @@ fn main() {
     info!("input args{:?}", args);
     let input_file = &args[1];
     let compression = &args[2];
+    if compression == "range" {
+        run_range_query(input_file, &args[3..]);
+        return;
+    }
     let scl = args[3].parse::<usize>().unwrap();
     let size = args[4].parse::<usize>().unwrap();
     let o_ratio = args[5].parse::<f64>().unwrap();
@@ -128,3 +135,38 @@ fn main() {
 
 
 }
+
+/*
+ * Loads the file into a buffer as one segment a second and runs an
+ * aggregation over the segments in [start, end), both in seconds.
+ * usage: query <input file> range <count|min|max|sum|avg> <segment size> [start] [end]
+ */
+fn run_range_query(input_file: &str, args: &[String]) {
+    let aggregation = &args[0];
+    let seg_size = args[1].parse::<usize>().unwrap();
+    let start = args.get(2).map(|s| UNIX_EPOCH + Duration::from_secs(s.parse::<u64>().unwrap()));
+    let end = args.get(3).map(|s| UNIX_EPOCH + Duration::from_secs(s.parse::<u64>().unwrap()));
+
+    let file_vec: Vec<f64> = construct_file_iterator_skip_newline::<f64>(input_file, 0, ',').unwrap().collect();
+    let segs = (file_vec.len() + seg_size - 1) / seg_size;
+    let buffer: Arc<Mutex<SegmentBuffer<f64>>> = Arc::new(Mutex::new(NoFmClockBuffer::<f64>::new(segs.max(1))));
+    for (i, chunk) in file_vec.chunks(seg_size).enumerate() {
+        let seg = Segment::new(None, UNIX_EPOCH + Duration::from_secs(i as u64), 0, chunk.to_vec(), None, None);
+        buffer.lock().unwrap().put(seg).expect("Failed to put segment");
+    }
+
+    let start_time = Instant::now();
+    let res = match aggregation.as_str() {
+        "count" => Count::new(start, end).run_range(0, buffer).map(|c| format!("{}", c)),
+        "min" => Min::new(start, end).run_range(0, buffer).map(|v| format!("{:?}", v)),
+        "max" => Max::new(start, end).run_range(0, buffer).map(|v| format!("{:?}", v)),
+        "sum" => Sum::new(start, end).run_range(0, buffer).map(|v| format!("{}", v)),
+        "avg" => Average::new(start, end).run_range(0, buffer).map(|v| format!("{:?}", v)),
+        x => panic!("Aggregation {} not supported yet.", x),
+    };
+    let duration = start_time.elapsed();
+    match res {
+        Ok(res) => println!("res:{},{},{},{:?}", input_file, aggregation, res, duration),
+        Err(e) => panic!("Failed to run {} over the range: {:?}", aggregation, e),
+    }
+}
@@ -1,4 +1,3 @@
-use rocksdb::DBVector;
 use crate::buffer_pool::{BufErr};
 use serde::Serialize;
 use serde::de::DeserializeOwned;
@@ -6,7 +5,7 @@ use crate::segment::{Segment, SegmentKey};
 use std::sync::{Arc,Mutex};
 use crate::buffer_pool::{SegmentBuffer,ClockBuffer};
 use crate::file_handler::{FileManager};
-use crate::{file_handler, GZipCompress, ZlibCompress, DeflateCompress, SnappyCompress, PAACompress, FourierCompress};
+use crate::{PAACompress, FourierCompress};
 use crate::methods::compress::CompressionMethod;
 use std::any::Any;
 use std::{fs, thread};
@@ -24,11 +23,10 @@ use smartcore::math::num::RealNumber;
 use crate::buffer_pool::BufErr::BufEmpty;
 use crate::compress::buff_lossy::BUFFlossy;
 use crate::methods::{IsLossless, Methods};
-use crate::compress::split_double::SplitBDDoubleCompress;
-use crate::compress::sprintz::SprintzDoubleCompress;
-use crate::compress::gorilla::{GorillaBDCompress, GorillaCompress};
+use crate::methods::registry::codec_for;
 use crate::compress::pla_lttb::PLACompress;
 use crate::compress::rrd_sample::RRDsample;
+use crate::shutdown::Shutdown;
 
 pub fn GetMatrix<T:RealNumber> (seg: &Segment<T>) -> DenseMatrix<T>{
 	let x = DenseMatrix::from_array(
@@ -44,7 +42,7 @@ pub fn GetMatrix<T:RealNumber> (seg: &Segment<T>) -> DenseMatrix<T>{
 
 pub struct RecodingDaemon<T,U>
 	where T: Copy + Send + Serialize + DeserializeOwned + RealNumber,
-	      U: FileManager<Vec<u8>,DBVector> + Sync + Send,
+	      U: FileManager<Vec<u8>,Vec<u8>> + Sync + Send,
 {
 	seg_buf: Arc<Mutex<SegmentBuffer<T> + Send + Sync>>,
 	comp_seg_buf: Arc<Mutex<SegmentBuffer<T> + Send + Sync>>,
@@ -55,12 +53,13 @@ pub struct RecodingDaemon<T,U>
 	tcr : f64,
 	batch: usize,
 	lossy: Methods,
-	nocomp: bool
+	nocomp: bool,
+	shutdown: Shutdown,
 }
 
 impl<T,U> RecodingDaemon<T,U>
 	where T: 'static + Copy + Send + Serialize + DeserializeOwned + FromPrimitive + Num+ FFTnum+Into<f64> + RealNumber,
-		  U: FileManager<Vec<u8>,DBVector> + Sync + Send,
+		  U: FileManager<Vec<u8>,Vec<u8>> + Sync + Send,
 {
 	pub fn new(seg_buf: Arc<Mutex<SegmentBuffer<T> + Send + Sync>>,
 			   comp_seg_buf: Arc<Mutex<SegmentBuffer<T> + Send + Sync>>,
@@ -85,7 +84,8 @@ impl<T,U> RecodingDaemon<T,U>
 			tcr: 0.0,
 			batch: batch,
 			lossy: lossy,
-			nocomp: nocomp
+			nocomp: nocomp,
+			shutdown: Shutdown::new(),
 		}
 	}
 	pub fn set_targetCR(&mut self, tcr: f64){
@@ -93,8 +93,16 @@ impl<T,U> RecodingDaemon<T,U>
 		self.tcr = tcr;
 	}
 
+	/* Once the run shuts down the daemon exits after the batch it is recoding */
+	pub fn set_shutdown(&mut self, shutdown: Shutdown){
+		self.shutdown = shutdown;
+	}
+
 	fn get_seg_from_uncomp_buf(&self) -> Result<Vec<Segment<T>>,BufErr>
 	{
+		if self.shutdown.is_stopping() {
+			return Err(BufErr::BufEmpty);
+		}
 		match self.seg_buf.lock() {
 			Ok(mut buf) => {
 				//println!("Lock aquired");
@@ -263,7 +271,8 @@ impl<T,U> RecodingDaemon<T,U>
 					println!("segment recoded {}", self.processed);
 					match &self.file_manager {
 						Some(fm) => {
-							for mut seg in segs{
+							let mut batch = Vec::with_capacity(segs.len());
+							for seg in segs{
 								let key_bytes = match seg.get_key().convert_to_bytes() {
 									Ok(bytes) => bytes,
 									Err(_) => continue, /* silence failure to byte convert */
@@ -272,10 +281,11 @@ impl<T,U> RecodingDaemon<T,U>
 									Ok(bytes) => bytes,
 									Err(_) => continue, /* silence failure to byte convert */
 								};
-								match fm.fm_write(key_bytes, seg_bytes) {
-									Ok(()) => continue,
-									Err(_) => continue, /* currently silence error from fialed write */
-								}
+								batch.push((key_bytes, seg_bytes));
+							}
+							match fm.fm_write_batch(batch) {
+								Ok(()) => (),
+								Err(_) => (), /* currently silence error from fialed write */
 							}
 						}
 						None => {
@@ -291,34 +301,9 @@ impl<T,U> RecodingDaemon<T,U>
 									// println!("segment key: {:?} with comp time:{}",seg.get_key(),seg.get_comp_times());
 									if self.nocomp == false{
 										match seg.get_method().as_ref().unwrap() {
-											Methods::Sprintz (scale) => {
-												let pre = SprintzDoubleCompress::new(10, 20,*scale);
-												pre.run_decompress(seg);
-
-												self.lossy_comp_with_tcr(seg);
-
-											},
-											Methods::Buff(scale)=> {
-												let pre = SplitBDDoubleCompress::new(10,20, *scale);
-												pre.run_decompress(seg);
-
-												self.lossy_comp_with_tcr(seg);
-											},
-											Methods::Snappy=> {
-												let pre = SnappyCompress::new(10,20);
-												pre.run_decompress(seg);
-
-												self.lossy_comp_with_tcr(seg);
-											},
-											Methods::Gzip => {
-												let pre = GZipCompress::new(10,20);
-												pre.run_decompress(seg);
-
-												self.lossy_comp_with_tcr(seg);
-											},
-											Methods::Gorilla => {
-												let pre = GorillaCompress::new(10,20);
-												pre.run_decompress(seg);
+											m if IsLossless(m) => {
+												let codec = codec_for(m).unwrap();
+												codec.decompress(seg);
 
 												self.lossy_comp_with_tcr(seg);
 											},
@@ -337,9 +322,6 @@ impl<T,U> RecodingDaemon<T,U>
 											Methods::Rrd_sample => {
 												// do nothing
 											}
-											Methods::Zlib => {
-											},
-
 											_ => todo!()
 										}
 										seg.update_comp_times();
@@ -351,34 +333,9 @@ impl<T,U> RecodingDaemon<T,U>
 								for seg in &mut segs {
 									// println!("segment key: {:?} with comp time:{}",seg.get_key(),seg.get_comp_times());
 									match seg.get_method().as_ref().unwrap() {
-										Methods::Sprintz (scale) => {
-											let pre = SprintzDoubleCompress::new(10, 20,*scale);
-											pre.run_decompress(seg);
-
-											self.lossy_comp(seg);
-
-										},
-										Methods::Buff(scale)=> {
-											let pre = SplitBDDoubleCompress::new(10,20, *scale);
-											pre.run_decompress(seg);
-
-											self.lossy_comp(seg);
-										},
-										Methods::Snappy=> {
-											let pre = SnappyCompress::new(10,20);
-											pre.run_decompress(seg);
-
-											self.lossy_comp(seg);
-										},
-										Methods::Gzip => {
-											let pre = GZipCompress::new(10,20);
-											pre.run_decompress(seg);
-
-											self.lossy_comp(seg);
-										},
-										Methods::Gorilla => {
-											let pre = GorillaCompress::new(10,20);
-											pre.run_decompress(seg);
+										m if IsLossless(m) => {
+											let codec = codec_for(m).unwrap();
+											codec.decompress(seg);
 
 											self.lossy_comp(seg);
 										},
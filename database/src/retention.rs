@@ -0,0 +1,359 @@
+use std::collections::HashMap;
+use std::sync::{Arc,Mutex};
+use std::thread;
+use std::time::{Duration,Instant,SystemTime,UNIX_EPOCH};
+
+use crate::buffer_pool::{SegmentBuffer,BufErr,Expired,share_buffer};
+use crate::catalog::SignalCatalog;
+use crate::future_signal::SignalId;
+use crate::shutdown::{Shutdown,ShutdownHandle};
+
+/*
+ * Overview:
+ * Retention rules bound how long the segments of a signal are kept, one
+ * period for the raw pool and one for the compressed pool, e.g. raw
+ * segments for 7 days and compressed ones for 90. Without them every
+ * segment stayed in the file managers for good.
+ *
+ * Design Choice:
+ * The RetentionDaemon wakes up every interval and asks both pools to
+ * expire the segments of every cataloged signal that are older than the
+ * period of its rule. Expiry goes through the buffers, which drop the
+ * segments from memory and delete them from their file manager, so no
+ * buffer is left holding a segment that no longer exists on disk.
+ * The catalog is kept in step and the daemon counts what it reclaimed.
+ * Signals without a rule of their own use the default rule, a period
+ * that is not set keeps the segments of that pool forever. Age is taken
+ * from the last point of a segment, so a segment is only expired once
+ * every point in it is past the period.
+ * Expired segments leave no dictionary references behind: no codec
+ * writes one and the dictionary trackers are not implemented, so no
+ * dictionary is ever persisted. Once they are, expiry has to release
+ * the references of the segments it deletes.
+ */
+
+/* Milliseconds between two passes when the config does not provide them */
+pub const DEFAULT_RETENTION_INTERVAL: u64 = 60_000;
+
+/* Reads periods like "45s", "30m", "12h", "7d" or "2w", a bare number is in seconds */
+pub fn parse_duration(text: &str) -> Option<Duration> {
+	let text = text.trim();
+	let (value, unit) = match text.find(|c: char| !c.is_ascii_digit()) {
+		Some(idx) => (&text[..idx], text[idx..].trim()),
+		None => (text, "s"),
+	};
+	let value: u64 = value.parse().ok()?;
+	let secs = match unit {
+		"s" => 1,
+		"m" => 60,
+		"h" => 60 * 60,
+		"d" => 24 * 60 * 60,
+		"w" => 7 * 24 * 60 * 60,
+		_ => return None,
+	};
+	Some(Duration::from_secs(value.checked_mul(secs)?))
+}
+
+#[derive(Clone,Copy,Debug,Default,PartialEq)]
+pub struct RetentionRule {
+	pub raw: Option<Duration>,
+	pub compressed: Option<Duration>,
+}
+
+impl RetentionRule {
+	pub fn parse(raw: Option<&str>, compressed: Option<&str>) -> Result<RetentionRule,String> {
+		let period = |text: Option<&str>| -> Result<Option<Duration>,String> {
+			match text {
+				Some(text) => parse_duration(text).map(Some).ok_or_else(|| format!("Invalid retention period {:?}", text)),
+				None => Ok(None),
+			}
+		};
+		Ok(RetentionRule {
+			raw: period(raw)?,
+			compressed: period(compressed)?,
+		})
+	}
+
+	/* Takes the periods this rule does not set from the other one */
+	pub fn or(self, other: RetentionRule) -> RetentionRule {
+		RetentionRule {
+			raw: self.raw.or(other.raw),
+			compressed: self.compressed.or(other.compressed),
+		}
+	}
+}
+
+#[derive(Clone,Debug,Default,PartialEq)]
+pub struct RetentionPolicy {
+	default: RetentionRule,
+	signals: HashMap<SignalId,RetentionRule>,
+}
+
+impl RetentionPolicy {
+	pub fn new(default: RetentionRule) -> RetentionPolicy {
+		RetentionPolicy {
+			default: default,
+			signals: HashMap::new(),
+		}
+	}
+
+	pub fn set(&mut self, signal: SignalId, rule: RetentionRule) {
+		self.signals.insert(signal, rule);
+	}
+
+	pub fn rule_for(&self, signal: SignalId) -> RetentionRule {
+		*self.signals.get(&signal).unwrap_or(&self.default)
+	}
+
+	/* Reads the default rule and the rules keyed by signal selectors from
+	 * the retention table of the config, the periods a signal rule leaves
+	 * out come from the default rule
+	 */
+	pub fn from_config(params: &toml::Value, catalog: &SignalCatalog) -> RetentionPolicy {
+		let rule = |table: &toml::Value| RetentionRule::parse(
+			table.lookup("raw").map(|v| v.as_str().expect("Retention periods must be provided as strings like \"7d\"")),
+			table.lookup("compressed").map(|v| v.as_str().expect("Retention periods must be provided as strings like \"90d\"")));
+		let default = rule(params).expect("Failed to parse the default retention rule");
+		let mut policy = RetentionPolicy::new(default);
+		if let Some(rules) = params.lookup("signals") {
+			for (selector, table) in rules.as_table().expect("Per signal retention rules must be provided as a TOML table") {
+				let rule = rule(table).expect("Failed to parse a signal retention rule").or(default);
+				for signal in catalog.resolve(selector) {
+					policy.set(signal, rule);
+				}
+			}
+		}
+		policy
+	}
+}
+
+/* What the daemon reclaimed over all its passes */
+#[derive(Clone,Copy,Debug,Default,PartialEq)]
+pub struct RetentionStats {
+	pub passes: u64,
+	pub segments: u64,
+	pub bytes: u64,
+}
+
+pub struct RetentionDaemon<T: Copy + Send> {
+	raw: Option<Arc<Mutex<SegmentBuffer<T> + Send + Sync>>>,
+	comp: Option<Arc<Mutex<SegmentBuffer<T> + Send + Sync>>>,
+	catalog: Arc<Mutex<SignalCatalog>>,
+	policy: RetentionPolicy,
+	interval: Duration,
+	shutdown: Shutdown,
+	stats: RetentionStats,
+}
+
+impl<T: Copy + Send> RetentionDaemon<T> {
+	pub fn new(raw: Option<Arc<Mutex<SegmentBuffer<T> + Send + Sync>>>,
+			   comp: Option<Arc<Mutex<SegmentBuffer<T> + Send + Sync>>>,
+			   catalog: Arc<Mutex<SignalCatalog>>, policy: RetentionPolicy,
+			   interval: Duration) -> RetentionDaemon<T>
+	{
+		RetentionDaemon {
+			raw: raw,
+			comp: comp,
+			catalog: catalog,
+			policy: policy,
+			interval: interval,
+			shutdown: Shutdown::new(),
+			stats: RetentionStats::default(),
+		}
+	}
+
+	pub fn set_shutdown(&mut self, shutdown: Shutdown) {
+		self.shutdown = shutdown;
+	}
+
+	pub fn get_stats(&self) -> RetentionStats {
+		self.stats
+	}
+
+	/* Expires everything that is past its period at now */
+	pub fn run_once(&mut self, now: SystemTime) -> Result<Expired,BufErr> {
+		let signals = match self.catalog.lock() {
+			Ok(catalog) => catalog.signals(),
+			Err(_) => return Err(BufErr::CantGrabMutex),
+		};
+		let mut expired = Expired::default();
+		for signal in signals {
+			let rule = self.policy.rule_for(signal);
+			for (pool, keep) in vec![(&self.raw, rule.raw), (&self.comp, rule.compressed)] {
+				let (pool, keep) = match (pool, keep) {
+					(Some(pool), Some(keep)) => (pool, keep),
+					_ => continue,
+				};
+				let cutoff = match now.checked_sub(keep) {
+					Some(cutoff) if cutoff > UNIX_EPOCH => cutoff,
+					_ => continue,
+				};
+				let removed = match pool.lock() {
+					Ok(mut buf) => buf.expire(signal, cutoff)?,
+					Err(_) => return Err(BufErr::CantGrabMutex),
+				};
+				if let Ok(mut catalog) = self.catalog.lock() {
+					catalog.expire(signal, &removed.segments);
+				}
+				expired.merge(removed);
+			}
+		}
+
+		self.stats.passes += 1;
+		self.stats.segments += expired.segments.len() as u64;
+		self.stats.bytes += expired.bytes() as u64;
+		Ok(expired)
+	}
+
+	/* Runs a pass every interval until the run shuts down */
+	pub fn run(&mut self) {
+		let mut last = Instant::now();
+		while !self.shutdown.is_stopping() {
+			thread::sleep(Duration::from_millis(10).min(self.interval));
+			if last.elapsed() < self.interval {
+				continue;
+			}
+			match self.run_once(SystemTime::now()) {
+				Ok(expired) if !expired.segments.is_empty() => println!("Expired {} segments, reclaimed {} bytes",
+					expired.segments.len(), expired.bytes()),
+				Ok(_) => (),
+				Err(e) => println!("Failed to expire segments: {:?}", e),
+			}
+			last = Instant::now();
+		}
+		println!("Retention reclaimed {} bytes in {} segments over {} passes",
+			self.stats.bytes, self.stats.segments, self.stats.passes);
+	}
+}
+
+/* Runs a retention daemon over the pools until the run shuts down */
+pub fn start_retention<T: Copy + Send + 'static>(policy: RetentionPolicy, interval: Duration,
+		raw: Option<&Arc<Mutex<SegmentBuffer<T> + Send + Sync>>>,
+		comp: Option<&Arc<Mutex<SegmentBuffer<T> + Send + Sync>>>,
+		catalog: &Arc<Mutex<SignalCatalog>>,
+		shutdown: &mut ShutdownHandle<T>)
+{
+	let mut daemon = RetentionDaemon::new(raw.map(share_buffer), comp.map(share_buffer), catalog.clone(), policy, interval);
+	daemon.set_shutdown(shutdown.signal());
+	shutdown.add_daemon(thread::spawn(move || daemon.run()));
+}
+
+#[test]
+fn retention_test() {
+	use crate::buffer_pool::ClockBuffer;
+	use crate::catalog::CODEC_RAW;
+	use crate::file_handler::LogFileManager;
+	use crate::segment::Segment;
+
+	assert_eq!(parse_duration("7d"), Some(Duration::from_secs(7 * 24 * 3600)));
+	assert_eq!(parse_duration("90"), Some(Duration::from_secs(90)));
+	assert_eq!(parse_duration("3 h"), Some(Duration::from_secs(3 * 3600)));
+	assert_eq!(parse_duration("3y"), None);
+	assert_eq!(parse_duration("d"), None);
+
+	let path = "../retention_test";
+	let _ = LogFileManager::destroy(path);
+	let raw: Arc<Mutex<SegmentBuffer<f64> + Send + Sync>> = Arc::new(Mutex::new(
+		ClockBuffer::<f64,_>::new(32, LogFileManager::open(path).expect("Failed to open log file manager"))));
+	let catalog = Arc::new(Mutex::new(SignalCatalog::new()));
+	let start = UNIX_EPOCH + Duration::from_secs(1_000_000);
+	for signal in 1..3u64 {
+		for i in 0..10u64 {
+			let seg = Segment::new(None, start + Duration::from_secs(i * 3600), signal, vec![i as f64; 10], None, None);
+			catalog.lock().unwrap().record(seg.get_key(), CODEC_RAW, 10);
+			raw.lock().unwrap().put(seg).expect("Failed to put segment");
+		}
+	}
+	/* Clock evictions are not written out, so persist to have every segment in both places */
+	raw.lock().unwrap().persist().expect("Failed to persist segments");
+
+	/* Signal 2 keeps its raw segments for 2 hours, signal 1 for the default 5 */
+	let mut policy = RetentionPolicy::new(RetentionRule::parse(Some("5h"), None).unwrap());
+	policy.set(2, RetentionRule::parse(Some("2h"), None).unwrap().or(policy.rule_for(2)));
+	let mut daemon = RetentionDaemon::new(Some(raw.clone()), None, catalog.clone(), policy, Duration::from_millis(10));
+	let now = start + Duration::from_secs(10 * 3600);
+	let expired = daemon.run_once(now).expect("Failed to expire segments");
+	assert_eq!(expired.segments.len(), 5 + 8);
+	assert!(expired.bytes() > 0);
+
+	/* Segments are gone from memory and the file manager alike */
+	let buf = raw.lock().unwrap();
+	let end = start + Duration::from_secs(20 * 3600);
+	assert_eq!(buf.range_keys(1, UNIX_EPOCH, end).unwrap().len(), 5);
+	assert_eq!(buf.range_keys(2, UNIX_EPOCH, end).unwrap().len(), 2);
+	assert_eq!(catalog.lock().unwrap().get(2).unwrap().get_segments(), 2);
+	assert_eq!(daemon.get_stats().segments, 13);
+	drop(buf);
+
+	/* A second pass at the same time finds nothing left to expire */
+	assert!(daemon.run_once(now).unwrap().segments.is_empty());
+	LogFileManager::destroy(path).expect("Failed to destroy log file manager");
+}
+
+#[test]
+fn retention_compressed_test() {
+	use crate::buffer_pool::NoFmClockBuffer;
+	use crate::catalog::CODEC_RAW;
+	use crate::segment::Segment;
+
+	let raw: Arc<Mutex<SegmentBuffer<f64> + Send + Sync>> = Arc::new(Mutex::new(NoFmClockBuffer::<f64>::new(16)));
+	let comp: Arc<Mutex<SegmentBuffer<f64> + Send + Sync>> = Arc::new(Mutex::new(NoFmClockBuffer::<f64>::new(16)));
+	let catalog = Arc::new(Mutex::new(SignalCatalog::new()));
+	let start = UNIX_EPOCH + Duration::from_secs(1_000_000);
+	/* The first segment has points up to 3 hours after it started, the second none after its start */
+	let lapse: Vec<Duration> = (0..7u64).map(|i| Duration::from_secs(i * 1800)).collect();
+	let segs = vec![
+		Segment::new(None, start, 1, vec![1.0; 7], Some(lapse), None),
+		Segment::new(None, start + Duration::from_secs(4 * 3600), 1, vec![2.0; 7], None, None),
+	];
+	for pool in vec![&raw, &comp] {
+		for seg in segs.iter() {
+			catalog.lock().unwrap().record(seg.get_key(), CODEC_RAW, 7);
+			pool.lock().unwrap().put(seg.clone()).expect("Failed to put segment");
+		}
+	}
+
+	/* Only the compressed pool has a period, the raw pool keeps everything */
+	let policy = RetentionPolicy::new(RetentionRule::parse(None, Some("3h")).unwrap());
+	let mut daemon = RetentionDaemon::new(Some(raw.clone()), Some(comp.clone()), catalog.clone(), policy, Duration::from_millis(10));
+
+	/* Started before the cutoff but its last point is not, so nothing goes */
+	assert!(daemon.run_once(start + Duration::from_secs(5 * 3600)).unwrap().segments.is_empty());
+
+	let expired = daemon.run_once(start + Duration::from_secs(7 * 3600)).expect("Failed to expire segments");
+	assert_eq!(expired.segments.len(), 1);
+	assert_eq!(expired.segments[0].key, segs[0].get_key());
+	assert_eq!(expired.segments[0].end, start + Duration::from_secs(3 * 3600));
+	let end = start + Duration::from_secs(24 * 3600);
+	assert_eq!(comp.lock().unwrap().range_keys(1, UNIX_EPOCH, end).unwrap(), vec![segs[1].get_key()]);
+	assert_eq!(raw.lock().unwrap().range_keys(1, UNIX_EPOCH, end).unwrap().len(), 2);
+	assert_eq!(catalog.lock().unwrap().get(1).unwrap().get_segments(), 3);
+}
+
+#[test]
+fn retention_config_test() {
+	use crate::catalog::SignalMeta;
+	use crate::segment::header::element_type_id;
+
+	let text = r#"
+		interval = 1000
+		raw = "7d"
+		compressed = "90d"
+
+		[signals.boiler]
+		raw = "1d"
+
+		[signals."host=h2"]
+		compressed = "30d"
+	"#;
+	let params = toml::Value::Table(toml::Parser::new(text).parse().expect("Failed to parse the retention table"));
+	let mut catalog = SignalCatalog::new();
+	catalog.register(7, SignalMeta::new("boiler", element_type_id::<f64>()).with_tag("host", "h1")).unwrap();
+	catalog.register(8, SignalMeta::new("fan", element_type_id::<f64>()).with_tag("host", "h2")).unwrap();
+
+	let days = |d: u64| Some(Duration::from_secs(d * 24 * 3600));
+	let policy = RetentionPolicy::from_config(&params, &catalog);
+	assert_eq!(policy.rule_for(7), RetentionRule { raw: days(1), compressed: days(90) });
+	assert_eq!(policy.rule_for(8), RetentionRule { raw: days(7), compressed: days(30) });
+	assert_eq!(policy.rule_for(9), RetentionRule { raw: days(7), compressed: days(90) });
+}
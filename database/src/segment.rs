@@ -1,8 +1,9 @@
 //use std::convert::TryFrom;
 use std::sync::Arc;
 use std::sync::Mutex;
-use crate::buffer_pool::SegmentBuffer;
-use std::time::SystemTime;
+use crate::buffer_pool::{SegmentBuffer,BufErr};
+use std::time::{SystemTime,UNIX_EPOCH};
+use std::collections::VecDeque;
 use std::ops::Sub;
 use num::FromPrimitive;
 use std::ops::Div;
@@ -16,6 +17,7 @@ use rustfft::num_traits::Zero;
 
 use std::time::{Duration};
 use crate::future_signal::SignalId;
+use crate::file_handler::{self,FileManager};
 use num::Num;
 
 /* Currently plan to move methods into this file */
@@ -32,6 +34,13 @@ use crate::knn::fft_ifft_ratio;
 use croaring::Bitmap;
 use itertools::Itertools;
 
+pub mod header;
+pub mod time_column;
+pub mod columnar;
+pub mod validity;
+use header::FormatError;
+use validity::Validity;
+
 
 /* 
  * Overview:
@@ -85,7 +94,8 @@ pub struct Segment<T> {
 	prev_seg_offset: Option<Duration>,
 	comp_time: usize,
 	size: usize,
-	comp_runtime:f64
+	comp_runtime:f64,
+	validity: Option<Validity<T>>,
 	//next_seg_offset: Option<Duration>,
 }
 
@@ -104,7 +114,8 @@ impl<T> Segment<T> {
 			prev_seg_offset: next_seg_offset,
 			comp_time: 0,
 			size: size,
-			comp_runtime: 0.0
+			comp_runtime: 0.0,
+			validity: None
 		}
 	}
 
@@ -169,13 +180,93 @@ impl<T> Segment<T> {
 	pub fn set_method(&mut self, m : Methods) {
 		self.method = Some(m);
 	}
+
+	/* Heap bytes held by the segment: its data, compressed binary, point
+	 * times and set aside values, counted by capacity rather than length
+	 */
+	pub fn get_heap_size(&self) -> usize {
+		self.data.capacity() * std::mem::size_of::<T>()
+			+ self.binary.as_ref().map_or(0, |b| b.capacity())
+			+ self.time_lapse.as_ref().map_or(0, |t| t.capacity() * std::mem::size_of::<Duration>())
+			+ self.validity.as_ref().map_or(0, |v| v.get_heap_size())
+	}
+
+	/* The non finite values set aside while the segment is compressed */
+	pub fn get_validity(&self) -> &Option<Validity<T>> {
+		&self.validity
+	}
+
+	pub fn set_validity(&mut self, v: Option<Validity<T>>) {
+		self.validity = v;
+	}
+
+	pub fn get_time_lapse(&self) -> &Option<Vec<Duration>> {
+		&self.time_lapse
+	}
+
+	/* The time of the last point, the start when the segment does not record per point times */
+	pub fn get_end(&self) -> SystemTime {
+		match self.time_lapse.as_ref().and_then(|lapse| lapse.last()) {
+			Some(d) => self.timestamp + *d,
+			None    => self.timestamp,
+		}
+	}
+
+	/* The time of every point in nanoseconds since the unix epoch,
+	 * None when the segment does not record per point times
+	 */
+	pub fn get_timestamps(&self) -> Option<Vec<i64>> {
+		let lapse = self.time_lapse.as_ref()?;
+		let mut times = Vec::with_capacity(lapse.len());
+		for d in lapse {
+			times.push(time_column::to_nanos(self.timestamp + *d)?);
+		}
+		Some(times)
+	}
+
+	/* Rows whose point time lies within [start, end), the same half open
+	 * range the segment keys are scanned with. Without per point times
+	 * every row is taken to be at the segment timestamp.
+	 */
+	pub fn time_filter(&self, start: SystemTime, end: SystemTime) -> Bitmap {
+		let mut res = Bitmap::create();
+		match &self.time_lapse {
+			Some(lapse) => {
+				for (i, d) in lapse.iter().enumerate() {
+					let t = self.timestamp + *d;
+					if t >= start && t < end {
+						res.add(i as u32);
+					}
+				}
+			}
+			None => if self.timestamp >= start && self.timestamp < end {
+				res.add_range(0..self.size as u64);
+			}
+		}
+		res
+	}
+}
+
+impl<T: Copy> Segment<T> {
+	/* Keeps only the provided rows, such as the ones time_filter returns,
+	 * of the data along with their point times and set aside values
+	 */
+	pub fn retain_rows(&mut self, rows: &Bitmap) {
+		self.data = rows.iter().filter_map(|i| self.data.get(i as usize).cloned()).collect();
+		if let Some(lapse) = self.time_lapse.as_ref() {
+			self.time_lapse = Some(rows.iter().filter_map(|i| lapse.get(i as usize).cloned()).collect());
+		}
+		self.validity = self.validity.as_ref().and_then(|v| v.select(rows));
+		self.size = self.data.len();
+	}
 }
 
 impl<'a,T> Segment<T> 
 	where T: Serialize + Deserialize<'a>
 {
+	/* Frames the segment with a versioned header, see segment/header.rs */
 	pub fn convert_to_bytes(&self) -> Result<Vec<u8>,()> {
-		match bincode::serialize(self) {
+		match header::encode(self) {
 			Ok(seg) => Ok(seg),
 			Err(_)  => Err(())
 		}
@@ -188,11 +279,11 @@ impl<'a,T> Segment<T>
 		}
 	}
 
-	pub fn convert_from_bytes(bytes: &'a [u8]) -> Result<Segment<T>,()> {
-		match bincode::deserialize(bytes) {
-			Ok(seg) => Ok(seg),
-			Err(_)  => Err(())
-		}
+	/* Accepts the current framed format as well as unframed segments
+	 * written before the header existed, anything else is an error.
+	 */
+	pub fn convert_from_bytes(bytes: &'a [u8]) -> Result<Segment<T>,FormatError> {
+		header::decode(bytes)
 	}
 }
 
@@ -213,6 +304,19 @@ impl<'a,T> Segment<T>
  *******************Segment Key Implementation******************
  ***************************************************************/ 
 
+/* The byte form of a key is ordered so that a range scan over the
+ * file manager returns the segments of a signal in time order:
+ *   [KEY_TAG_SEGMENT][signal: u64 BE][secs since epoch: u64 BE][nanos: u32 BE]
+ * Keys written before this encoding were plain bincode (20 bytes).
+ * Range scans can not find those, so convert_from_bytes rejects them
+ * and migrate_legacy_keys moves their segments to the ordered keys.
+ */
+pub const KEY_TAG_SEGMENT: u8 = b'S';
+/* Dictionaries are kept under [KEY_TAG_DICTIONARY][dictionary id: u32 BE] */
+pub const KEY_TAG_DICTIONARY: u8 = b'D';
+pub const SEGMENT_KEY_LEN: usize = 21;
+const LEGACY_SEGMENT_KEY_LEN: usize = 20;
+
 #[derive(Serialize,Deserialize,Debug,PartialEq,Eq,Hash,Copy,Clone,Ord,PartialOrd)]
 pub struct SegmentKey {
 	timestamp: SystemTime,
@@ -227,19 +331,88 @@ impl<'a> SegmentKey {
 		}
 	}
 
+	pub fn get_signal(&self) -> SignalId {
+		self.signal
+	}
+
+	pub fn get_timestamp(&self) -> SystemTime {
+		self.timestamp
+	}
+
+	/* True if the key belongs to the signal and its timestamp is in [start, end) */
+	pub fn in_range(&self, signal: SignalId, start: SystemTime, end: SystemTime) -> bool {
+		self.signal == signal && self.timestamp >= start && self.timestamp < end
+	}
+
+	/* Fails for timestamps before the UNIX epoch as those can not be ordered */
 	pub fn convert_to_bytes(&self) -> Result<Vec<u8>,()> {
-		match bincode::serialize(self) {
-			Ok(key) => Ok(key),
-			Err(_)  => Err(())
-		}
+		let since_epoch = match self.timestamp.duration_since(UNIX_EPOCH) {
+			Ok(d) => d,
+			Err(_) => return Err(()),
+		};
+		let mut bytes = Vec::with_capacity(SEGMENT_KEY_LEN);
+		bytes.push(KEY_TAG_SEGMENT);
+		bytes.extend_from_slice(&self.signal.to_be_bytes());
+		bytes.extend_from_slice(&since_epoch.as_secs().to_be_bytes());
+		bytes.extend_from_slice(&since_epoch.subsec_nanos().to_be_bytes());
+		Ok(bytes)
 	}
 
 	pub fn convert_from_bytes(bytes: &'a [u8]) -> Result<SegmentKey,()> {
-		match bincode::deserialize(bytes) {
-			Ok(key) => Ok(key),
-			Err(_)  => Err(())
+		match bytes.len() {
+			SEGMENT_KEY_LEN if bytes[0] == KEY_TAG_SEGMENT => {
+				let mut signal = [0u8; 8];
+				let mut secs = [0u8; 8];
+				let mut nanos = [0u8; 4];
+				signal.copy_from_slice(&bytes[1..9]);
+				secs.copy_from_slice(&bytes[9..17]);
+				nanos.copy_from_slice(&bytes[17..21]);
+				let since_epoch = Duration::new(u64::from_be_bytes(secs), u32::from_be_bytes(nanos));
+				Ok(SegmentKey::new(UNIX_EPOCH + since_epoch, u64::from_be_bytes(signal)))
+			}
+			_ => Err(()),
 		}
 	}
+
+	/* Produces the start and end key bytes of a file manager scan
+	 * covering the segments of the signal in [start, end)
+	 */
+	pub fn range_bounds(signal: SignalId, start: SystemTime, end: SystemTime) -> Result<(Vec<u8>,Vec<u8>),()> {
+		let start = if start < UNIX_EPOCH { UNIX_EPOCH } else { start };
+		let start_bytes = SegmentKey::new(start, signal).convert_to_bytes()?;
+		let end_bytes = SegmentKey::new(end, signal).convert_to_bytes()?;
+		Ok((start_bytes, end_bytes))
+	}
+}
+
+/* Moves every segment stored under a key in the legacy bincode encoding
+ * to its ordered key, the new key is written before the old one is
+ * deleted so a crash in between leaves a duplicate rather than a loss.
+ * Returns how many segments were moved.
+ */
+pub fn migrate_legacy_keys<U>(fm: &U) -> Result<usize,file_handler::Error>
+	where U: FileManager<Vec<u8>,Vec<u8>> + ?Sized
+{
+	let mut moved = Vec::new();
+	for (key, bytes) in fm.fm_scan(Vec::new(), vec![0xFF; SEGMENT_KEY_LEN + 1])? {
+		if key.len() != LEGACY_SEGMENT_KEY_LEN {
+			continue;
+		}
+		if let Ok(seg_key) = bincode::deserialize::<SegmentKey>(&key) {
+			if let Ok(new_key) = seg_key.convert_to_bytes() {
+				moved.push((key, new_key, bytes));
+			}
+		}
+	}
+	if moved.is_empty() {
+		return Ok(0);
+	}
+
+	fm.fm_write_batch(moved.iter().map(|(_, new_key, bytes)| (new_key.clone(), bytes.clone())).collect())?;
+	for (key, _, _) in moved.iter() {
+		fm.fm_delete(key.clone())?;
+	}
+	Ok(moved.len())
 }
 
 
@@ -247,12 +420,24 @@ impl<'a> SegmentKey {
  ************************Segment Verifier***********************
  ***************************************************************/
 
-
+/* Walks the segments of a signal in one of two ways
+ * 1. new/get_last_n: follows the implicit linked list backwards
+ *    from the provided key through prev_seg_offset.
+ * 2. range: streams every segment of the signal keyed in [start, end),
+ *    the keys are gathered from the buffer and the file manager
+ *    behind it, so the chain does not need to be intact.
+ *    covering adds the segment before start whose points reach it.
+ *    Use next for ascending time order and next_back (or rev)
+ *    for descending time order.
+ * A segment the buffer fails to load, a corrupt one included, is
+ * yielded as an error. Walking the chain stops after an error.
+ */
 pub struct SegmentIter<T> 
 	where T: Copy + Send
 {
 	buffer: Arc<Mutex<SegmentBuffer<T>>>,
-	cur_seg_key: SegmentKey,
+	cur_seg_key: Option<SegmentKey>,
+	range_keys: Option<VecDeque<SegmentKey>>,
 }
 
 impl<T> SegmentIter<T> 
@@ -261,32 +446,109 @@ impl<T> SegmentIter<T>
 	pub fn new(s_id: SignalId, timestamp: SystemTime, buffer: Arc<Mutex<SegmentBuffer<T>>>) -> SegmentIter<T> {
 		SegmentIter {
 			buffer: buffer,
-			cur_seg_key: SegmentKey::new(timestamp, s_id),
+			cur_seg_key: Some(SegmentKey::new(timestamp, s_id)),
+			range_keys: None,
+		}
+	}
+
+	pub fn range(s_id: SignalId, start: SystemTime, end: SystemTime, buffer: Arc<Mutex<SegmentBuffer<T>>>) -> Result<SegmentIter<T>,BufErr> {
+		let keys = match buffer.lock() {
+			Ok(buf) => buf.range_keys(s_id, start, end)?,
+			Err(_) => return Err(BufErr::CantGrabMutex),
+		};
+		Ok(SegmentIter {
+			buffer: buffer,
+			cur_seg_key: None,
+			range_keys: Some(keys.into_iter().collect()),
+		})
+	}
+
+	/* Like range, but also starts with the last segment keyed before start
+	 * when its points run into the range, so a range that begins in the
+	 * middle of a segment sees the points of that segment too
+	 */
+	pub fn covering(s_id: SignalId, start: SystemTime, end: SystemTime, buffer: Arc<Mutex<SegmentBuffer<T>>>) -> Result<SegmentIter<T>,BufErr> {
+		let mut iter = SegmentIter::range(s_id, start, end, buffer)?;
+		if start >= end {
+			return Ok(iter);
 		}
+		let before = match iter.buffer.lock() {
+			Ok(buf) => buf.range_keys(s_id, UNIX_EPOCH, start)?.pop(),
+			Err(_) => return Err(BufErr::CantGrabMutex),
+		};
+		if let Some(key) = before {
+			match iter.fetch(key) {
+				Ok(Some(ref seg)) if seg.get_end() < start => (),
+				Ok(None) => (),
+				/* A segment that fails to load is yielded as the error */
+				_ => iter.range_keys.as_mut().unwrap().push_front(key),
+			}
+		}
+		Ok(iter)
+	}
+
+	/* Starts at the newest segment of a signal, such as the last key the catalog holds
+	 * for it, and follows the chain back
+	 */
+	pub fn from_tail(tail: SegmentKey, buffer: Arc<Mutex<SegmentBuffer<T>>>) -> SegmentIter<T> {
+		SegmentIter::new(tail.get_signal(), tail.get_timestamp(), buffer)
 	}
 
-	pub fn get_last_n(s_id: SignalId, timestamp: SystemTime, buffer: Arc<Mutex<SegmentBuffer<T>>>, n: usize) -> Vec<Segment<T>> {
+	pub fn get_last_n(s_id: SignalId, timestamp: SystemTime, buffer: Arc<Mutex<SegmentBuffer<T>>>, n: usize) -> Result<Vec<Segment<T>>,BufErr> {
 		SegmentIter::new(s_id, timestamp, buffer).take(n).collect()
 	}
+
+	fn fetch(&self, key: SegmentKey) -> Result<Option<Segment<T>>,BufErr> {
+		match self.buffer.lock() {
+			Ok(mut buf) => Ok(buf.get(key)?.cloned()),
+			Err(_) => Err(BufErr::CantGrabMutex),
+		}
+	}
+
+	/* Segments removed after the keys were gathered are skipped */
+	fn fetch_range(&mut self, back: bool) -> Option<Result<Segment<T>,BufErr>> {
+		loop {
+			let keys = self.range_keys.as_mut()?;
+			let key = if back { keys.pop_back()? } else { keys.pop_front()? };
+			match self.fetch(key) {
+				Ok(Some(seg)) => return Some(Ok(seg)),
+				Ok(None) => continue,
+				Err(e) => return Some(Err(e)),
+			}
+		}
+	}
 }
 
 impl<T> Iterator for SegmentIter<T> 
 	where T: Copy + Send,
 {
-	type Item = Segment<T>;
-
-	fn next(&mut self) -> Option<Segment<T>> {
-		if let Ok(mut buf) = self.buffer.lock() {
-			if let Ok(Some(seg)) = buf.get(self.cur_seg_key) {
-				self.cur_seg_key = match seg.get_prev_key() {
-					Some(key) => key,
-					_ => return None,
-				};
-				return Some(seg.clone());
+	type Item = Result<Segment<T>,BufErr>;
+
+	fn next(&mut self) -> Option<Result<Segment<T>,BufErr>> {
+		if self.range_keys.is_some() {
+			return self.fetch_range(false);
+		}
+
+		let key = self.cur_seg_key.take()?;
+		match self.fetch(key) {
+			Ok(Some(seg)) => {
+				self.cur_seg_key = seg.get_prev_key();
+				Some(Ok(seg))
 			}
+			Ok(None) => None,
+			Err(e) => Some(Err(e)),
 		}
+	}
+}
 
-		None
+/* Only range iterators can be walked from the back,
+ * the linked list iterator already walks backwards in time
+ */
+impl<T> DoubleEndedIterator for SegmentIter<T> 
+	where T: Copy + Send,
+{
+	fn next_back(&mut self) -> Option<Result<Segment<T>,BufErr>> {
+		self.fetch_range(true)
 	}
 }
 
@@ -328,7 +590,8 @@ impl<'a,T: FFTnum + Serialize + Deserialize<'a>> Segment<T> {
 			prev_seg_offset: self.prev_seg_offset,
 			comp_time: 0,
 			size: size,
-			comp_runtime: 0.0
+			comp_runtime: 0.0,
+			validity: None
 		}
 
 	}
@@ -365,7 +628,8 @@ impl<'a,T: FFTnum + Serialize + Deserialize<'a>> Segment<T> {
 			prev_seg_offset: self.prev_seg_offset,
 			comp_time: 0,
 			size: size,
-			comp_runtime: 0.0
+			comp_runtime: 0.0,
+			validity: None
 		}
 
 	}
@@ -403,7 +667,8 @@ impl<'a,T: FFTnum + Serialize +Into<f64>+ Deserialize<'a>> Segment<Complex<T>> {
 			prev_seg_offset: self.prev_seg_offset,
 			comp_time: 0,
 			size: size,
-			comp_runtime: 0.0
+			comp_runtime: 0.0,
+			validity: None
 		}
 	}
 
@@ -428,7 +693,8 @@ impl<'a,T: FFTnum + Serialize +Into<f64>+ Deserialize<'a>> Segment<Complex<T>> {
 			prev_seg_offset: self.prev_seg_offset,
 			comp_time: 0,
 			size: size,
-			comp_runtime: 0.0
+			comp_runtime: 0.0,
+			validity: None
 		}
 	}
 }
@@ -534,7 +800,8 @@ pub fn paa_compress_and_retain<T>(seg: &Segment<T>, chunk_size: usize) -> Segmen
 		prev_seg_offset: seg.prev_seg_offset,
 		comp_time: 0,
 		size: size,
-		comp_runtime: 0.0
+		comp_runtime: 0.0,
+		validity: None
 	}
 }
 
@@ -966,7 +1233,7 @@ fn test_fourier_on_segment() {
 	println!("decompressed {:?}", decompressed_seg.data.iter().map(|&x|{x/size as f32 }).collect::<Vec<_>>());
 
 	assert!(compare_vectors(init_seg.data.as_slice(), decompressed_seg.data.as_slice()));
-	assert_eq!(compressed_seg.method, Some(Fourier));
+	assert_eq!(compressed_seg.method, Some(Fourier(1.0)));
 	assert_eq!(decompressed_seg.method, None)
 }
 
@@ -1002,7 +1269,8 @@ fn test_complex_segment_byte_conversion() {
 			prev_seg_offset: None,
 			comp_time: 0,
 			size: 0,
-			comp_runtime: 0.0
+			comp_runtime: 0.0,
+			validity: None
 		}).collect();
 
 	let mut converted_segs: Vec<Segment<Complex<f32>>> = segs.iter().map({|seg|
@@ -1036,7 +1304,8 @@ fn test_segment_byte_conversion() {
 			prev_seg_offset: None,
 			comp_time: 0,
 			size: 0,
-			comp_runtime: 0.0
+			comp_runtime: 0.0,
+			validity: None
 		}).collect();
 
 	let mut converted_segs: Vec<Segment<f32>> = segs.iter().map({|seg|
@@ -1077,7 +1346,8 @@ fn test_paa_compression() {
 				prev_seg_offset: None,
 		comp_time: 0,
 		size: 0,
-		comp_runtime: 0.0
+		comp_runtime: 0.0,
+		validity: None
 	};
 
 	let seg2 = seg1.clone();
@@ -1148,7 +1418,8 @@ fn test_paa_compression_int() {
 		prev_seg_offset: None,
 		comp_time: 0,
 		size: 0,
-		comp_runtime: 0.0
+		comp_runtime: 0.0,
+		validity: None
 	};
 
 	let seg2 = seg1.clone();
@@ -1200,6 +1471,70 @@ fn test_key_byte_conversion() {
 	}
 }
 
+#[test]
+fn test_segment_header() {
+	let mut seg = Segment::new(Some(Methods::Bufflossy(10000,8)), SystemTime::now(), 3,
+		vec![1.5f64, 2.5, 3.5], Some(vec![Duration::from_millis(0), Duration::from_millis(5), Duration::from_millis(9)]), None);
+	seg.set_comp(Some(vec![7u8; 12]));
+
+	let bytes = seg.convert_to_bytes().unwrap();
+	let (hdr, _) = header::read_header(&bytes).unwrap();
+	assert_eq!(hdr.version, header::FORMAT_VERSION);
+	assert_eq!(hdr.element_type, 2);
+	assert_eq!((header::element_type_id::<f32>(), header::element_type_id::<i64>(), header::element_type_id::<u8>()), (1, 6, 7));
+	assert_eq!(header::element_type_id::<ComplexDef<f64>>(), 0);
+	assert_eq!(hdr.method, Some(Methods::Bufflossy(10000,8)));
+	assert_eq!(hdr.count, 3);
+	assert_eq!(hdr.end, seg.timestamp + Duration::from_millis(9));
+	assert_eq!(Segment::<f64>::convert_from_bytes(&bytes), Ok(seg.clone()));
+
+	/* Segments written before the header existed are migrated */
+	let legacy = bincode::serialize(&seg).unwrap();
+	assert_eq!(Segment::<f64>::convert_from_bytes(&legacy), Ok(seg.clone()));
+
+	/* Corruption, newer versions and the wrong element type are rejected */
+	let mut corrupt = bytes.clone();
+	let last = corrupt.len() - 1;
+	corrupt[last] ^= 0xff;
+	match Segment::<f64>::convert_from_bytes(&corrupt) {
+		Err(FormatError::ChecksumMismatch{..}) => (),
+		other => panic!("Expected a checksum mismatch, got {:?}", other),
+	}
+	let mut newer = bytes.clone();
+	newer[4] = 0xff;
+	assert_eq!(Segment::<f64>::convert_from_bytes(&newer), Err(FormatError::UnsupportedVersion(0xff | (header::FORMAT_VERSION & 0xff00))));
+	match Segment::<f32>::convert_from_bytes(&bytes) {
+		Err(FormatError::ElementTypeMismatch{..}) => (),
+		other => panic!("Expected an element type mismatch, got {:?}", other),
+	}
+	assert_eq!(Segment::<f64>::convert_from_bytes(&bytes[..20]), Err(FormatError::Truncated));
+}
+
+#[test]
+fn test_segment_time_filter() {
+	let start = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+	let lapse: Vec<Duration> = (0..10).map(|i| Duration::from_millis(i * 100)).collect();
+	let seg = Segment::new(None, start, 1, (0..10).map(|x| x as f64).collect(), Some(lapse), None);
+
+	let times = seg.get_timestamps().unwrap();
+	assert_eq!(times[3] - times[0], 300_000_000);
+	let rows = seg.time_filter(start + Duration::from_millis(250), start + Duration::from_millis(550));
+	assert_eq!(rows.iter().collect::<Vec<u32>>(), vec![3, 4, 5]);
+	/* A point at the start is in the range, one at the end is not */
+	let rows = seg.time_filter(start + Duration::from_millis(300), start + Duration::from_millis(500));
+	assert_eq!(rows.iter().collect::<Vec<u32>>(), vec![3, 4]);
+	let rows = seg.time_filter(start + Duration::from_millis(300), start + Duration::from_millis(300));
+	assert!(rows.is_empty());
+
+	let bytes = seg.convert_to_bytes().unwrap();
+	assert_eq!(Segment::<f64>::convert_from_bytes(&bytes), Ok(seg.clone()));
+
+	let bare = Segment::new(None, start, 1, vec![1.0f64, 2.0], None, None);
+	assert_eq!(bare.get_timestamps(), None);
+	assert_eq!(bare.time_filter(start, start + Duration::from_millis(1)).cardinality(), 2);
+	assert!(bare.time_filter(start - Duration::from_millis(1), start).is_empty());
+}
+
 #[test]
 fn test_fourier() {
 	let data = vec![-8.267001490320215, -4.701408995824961, -3.9473912522030634, 1.50407251209921, -4.999423104642167, -0.28289749385261587, -0.6753507278963333, -5.326739149145712,
@@ -1223,7 +1558,8 @@ fn test_fourier() {
 		prev_seg_offset: None,
 		comp_time: 0,
 		size: size,
-		comp_runtime: 0.0
+		comp_runtime: 0.0,
+		validity: None
 	};
 	let fft = FourierCompress::new(2,10, 0.5);
 	fft.fourier_compress_budget_mut(&mut seg,1.0);
@@ -1231,3 +1567,133 @@ fn test_fourier() {
 	println!("{:?}", vec.as_slice())
 
 }
+
+#[test]
+fn test_key_byte_order() {
+	let base = UNIX_EPOCH + Duration::new(1_600_000_000, 0);
+	let keys = vec![
+		SegmentKey::new(base, 1),
+		SegmentKey::new(base + Duration::new(0,1), 1),
+		SegmentKey::new(base + Duration::new(1,0), 1),
+		SegmentKey::new(base + Duration::new(256,0), 1),
+		SegmentKey::new(base, 2),
+		SegmentKey::new(base + Duration::new(70_000,0), 2),
+		SegmentKey::new(base, 256),
+	];
+	let bytes: Vec<Vec<u8>> = keys.iter().map(|k| k.convert_to_bytes().unwrap()).collect();
+	for pair in bytes.windows(2) {
+		assert!(pair[0] < pair[1]);
+	}
+
+	let (start, end) = SegmentKey::range_bounds(2, UNIX_EPOCH, base + Duration::new(1,0)).unwrap();
+	let in_range: Vec<&SegmentKey> = keys.iter().zip(bytes.iter())
+		.filter(|(_,b)| **b >= start && **b < end)
+		.map(|(k,_)| k).collect();
+	assert_eq!(in_range, vec![&keys[4]]);
+
+	/* Legacy keys are rejected until they are migrated */
+	let legacy = bincode::serialize(&keys[2]).unwrap();
+	assert_eq!(SegmentKey::convert_from_bytes(&legacy), Err(()));
+}
+
+#[test]
+fn test_segment_iter_range() {
+	use crate::buffer_pool::NoFmClockBuffer;
+
+	let base = UNIX_EPOCH + Duration::new(1_600_000_000, 0);
+	let mut buf = NoFmClockBuffer::<f64>::new(16);
+	for i in 0..4u64 {
+		buf.put(Segment::new(None, base + Duration::new(10 * i, 0), 1, vec![i as f64; 4], None, None)).unwrap();
+	}
+	buf.put(Segment::new(None, base + Duration::new(5, 0), 2, vec![9.0; 4], None, None)).unwrap();
+	let buf: Arc<Mutex<SegmentBuffer<f64>>> = Arc::new(Mutex::new(buf));
+	let secs = |start: u64, end: u64| -> Vec<u64> {
+		SegmentIter::range(1, base + Duration::new(start, 0), base + Duration::new(end, 0), buf.clone()).unwrap()
+			.map(|seg| seg.unwrap().get_key().get_timestamp().duration_since(base).unwrap().as_secs())
+			.collect()
+	};
+
+	/* The start is inclusive and the end exclusive, at the first and the last key */
+	assert_eq!(secs(0, 30), vec![0, 10, 20]);
+	assert_eq!(secs(0, 31), vec![0, 10, 20, 30]);
+	assert_eq!(secs(1, 31), vec![10, 20, 30]);
+	assert_eq!(secs(30, 31), vec![30]);
+	/* Empty ranges */
+	assert_eq!(secs(10, 10), Vec::<u64>::new());
+	assert_eq!(secs(31, 100), Vec::<u64>::new());
+	assert_eq!(secs(20, 10), Vec::<u64>::new());
+	assert!(buf.lock().unwrap().range_keys(3, UNIX_EPOCH, base + Duration::new(100, 0)).unwrap().is_empty());
+
+	let rev: Vec<SystemTime> = SegmentIter::range(1, base, base + Duration::new(31, 0), buf.clone()).unwrap()
+		.rev().map(|seg| seg.unwrap().get_key().get_timestamp()).collect();
+	assert_eq!(rev, (0..4u64).rev().map(|i| base + Duration::new(10 * i, 0)).collect::<Vec<_>>());
+
+	/* Covering starts with the segment before the range only when its points reach into it */
+	buf.lock().unwrap().put(Segment::new(None, base + Duration::new(40, 0), 1, vec![4.0; 4],
+		Some((0..4).map(|j| Duration::new(j * 2, 0)).collect()), None)).unwrap();
+	let covering = |start: u64, end: u64| -> Vec<u64> {
+		SegmentIter::covering(1, base + Duration::new(start, 0), base + Duration::new(end, 0), buf.clone()).unwrap()
+			.map(|seg| seg.unwrap().get_key().get_timestamp().duration_since(base).unwrap().as_secs())
+			.collect()
+	};
+	assert_eq!(covering(45, 50), vec![40]);
+	assert_eq!(covering(47, 50), Vec::<u64>::new());
+	assert_eq!(covering(35, 50), vec![40]);
+	assert_eq!(covering(15, 31), vec![20, 30]);
+	assert_eq!(covering(40, 45), vec![40]);
+}
+
+#[test]
+fn test_segment_iter_corrupt() {
+	use crate::buffer_pool::ClockBuffer;
+	use crate::file_handler::LogFileManager;
+
+	let path = "../segment_iter_corrupt_test";
+	let _ = LogFileManager::destroy(path);
+	let fm = LogFileManager::open(path).expect("Failed to open log file manager");
+	let base = UNIX_EPOCH + Duration::new(1_600_000_000, 0);
+	for i in 0..3u64 {
+		let seg = Segment::new(None, base + Duration::new(i, 0), 1, vec![i as f64; 4], None, None);
+		let mut bytes = seg.convert_to_bytes().unwrap();
+		if i == 1 {
+			let last = bytes.len() - 1;
+			bytes[last] ^= 0xff;
+		}
+		FileManager::<Vec<u8>,Vec<u8>>::fm_write(&fm, seg.get_key().convert_to_bytes().unwrap(), bytes).unwrap();
+	}
+
+	let buf: Arc<Mutex<SegmentBuffer<f64>>> = Arc::new(Mutex::new(ClockBuffer::<f64,_>::new(16, fm)));
+	let segs: Vec<Result<Segment<f64>,BufErr>> = SegmentIter::range(1, base, base + Duration::new(3, 0), buf).unwrap().collect();
+	assert_eq!(segs.len(), 3);
+	assert!(segs[0].is_ok() && segs[2].is_ok());
+	match &segs[1] {
+		Err(BufErr::CorruptSegment(key)) => assert_eq!(*key, SegmentKey::new(base + Duration::new(1, 0), 1)),
+		other => panic!("Expected a corrupt segment, got {:?}", other),
+	}
+	LogFileManager::destroy(path).expect("Failed to destroy log file manager");
+}
+
+#[test]
+fn test_migrate_legacy_keys() {
+	use crate::file_handler::LogFileManager;
+
+	let path = "../legacy_key_test";
+	let _ = LogFileManager::destroy(path);
+	let fm = LogFileManager::open(path).expect("Failed to open log file manager");
+	let base = UNIX_EPOCH + Duration::new(1_600_000_000, 0);
+	let keys: Vec<SegmentKey> = (0..3).map(|i| SegmentKey::new(base + Duration::new(i, 0), 4)).collect();
+	for (i, key) in keys.iter().enumerate() {
+		FileManager::<Vec<u8>,Vec<u8>>::fm_write(&fm, bincode::serialize(key).unwrap(), vec![i as u8; 8]).unwrap();
+	}
+	FileManager::<Vec<u8>,Vec<u8>>::fm_write(&fm, vec![KEY_TAG_DICTIONARY, 0, 0, 0, 1], vec![9; 4]).unwrap();
+
+	assert_eq!(migrate_legacy_keys(&fm).unwrap(), 3);
+	let (start, end) = SegmentKey::range_bounds(4, base, base + Duration::new(3, 0)).unwrap();
+	let found: Vec<(SegmentKey, Vec<u8>)> = FileManager::<Vec<u8>,Vec<u8>>::fm_scan(&fm, start, end).unwrap()
+		.map(|(k, v)| (SegmentKey::convert_from_bytes(&k).unwrap(), v)).collect();
+	assert_eq!(found, keys.iter().enumerate().map(|(i, k)| (*k, vec![i as u8; 8])).collect::<Vec<_>>());
+	assert_eq!(FileManager::<Vec<u8>,Vec<u8>>::fm_get(&fm, bincode::serialize(&keys[0]).unwrap()).unwrap(), None);
+	assert_eq!(FileManager::<Vec<u8>,Vec<u8>>::fm_get(&fm, vec![KEY_TAG_DICTIONARY, 0, 0, 0, 1]).unwrap(), Some(vec![9; 4]));
+	assert_eq!(migrate_legacy_keys(&fm).unwrap(), 0);
+	LogFileManager::destroy(path).expect("Failed to destroy log file manager");
+}
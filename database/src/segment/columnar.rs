@@ -0,0 +1,341 @@
+/*
+ * Overview:
+ * A segment of a multivariate signal. All values reported together by
+ * a device share one timestamp column and are kept as named value
+ * columns, so the whole tuple is read back with a single key lookup.
+ *
+ * Design Choice:
+ * Every value column is held as a Segment of its own. This lets each
+ * column be compressed with its own Methods through the codec registry
+ * and be persisted with the same checksummed header as a plain segment.
+ * The per point times live once on the columnar segment, the column
+ * segments carry none.
+ * Columnar segments are keyed like segments but under KEY_TAG_COLUMNAR,
+ * so scans over plain segments of the same signal never see them.
+ *
+ * Layout of the persisted form (all integers little endian):
+ *   magic           4 bytes  "TCOL"
+ *   format version  u16
+ *   payload length  u64
+ *   checksum        u32      crc32 over every byte before it and the payload
+ *   payload         bincode of ColumnarPayload, each column framed by
+ *                   header::encode and the times by time_column::encode
+ * A version the decoder does not know is rejected, the same as for
+ * plain segments.
+ */
+
+use std::time::{SystemTime, Duration};
+use std::slice::Iter;
+use num::{FromPrimitive, Num};
+use rustfft::FFTnum;
+use croaring::Bitmap;
+use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+use crate::bincode;
+use crate::file_handler::crc32;
+use crate::future_signal::SignalId;
+use crate::methods::Methods;
+use crate::methods::registry::{codec_for, decode_segment};
+use super::{Segment, SegmentKey};
+use super::header::{self, FormatError};
+use super::time_column;
+
+pub const COLUMNAR_MAGIC: &[u8; 4] = b"TCOL";
+pub const COLUMNAR_FORMAT_VERSION: u16 = 1;
+pub const KEY_TAG_COLUMNAR: u8 = b'C';
+
+/* Magic, version, payload length and checksum */
+const COLUMNAR_HEADER_LEN: usize = 4 + 2 + 8 + 4;
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct ColumnarSegment<T> {
+	timestamp: SystemTime,
+	signal: SignalId,
+	time_lapse: Option<Vec<Duration>>,
+	prev_seg_offset: Option<Duration>,
+	size: usize,
+	names: Vec<String>,
+	columns: Vec<Segment<T>>,
+}
+
+#[derive(Serialize,Deserialize)]
+struct ColumnarPayload {
+	timestamp: SystemTime,
+	signal: SignalId,
+	time_column: Option<Vec<u8>>,
+	prev_seg_offset: Option<Duration>,
+	size: usize,
+	names: Vec<String>,
+	columns: Vec<Vec<u8>>,
+}
+
+/* The key bytes of a columnar segment, tagged so they never collide with plain segments */
+pub fn columnar_key_bytes(key: SegmentKey) -> Result<Vec<u8>,()> {
+	let mut bytes = key.convert_to_bytes()?;
+	bytes[0] = KEY_TAG_COLUMNAR;
+	Ok(bytes)
+}
+
+impl<T: Copy> ColumnarSegment<T> {
+	/* Builds the segment from tuples, every row must hold one value per name */
+	pub fn from_rows(timestamp: SystemTime, signal: SignalId, names: Vec<String>, rows: Vec<Vec<T>>,
+		time_lapse: Option<Vec<Duration>>, prev_seg_offset: Option<Duration>) -> ColumnarSegment<T>
+	{
+		let mut values: Vec<Vec<T>> = names.iter().map(|_| Vec::with_capacity(rows.len())).collect();
+		for row in &rows {
+			assert_eq!(row.len(), names.len(), "A row must hold a value for every column");
+			for (col, v) in values.iter_mut().zip(row.iter()) {
+				col.push(*v);
+			}
+		}
+		let columns = values.into_iter()
+			.map(|data| Segment::new(None, timestamp, signal, data, None, None))
+			.collect();
+		ColumnarSegment {
+			timestamp: timestamp,
+			signal: signal,
+			time_lapse: time_lapse,
+			prev_seg_offset: prev_seg_offset,
+			size: rows.len(),
+			names: names,
+			columns: columns,
+		}
+	}
+}
+
+impl<T> ColumnarSegment<T> {
+	pub fn get_key(&self) -> SegmentKey {
+		SegmentKey::new(self.timestamp, self.signal)
+	}
+
+	pub fn get_key_bytes(&self) -> Result<Vec<u8>,()> {
+		columnar_key_bytes(self.get_key())
+	}
+
+	pub fn get_signal(&self) -> SignalId {
+		self.signal
+	}
+
+	pub fn get_timestamp(&self) -> SystemTime {
+		self.timestamp
+	}
+
+	pub fn get_size(&self) -> usize {
+		self.size
+	}
+
+	pub fn get_names(&self) -> &Vec<String> {
+		&self.names
+	}
+
+	/* The segment holding the values of the column, still in its compressed form */
+	pub fn get_column(&self, name: &str) -> Option<&Segment<T>> {
+		let i = self.names.iter().position(|n| n == name)?;
+		Some(&self.columns[i])
+	}
+
+	/* Nanoseconds since the unix epoch of every row, None without per point times */
+	pub fn get_timestamps(&self) -> Option<Vec<i64>> {
+		let lapse = self.time_lapse.as_ref()?;
+		let mut times = Vec::with_capacity(lapse.len());
+		for d in lapse {
+			times.push(time_column::to_nanos(self.timestamp + *d)?);
+		}
+		Some(times)
+	}
+}
+
+impl<T> ColumnarSegment<T>
+	where T: Num + FromPrimitive + Copy + Send + FFTnum + Into<f64> + Serialize + DeserializeOwned
+{
+	/* Compresses one column with the method, fails if the column does not
+	 * exist or the method can not be built without a dictionary
+	 */
+	pub fn compress_column(&mut self, name: &str, method: &Methods) -> Result<(),()> {
+		let codec = codec_for(method).ok_or(())?;
+		let i = self.names.iter().position(|n| n == name).ok_or(())?;
+		codec.encode(&mut self.columns[i]);
+		Ok(())
+	}
+
+	/* Decodes the values of the column, None for an unknown column or one the registry can not decode */
+	pub fn column(&self, name: &str) -> Option<Vec<T>> {
+		self.get_column(name).and_then(decode_segment)
+	}
+
+	/* The values of the named columns at the given rows, one vector per name */
+	pub fn project(&self, names: &[&str], rows: Iter<usize>) -> Option<Vec<Vec<T>>> {
+		let mut decoded = Vec::with_capacity(names.len());
+		for name in names {
+			decoded.push(self.column(name)?);
+		}
+		let mut res: Vec<Vec<T>> = names.iter().map(|_| Vec::new()).collect();
+		for &row in rows {
+			for (out, col) in res.iter_mut().zip(decoded.iter()) {
+				if let Some(v) = col.get(row) {
+					out.push(*v);
+				}
+			}
+		}
+		Some(res)
+	}
+
+	/* Rows whose time lies within [start, end), every row when no per point times are kept */
+	pub fn time_filter(&self, start: SystemTime, end: SystemTime) -> Bitmap {
+		let mut res = Bitmap::create();
+		match &self.time_lapse {
+			Some(lapse) => {
+				for (i, d) in lapse.iter().enumerate() {
+					let t = self.timestamp + *d;
+					if t >= start && t < end {
+						res.add(i as u32);
+					}
+				}
+			}
+			None => if self.timestamp >= start && self.timestamp < end {
+				res.add_range(0..self.size as u64);
+			}
+		}
+		res
+	}
+
+	pub fn convert_to_bytes(&self) -> Result<Vec<u8>,FormatError> {
+		let time_column = match self.get_timestamps() {
+			Some(times) => Some(time_column::encode(&times)),
+			None if self.time_lapse.is_some() => return Err(FormatError::PreEpochTimestamp),
+			None => None,
+		};
+		let mut columns = Vec::with_capacity(self.columns.len());
+		for col in &self.columns {
+			columns.push(header::encode(col)?);
+		}
+		let payload = bincode::serialize(&ColumnarPayload {
+			timestamp: self.timestamp,
+			signal: self.signal,
+			time_column: time_column,
+			prev_seg_offset: self.prev_seg_offset,
+			size: self.size,
+			names: self.names.clone(),
+			columns: columns,
+		}).map_err(|_| FormatError::PayloadEncode)?;
+		let mut out = Vec::with_capacity(COLUMNAR_HEADER_LEN + payload.len());
+		out.extend_from_slice(COLUMNAR_MAGIC);
+		out.extend_from_slice(&COLUMNAR_FORMAT_VERSION.to_le_bytes());
+		out.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+
+		let mut checked = out.clone();
+		checked.extend_from_slice(&payload);
+		out.extend_from_slice(&crc32(&checked).to_le_bytes());
+		out.extend_from_slice(&payload);
+		Ok(out)
+	}
+
+	pub fn convert_from_bytes(bytes: &[u8]) -> Result<ColumnarSegment<T>,FormatError> {
+		if bytes.len() < COLUMNAR_MAGIC.len() || &bytes[..COLUMNAR_MAGIC.len()] != COLUMNAR_MAGIC {
+			return Err(FormatError::UnsupportedVersion(0));
+		}
+		if bytes.len() < COLUMNAR_HEADER_LEN {
+			return Err(FormatError::Truncated);
+		}
+		let mut version = [0u8; 2];
+		version.copy_from_slice(&bytes[4..6]);
+		let version = u16::from_le_bytes(version);
+		if version != COLUMNAR_FORMAT_VERSION {
+			return Err(FormatError::UnsupportedVersion(version));
+		}
+		let mut len = [0u8; 8];
+		len.copy_from_slice(&bytes[6..14]);
+		let len = u64::from_le_bytes(len);
+		let mut stored = [0u8; 4];
+		stored.copy_from_slice(&bytes[14..COLUMNAR_HEADER_LEN]);
+		let stored = u32::from_le_bytes(stored);
+		let body = &bytes[COLUMNAR_HEADER_LEN..];
+		if (body.len() as u64) < len {
+			return Err(FormatError::Truncated);
+		}
+		let body = &body[..len as usize];
+
+		let mut checked = bytes[..14].to_vec();
+		checked.extend_from_slice(body);
+		let computed = crc32(&checked);
+		if computed != stored {
+			return Err(FormatError::ChecksumMismatch { stored: stored, computed: computed });
+		}
+		let payload: ColumnarPayload = bincode::deserialize(body)
+			.map_err(|_| FormatError::PayloadDecode)?;
+		let mut columns = Vec::with_capacity(payload.columns.len());
+		for col in &payload.columns {
+			columns.push(header::decode(col)?);
+		}
+		let time_lapse = match payload.time_column {
+			Some(bytes) => {
+				let start = time_column::to_nanos(payload.timestamp).ok_or(FormatError::PreEpochTimestamp)?;
+				let times = time_column::decode(&bytes).ok_or(FormatError::PayloadDecode)?;
+				Some(times.iter().map(|t| Duration::from_nanos((t - start).max(0) as u64)).collect())
+			}
+			None => None,
+		};
+		if columns.len() != payload.names.len() {
+			return Err(FormatError::PayloadDecode);
+		}
+		Ok(ColumnarSegment {
+			timestamp: payload.timestamp,
+			signal: payload.signal,
+			time_lapse: time_lapse,
+			prev_seg_offset: payload.prev_seg_offset,
+			size: payload.size,
+			names: payload.names,
+			columns: columns,
+		})
+	}
+}
+
+#[test]
+fn test_columnar_segment() {
+	let start = std::time::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+	let names = vec!["temperature".to_string(), "humidity".to_string(), "pressure".to_string()];
+	let rows: Vec<Vec<f64>> = (0..100).map(|i| vec![20.0 + (i % 7) as f64 * 0.5, 40.0 + (i % 3) as f64, 1013.25]).collect();
+	let lapse: Vec<Duration> = (0..100).map(|i| Duration::from_millis(i * 10)).collect();
+	let mut seg = ColumnarSegment::from_rows(start, 4, names, rows.clone(), Some(lapse), None);
+
+	assert_eq!(seg.compress_column("temperature", &Methods::Sprintz (100)), Ok(()));
+	assert_eq!(seg.compress_column("humidity", &Methods::Gorilla), Ok(()));
+	assert_eq!(seg.compress_column("wind", &Methods::Gorilla), Err(()));
+	assert_eq!(seg.get_column("pressure").unwrap().get_method(), &None);
+
+	let bytes = seg.convert_to_bytes().unwrap();
+	let back = ColumnarSegment::<f64>::convert_from_bytes(&bytes).unwrap();
+	assert_eq!(back, seg);
+	assert_eq!(back.get_column("humidity").unwrap().get_method(), &Some(Methods::Gorilla));
+
+	let picked = vec![1usize, 5, 99];
+	let projected = back.project(&["humidity", "temperature"], picked.iter()).unwrap();
+	assert_eq!(projected[0], picked.iter().map(|&i| rows[i][1]).collect::<Vec<f64>>());
+	assert_eq!(projected[1], picked.iter().map(|&i| rows[i][0]).collect::<Vec<f64>>());
+	assert_eq!(back.time_filter(start, start + Duration::from_millis(45)).cardinality(), 5);
+	assert_eq!(seg.get_key_bytes().unwrap()[0], KEY_TAG_COLUMNAR);
+}
+
+#[test]
+fn test_columnar_segment_framing() {
+	let start = std::time::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+	let names = vec!["x".to_string(), "y".to_string()];
+	let rows: Vec<Vec<f64>> = (0..50).map(|i| vec![i as f64, 0.5 * i as f64]).collect();
+	let seg = ColumnarSegment::from_rows(start, 2, names, rows, None, None);
+	let bytes = seg.convert_to_bytes().unwrap();
+
+	let mut corrupt = bytes.clone();
+	let last = corrupt.len() - 1;
+	corrupt[last] ^= 0xff;
+	match ColumnarSegment::<f64>::convert_from_bytes(&corrupt) {
+		Err(FormatError::ChecksumMismatch { .. }) => (),
+		other => panic!("expected a checksum mismatch, got {:?}", other),
+	}
+
+	let mut newer = bytes.clone();
+	newer[4..6].copy_from_slice(&(COLUMNAR_FORMAT_VERSION + 1).to_le_bytes());
+	assert_eq!(ColumnarSegment::<f64>::convert_from_bytes(&newer), Err(FormatError::UnsupportedVersion(COLUMNAR_FORMAT_VERSION + 1)));
+	assert_eq!(ColumnarSegment::<f64>::convert_from_bytes(&bytes[..bytes.len() - 1]), Err(FormatError::Truncated));
+	assert_eq!(ColumnarSegment::<f64>::convert_from_bytes(&bytes[..10]), Err(FormatError::Truncated));
+	assert_eq!(ColumnarSegment::<f64>::convert_from_bytes(&bytes).unwrap(), seg);
+}
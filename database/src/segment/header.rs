@@ -0,0 +1,426 @@
+/*
+ * Overview:
+ * The on-disk framing of a segment. Every persisted segment starts
+ * with a small self describing header so that a reader can tell how
+ * the payload was encoded without deserializing it first.
+ *
+ * Layout (all integers little endian):
+ *   magic           4 bytes  "TSEG"
+ *   format version  u16
+ *   codec id        u8       see Methods::codec_id, 255 when uncompressed
+ *   element type    u8       see element_type_id
+ *   element count   u64
+ *   start time      u64 secs + u32 nanos since the unix epoch
+ *   end time        u64 secs + u32 nanos since the unix epoch
+ *   params length   u16
+ *   codec params    params length bytes
+ *   payload length  u64
+ *   checksum        u32      crc32 over every byte before it and the payload
+ *   payload         bincode of SegmentPayload
+ *
+ * The payload keeps the per point timestamps as a delta of delta
+ * compressed column (see time_column.rs) and ends with the Validity
+ * holding the non finite values of the segment.
+ *
+ * Design Choice:
+ * Bytes that do not start with the magic are treated as format
+ * version 0, the plain bincode dump of Segment that was written before
+ * the header existed, and are migrated on read. Any other version the
+ * decoder does not know is rejected with an error instead of being
+ * guessed at.
+ */
+
+use std::fmt;
+use std::time::{SystemTime,UNIX_EPOCH,Duration};
+use serde::{de, Serialize, Deserialize};
+use crate::bincode;
+use crate::file_handler::crc32;
+use crate::future_signal::SignalId;
+use crate::methods::{Methods,CODEC_NONE};
+use super::Segment;
+use super::time_column;
+use super::validity::Validity;
+
+pub const SEGMENT_MAGIC: &[u8; 4] = b"TSEG";
+pub const FORMAT_VERSION: u16 = 1;
+
+/* Size of the fixed part of the header, up to and including the params length */
+const FIXED_HEADER_LEN: usize = 4 + 2 + 1 + 1 + 8 + 12 + 12 + 2;
+
+/* Bytes of a segment that hold its header when the codec params are short */
+pub const HEADER_PEEK_LEN: usize = FIXED_HEADER_LEN + 64 + 8 + 4;
+
+#[derive(Debug,Clone,PartialEq)]
+pub enum FormatError {
+	Truncated,
+	UnsupportedVersion(u16),
+	UnknownCodec(u8),
+	ElementTypeMismatch { stored: u8, expected: u8 },
+	ChecksumMismatch { stored: u32, computed: u32 },
+	PreEpochTimestamp,
+	PayloadEncode,
+	PayloadDecode,
+}
+
+impl fmt::Display for FormatError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			FormatError::Truncated => write!(f, "segment bytes are truncated"),
+			FormatError::UnsupportedVersion(v) => write!(f, "unsupported segment format version {}", v),
+			FormatError::UnknownCodec(id) => write!(f, "unknown codec id {}", id),
+			FormatError::ElementTypeMismatch { stored, expected } =>
+				write!(f, "segment holds element type {} but {} was requested", stored, expected),
+			FormatError::ChecksumMismatch { stored, computed } =>
+				write!(f, "segment checksum mismatch, stored {:#010x} computed {:#010x}", stored, computed),
+			FormatError::PreEpochTimestamp => write!(f, "segment timestamp precedes the unix epoch"),
+			FormatError::PayloadEncode => write!(f, "failed to encode segment payload"),
+			FormatError::PayloadDecode => write!(f, "failed to decode segment payload"),
+		}
+	}
+}
+
+/* The id a probe deserializer reports for the type its Deserialize
+ * impl asks for, deserializing stops there
+ */
+#[derive(Debug)]
+struct ProbedType(u8);
+
+impl fmt::Display for ProbedType {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "element type {}", self.0)
+	}
+}
+
+impl std::error::Error for ProbedType {}
+
+impl de::Error for ProbedType {
+	fn custom<M: fmt::Display>(_msg: M) -> ProbedType {
+		ProbedType(0)
+	}
+}
+
+struct TypeProbe;
+
+macro_rules! probe_types {
+	($($method:ident => $id:expr),*) => {
+		$(fn $method<V: de::Visitor<'de>>(self, _visitor: V) -> Result<V::Value,ProbedType> {
+			Err(ProbedType($id))
+		})*
+	}
+}
+
+impl<'de> de::Deserializer<'de> for TypeProbe {
+	type Error = ProbedType;
+
+	fn deserialize_any<V: de::Visitor<'de>>(self, _visitor: V) -> Result<V::Value,ProbedType> {
+		Err(ProbedType(0))
+	}
+
+	probe_types!(deserialize_f32 => 1, deserialize_f64 => 2,
+	             deserialize_i8 => 3, deserialize_i16 => 4, deserialize_i32 => 5, deserialize_i64 => 6,
+	             deserialize_u8 => 7, deserialize_u16 => 8, deserialize_u32 => 9, deserialize_u64 => 10);
+
+	serde::forward_to_deserialize_any! {
+		bool i128 u128 char str string bytes byte_buf option unit unit_struct
+		newtype_struct seq tuple tuple_struct map struct enum identifier ignored_any
+	}
+}
+
+/* Fixed ids of the element types a segment can hold, 0 for anything else.
+ * The id is the serde type the values are encoded as, which the payload
+ * depends on anyway, so it does not change with the compiler.
+ */
+pub fn element_type_id<'a,T: Deserialize<'a>>() -> u8 {
+	match T::deserialize(TypeProbe) {
+		Err(ProbedType(id)) => id,
+		Ok(_) => 0,
+	}
+}
+
+/* The decoded header of a framed segment */
+#[derive(Debug,Clone,PartialEq)]
+pub struct SegmentHeader {
+	pub version: u16,
+	pub method: Option<Methods>,
+	pub element_type: u8,
+	pub count: u64,
+	pub start: SystemTime,
+	pub end: SystemTime,
+	pub payload_len: u64,
+	pub checksum: u32,
+}
+
+/* What follows the header, the parts of a segment the header does not describe */
+#[derive(Serialize)]
+struct SegmentPayloadRef<'b,T> {
+	signal: SignalId,
+	data: &'b Vec<T>,
+	binary: &'b Option<Vec<u8>>,
+	time_column: Option<Vec<u8>>,
+	prev_seg_offset: Option<Duration>,
+	comp_time: usize,
+	comp_runtime: f64,
+	validity: &'b Option<Validity<T>>,
+}
+
+#[derive(Deserialize)]
+struct SegmentPayload<T> {
+	signal: SignalId,
+	data: Vec<T>,
+	binary: Option<Vec<u8>>,
+	time_column: Option<Vec<u8>>,
+	prev_seg_offset: Option<Duration>,
+	comp_time: usize,
+	comp_runtime: f64,
+	validity: Option<Validity<T>>,
+}
+
+/* Format version 0, the segment as bincode wrote it before framing */
+#[derive(Deserialize)]
+struct SegmentV0<T> {
+	method: Option<Methods>,
+	timestamp: SystemTime,
+	signal: SignalId,
+	data: Vec<T>,
+	binary: Option<Vec<u8>>,
+	time_lapse: Option<Vec<Duration>>,
+	prev_seg_offset: Option<Duration>,
+	comp_time: usize,
+	size: usize,
+	comp_runtime: f64,
+}
+
+fn put_time(out: &mut Vec<u8>, time: SystemTime) -> Result<(),FormatError> {
+	let since = time.duration_since(UNIX_EPOCH).map_err(|_| FormatError::PreEpochTimestamp)?;
+	out.extend_from_slice(&since.as_secs().to_le_bytes());
+	out.extend_from_slice(&since.subsec_nanos().to_le_bytes());
+	Ok(())
+}
+
+/* Bounds checked little endian reader over the header bytes */
+struct Reader<'b> {
+	bytes: &'b [u8],
+	pos: usize,
+}
+
+impl<'b> Reader<'b> {
+	fn take(&mut self, n: usize) -> Result<&'b [u8],FormatError> {
+		if self.bytes.len() - self.pos < n {
+			return Err(FormatError::Truncated);
+		}
+		let slice = &self.bytes[self.pos..self.pos+n];
+		self.pos += n;
+		Ok(slice)
+	}
+
+	fn u8(&mut self) -> Result<u8,FormatError> {
+		Ok(self.take(1)?[0])
+	}
+
+	fn u16(&mut self) -> Result<u16,FormatError> {
+		let mut buf = [0u8; 2];
+		buf.copy_from_slice(self.take(2)?);
+		Ok(u16::from_le_bytes(buf))
+	}
+
+	fn u32(&mut self) -> Result<u32,FormatError> {
+		let mut buf = [0u8; 4];
+		buf.copy_from_slice(self.take(4)?);
+		Ok(u32::from_le_bytes(buf))
+	}
+
+	fn u64(&mut self) -> Result<u64,FormatError> {
+		let mut buf = [0u8; 8];
+		buf.copy_from_slice(self.take(8)?);
+		Ok(u64::from_le_bytes(buf))
+	}
+
+	fn time(&mut self) -> Result<SystemTime,FormatError> {
+		let secs = self.u64()?;
+		let nanos = self.u32()?;
+		Ok(UNIX_EPOCH + Duration::new(secs, nanos))
+	}
+}
+
+/* True when the bytes carry a header rather than a version 0 dump */
+pub fn is_framed(bytes: &[u8]) -> bool {
+	bytes.len() >= SEGMENT_MAGIC.len() && &bytes[..SEGMENT_MAGIC.len()] == SEGMENT_MAGIC
+}
+
+/* Compresses the time lapse of the segment into its timestamp column */
+fn encode_time_column<T>(seg: &Segment<T>) -> Result<Option<Vec<u8>>,FormatError> {
+	match seg.get_timestamps() {
+		Some(times) => Ok(Some(time_column::encode(&times))),
+		None if seg.time_lapse.is_some() => Err(FormatError::PreEpochTimestamp),
+		None => Ok(None),
+	}
+}
+
+/* Turns the timestamp column back into offsets from the segment start */
+fn decode_time_column(column: Option<Vec<u8>>, start: SystemTime) -> Result<Option<Vec<Duration>>,FormatError> {
+	let bytes = match column {
+		Some(bytes) => bytes,
+		None => return Ok(None),
+	};
+	let start = time_column::to_nanos(start).ok_or(FormatError::PreEpochTimestamp)?;
+	let times = time_column::decode(&bytes).ok_or(FormatError::PayloadDecode)?;
+	Ok(Some(times.iter().map(|t| Duration::from_nanos((t - start).max(0) as u64)).collect()))
+}
+
+pub fn encode<'a,T: Serialize + Deserialize<'a>>(seg: &Segment<T>) -> Result<Vec<u8>,FormatError> {
+	let payload = bincode::serialize(&SegmentPayloadRef {
+		signal: seg.signal,
+		data: &seg.data,
+		binary: &seg.binary,
+		time_column: encode_time_column(seg)?,
+		prev_seg_offset: seg.prev_seg_offset,
+		comp_time: seg.comp_time,
+		comp_runtime: seg.comp_runtime,
+		validity: &seg.validity,
+	}).map_err(|_| FormatError::PayloadEncode)?;
+
+	let end = seg.get_end();
+	let (codec, params) = match &seg.method {
+		Some(m) => (m.codec_id(), m.codec_params()),
+		None    => (CODEC_NONE, Vec::new()),
+	};
+	if params.len() > u16::max_value() as usize {
+		return Err(FormatError::PayloadEncode);
+	}
+
+	let mut out = Vec::with_capacity(FIXED_HEADER_LEN + params.len() + 12 + payload.len());
+	out.extend_from_slice(SEGMENT_MAGIC);
+	out.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+	out.push(codec);
+	out.push(element_type_id::<'a,T>());
+	out.extend_from_slice(&(seg.size as u64).to_le_bytes());
+	put_time(&mut out, seg.timestamp)?;
+	put_time(&mut out, end)?;
+	out.extend_from_slice(&(params.len() as u16).to_le_bytes());
+	out.extend_from_slice(&params);
+	out.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+
+	let mut checked = out.clone();
+	checked.extend_from_slice(&payload);
+	out.extend_from_slice(&crc32(&checked).to_le_bytes());
+	out.extend_from_slice(&payload);
+	Ok(out)
+}
+
+/* Parses the header without touching the payload, returning it with the
+ * offset of its checksum and of the payload. Only the fixed part and the
+ * codec params have to be present, the checksum is not verified.
+ */
+fn parse_header(bytes: &[u8]) -> Result<(SegmentHeader,usize,usize),FormatError> {
+	if !is_framed(bytes) {
+		return Err(FormatError::UnsupportedVersion(0));
+	}
+	let mut r = Reader { bytes: bytes, pos: SEGMENT_MAGIC.len() };
+	let version = r.u16()?;
+	if version != FORMAT_VERSION {
+		return Err(FormatError::UnsupportedVersion(version));
+	}
+	let codec = r.u8()?;
+	let element_type = r.u8()?;
+	let count = r.u64()?;
+	let start = r.time()?;
+	let end = r.time()?;
+	let params_len = r.u16()? as usize;
+	let params = r.take(params_len)?;
+	let payload_len = r.u64()?;
+	let checksum_pos = r.pos;
+	let checksum = r.u32()?;
+	let payload_start = r.pos;
+
+	let method = if codec == CODEC_NONE {
+		None
+	} else {
+		match Methods::from_codec(codec, params) {
+			Some(m) => Some(m),
+			None    => return Err(FormatError::UnknownCodec(codec)),
+		}
+	};
+
+	Ok((SegmentHeader {
+		version: version,
+		method: method,
+		element_type: element_type,
+		count: count,
+		start: start,
+		end: end,
+		payload_len: payload_len,
+		checksum: checksum,
+	}, checksum_pos, payload_start))
+}
+
+/* Parses the header from the first bytes of a segment, which need not
+ * hold the payload. HEADER_PEEK_LEN bytes hold the header unless its codec
+ * params are unusually long, then this fails with Truncated.
+ */
+pub fn peek_header(bytes: &[u8]) -> Result<SegmentHeader,FormatError> {
+	parse_header(bytes).map(|(header, _, _)| header)
+}
+
+/* Parses and verifies the header, returning it with the offset of the payload */
+pub fn read_header(bytes: &[u8]) -> Result<(SegmentHeader,usize),FormatError> {
+	let (header, checksum_pos, payload_start) = parse_header(bytes)?;
+	if ((bytes.len() - payload_start) as u64) < header.payload_len {
+		return Err(FormatError::Truncated);
+	}
+
+	let payload_end = payload_start + header.payload_len as usize;
+	let mut checked = bytes[..checksum_pos].to_vec();
+	checked.extend_from_slice(&bytes[payload_start..payload_end]);
+	let computed = crc32(&checked);
+	if computed != header.checksum {
+		return Err(FormatError::ChecksumMismatch { stored: header.checksum, computed: computed });
+	}
+	Ok((header, payload_start))
+}
+
+pub fn decode<'a,T: Deserialize<'a>>(bytes: &'a [u8]) -> Result<Segment<T>,FormatError> {
+	if !is_framed(bytes) {
+		return migrate_v0(bytes);
+	}
+
+	let (header, payload_start) = read_header(bytes)?;
+	let expected = element_type_id::<'a,T>();
+	if header.element_type != expected {
+		return Err(FormatError::ElementTypeMismatch { stored: header.element_type, expected: expected });
+	}
+
+	let payload_end = payload_start + header.payload_len as usize;
+	let payload = &bytes[payload_start..payload_end];
+	let payload: SegmentPayload<T> = bincode::deserialize(payload).map_err(|_| FormatError::PayloadDecode)?;
+
+	Ok(Segment {
+		method: header.method,
+		timestamp: header.start,
+		signal: payload.signal,
+		data: payload.data,
+		binary: payload.binary,
+		time_lapse: decode_time_column(payload.time_column, header.start)?,
+		prev_seg_offset: payload.prev_seg_offset,
+		comp_time: payload.comp_time,
+		size: header.count as usize,
+		comp_runtime: payload.comp_runtime,
+		validity: payload.validity,
+	})
+}
+
+fn migrate_v0<'a,T: Deserialize<'a>>(bytes: &'a [u8]) -> Result<Segment<T>,FormatError> {
+	let old: SegmentV0<T> = bincode::deserialize(bytes).map_err(|_| FormatError::PayloadDecode)?;
+	Ok(Segment {
+		method: old.method,
+		timestamp: old.timestamp,
+		signal: old.signal,
+		data: old.data,
+		binary: old.binary,
+		time_lapse: old.time_lapse,
+		prev_seg_offset: old.prev_seg_offset,
+		comp_time: old.comp_time,
+		size: old.size,
+		comp_runtime: old.comp_runtime,
+		validity: None,
+	})
+}
@@ -0,0 +1,161 @@
+/*
+ * Overview:
+ * The per point timestamps of a segment as a column of i64 nanoseconds
+ * since the unix epoch, compressed with delta of delta encoding.
+ *
+ * Layout:
+ *   point count     32 bits
+ *   first timestamp 64 bits
+ *   then for every following point the delta of delta against the
+ *   previous delta (the first delta is taken against 0):
+ *     0                  '0'
+ *     [-64, 63]          '10'    + 7 bits
+ *     [-256, 255]        '110'   + 9 bits
+ *     [-2048, 2047]      '1110'  + 12 bits
+ *     fits in an i32     '11110' + 32 bits
+ *     anything else      '11111' + 64 bits
+ *
+ * Design Choice:
+ * The control codes follow GorillaEncoder::write_next_timestamp, with an
+ * extra 64 bit case since nanosecond deltas routinely exceed 32 bits.
+ * The count is stored up front so the stream needs no end marker, a
+ * regularly sampled signal costs one bit per point after the first two.
+ */
+
+use tsz::Bit;
+use tsz::stream::{BufferedWriter, BufferedReader, Write, Read};
+use std::time::{SystemTime, UNIX_EPOCH, Duration};
+
+/* Nanoseconds since the unix epoch, None before the epoch or past year 2262 */
+pub fn to_nanos(time: SystemTime) -> Option<i64> {
+	let since = time.duration_since(UNIX_EPOCH).ok()?;
+	let nanos = since.as_secs().checked_mul(1_000_000_000)?.checked_add(since.subsec_nanos() as u64)?;
+	if nanos > i64::max_value() as u64 {
+		return None;
+	}
+	Some(nanos as i64)
+}
+
+pub fn from_nanos(nanos: i64) -> SystemTime {
+	UNIX_EPOCH + Duration::from_nanos(nanos.max(0) as u64)
+}
+
+fn write_dod(w: &mut BufferedWriter, dod: i64) {
+	match dod {
+		0 => w.write_bit(Bit::Zero),
+		-64..=63 => {
+			w.write_bits(0b10, 2);
+			w.write_bits(dod as u64, 7);
+		}
+		-256..=255 => {
+			w.write_bits(0b110, 3);
+			w.write_bits(dod as u64, 9);
+		}
+		-2048..=2047 => {
+			w.write_bits(0b1110, 4);
+			w.write_bits(dod as u64, 12);
+		}
+		_ if dod >= i32::min_value() as i64 && dod <= i32::max_value() as i64 => {
+			w.write_bits(0b11110, 5);
+			w.write_bits(dod as u64, 32);
+		}
+		_ => {
+			w.write_bits(0b11111, 5);
+			w.write_bits(dod as u64, 64);
+		}
+	}
+}
+
+fn read_dod(r: &mut BufferedReader) -> Option<i64> {
+	let mut control_bits = 0;
+	for _ in 0..5 {
+		if r.read_bit().ok()? == Bit::One {
+			control_bits += 1;
+		} else {
+			break;
+		}
+	}
+	let size = match control_bits {
+		0 => return Some(0),
+		1 => 7,
+		2 => 9,
+		3 => 12,
+		4 => 32,
+		_ => 64,
+	};
+	let bits = r.read_bits(size).ok()?;
+	if size == 64 {
+		return Some(bits as i64);
+	}
+	/* The values were written two's complement, sign extend them back */
+	let shift = 64 - size;
+	Some(((bits << shift) as i64) >> shift)
+}
+
+pub fn encode(times: &[i64]) -> Vec<u8> {
+	let mut w = BufferedWriter::new();
+	w.write_bits(times.len() as u64, 32);
+	if let Some(first) = times.first() {
+		w.write_bits(*first as u64, 64);
+	}
+	let mut prev = times.first().cloned().unwrap_or(0);
+	let mut delta = 0i64;
+	for t in times.iter().skip(1) {
+		let cur_delta = t.wrapping_sub(prev);
+		write_dod(&mut w, cur_delta.wrapping_sub(delta));
+		delta = cur_delta;
+		prev = *t;
+	}
+	w.close().into_vec()
+}
+
+/* None when the bytes end before every point was read */
+pub fn decode(bytes: &[u8]) -> Option<Vec<i64>> {
+	let mut r = BufferedReader::new(bytes.to_vec().into_boxed_slice());
+	let count = r.read_bits(32).ok()? as usize;
+	let mut times = Vec::with_capacity(count);
+	if count == 0 {
+		return Some(times);
+	}
+	let mut prev = r.read_bits(64).ok()? as i64;
+	times.push(prev);
+	let mut delta = 0i64;
+	for _ in 1..count {
+		delta = delta.wrapping_add(read_dod(&mut r)?);
+		prev = prev.wrapping_add(delta);
+		times.push(prev);
+	}
+	Some(times)
+}
+
+#[test]
+fn test_time_column_round_trip() {
+	let base = 1_600_000_000_000_000_000i64;
+	let mut times = vec![base];
+	let mut t = base;
+	for i in 1..500i64 {
+		/* Mostly regular with jitter of every size class and a long gap */
+		t += 1_000_000_000 + match i % 7 {
+			0 => 0,
+			1 => 50,
+			2 => -200,
+			3 => 2000,
+			4 => -70_000,
+			5 => 5_000_000_000_000,
+			_ => 64,
+		};
+		times.push(t);
+	}
+	let bytes = encode(&times);
+	assert!(bytes.len() < times.len() * 8);
+	assert_eq!(decode(&bytes), Some(times.clone()));
+
+	let regular: Vec<i64> = (0..1000).map(|i| base + i * 1_000_000).collect();
+	let bytes = encode(&regular);
+	assert!(bytes.len() < 200);
+	assert_eq!(decode(&bytes), Some(regular));
+
+	assert_eq!(decode(&encode(&[])), Some(vec![]));
+	assert_eq!(decode(&encode(&[base])), Some(vec![base]));
+	assert_eq!(decode(&bytes[..bytes.len() / 2]), None);
+}
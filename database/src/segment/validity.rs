@@ -0,0 +1,192 @@
+/*
+ * Overview:
+ * Support for gaps, NaN and infinities in segments. A missing value is
+ * held in memory as a NaN with a reserved payload, the NA of R, so a
+ * Vec<f64> can carry it next to real NaNs without another column.
+ *
+ * Design Choice:
+ * The float codecs (BUFF, Sprintz, Gorilla, the bit packers) and
+ * PrecisionBound only handle finite values. Before a segment is
+ * compressed every non finite value is set aside in a Validity: a
+ * bitmap of the rows it was taken from and the original values in row
+ * order. The rows are refilled with the previous finite value, so delta
+ * based codecs stay tight, and the original bits are put back on
+ * decode. Rows are kept as a portable croaring bitmap.
+ */
+
+use croaring::Bitmap;
+use num::Num;
+use serde::{Serialize, Deserialize};
+
+/* The quiet NaN payload that marks a missing f64 */
+pub const NULL_BITS_F64: u64 = 0x7FF8_0000_0000_07A2;
+/* The quiet NaN payload that marks a missing f32 */
+pub const NULL_BITS_F32: u32 = 0x7FC0_07A2;
+
+/* Element types that can tell a missing value apart from any other */
+pub trait Nullable: Sized {
+	/* The missing value, None for types that can not hold one */
+	fn null() -> Option<Self>;
+	fn is_null(&self) -> bool;
+
+	/* True for a gap and for any other NaN, the values an aggregation
+	 * skips since one of them would turn every sum and comparison into
+	 * a NaN. Infinities are kept, they are values.
+	 */
+	fn is_undefined(&self) -> bool {
+		self.is_null()
+	}
+}
+
+impl Nullable for f64 {
+	fn null() -> Option<f64> {
+		Some(f64::from_bits(NULL_BITS_F64))
+	}
+
+	fn is_null(&self) -> bool {
+		self.to_bits() == NULL_BITS_F64
+	}
+
+	fn is_undefined(&self) -> bool {
+		self.is_nan()
+	}
+}
+
+impl Nullable for f32 {
+	fn null() -> Option<f32> {
+		Some(f32::from_bits(NULL_BITS_F32))
+	}
+
+	fn is_null(&self) -> bool {
+		self.to_bits() == NULL_BITS_F32
+	}
+
+	fn is_undefined(&self) -> bool {
+		self.is_nan()
+	}
+}
+
+macro_rules! never_null {
+	($($t:ty),*) => {
+		$(impl Nullable for $t {
+			fn null() -> Option<$t> {
+				None
+			}
+
+			fn is_null(&self) -> bool {
+				false
+			}
+		})*
+	}
+}
+
+never_null!(i8, i16, i32, i64, u8, u16, u32, u64, usize, isize);
+
+#[derive(Clone,Serialize,Deserialize,Debug,PartialEq)]
+pub struct Validity<T> {
+	rows: Vec<u8>,
+	values: Vec<T>,
+}
+
+impl<T> Validity<T> {
+	pub fn get_heap_size(&self) -> usize {
+		self.rows.capacity() + self.values.capacity() * std::mem::size_of::<T>()
+	}
+}
+
+impl<T: Copy> Validity<T> {
+	/* The rows holding a value that is not finite, missing values included */
+	pub fn get_rows(&self) -> Bitmap {
+		Bitmap::deserialize(&self.rows)
+	}
+
+	/* The original values of those rows, in row order */
+	pub fn get_values(&self) -> &Vec<T> {
+		&self.values
+	}
+
+	/* The set aside values of the kept rows, numbered by their place among
+	 * them. None when no kept row held one.
+	 */
+	pub fn select(&self, kept: &Bitmap) -> Option<Validity<T>> {
+		let set_aside = self.get_rows();
+		let mut set_aside = set_aside.iter().zip(self.values.iter()).peekable();
+		let mut rows = Bitmap::create();
+		let mut values = Vec::new();
+		for (i, row) in kept.iter().enumerate() {
+			while set_aside.peek().map_or(false, |(r, _)| *r < row) {
+				set_aside.next();
+			}
+			if let Some((r, v)) = set_aside.peek() {
+				if *r == row {
+					rows.add(i as u32);
+					values.push(**v);
+				}
+			}
+		}
+		if values.is_empty() {
+			return None;
+		}
+		Some(Validity { rows: rows.serialize(), values: values })
+	}
+
+	/* Puts the set aside values back, rows past the end of data are ignored */
+	pub fn restore(&self, data: &mut Vec<T>) {
+		for (row, v) in self.get_rows().iter().zip(self.values.iter()) {
+			if let Some(slot) = data.get_mut(row as usize) {
+				*slot = *v;
+			}
+		}
+	}
+}
+
+/* Splits the non finite values out of data. Returns None when every
+ * value is finite, otherwise the data with those rows refilled and the
+ * Validity recording what was taken out.
+ */
+pub fn split<T: Num + Copy + Into<f64>>(data: &[T]) -> Option<(Vec<T>, Validity<T>)> {
+	if data.iter().all(|v| (*v).into().is_finite()) {
+		return None;
+	}
+	let mut rows = Bitmap::create();
+	let mut values = Vec::new();
+	let mut filled = Vec::with_capacity(data.len());
+	let mut last = data.iter().cloned().find(|v| (*v).into().is_finite()).unwrap_or(T::zero());
+	for (i, v) in data.iter().enumerate() {
+		if (*v).into().is_finite() {
+			last = *v;
+			filled.push(*v);
+		} else {
+			rows.add(i as u32);
+			values.push(*v);
+			filled.push(last);
+		}
+	}
+	Some((filled, Validity { rows: rows.serialize(), values: values }))
+}
+
+#[test]
+fn test_validity_round_trip() {
+	let null = f64::null().unwrap();
+	let data = vec![1.5, null, 2.5, std::f64::NAN, std::f64::INFINITY, 3.0, std::f64::NEG_INFINITY, null];
+	let (filled, validity) = split(&data).unwrap();
+	assert!(filled.iter().all(|v| v.is_finite()));
+	assert_eq!(filled, vec![1.5, 1.5, 2.5, 2.5, 2.5, 3.0, 3.0, 3.0]);
+	assert_eq!(validity.get_rows().iter().collect::<Vec<u32>>(), vec![1, 3, 4, 6, 7]);
+
+	let mut restored = filled.clone();
+	validity.restore(&mut restored);
+	let bits: Vec<u64> = restored.iter().map(|v| v.to_bits()).collect();
+	assert_eq!(bits, data.iter().map(|v| v.to_bits()).collect::<Vec<u64>>());
+	assert!(restored[1].is_null() && !restored[3].is_null());
+	assert!(restored[1].is_undefined() && restored[3].is_undefined() && !restored[4].is_undefined());
+
+	let kept: Bitmap = vec![0u32, 3, 5, 7].into_iter().collect();
+	let selected = validity.select(&kept).unwrap();
+	assert_eq!(selected.get_rows().iter().collect::<Vec<u32>>(), vec![1, 3]);
+	assert!(selected.get_values()[0].is_nan() && selected.get_values()[1].is_null());
+	assert_eq!(validity.select(&vec![0u32, 2, 5].into_iter().collect()), None);
+
+	assert_eq!(split(&vec![1.0f64, 2.0]), None);
+	assert_eq!(i32::null(), None);
+}
@@ -0,0 +1,175 @@
+use std::sync::{Arc,Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use crate::buffer_pool::{SegmentBuffer,BufErr,share_buffer};
+use crate::buffer_pool::governor::governor;
+use crate::wal::WriteAheadLog;
+
+/*
+ * Overview:
+ * Ends a run without losing what it buffered. The signals and daemons
+ * otherwise only stop when their stream runs out or never at all, so
+ * the segments still held by the pools were dropped with the process.
+ *
+ * Design Choice:
+ * Signals and daemons only share a Shutdown flag with the handle and
+ * check it between values and between batches, nothing is interrupted
+ * midway. Once it is raised the signals return, the compression daemons
+ * stop waiting for their thresholds and compress every full batch left
+ * in the raw pool before they exit, and the recoding daemons exit after
+ * the batch they are working on. The handle then joins the daemon
+ * threads and persists both pools through their file managers, so a
+ * restart over the same files finds every segment through get and
+ * range_keys. Less than a batch of raw segments stays in the raw pool
+ * and is persisted uncompressed. Pools without a file manager have
+ * nothing to persist to. A write ahead log given to the handle is
+ * truncated last, what the pools could not persist stays in it.
+ */
+
+#[derive(Clone,Debug,Default)]
+pub struct Shutdown {
+	stopping: Arc<AtomicBool>,
+}
+
+impl Shutdown {
+	pub fn new() -> Shutdown {
+		Shutdown::default()
+	}
+
+	pub fn trigger(&self) {
+		self.stopping.store(true, Ordering::Release);
+	}
+
+	pub fn is_stopping(&self) -> bool {
+		self.stopping.load(Ordering::Acquire)
+	}
+}
+
+pub struct ShutdownHandle<T: Copy + Send> {
+	shutdown: Shutdown,
+	daemons: Vec<JoinHandle<()>>,
+	pools: Vec<Arc<Mutex<SegmentBuffer<T> + Send + Sync>>>,
+	wal: Option<Arc<WriteAheadLog>>,
+}
+
+impl<T: Copy + Send> ShutdownHandle<T> {
+	pub fn new() -> ShutdownHandle<T> {
+		ShutdownHandle {
+			shutdown: Shutdown::new(),
+			daemons: Vec::new(),
+			pools: Vec::new(),
+			wal: None,
+		}
+	}
+
+	/* The flag to hand to every signal and daemon of the run */
+	pub fn signal(&self) -> Shutdown {
+		self.shutdown.clone()
+	}
+
+	pub fn add_daemon(&mut self, daemon: JoinHandle<()>) {
+		self.daemons.push(daemon);
+	}
+
+	/* Pools are persisted in the order they are added */
+	pub fn add_pool(&mut self, pool: &Arc<Mutex<SegmentBuffer<T> + Send + Sync>>) {
+		self.pools.push(share_buffer(pool));
+	}
+
+	pub fn set_wal(&mut self, wal: Arc<WriteAheadLog>) {
+		self.wal = Some(wal);
+	}
+
+	/* Tells the signals to return without waiting for them, signals held
+	 * back by the memory governor are woken so they see the flag
+	 */
+	pub fn stop_ingestion(&self) {
+		self.shutdown.trigger();
+		governor().wake();
+	}
+
+	/* Stops ingestion, waits for the daemons to drain and exit and then
+	 * persists every pool. Each pool is persisted even if another failed,
+	 * the first failure is returned.
+	 */
+	pub fn shutdown(self) -> Result<(), BufErr> {
+		self.stop_ingestion();
+		for daemon in self.daemons {
+			if daemon.join().is_err() {
+				println!("A daemon panicked before it finished draining");
+			}
+		}
+		let mut res = Ok(());
+		for pool in self.pools.iter() {
+			let persisted = match pool.lock() {
+				Ok(buf) => buf.persist(),
+				Err(_) => Err(BufErr::CantGrabMutex),
+			};
+			if res.is_ok() {
+				res = persisted;
+			}
+		}
+		if let Some(wal) = &self.wal {
+			if let Err(e) = wal.truncate_persisted(&self.pools) {
+				println!("Failed to truncate the write ahead log: {:?}", e);
+			}
+		}
+		res
+	}
+}
+
+#[test]
+fn shutdown_test() {
+	use std::thread;
+	use std::time::{Duration, UNIX_EPOCH};
+	use crate::buffer_pool::ClockBuffer;
+	use crate::compression_daemon::CompressionDaemon;
+	use crate::file_handler::LogFileManager;
+	use crate::methods::registry::{codec_by_name, decode_segment};
+	use crate::segment::Segment;
+
+	let (raw_path, comp_path) = ("../shutdown_raw_test", "../shutdown_comp_test");
+	let _ = LogFileManager::destroy(raw_path);
+	let _ = LogFileManager::destroy(comp_path);
+	let end = UNIX_EPOCH + Duration::from_secs(10);
+	{
+		let raw: Arc<Mutex<SegmentBuffer<f64> + Send + Sync>> = Arc::new(Mutex::new(
+			ClockBuffer::<f64,_>::new(16, LogFileManager::open(raw_path).expect("Failed to open log file manager"))));
+		let comp: Arc<Mutex<SegmentBuffer<f64> + Send + Sync>> = Arc::new(Mutex::new(
+			ClockBuffer::<f64,_>::new(16, LogFileManager::open(comp_path).expect("Failed to open log file manager"))));
+		for i in 0..10u64 {
+			let seg = Segment::new(None, UNIX_EPOCH + Duration::from_secs(i), 1, vec![i as f64; 100], None, None);
+			raw.lock().unwrap().put(seg).expect("Failed to put segment");
+		}
+
+		/* The threshold is never reached, only the shutdown makes the daemon compress */
+		let mut shutdown = ShutdownHandle::new();
+		let mut daemon: CompressionDaemon<_,LogFileManager,_> = CompressionDaemon::new(share_buffer(&raw), share_buffer(&comp),
+			None, 1.0, 1.1, codec_by_name("gorilla", 4).unwrap());
+		daemon.set_shutdown(shutdown.signal());
+		shutdown.add_daemon(thread::spawn(move || daemon.run()));
+		thread::sleep(Duration::from_millis(50));
+		assert!(raw.lock().unwrap().exceed_batch(10));
+		shutdown.add_pool(&raw);
+		shutdown.add_pool(&comp);
+		shutdown.shutdown().expect("Failed to persist the pools");
+
+		/* Two full batches were compressed, the rest stayed raw */
+		assert!(raw.lock().unwrap().exceed_batch(2) && !raw.lock().unwrap().exceed_batch(3));
+		assert!(comp.lock().unwrap().exceed_batch(8));
+	}
+
+	/* A restart over the same files finds every segment */
+	let raw = ClockBuffer::<f64,_>::new(16, LogFileManager::open(raw_path).expect("Failed to reopen log file manager"));
+	let mut comp = ClockBuffer::<f64,_>::new(16, LogFileManager::open(comp_path).expect("Failed to reopen log file manager"));
+	assert_eq!(raw.range_keys(1, UNIX_EPOCH, end).unwrap().len(), 2);
+	let keys = comp.range_keys(1, UNIX_EPOCH, end).unwrap();
+	assert_eq!(keys.len(), 8);
+	for key in keys {
+		let seg = comp.get(key).unwrap().expect("Persisted segment is missing");
+		let value = seg.get_key().get_timestamp().duration_since(UNIX_EPOCH).unwrap().as_secs() as f64;
+		assert_eq!(decode_segment(seg).unwrap(), vec![value; 100]);
+	}
+	LogFileManager::destroy(raw_path).expect("Failed to destroy log file manager");
+	LogFileManager::destroy(comp_path).expect("Failed to destroy log file manager");
+}
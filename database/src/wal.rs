@@ -0,0 +1,438 @@
+use std::collections::{BTreeMap,BTreeSet};
+use std::fs::{self,File,OpenOptions};
+use std::io::{self,Read,Write,Seek,SeekFrom};
+use std::path::{Path,PathBuf};
+use std::sync::{Arc,Mutex};
+use std::thread::{self,JoinHandle};
+use std::time::{Duration,Instant,SystemTime,UNIX_EPOCH};
+use num::Float;
+
+use crate::buffer_pool::{SegmentBuffer,share_buffer};
+use crate::catalog::{SignalCatalog,CODEC_RAW};
+use crate::file_handler::{Error,crc32,read_u32,read_u64,sync_dir};
+use crate::future_signal::SignalId;
+use crate::segment::{Segment,SegmentKey};
+use crate::shutdown::{Shutdown,ShutdownHandle};
+
+/*
+ * Overview:
+ * A write ahead log of the points that are only held in memory, the
+ * segment a signal is still filling and the segments waiting in the
+ * raw buffer. Without it a crash loses every point a daemon has not
+ * compressed and written yet.
+ *
+ * Layout of the directory owned by a WriteAheadLog:
+ *   points.wal: every record appended with a fixed size
+ *       [op: u8][signal: u64][nanos: u64][value: u64][crc32: u32]
+ *       RECORD_POINT carries the bits of the value as an f64 and the
+ *       nanoseconds since the epoch it arrived at. RECORD_SEAL closes
+ *       the segment of the signal that started at its nanos.
+ *
+ * Design Choice:
+ * A signal logs a point before it adds the value to the segment it is
+ * filling and seals the segment before it hands it to the buffer. The
+ * points of a signal up to a seal are one segment, those past its last
+ * seal are the segment it was filling. Values are logged as f64 so the
+ * log does not depend on the type of the signal.
+ * A point reaches the OS before it is acknowledged, which survives the
+ * process crashing. The log is only fsynced when a segment is sealed,
+ * otherwise every point would wait on the disk.
+ * Truncation rewrites the log without the sealed segments a buffer
+ * reports as persisted and swaps it in through a rename, the same way
+ * LogFileManager::compact does. The keys of the sealed segments in the
+ * log are kept in memory, so a truncation that has nothing to drop asks
+ * the buffers about those and leaves the file alone. Recovery rebuilds every segment left in
+ * the log, the partial ones included, and seals those so the points
+ * that arrive after the restart start new segments.
+ */
+
+const WAL_FILE: &str = "points.wal";
+const RECORD_POINT: u8 = 0;
+const RECORD_SEAL: u8 = 1;
+const RECORD_SIZE: usize = 29;
+
+/* Milliseconds between truncations when the config does not provide them */
+pub const DEFAULT_TRUNCATE_INTERVAL: u64 = 1000;
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+struct Record {
+	op: u8,
+	signal: SignalId,
+	nanos: u64,
+	value: u64,
+}
+
+impl Record {
+	fn encode(&self) -> Vec<u8> {
+		let mut bytes = Vec::with_capacity(RECORD_SIZE);
+		bytes.push(self.op);
+		bytes.extend_from_slice(&self.signal.to_le_bytes());
+		bytes.extend_from_slice(&self.nanos.to_le_bytes());
+		bytes.extend_from_slice(&self.value.to_le_bytes());
+		let crc = crc32(&bytes);
+		bytes.extend_from_slice(&crc.to_le_bytes());
+		bytes
+	}
+
+	/* None if the record is torn or does not pass its checksum */
+	fn decode(bytes: &[u8]) -> Option<Record> {
+		if bytes.len() < RECORD_SIZE || read_u32(&bytes[RECORD_SIZE-4..]) != crc32(&bytes[..RECORD_SIZE-4]) {
+			return None;
+		}
+		match bytes[0] {
+			RECORD_POINT | RECORD_SEAL => Some(Record {
+				op: bytes[0],
+				signal: read_u64(&bytes[1..9]),
+				nanos: read_u64(&bytes[9..17]),
+				value: read_u64(&bytes[17..25]),
+			}),
+			_ => None,
+		}
+	}
+}
+
+fn to_nanos(time: SystemTime) -> u64 {
+	match time.duration_since(UNIX_EPOCH) {
+		Ok(d) => d.as_secs() * 1_000_000_000 + d.subsec_nanos() as u64,
+		Err(_) => 0,
+	}
+}
+
+/* The records of one segment, seal is None for a segment still being filled */
+struct Group {
+	signal: SignalId,
+	points: Vec<Record>,
+	seal: Option<Record>,
+}
+
+impl Group {
+	fn start(&self) -> u64 {
+		match (&self.seal, self.points.first()) {
+			(Some(seal), _) => seal.nanos,
+			(None, Some(point)) => point.nanos,
+			(None, None) => 0,
+		}
+	}
+
+	fn key(&self) -> SegmentKey {
+		SegmentKey::new(UNIX_EPOCH + Duration::from_nanos(self.start()), self.signal)
+	}
+
+	fn to_segment<T: Float + Send>(&self) -> Segment<T> {
+		let start = self.start();
+		let data = self.points.iter()
+			.map(|p| num::cast(f64::from_bits(p.value)).unwrap_or(T::nan()))
+			.collect();
+		let time_lapse = self.points.iter()
+			.map(|p| Duration::from_nanos(p.nanos.saturating_sub(start)))
+			.collect();
+		Segment::new(None, UNIX_EPOCH + Duration::from_nanos(start), self.signal, data, Some(time_lapse), None)
+	}
+}
+
+/* Splits the records into segments, sealed ones in the order they were
+ * sealed followed by those still being filled
+ */
+fn group(records: Vec<Record>) -> Vec<Group> {
+	let mut filling: BTreeMap<SignalId,Vec<Record>> = BTreeMap::new();
+	let mut groups = Vec::new();
+	for rec in records {
+		match rec.op {
+			RECORD_SEAL => groups.push(Group {
+				signal: rec.signal,
+				points: filling.remove(&rec.signal).unwrap_or_default(),
+				seal: Some(rec),
+			}),
+			_ => filling.entry(rec.signal).or_insert_with(Vec::new).push(rec),
+		}
+	}
+	for (signal, points) in filling {
+		groups.push(Group { signal: signal, points: points, seal: None });
+	}
+	groups
+}
+
+struct WalState {
+	log: File,
+	log_len: u64,
+	/* Keys of the sealed segments the log holds */
+	sealed: BTreeSet<SegmentKey>,
+}
+
+/* Keys of the sealed segments among the groups */
+fn sealed_keys(groups: &[Group]) -> BTreeSet<SegmentKey> {
+	groups.iter().filter(|group| group.seal.is_some()).map(|group| group.key()).collect()
+}
+
+pub struct WriteAheadLog {
+	dir: PathBuf,
+	state: Mutex<WalState>,
+}
+
+impl WriteAheadLog {
+	/* Opens (or creates) the log in the provided directory, a torn
+	 * record at its end is truncated away
+	 */
+	pub fn open<P: AsRef<Path>>(dir: P) -> Result<WriteAheadLog,Error> {
+		let dir = dir.as_ref().to_path_buf();
+		fs::create_dir_all(&dir).map_err(Error::IoError)?;
+
+		let mut log = OpenOptions::new().read(true).append(true).create(true)
+							.open(dir.join(WAL_FILE)).map_err(Error::IoError)?;
+		let file_len = log.metadata().map_err(Error::IoError)?.len();
+		let records = WriteAheadLog::read_records(&mut log)?;
+		let log_len = (records.len() * RECORD_SIZE) as u64;
+		let sealed = sealed_keys(&group(records));
+		if log_len < file_len {
+			println!("Truncating {} bytes of incomplete records from {:?}", file_len - log_len, dir.join(WAL_FILE));
+			log.set_len(log_len).map_err(Error::IoError)?;
+			log.sync_all().map_err(Error::IoError)?;
+		}
+
+		Ok(WriteAheadLog {
+			dir: dir,
+			state: Mutex::new(WalState {
+				log: log,
+				log_len: log_len,
+				sealed: sealed,
+			}),
+		})
+	}
+
+	/* Removes every file owned by the log in the provided directory */
+	pub fn destroy<P: AsRef<Path>>(dir: P) -> Result<(),Error> {
+		match fs::remove_dir_all(dir) {
+			Ok(()) => Ok(()),
+			Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+			Err(e) => Err(Error::IoError(e)),
+		}
+	}
+
+	/* Bytes occupied by the log */
+	pub fn log_size(&self) -> u64 {
+		self.state.lock().map(|st| st.log_len).unwrap_or(0)
+	}
+
+	/* Logs a value the signal received at the provided time */
+	pub fn append(&self, signal: SignalId, time: SystemTime, value: f64) -> Result<(),Error> {
+		self.write(&[Record { op: RECORD_POINT, signal: signal, nanos: to_nanos(time), value: value.to_bits() }], false)
+	}
+
+	/* Closes the segment of the signal that started at the provided
+	 * timestamp and fsyncs every point logged before it
+	 */
+	pub fn seal(&self, signal: SignalId, timestamp: SystemTime) -> Result<(),Error> {
+		let mut st = self.state.lock().map_err(|_| Error::LockError)?;
+		WriteAheadLog::write_locked(&mut st, &[Record { op: RECORD_SEAL, signal: signal, nanos: to_nanos(timestamp), value: 0 }], true)?;
+		st.sealed.insert(SegmentKey::new(UNIX_EPOCH + Duration::from_nanos(to_nanos(timestamp)), signal));
+		Ok(())
+	}
+
+	/* Drops the sealed segments persisted returns true for,
+	 * returns the bytes reclaimed. The log is only read when one of them is.
+	 */
+	pub fn truncate<F: Fn(SegmentKey) -> bool>(&self, persisted: F) -> Result<u64,Error> {
+		let mut st = self.state.lock().map_err(|_| Error::LockError)?;
+		if !st.sealed.iter().any(|key| persisted(*key)) {
+			return Ok(0);
+		}
+		let records = WriteAheadLog::read_records(&mut st.log)?;
+		let total = records.len();
+		let mut kept = Vec::with_capacity(total);
+		for group in group(records) {
+			if group.points.is_empty() || (group.seal.is_some() && persisted(group.key())) {
+				continue;
+			}
+			kept.extend(group.points);
+			kept.extend(group.seal);
+		}
+		if kept.len() == total {
+			return Ok(0);
+		}
+		self.rewrite(&mut st, &kept)?;
+		Ok(((total - kept.len()) * RECORD_SIZE) as u64)
+	}
+
+	/* Drops the sealed segments any of the pools persisted */
+	pub fn truncate_persisted<T: Copy + Send>(&self, pools: &[Arc<Mutex<SegmentBuffer<T> + Send + Sync>>]) -> Result<u64,Error> {
+		self.truncate(|key| pools.iter().any(|pool| match pool.lock() {
+			Ok(buf) => buf.is_persisted(key),
+			Err(_) => false,
+		}))
+	}
+
+	/* Rebuilds every segment left in the log and seals the partial ones */
+	pub fn recover<T: Float + Send>(&self) -> Result<Vec<Segment<T>>,Error> {
+		let mut st = self.state.lock().map_err(|_| Error::LockError)?;
+		let records = WriteAheadLog::read_records(&mut st.log)?;
+		let mut segs = Vec::new();
+		let mut seals = Vec::new();
+		for group in group(records) {
+			if group.points.is_empty() {
+				continue;
+			}
+			if group.seal.is_none() {
+				seals.push(Record { op: RECORD_SEAL, signal: group.signal, nanos: group.start(), value: 0 });
+			}
+			segs.push(group.to_segment());
+		}
+		if !seals.is_empty() {
+			WriteAheadLog::write_locked(&mut st, &seals, true)?;
+			st.sealed.extend(seals.iter().map(|seal| SegmentKey::new(UNIX_EPOCH + Duration::from_nanos(seal.nanos), seal.signal)));
+		}
+		Ok(segs)
+	}
+
+	fn write(&self, records: &[Record], sync: bool) -> Result<(),Error> {
+		let mut st = self.state.lock().map_err(|_| Error::LockError)?;
+		WriteAheadLog::write_locked(&mut st, records, sync)
+	}
+
+	fn write_locked(st: &mut WalState, records: &[Record], sync: bool) -> Result<(),Error> {
+		let mut buf = Vec::with_capacity(records.len() * RECORD_SIZE);
+		for rec in records {
+			buf.extend_from_slice(&rec.encode());
+		}
+		let res = st.log.write_all(&buf).and_then(|_| if sync { st.log.sync_data() } else { Ok(()) });
+		if let Err(e) = res {
+			/* Drop whatever part of the records made it to disk */
+			let len = st.log_len;
+			let _ = st.log.set_len(len);
+			return Err(Error::IoError(e));
+		}
+		st.log_len += buf.len() as u64;
+		Ok(())
+	}
+
+	/* Reads every complete record, stopping at the first one that is torn */
+	fn read_records(log: &mut File) -> Result<Vec<Record>,Error> {
+		let mut bytes = Vec::new();
+		log.seek(SeekFrom::Start(0)).map_err(Error::IoError)?;
+		log.read_to_end(&mut bytes).map_err(Error::IoError)?;
+		Ok(bytes.chunks(RECORD_SIZE).map(Record::decode).take_while(|rec| rec.is_some()).map(|rec| rec.unwrap()).collect())
+	}
+
+	/* Replaces the log with the provided records through a rename */
+	fn rewrite(&self, st: &mut WalState, records: &[Record]) -> Result<(),Error> {
+		let tmp_path = self.dir.join(format!("{}.tmp", WAL_FILE));
+		let mut tmp = File::create(&tmp_path).map_err(Error::IoError)?;
+		let mut buf = Vec::with_capacity(records.len() * RECORD_SIZE);
+		for rec in records {
+			buf.extend_from_slice(&rec.encode());
+		}
+		let sealed = sealed_keys(&group(records.to_vec()));
+		tmp.write_all(&buf).and_then(|_| tmp.sync_all()).map_err(Error::IoError)?;
+		drop(tmp);
+
+		fs::rename(&tmp_path, self.dir.join(WAL_FILE)).map_err(Error::IoError)?;
+		sync_dir(&self.dir)?;
+		st.log = OpenOptions::new().read(true).append(true).open(self.dir.join(WAL_FILE)).map_err(Error::IoError)?;
+		st.log_len = buf.len() as u64;
+		st.sealed = sealed;
+		Ok(())
+	}
+}
+
+/* Opens the log of a run and puts the segments it still holds, that
+ * neither pool persisted, back into the raw pool. The shutdown handle
+ * truncates it every interval while the run lasts and once more after
+ * it persisted the pools.
+ */
+pub fn start_wal<T: Float + Send + 'static>(dir: &str, interval: Duration,
+		raw: &Arc<Mutex<SegmentBuffer<T> + Send + Sync>>,
+		comp: Option<&Arc<Mutex<SegmentBuffer<T> + Send + Sync>>>,
+		catalog: Option<&Arc<Mutex<SignalCatalog>>>,
+		shutdown: &mut ShutdownHandle<T>) -> Arc<WriteAheadLog>
+{
+	let wal = Arc::new(WriteAheadLog::open(dir).expect("Failed to open the write ahead log"));
+	let mut pools = vec![share_buffer(raw)];
+	pools.extend(comp.map(share_buffer));
+
+	wal.truncate_persisted(&pools).expect("Failed to truncate the write ahead log");
+	let segs = wal.recover::<T>().expect("Failed to recover the write ahead log");
+	println!("Recovered {} segments from the write ahead log", segs.len());
+	for seg in segs {
+		/* Recovered segments were never persisted, so the catalog does not hold them yet */
+		if let Some(catalog) = catalog {
+			catalog.lock().unwrap().record(seg.get_key(), CODEC_RAW, seg.get_size() as u64);
+		}
+		if let Err(e) = raw.lock().unwrap().put(seg) {
+			panic!("Failed to put a recovered segment in the buffer: {:?}", e);
+		}
+	}
+
+	shutdown.add_daemon(spawn_truncation(wal.clone(), pools, interval, shutdown.signal()));
+	shutdown.set_wal(wal.clone());
+	wal
+}
+
+/* Truncates the log every interval until the run shuts down */
+pub fn spawn_truncation<T: Copy + Send + 'static>(wal: Arc<WriteAheadLog>,
+		pools: Vec<Arc<Mutex<SegmentBuffer<T> + Send + Sync>>>,
+		interval: Duration, shutdown: Shutdown) -> JoinHandle<()>
+{
+	thread::spawn(move || {
+		let mut last = Instant::now();
+		while !shutdown.is_stopping() {
+			thread::sleep(Duration::from_millis(10).min(interval));
+			if last.elapsed() < interval {
+				continue;
+			}
+			if let Err(e) = wal.truncate_persisted(&pools) {
+				println!("Failed to truncate the write ahead log: {:?}", e);
+			}
+			last = Instant::now();
+		}
+	})
+}
+
+#[test]
+fn write_ahead_log_test() {
+	let path = "../wal_test";
+	let _ = WriteAheadLog::destroy(path);
+	let at = |secs: u64, millis: u64| UNIX_EPOCH + Duration::from_secs(secs) + Duration::from_millis(millis);
+	{
+		let wal = WriteAheadLog::open(path).expect("Failed to open write ahead log");
+		for i in 0..3 {
+			wal.append(1, at(10, i), i as f64).unwrap();
+		}
+		wal.append(2, at(11, 0), -1.5).unwrap();
+		wal.seal(1, at(10, 0)).unwrap();
+		wal.append(1, at(12, 0), 7.0).unwrap();
+		wal.append(1, at(12, 5), std::f64::NAN).unwrap();
+		assert_eq!(wal.truncate(|_| false).unwrap(), 0);
+		assert_eq!(wal.log_size(), 7 * RECORD_SIZE as u64);
+	}
+
+	/* A crash in the middle of a record only loses that record */
+	let mut file = OpenOptions::new().append(true).open(Path::new(path).join(WAL_FILE)).unwrap();
+	file.write_all(&[RECORD_POINT, 1, 2, 3]).unwrap();
+	drop(file);
+	let wal = WriteAheadLog::open(path).expect("Failed to reopen write ahead log");
+	assert_eq!(wal.log_size(), 7 * RECORD_SIZE as u64);
+
+	let segs = wal.recover::<f64>().unwrap();
+	assert_eq!(segs.len(), 3);
+	assert_eq!(segs[0].get_key(), SegmentKey::new(at(10, 0), 1));
+	assert_eq!(segs[0].get_data(), &vec![0.0, 1.0, 2.0]);
+	assert_eq!(segs[0].get_time_lapse().as_ref().unwrap()[2], Duration::from_millis(2));
+	assert_eq!(segs[1].get_key(), SegmentKey::new(at(12, 0), 1));
+	assert!(segs[1].get_data()[0] == 7.0 && segs[1].get_data()[1].is_nan());
+	assert_eq!(segs[2].get_key(), SegmentKey::new(at(11, 0), 2));
+
+	/* The partial segments were sealed, new points start new segments */
+	wal.append(1, at(13, 0), 3.0).unwrap();
+	assert_eq!(wal.recover::<f64>().unwrap().len(), 4);
+
+	/* Without a persisted segment the log is not read, only the sealed keys are asked about */
+	let asked = std::cell::RefCell::new(Vec::new());
+	assert_eq!(wal.truncate(|key| { asked.borrow_mut().push(key); false }).unwrap(), 0);
+	assert_eq!(asked.into_inner(), vec![SegmentKey::new(at(10, 0), 1), SegmentKey::new(at(11, 0), 2), SegmentKey::new(at(12, 0), 1), SegmentKey::new(at(13, 0), 1)]);
+
+	let persisted = SegmentKey::new(at(10, 0), 1);
+	assert_eq!(wal.truncate(|key| key == persisted).unwrap(), 4 * RECORD_SIZE as u64);
+	assert_eq!(wal.truncate(|key| key == persisted).unwrap(), 0);
+	let keys: Vec<SegmentKey> = wal.recover::<f64>().unwrap().iter().map(|seg| seg.get_key()).collect();
+	assert_eq!(keys, vec![SegmentKey::new(at(12, 0), 1), SegmentKey::new(at(11, 0), 2), SegmentKey::new(at(13, 0), 1)]);
+	WriteAheadLog::destroy(path).expect("Failed to destroy write ahead log");
+}